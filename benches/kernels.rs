@@ -0,0 +1,105 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Compare the SIMD and scalar kernel building blocks and the support vector layouts,
+//! run with `cargo bench`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use nrps_rs::encodings::FeatureEncoding;
+use nrps_rs::predictors::predictions::PredictionCategory;
+use nrps_rs::svm::models::{KernelParameters, KernelType, SVMlightModel};
+use nrps_rs::svm::simd;
+use nrps_rs::svm::vectors::{FeatureVector, SupportVector, SupportVectors, Vector};
+
+const DIMENSIONS: usize = 510;
+const ITERATIONS: usize = 1_000_000;
+const SUPPORT_VECTORS: usize = 2_000;
+const PREDICTIONS: usize = 500;
+
+fn report(name: &str, elapsed: Duration, iterations: usize) {
+    println!(
+        "{name:<20}{:>12.1} ns/iter",
+        elapsed.as_nanos() as f64 / iterations as f64
+    );
+}
+
+fn time(name: &str, func: fn(&[f64], &[f64]) -> f64, a: &[f64], b: &[f64]) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(func(black_box(a), black_box(b)));
+    }
+    let elapsed = start.elapsed();
+    report(name, elapsed, ITERATIONS);
+    elapsed
+}
+
+fn values(seed: usize) -> Vec<f64> {
+    (0..DIMENSIONS)
+        .map(|i| ((i * 31 + seed * 17) as f64 * 0.01).sin())
+        .collect()
+}
+
+fn main() {
+    let a = values(1);
+    let b = values(2);
+
+    println!("{DIMENSIONS} dimensions, {ITERATIONS} iterations");
+    let scalar = time("dot_scalar", simd::dot_scalar, &a, &b);
+    let fast = time("dot", simd::dot, &a, &b);
+    println!("speedup {:.2}x", scalar.as_secs_f64() / fast.as_secs_f64());
+
+    let scalar = time("square_dist_scalar", simd::square_dist_scalar, &a, &b);
+    let fast = time("square_dist", simd::square_dist, &a, &b);
+    println!("speedup {:.2}x", scalar.as_secs_f64() / fast.as_secs_f64());
+
+    // One RBF model, evaluated with one Vec per support vector and with the flat layout
+    let gamma = 0.5;
+    let separate: Vec<SupportVector> = (0..SUPPORT_VECTORS)
+        .map(|i| SupportVector::new(values(i), if i % 2 == 0 { 1.0 } else { -1.0 }))
+        .collect();
+    let flat = SupportVectors::try_from(
+        separate
+            .iter()
+            .map(|v| SupportVector::new(v.values().clone(), v.yalpha))
+            .collect::<Vec<_>>(),
+    )
+    .unwrap();
+    let model = SVMlightModel::new(
+        "bench".to_string(),
+        PredictionCategory::SingleV3,
+        flat,
+        0.0,
+        FeatureEncoding::Custom("bench"),
+        KernelType::RBF,
+        KernelParameters {
+            gamma,
+            ..Default::default()
+        },
+    );
+    let query = FeatureVector::new(values(SUPPORT_VECTORS));
+
+    println!("\n{SUPPORT_VECTORS} RBF support vectors, {PREDICTIONS} predictions");
+    let start = Instant::now();
+    for _ in 0..PREDICTIONS {
+        let sum: f64 = separate
+            .iter()
+            .map(|v| v.yalpha * (-gamma * simd::square_dist(v.values(), query.values())).exp())
+            .sum();
+        black_box(sum);
+    }
+    let separate_elapsed = start.elapsed();
+    report("separate vectors", separate_elapsed, PREDICTIONS);
+
+    let start = Instant::now();
+    for _ in 0..PREDICTIONS {
+        black_box(model.predict(black_box(&query)).unwrap());
+    }
+    let flat_elapsed = start.elapsed();
+    report("flat vectors", flat_elapsed, PREDICTIONS);
+    println!(
+        "speedup {:.2}x",
+        separate_elapsed.as_secs_f64() / flat_elapsed.as_secs_f64()
+    );
+}
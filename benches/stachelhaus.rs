@@ -0,0 +1,183 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Compare a naive Stachelhaus scan with the early-exit scan and the exact match index, and the
+//! `String` based aa10 extraction and Hamming distance with the byte based ones, run with
+//! `cargo bench --bench stachelhaus`
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use nrps_rs::predictors::predictions::ADomain;
+use nrps_rs::predictors::stachelhaus::{
+    aa10_bytes, extract_aa10, predict, StachelhausDb, StachelhausSignature,
+};
+use nrps_rs::predictors::PredictionBackend;
+
+const SIGNATURES: usize = 10_000;
+const DOMAINS: usize = 1_000;
+const AMINO_ACIDS: &[u8; 20] = b"ACDEFGHIKLMNPQRSTVWY";
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{name:<20}{:>12.1} µs/domain",
+        elapsed.as_micros() as f64 / DOMAINS as f64
+    );
+}
+
+/// Deterministic pseudo-random aa34 signatures, with a few residues from a small alphabet so
+/// some of them are close enough to count as hits
+fn random_aa34(seed: &mut u64) -> String {
+    (0..34)
+        .map(|_| {
+            *seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let alphabet = if (*seed >> 60) < 12 {
+                4
+            } else {
+                AMINO_ACIDS.len()
+            };
+            AMINO_ACIDS[(*seed >> 33) as usize % alphabet] as char
+        })
+        .collect()
+}
+
+fn signature(aa34: String, i: usize) -> StachelhausSignature {
+    let winner = ["Leu", "Val", "Ile", "Ala", "Gly", "Ser"][i % 6].to_string();
+    StachelhausSignature {
+        aa10: extract_aa10(&aa34).unwrap(),
+        aa34,
+        all: winner.clone(),
+        winner,
+        ids: format!("BGC{i:07}"),
+        evidence: None,
+    }
+}
+
+fn hamming_dist(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).filter(|t| t.0 != t.1).count()
+}
+
+/// aa10 extraction the way it used to be, collecting the residues into a `String`
+fn extract_aa10_string(aa34: &str) -> String {
+    let mut aa10: String = aa34
+        .chars()
+        .enumerate()
+        .filter(|(i, _)| [5, 6, 9, 12, 14, 16, 21, 29, 30].contains(i))
+        .map(|(_, c)| c)
+        .collect();
+    aa10.push('K');
+    aa10
+}
+
+/// aa10 Hamming distances of all domains to all signatures, extracting the domain aa10 with
+/// `extract` and comparing with `distance`
+fn all_distances<T>(
+    domains: &[ADomain],
+    signatures: &[StachelhausSignature],
+    extract: impl Fn(&str) -> T,
+    distance: impl Fn(&T, &str) -> usize,
+) -> usize {
+    domains
+        .iter()
+        .map(|domain| {
+            let aa10 = extract(&domain.aa34);
+            signatures
+                .iter()
+                .map(|sig| distance(&aa10, &sig.aa10))
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+/// The full scan without early exit, what the lookup used to do
+fn naive(domains: &[ADomain], signatures: &[StachelhausSignature]) -> usize {
+    let mut hits = 0;
+    for domain in domains.iter() {
+        let aa10 = extract_aa10(&domain.aa34).unwrap();
+        for sig in signatures.iter() {
+            let aa10_matches = aa10.len() - hamming_dist(&aa10, &sig.aa10);
+            let aa34_matches = domain.aa34.len() - hamming_dist(&domain.aa34, &sig.aa34);
+            if aa10_matches >= 7 {
+                hits += aa34_matches;
+            }
+        }
+    }
+    hits
+}
+
+fn main() {
+    let mut seed = 42;
+    let signatures: Vec<StachelhausSignature> = (0..SIGNATURES)
+        .map(|i| signature(random_aa34(&mut seed), i))
+        .collect();
+    // Half the domains have an exact match in the reference set
+    let domains: Vec<ADomain> = (0..DOMAINS)
+        .map(|i| {
+            let aa34 = if i % 2 == 0 {
+                signatures[i * 7 % SIGNATURES].aa34.clone()
+            } else {
+                random_aa34(&mut seed)
+            };
+            ADomain::new(format!("domain_{i}"), aa34)
+        })
+        .collect();
+
+    println!("{SIGNATURES} signatures, {DOMAINS} domains");
+    let start = Instant::now();
+    let string_distances = all_distances(
+        black_box(&domains),
+        black_box(&signatures),
+        extract_aa10_string,
+        |aa10, other| hamming_dist(aa10, other),
+    );
+    let string_elapsed = start.elapsed();
+    report("String aa10", string_elapsed);
+
+    let start = Instant::now();
+    let byte_distances = all_distances(
+        black_box(&domains),
+        black_box(&signatures),
+        |aa34| aa10_bytes(aa34.as_bytes()).unwrap(),
+        |aa10, other| {
+            aa10.iter()
+                .zip(other.as_bytes())
+                .filter(|(a, b)| a != b)
+                .count()
+        },
+    );
+    let byte_elapsed = start.elapsed();
+    report("byte aa10", byte_elapsed);
+    assert_eq!(string_distances, byte_distances);
+
+    println!(
+        "speedup {:.2}x bytes",
+        string_elapsed.as_secs_f64() / byte_elapsed.as_secs_f64()
+    );
+
+    let start = Instant::now();
+    black_box(naive(black_box(&domains), black_box(&signatures)));
+    let naive_elapsed = start.elapsed();
+    report("naive scan", naive_elapsed);
+
+    let mut scanned = domains.clone();
+    let start = Instant::now();
+    predict(black_box(&mut scanned), black_box(&signatures)).unwrap();
+    let scan_elapsed = start.elapsed();
+    report("early-exit scan", scan_elapsed);
+
+    let mut db = StachelhausDb::new(signatures);
+    db.runners_up = false;
+    let mut indexed = domains.clone();
+    let start = Instant::now();
+    db.predict(black_box(&mut indexed)).unwrap();
+    let index_elapsed = start.elapsed();
+    report("exact match index", index_elapsed);
+
+    println!(
+        "speedup {:.2}x scan, {:.2}x index",
+        naive_elapsed.as_secs_f64() / scan_elapsed.as_secs_f64(),
+        naive_elapsed.as_secs_f64() / index_elapsed.as_secs_f64()
+    );
+}
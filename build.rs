@@ -0,0 +1,133 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+fn main() -> io::Result<()> {
+    #[cfg(feature = "ffi")]
+    generate_header();
+
+    if env::var_os("CARGO_FEATURE_BUNDLED_MODELS").is_none() {
+        return Ok(());
+    }
+
+    println!("cargo:rerun-if-env-changed=NRPS_BUNDLED_MODEL_DIR");
+    let model_dir = match env::var_os("NRPS_BUNDLED_MODEL_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("data/models"),
+    };
+    println!("cargo:rerun-if-changed={}", model_dir.display());
+
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("bundled_models.rs");
+    let mut out = fs::File::create(out_path)?;
+
+    let mut models: Vec<(String, String, PathBuf)> = Vec::new();
+    let mut signatures: Option<PathBuf> = None;
+    let mut hmm_profile: Option<PathBuf> = None;
+
+    if model_dir.is_dir() {
+        for category_entry in sorted_entries(&model_dir)? {
+            if category_entry.is_file()
+                && category_entry.file_name().unwrap_or_default() == "signatures.tsv"
+            {
+                signatures = Some(fs::canonicalize(&category_entry)?);
+                continue;
+            }
+            if category_entry.is_file()
+                && category_entry.file_name().unwrap_or_default() == "AMP-binding.hmm"
+            {
+                hmm_profile = Some(fs::canonicalize(&category_entry)?);
+                continue;
+            }
+            if !category_entry.is_dir() {
+                continue;
+            }
+            let category = category_entry
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            for model_file in sorted_entries(&category_entry)? {
+                if model_file.extension().unwrap_or_default() != "mdl" {
+                    continue;
+                }
+                let file_name = model_file
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                models.push((category.clone(), file_name, fs::canonicalize(&model_file)?));
+            }
+        }
+    } else {
+        println!(
+            "cargo:warning=bundled-models enabled, but {} doesn't exist",
+            model_dir.display()
+        );
+    }
+
+    writeln!(
+        out,
+        "pub static BUNDLED_MODELS: &[(&str, &str, &[u8])] = &["
+    )?;
+    for (category, file_name, path) in models.iter() {
+        writeln!(
+            out,
+            "    ({category:?}, {file_name:?}, include_bytes!({:?})),",
+            path.display().to_string()
+        )?;
+    }
+    writeln!(out, "];")?;
+
+    match signatures {
+        Some(path) => writeln!(
+            out,
+            "pub static BUNDLED_SIGNATURES: Option<&[u8]> = Some(include_bytes!({:?}));",
+            path.display().to_string()
+        )?,
+        None => writeln!(out, "pub static BUNDLED_SIGNATURES: Option<&[u8]> = None;")?,
+    }
+
+    match hmm_profile {
+        Some(path) => writeln!(
+            out,
+            "pub static BUNDLED_HMM_PROFILE: Option<&[u8]> = Some(include_bytes!({:?}));",
+            path.display().to_string()
+        )?,
+        None => writeln!(out, "pub static BUNDLED_HMM_PROFILE: Option<&[u8]> = None;")?,
+    }
+
+    Ok(())
+}
+
+fn sorted_entries(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+/// Generate the C header in `OUT_DIR`, `ffi::tests` checks that `include/nrps_rs.h` matches it
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = env::var("OUT_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("NRPS_RS_H".to_string()),
+        documentation: true,
+        usize_is_size_t: true,
+        ..Default::default()
+    };
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(Path::new(&crate_dir).join("src/ffi.rs"))
+        .generate()
+        .expect("Failed to generate C header")
+        .write_to_file(Path::new(&out_dir).join("nrps_rs.h"));
+}
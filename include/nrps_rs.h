@@ -0,0 +1,48 @@
+#ifndef NRPS_RS_H
+#define NRPS_RS_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a prediction run
+ *
+ * On success, `data` holds the predictions as JSON, otherwise it holds the error message.
+ * Free with `nrps_result_free`.
+ */
+typedef struct NrpsResult {
+  bool success;
+  char *data;
+} NrpsResult;
+
+/**
+ * Run predictions on `n` signature lines (`signature<TAB>name`)
+ *
+ * `config_path` points to a TOML config file, or is NULL to use the default config. Internal
+ * errors are reported as failed results too.
+ *
+ * # Safety
+ *
+ * `signatures` must point to `n` valid NUL-terminated strings, `config_path` must be NULL
+ * or a valid NUL-terminated string.
+ */
+struct NrpsResult nrps_predict(const char *const *signatures, size_t n, const char *config_path);
+
+/**
+ * Free the data of a result returned by `nrps_predict`
+ *
+ * # Safety
+ *
+ * `result` must have been returned by `nrps_predict` and not been freed before.
+ */
+void nrps_result_free(struct NrpsResult result);
+
+/**
+ * Version of the nrps-rs library, the returned string is static and must not be freed
+ */
+const char *nrps_version(void);
+
+#endif  /* NRPS_RS_H */
@@ -0,0 +1,136 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Async API for embedding the predictor in tokio-based services.
+//!
+//! Loading the models and the CPU-bound predictions run on tokio's blocking thread pool so they
+//! don't stall the executor.
+
+use std::io;
+use std::sync::Arc;
+
+use tokio::task;
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::predictors::predictions::ADomain;
+use crate::predictors::{PredictionBackend, SvmPredictor};
+use crate::run;
+
+/// Async version of `run()`, loads the configured backends and predicts `domains`
+pub async fn run_async(config: &Config, domains: Vec<ADomain>) -> Result<Vec<ADomain>, NrpsError> {
+    let config = config.clone();
+    task::spawn_blocking(move || {
+        let mut domains = domains;
+        run(&config, &mut domains)?;
+        Ok(domains)
+    })
+    .await
+    .map_err(io::Error::from)?
+}
+
+/// Predict `domains` with an already loaded backend, e.g. one shared between requests
+pub async fn predict_async<B>(
+    backend: Arc<B>,
+    domains: Vec<ADomain>,
+) -> Result<Vec<ADomain>, NrpsError>
+where
+    B: PredictionBackend + Send + Sync + 'static,
+{
+    task::spawn_blocking(move || {
+        let mut domains = domains;
+        backend.predict(&mut domains)?;
+        Ok(domains)
+    })
+    .await
+    .map_err(io::Error::from)?
+}
+
+/// Load an `SvmPredictor` like `SvmPredictor::from_config()`, with all enabled categories loaded
+/// on tokio's blocking thread pool
+pub async fn load_predictor_async(config: &Config) -> Result<SvmPredictor, NrpsError> {
+    let config = config.clone();
+    task::spawn_blocking(move || {
+        let predictor = SvmPredictor::from_config(&config)?;
+        predictor.store.load_all()?;
+        Ok(predictor)
+    })
+    .await
+    .map_err(io::Error::from)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::encodings::EncodingOptions;
+    use crate::predictors::predictions::PredictionCategory;
+    use crate::predictors::store::ModelStore;
+    use crate::svm::models::SVMlightModel;
+    use crate::testing::svmlight_model;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn test_load_predictor_async() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let single = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        std::fs::create_dir_all(&single).unwrap();
+        std::fs::write(single.join("[val].mdl"), svmlight_model(102)).unwrap();
+        std::fs::write(single.join("[leu].mdl"), svmlight_model(102)).unwrap();
+        std::fs::write(single.join("README"), "not a model").unwrap();
+
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.clone());
+        config.skip_stachelhaus = true;
+        let predictor = block_on(load_predictor_async(&config)).unwrap();
+        let models = predictor
+            .store
+            .models_for(&PredictionCategory::SingleV2)
+            .unwrap();
+        let names: Vec<&str> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["leu", "val"]);
+
+        // Only the wanted substrates are loaded, like with `SvmPredictor::from_config()`
+        config.model_substrates = Vec::from(["val".to_string()]);
+        let predictor = block_on(load_predictor_async(&config)).unwrap();
+        assert_eq!(predictor.store.model_count(), 1);
+
+        config.strict_models = true;
+        assert!(block_on(load_predictor_async(&config)).is_err());
+    }
+
+    #[test]
+    fn test_predict_async() {
+        let model = SVMlightModel::from_handle(
+            svmlight_model(102).as_bytes(),
+            "leu".to_string(),
+            PredictionCategory::SingleV2,
+        )
+        .unwrap();
+        let predictor = Arc::new(SvmPredictor {
+            store: ModelStore::from_models(Vec::from([model])),
+            min_score: f64::NEG_INFINITY,
+            category_min_scores: BTreeMap::new(),
+            attributions: false,
+            encoding_options: EncodingOptions::default(),
+        });
+        let domains = Vec::from([ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        )]);
+
+        let domains = block_on(predict_async(predictor, domains)).unwrap();
+        let got = domains[0].get_all(&PredictionCategory::SingleV2);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "leu");
+    }
+}
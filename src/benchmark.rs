@@ -0,0 +1,325 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Evaluation of the predictions against signatures with known substrates.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::encodings::EncodingOptions;
+use crate::errors::NrpsError;
+use crate::predictors::predictions::{ADomain, PredictionCategory};
+use crate::predictors::store::ModelStore;
+use crate::predictors::{PredictionBackend, SvmPredictor};
+use crate::run;
+use crate::svm::train::{train_models, TrainingParameters, TrainingSample};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SubstrateStats {
+    pub substrate: String,
+    pub true_positives: usize,
+    pub false_positives: usize,
+    pub false_negatives: usize,
+}
+
+impl SubstrateStats {
+    pub fn precision(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_positives,
+        )
+    }
+
+    pub fn recall(&self) -> f64 {
+        ratio(
+            self.true_positives,
+            self.true_positives + self.false_negatives,
+        )
+    }
+
+    pub fn f1(&self) -> f64 {
+        let (precision, recall) = (self.precision(), self.recall());
+        if precision + recall == 0.0 {
+            return 0.0;
+        }
+        2.0 * precision * recall / (precision + recall)
+    }
+
+    /// Number of samples labeled with this substrate
+    pub fn support(&self) -> usize {
+        self.true_positives + self.false_negatives
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        return 0.0;
+    }
+    numerator as f64 / denominator as f64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BenchmarkReport {
+    pub category: PredictionCategory,
+    /// Per-substrate stats, sorted by substrate
+    pub stats: Vec<SubstrateStats>,
+    pub correct: usize,
+    pub total: usize,
+}
+
+impl BenchmarkReport {
+    /// Score the best prediction(s) for each sample against its substrate
+    ///
+    /// A prediction is correct if any of its comma- or slash-separated substrates matches,
+    /// ignoring case, so cluster predictions like `val,leu,ile` count for all their members.
+    pub fn new(category: PredictionCategory, results: &[(&str, Vec<String>)]) -> Self {
+        let mut stats: BTreeMap<String, SubstrateStats> = BTreeMap::new();
+        let mut correct = 0;
+
+        for (truth, predicted) in results.iter() {
+            let truth = truth.to_lowercase();
+            let substrates: Vec<String> = predicted
+                .iter()
+                .flat_map(|name| name.split([',', '/']))
+                .map(|name| name.trim().to_lowercase())
+                .filter(|name| !name.is_empty())
+                .collect();
+
+            if substrates.contains(&truth) {
+                correct += 1;
+                entry(&mut stats, &truth).true_positives += 1;
+                continue;
+            }
+
+            entry(&mut stats, &truth).false_negatives += 1;
+            for substrate in substrates.iter() {
+                entry(&mut stats, substrate).false_positives += 1;
+            }
+        }
+
+        BenchmarkReport {
+            category,
+            stats: stats.into_values().collect(),
+            correct,
+            total: results.len(),
+        }
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.correct, self.total)
+    }
+
+    /// Unweighted mean F1 over all substrates with samples
+    pub fn macro_f1(&self) -> f64 {
+        let scored: Vec<f64> = self
+            .stats
+            .iter()
+            .filter(|s| s.support() > 0)
+            .map(|s| s.f1())
+            .collect();
+        if scored.is_empty() {
+            return 0.0;
+        }
+        scored.iter().sum::<f64>() / scored.len() as f64
+    }
+}
+
+fn entry<'a>(
+    stats: &'a mut BTreeMap<String, SubstrateStats>,
+    substrate: &str,
+) -> &'a mut SubstrateStats {
+    stats
+        .entry(substrate.to_string())
+        .or_insert_with(|| SubstrateStats {
+            substrate: substrate.to_string(),
+            ..Default::default()
+        })
+}
+
+fn best_names(domain: &ADomain, category: &PredictionCategory) -> Vec<String> {
+    if *category == PredictionCategory::Stachelhaus {
+        return domain
+            .stach_predictions
+            .get_best()
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+    }
+    domain
+        .get_best(category)
+        .into_iter()
+        .map(|p| p.name)
+        .collect()
+}
+
+fn to_domains(samples: &[TrainingSample]) -> Vec<ADomain> {
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| ADomain::new(format!("sample_{}", i + 1), sample.aa34.to_string()))
+        .collect()
+}
+
+/// Evaluate the configured models and Stachelhaus signatures, one report per category
+pub fn benchmark_models(
+    config: &Config,
+    samples: &[TrainingSample],
+) -> Result<Vec<BenchmarkReport>, NrpsError> {
+    let mut domains = to_domains(samples);
+    run(config, &mut domains)?;
+
+    Ok(config
+        .categories()
+        .iter()
+        .map(|category| {
+            let results: Vec<(&str, Vec<String>)> = samples
+                .iter()
+                .zip(domains.iter())
+                .map(|(sample, domain)| (sample.substrate.as_str(), best_names(domain, category)))
+                .collect();
+            BenchmarkReport::new(category.clone(), &results)
+        })
+        .collect())
+}
+
+/// Train and evaluate models by k-fold cross-validation, sample `i` is held out in fold `i % folds`
+///
+/// Use `folds = samples.len()` for leave-one-out cross-validation.
+pub fn cross_validate(
+    samples: &[TrainingSample],
+    params: &TrainingParameters,
+    folds: usize,
+) -> Result<BenchmarkReport, NrpsError> {
+    if folds < 2 || folds > samples.len() {
+        return Err(NrpsError::TrainingError(format!(
+            "Need between 2 and {} folds, got {folds}",
+            samples.len()
+        )));
+    }
+
+    let mut predicted: Vec<Vec<String>> = vec![Vec::new(); samples.len()];
+    for fold in 0..folds {
+        let (test, training): (Vec<usize>, Vec<usize>) =
+            (0..samples.len()).partition(|i| i % folds == fold);
+        let training: Vec<TrainingSample> = training.iter().map(|i| samples[*i].clone()).collect();
+        let test_samples: Vec<TrainingSample> = test.iter().map(|i| samples[*i].clone()).collect();
+
+        // Every one-vs-rest model gets a say, the best one wins
+        let predictor = SvmPredictor {
+            store: ModelStore::from_models(train_models(&training, params)?),
+            min_score: f64::NEG_INFINITY,
+            category_min_scores: BTreeMap::new(),
+            attributions: false,
+            encoding_options: EncodingOptions::default(),
+        };
+        let mut domains = to_domains(&test_samples);
+        predictor.predict(&mut domains)?;
+
+        for (i, domain) in test.iter().zip(domains.iter()) {
+            predicted[*i] = best_names(domain, &params.category);
+        }
+        log::debug!("Finished fold {}/{folds}", fold + 1);
+    }
+
+    let results: Vec<(&str, Vec<String>)> = samples
+        .iter()
+        .map(|s| s.substrate.as_str())
+        .zip(predicted)
+        .collect();
+    Ok(BenchmarkReport::new(params.category.clone(), &results))
+}
+
+/// Tab-separated per-substrate table of all reports, followed by a summary line per report
+pub fn format_reports(reports: &[BenchmarkReport]) -> String {
+    let mut lines = Vec::from(["Category\tSubstrate\tPrecision\tRecall\tF1\tSupport".to_string()]);
+    for report in reports.iter() {
+        for stats in report.stats.iter() {
+            lines.push(format!(
+                "{:?}\t{}\t{:.2}\t{:.2}\t{:.2}\t{}",
+                report.category,
+                stats.substrate,
+                stats.precision(),
+                stats.recall(),
+                stats.f1(),
+                stats.support()
+            ));
+        }
+    }
+    for report in reports.iter() {
+        lines.push(format!(
+            "# {:?}: accuracy {:.2} ({}/{}), macro F1 {:.2}",
+            report.category,
+            report.accuracy(),
+            report.correct,
+            report.total,
+            report.macro_f1()
+        ));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::svm::models::KernelType;
+    use crate::svm::train::parse_training_data;
+
+    #[test]
+    fn test_benchmark_report() {
+        let results = Vec::from([
+            ("leu", Vec::from(["Leu".to_string()])),
+            ("leu", Vec::from(["val,leu,ile".to_string()])),
+            ("leu", Vec::from(["val".to_string()])),
+            ("val", Vec::new()),
+            ("ala", Vec::from(["ala/gly".to_string()])),
+        ]);
+        let report = BenchmarkReport::new(PredictionCategory::SingleV3, &results);
+        assert_eq!(report.correct, 3);
+        assert_eq!(report.total, 5);
+        assert_approx_eq!(report.accuracy(), 0.6);
+
+        let substrates: Vec<&str> = report.stats.iter().map(|s| s.substrate.as_str()).collect();
+        assert_eq!(substrates, ["ala", "leu", "val"]);
+        let leu = &report.stats[1];
+        assert_eq!(leu.support(), 3);
+        assert_approx_eq!(leu.precision(), 1.0);
+        assert_approx_eq!(leu.recall(), 2.0 / 3.0);
+        assert_approx_eq!(leu.f1(), 0.8);
+        let val = &report.stats[2];
+        assert_eq!(val.false_positives, 1);
+        assert_eq!(val.false_negatives, 1);
+        assert_approx_eq!(val.f1(), 0.0);
+        assert_approx_eq!(report.macro_f1(), 0.6);
+    }
+
+    #[test]
+    fn test_cross_validate() {
+        let data = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tleu\n\
+                    LDASFDASLFEMYLLTGGDRNMYGPTEATMCATL\tleu\n\
+                    LDASFDASLFEMYLLSGGDRNMYGPTEATMCATW\tleu\n\
+                    LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW\thpg\n\
+                    LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATA\thpg\n\
+                    LEPAFDISLFEVHLLSGGDRHLYGPTEATLCATW\thpg\n";
+        let samples = parse_training_data(data.as_bytes()).unwrap();
+        let params = TrainingParameters {
+            kernel_type: KernelType::Linear,
+            ..Default::default()
+        };
+
+        let report = cross_validate(&samples, &params, samples.len()).unwrap();
+        assert_eq!(report.category, PredictionCategory::SingleV3);
+        assert_eq!(report.total, 6);
+        assert_eq!(report.correct, 6);
+
+        assert!(cross_validate(&samples, &params, 1).is_err());
+        assert!(cross_validate(&samples, &params, 7).is_err());
+    }
+}
@@ -0,0 +1,34 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+use std::path::Path;
+
+use crate::errors::NrpsError;
+use crate::predictors::extract_name;
+use crate::predictors::predictions::PredictionCategory;
+use crate::svm::models::SVMlightModel;
+
+include!(concat!(env!("OUT_DIR"), "/bundled_models.rs"));
+
+/// Load the models embedded at compile time, used when the model dir doesn't exist
+pub fn load_bundled_models(
+    categories: &[PredictionCategory],
+) -> Result<Vec<SVMlightModel>, NrpsError> {
+    let mut models = Vec::with_capacity(BUNDLED_MODELS.len());
+
+    for (dir_name, file_name, data) in BUNDLED_MODELS.iter() {
+        let Some(category) = PredictionCategory::from_dir_name(dir_name) else {
+            continue;
+        };
+        if !categories.contains(&category) {
+            continue;
+        }
+        let path = Path::new(dir_name).join(file_name);
+        let name = extract_name(&path);
+        models.push(SVMlightModel::from_handle_with_path(
+            *data, &path, name, category, None,
+        )?);
+    }
+
+    Ok(models)
+}
@@ -0,0 +1,291 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Single-linkage clustering of domains by signature identity, to condense large runs.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::output::{tsv_header, tsv_row, OutputFormat, ResultRow};
+use crate::predictors::predictions::ADomain;
+use crate::run;
+
+/// Domains linked by signature identity, as indices into the clustered domains
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cluster {
+    /// The member with the most other members above the identity threshold, the first on ties
+    pub representative: usize,
+    /// All members including the representative, in input order
+    pub members: Vec<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterResult<'a> {
+    pub cluster: usize,
+    pub size: usize,
+    pub members: Vec<&'a str>,
+    pub representative: ResultRow<'a>,
+}
+
+/// Group `domains` whose aa34 signatures are at least `min_identity` identical, transitively
+///
+/// Domains from aa10-only input are compared by their aa10 signature. Clusters are ordered by
+/// their first member.
+pub fn cluster_domains(domains: &[ADomain], min_identity: f64) -> Vec<Cluster> {
+    // Genome-scale inputs have lots of identical signatures, only compare each one once
+    let mut lookup: HashMap<&str, usize> = HashMap::new();
+    let mut unique: Vec<&str> = Vec::new();
+    let mut copies: Vec<usize> = Vec::new();
+    let unique_of: Vec<usize> = domains
+        .iter()
+        .map(|domain| {
+            let signature = signature(domain);
+            let index = *lookup.entry(signature).or_insert_with(|| {
+                unique.push(signature);
+                copies.push(0);
+                unique.len() - 1
+            });
+            copies[index] += 1;
+            index
+        })
+        .collect();
+
+    let mut parents: Vec<usize> = (0..unique.len()).collect();
+    let mut neighbours: Vec<usize> = copies.iter().map(|count| count - 1).collect();
+    for i in 0..unique.len() {
+        for j in i + 1..unique.len() {
+            if identity(unique[i], unique[j]) < min_identity {
+                continue;
+            }
+            neighbours[i] += copies[j];
+            neighbours[j] += copies[i];
+            let (root_i, root_j) = (find(&mut parents, i), find(&mut parents, j));
+            parents[root_i.max(root_j)] = root_i.min(root_j);
+        }
+    }
+
+    let mut clusters: Vec<Cluster> = Vec::new();
+    let mut cluster_of: HashMap<usize, usize> = HashMap::new();
+    for (i, unique_index) in unique_of.iter().enumerate() {
+        let root = find(&mut parents, *unique_index);
+        let cluster = *cluster_of.entry(root).or_insert_with(|| {
+            clusters.push(Cluster {
+                representative: i,
+                members: Vec::new(),
+            });
+            clusters.len() - 1
+        });
+        let cluster = &mut clusters[cluster];
+        if neighbours[*unique_index] > neighbours[unique_of[cluster.representative]] {
+            cluster.representative = i;
+        }
+        cluster.members.push(i);
+    }
+    clusters
+}
+
+/// Predict the representatives of `clusters` only, the other members stay unpredicted
+pub fn predict_representatives(
+    config: &Config,
+    domains: &mut [ADomain],
+    clusters: &[Cluster],
+) -> Result<(), NrpsError> {
+    let mut representatives: Vec<ADomain> = clusters
+        .iter()
+        .map(|cluster| domains[cluster.representative].clone())
+        .collect();
+    run(config, &mut representatives)?;
+    for (cluster, representative) in clusters.iter().zip(representatives) {
+        domains[cluster.representative] = representative;
+    }
+    Ok(())
+}
+
+/// Format the clusters as JSON or as TSV, other output formats fall back to TSV
+pub fn format_clusters(
+    config: &Config,
+    clusters: &[Cluster],
+    domains: &[ADomain],
+) -> Result<String, NrpsError> {
+    if config.count < 1 {
+        return Err(NrpsError::CountError(config.count));
+    }
+
+    match config.output_format {
+        OutputFormat::Json => Ok(format!("{}\n", to_json(config, clusters, domains)?)),
+        _ => Ok(to_tsv(config, clusters, domains)),
+    }
+}
+
+pub fn to_json(
+    config: &Config,
+    clusters: &[Cluster],
+    domains: &[ADomain],
+) -> Result<String, NrpsError> {
+    let results: Vec<ClusterResult> = clusters
+        .iter()
+        .enumerate()
+        .map(|(i, cluster)| ClusterResult {
+            cluster: i + 1,
+            size: cluster.members.len(),
+            members: member_names(cluster, domains),
+            representative: ResultRow::new(config, &domains[cluster.representative]),
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&results)?)
+}
+
+/// The usual TSV results of the representatives, prefixed with `Cluster Size Members` columns
+pub fn to_tsv(config: &Config, clusters: &[Cluster], domains: &[ADomain]) -> String {
+    let with_metadata = clusters
+        .iter()
+        .any(|cluster| domains[cluster.representative].has_metadata());
+    let mut output = String::new();
+    for line in tsv_header(config, with_metadata).lines() {
+        if line.starts_with('#') {
+            output.push_str(&format!("{line}\n"));
+        } else {
+            output.push_str(&format!("Cluster\tSize\tMembers\t{line}\n"));
+        }
+    }
+    for (i, cluster) in clusters.iter().enumerate() {
+        output.push_str(&format!(
+            "{}\t{}\t{}\t{}",
+            i + 1,
+            cluster.members.len(),
+            member_names(cluster, domains).join(","),
+            tsv_row(config, &domains[cluster.representative], with_metadata)
+        ));
+    }
+    output
+}
+
+fn member_names<'a>(cluster: &Cluster, domains: &'a [ADomain]) -> Vec<&'a str> {
+    cluster
+        .members
+        .iter()
+        .map(|i| domains[*i].name.as_str())
+        .collect()
+}
+
+fn signature(domain: &ADomain) -> &str {
+    if domain.aa34.is_empty() {
+        &domain.aa10
+    } else {
+        &domain.aa34
+    }
+}
+
+/// Fraction of identical positions, signatures of different lengths never match
+fn identity(a: &str, b: &str) -> f64 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let matches = a.bytes().zip(b.bytes()).filter(|(x, y)| x == y).count();
+    matches as f64 / a.len() as f64
+}
+
+/// Root of the union-find set of `i`, with path halving
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::predictors::predictions::{Prediction, PredictionCategory};
+
+    const LEU: &str = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+
+    fn domain(name: &str, aa34: &str) -> ADomain {
+        ADomain::new(name.to_string(), aa34.to_string())
+    }
+
+    /// `LEU` with the last `n` residues replaced by `A`
+    fn mutant(n: usize) -> String {
+        format!("{}{}", &LEU[..34 - n], "A".repeat(n))
+    }
+
+    #[test]
+    fn test_cluster_domains() {
+        let domains = [
+            domain("first", &mutant(3)),
+            domain("other", "LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW"),
+            domain("leu", LEU),
+            domain("copy", LEU),
+            domain("chained", &mutant(6)),
+            ADomain::from_aa10("aa10".to_string(), "DAFYLGMMCK".to_string()),
+        ];
+        // At 0.9 identity, mutant(6) only links to LEU through mutant(3)
+        let got = cluster_domains(&domains, 0.9);
+        assert_eq!(
+            got,
+            [
+                Cluster {
+                    representative: 0,
+                    members: Vec::from([0, 2, 3, 4]),
+                },
+                Cluster {
+                    representative: 1,
+                    members: Vec::from([1]),
+                },
+                Cluster {
+                    representative: 5,
+                    members: Vec::from([5]),
+                },
+            ]
+        );
+
+        let got = cluster_domains(&domains, 1.0);
+        assert_eq!(got.len(), 5);
+        assert_eq!(
+            got[2],
+            Cluster {
+                representative: 2,
+                members: Vec::from([2, 3]),
+            }
+        );
+        assert!(cluster_domains(&[], 0.9).is_empty());
+    }
+
+    #[test]
+    fn test_to_tsv() {
+        let mut config = Config::new();
+        config.skip_v1 = true;
+        config.skip_v2 = true;
+        config.skip_stachelhaus = true;
+
+        let mut domains = [domain("leu", LEU), domain("copy", LEU)];
+        domains[0].add(
+            PredictionCategory::SingleV3,
+            Prediction {
+                name: "leu".to_string(),
+                score: 0.5,
+                probability: None,
+                model: None,
+            },
+        );
+        let clusters = cluster_domains(&domains, 0.9);
+
+        let tsv = to_tsv(&config, &clusters, &domains);
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("Cluster\tSize\tMembers\tName\t"));
+        assert!(lines[1].starts_with(&format!("1\t2\tleu,copy\tleu\t{LEU}\t")));
+        assert!(lines[1].ends_with("\tleu(0.50)"));
+
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &clusters, &domains).unwrap()).unwrap();
+        assert_eq!(got[0]["size"], 2);
+        assert_eq!(got[0]["members"], serde_json::json!(["leu", "copy"]));
+        assert_eq!(got[0]["representative"]["name"], "leu");
+    }
+}
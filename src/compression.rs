@@ -0,0 +1,100 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Transparent decompression of gzip and xz compressed input files.
+//!
+//! Compression is detected from the magic bytes, the file extension is only used to find model
+//! files. Reading xz files needs the `xz` feature.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use flate2::bufread::MultiGzDecoder;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// File extensions of compressed files
+pub const COMPRESSED_EXTENSIONS: [&str; 2] = ["gz", "xz"];
+
+/// Open `path`, decompressing it on the fly if needed
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+/// Wrap `reader` in a decoder if the data it holds is compressed
+pub fn decompress<'a, R>(mut reader: R) -> io::Result<Box<dyn BufRead + 'a>>
+where
+    R: BufRead + 'a,
+{
+    let header = reader.fill_buf()?;
+    if header.starts_with(GZIP_MAGIC) {
+        return Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))));
+    }
+    if header.starts_with(XZ_MAGIC) {
+        #[cfg(feature = "xz")]
+        return Ok(Box::new(BufReader::new(
+            xz2::bufread::XzDecoder::new_multi_decoder(reader),
+        )));
+        #[cfg(not(feature = "xz"))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "reading xz compressed files needs the `xz` feature",
+        ));
+    }
+    Ok(Box::new(reader))
+}
+
+/// `path` without a compression extension, e.g. `[leu].mdl` for `[leu].mdl.gz`
+pub fn uncompressed_path(path: &Path) -> PathBuf {
+    match path.extension() {
+        Some(ext) if COMPRESSED_EXTENSIONS.iter().any(|known| ext == *known) => {
+            path.with_extension("")
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fn read_all(data: &[u8]) -> String {
+        let mut text = String::new();
+        decompress(data).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_decompress() {
+        let text = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA\n";
+        assert_eq!(read_all(text.as_bytes()), text);
+        assert_eq!(read_all(b""), "");
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        assert_eq!(read_all(&encoder.finish().unwrap()), text);
+    }
+
+    #[test]
+    fn test_uncompressed_path() {
+        assert_eq!(
+            uncompressed_path(Path::new("a/[leu].mdl.gz")),
+            Path::new("a/[leu].mdl")
+        );
+        assert_eq!(
+            uncompressed_path(Path::new("a/[leu].mdl")),
+            Path::new("a/[leu].mdl")
+        );
+        assert_eq!(
+            uncompressed_path(Path::new("sigs.tsv.xz")),
+            Path::new("sigs.tsv")
+        );
+    }
+}
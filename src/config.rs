@@ -1,90 +1,789 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
+use std::collections::BTreeMap;
 use std::convert::From;
 use std::env;
+use std::ffi::OsString;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+#[cfg(feature = "cli")]
+use std::str::FromStr;
 
-use clap::Parser;
-use serde::Deserialize;
+#[cfg(feature = "cli")]
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 use toml;
 
+use crate::encodings::tables::EncodingTables;
+#[cfg(feature = "cli")]
+use crate::encodings::FeatureEncoding;
+use crate::encodings::{EncodingOptions, ResiduePolicy};
 use crate::errors::NrpsError;
-use crate::predictors::predictions::PredictionCategory;
+use crate::output::{OutputFormat, SortBy};
+use crate::predictors::consensus::DEFAULT_SUMMARY_CATEGORIES;
+use crate::predictors::custom_categories;
+use crate::predictors::manifest::{self, ModelSetInfo};
+use crate::predictors::predictions::{PredictionCategory, Ties};
+use crate::predictors::stachelhaus::DEFAULT_GAP_PENALTY;
+#[cfg(feature = "cli")]
+use crate::svm::models::{KernelParameters, KernelType};
+#[cfg(feature = "cli")]
+use crate::svm::train::TrainingParameters;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    /// Tab-separated aa34 signatures and names
+    #[default]
+    Signatures,
+    /// Tab-separated aa10 Stachelhaus codes and names, only the Stachelhaus lookup runs
+    Aa10,
+    /// Full A domain protein sequences, signatures are extracted
+    Fasta,
+    /// GenBank file with annotated AMP-binding domains, signatures are extracted
+    Genbank,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ExtractionMethod {
+    /// Use hmmalign if it and the profile HMM are available, the built-in alignment otherwise
+    #[default]
+    Auto,
+    /// Align to the AMP-binding profile HMM with hmmalign
+    Hmmer,
+    /// Pairwise alignment to the GrsA reference
+    Alignment,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum StachelhausScoring {
+    /// Count identical residues
+    #[default]
+    Identity,
+    /// Sum BLOSUM62 substitution scores, so conservative substitutions score higher
+    Blosum62,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ResidueCheck {
+    /// Only check the signature length
+    #[default]
+    Off,
+    /// Reject signatures with anything but amino acids, `X` and `-` gaps
+    Strict,
+    /// Like strict, but replace unknown residues with `X`
+    Lenient,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Run predictions on a signature file
+    Predict {
+        /// Signature file to run predictions on
+        signatures: PathBuf,
+        #[command(flatten)]
+        options: PredictArgs,
+    },
+    /// Parse all models in the model dir and report problems
+    ValidateModels,
+    /// Extract aa34 signatures from A domain protein sequences in FASTA format
+    ExtractSignatures {
+        /// FASTA file with A domain sequences, or GenBank file with `--input-format genbank`
+        sequences: PathBuf,
+    },
+    /// Print the feature vector of a signature, to debug model scores
+    ///
+    /// The vector is printed in SVMlight notation, as in the model files. Rausch encoded vectors
+    /// use the legacy layout for v1 categories, set with `--category`.
+    Encode {
+        /// Signature to encode
+        signature: String,
+        /// Feature encoding
+        #[arg(long, value_enum, default_value_t = FeatureEncoding::Wold)]
+        encoding: FeatureEncoding,
+        /// Print the features of each residue on its own line
+        #[arg(long)]
+        per_residue: bool,
+    },
+    /// Print the property tables of an encoding, in the `--encoding-tables` file format
+    EncodingTables {
+        /// Feature encoding, wold or rausch
+        #[arg(long, value_enum, default_value_t = FeatureEncoding::Wold)]
+        encoding: FeatureEncoding,
+    },
+    /// Group domains by aa34 identity and only predict one representative per cluster
+    ///
+    /// Domains are clustered by single linkage, so a domain joins a cluster if it is at least
+    /// `--min-identity` identical to any of its members.
+    Cluster {
+        /// Signature file to cluster
+        signatures: PathBuf,
+        /// Minimum fraction of identical aa34 positions to link two domains
+        #[arg(long, value_parser = parse_identity, default_value_t = 0.9)]
+        min_identity: f64,
+        /// Write the results to FILE instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Print version information
+    VersionInfo,
+    /// Parse all models in the model dir and write the binary model cache
+    BuildCache,
+    /// Train one-vs-rest models from tab-separated `aa34 substrate` lines
+    Train {
+        /// Training data file
+        training_data: PathBuf,
+        /// Directory to write the models to, in model dir layout
+        #[arg(long, value_name = "DIR")]
+        output_dir: PathBuf,
+        #[command(flatten)]
+        training: TrainingArgs,
+    },
+    /// Build per-substrate position weight matrices from tab-separated `aa34 substrate` lines
+    BuildPwms {
+        /// Training data file
+        training_data: PathBuf,
+        /// Model dir to write the PWMs to, into its `pwm` dir
+        #[arg(long, value_name = "DIR")]
+        output_dir: PathBuf,
+    },
+    /// Report per-substrate precision, recall and F1 on tab-separated `aa34 substrate` lines
+    ///
+    /// By default, the configured models are evaluated. With `--folds` or `--leave-one-out`,
+    /// models are trained and evaluated by cross-validation instead.
+    Benchmark {
+        /// Labeled signature file
+        labeled_data: PathBuf,
+        /// Number of cross-validation folds
+        #[arg(long, conflicts_with = "leave_one_out")]
+        folds: Option<usize>,
+        /// Run leave-one-out cross-validation
+        #[arg(long, default_value_t = false)]
+        leave_one_out: bool,
+        #[command(flatten)]
+        training: TrainingArgs,
+    },
+    /// Load the models once and serve predictions over HTTP
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: String,
+        /// Reload the models when the model files change, checked every SECONDS
+        #[arg(long, value_name = "SECONDS")]
+        watch: Option<u64>,
+    },
+}
+
+/// SVM training options shared by `train` and `benchmark`
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Clone, PartialEq)]
+pub struct TrainingArgs {
+    /// Feature encoding
+    #[arg(long, value_enum, default_value_t = FeatureEncoding::Blin)]
+    pub encoding: FeatureEncoding,
+    /// Kernel type
+    #[arg(long, value_enum, default_value_t = KernelType::Linear)]
+    pub kernel: KernelType,
+    /// Gamma of the RBF kernel
+    #[arg(long, default_value_t = 1.0)]
+    pub gamma: f64,
+    /// Soft margin cost
+    #[arg(long, default_value_t = 1.0)]
+    pub cost: f64,
+}
+
+#[cfg(feature = "cli")]
+impl TrainingArgs {
+    /// The training parameters for models of `category`
+    pub fn parameters(&self, category: PredictionCategory) -> TrainingParameters {
+        TrainingParameters {
+            category,
+            encoding: self.encoding,
+            kernel_type: self.kernel,
+            kernel_params: KernelParameters {
+                gamma: self.gamma,
+                ..Default::default()
+            },
+            cost: self.cost,
+            ..Default::default()
+        }
+    }
+}
+
+/// Options of `predict`, also accepted by the `nrps-rs SIGNATURES` shorthand
+#[cfg(feature = "cli")]
+#[derive(Args, Debug, Default, Clone, PartialEq)]
+pub struct PredictArgs {
+    /// Write the results to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Check the config, models, Stachelhaus signatures and input, then print what would be run
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Predict and print each domain as soon as its line is read, e.g. when reading from `-`
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Print the support vector contributions to the reported predictions of DOMAIN instead of
+    /// the results
+    #[arg(long, value_name = "DOMAIN")]
+    pub explain: Option<String>,
+}
 
+/// Options before the subcommand apply to all subcommands. For backwards compatibility,
+/// `nrps-rs SIGNATURES` without a subcommand is the same as `nrps-rs predict SIGNATURES`.
+#[cfg(feature = "cli")]
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    subcommand_negates_reqs = true,
+    override_usage = "nrps-rs [OPTIONS] <SIGNATURES>\n       nrps-rs [OPTIONS] <COMMAND>"
+)]
 pub struct Cli {
-    /// Signature file to run predictions on
-    pub signatures: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Signature file to run predictions on, shorthand for the predict subcommand
+    #[arg(required = true)]
+    pub signatures: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub predict: PredictArgs,
 
     /// Number of results to return per category
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub count: Option<usize>,
 
+    /// Whether results tied with the last of the count are reported as well [default: include]
+    #[arg(long, value_enum, global = true)]
+    pub ties: Option<Ties>,
+
     /// Runs the NRPSPredictor2 fungal models
-    #[arg(short = 'F', long, default_value_t = false)]
+    #[arg(short = 'F', long, default_value_t = false, global = true)]
     pub fungal: bool,
 
     /// Sets a custom config file
-    #[arg(short = 'C', long, value_name = "FILE")]
+    #[arg(short = 'C', long, value_name = "FILE", global = true)]
     pub config: Option<PathBuf>,
 
     /// Overrides the config file settings for the Stachelhaus signature file
-    #[arg(short, long, value_name = "FILE")]
+    #[arg(short, long, value_name = "FILE", global = true)]
     pub stachelhaus_signatures: Option<PathBuf>,
 
+    /// Additional Stachelhaus signature file merged into the main one, can be repeated
+    #[arg(long, value_name = "FILE", global = true)]
+    pub extra_stachelhaus_signatures: Vec<PathBuf>,
+
     /// Overrides the config file settings for the SVM model dir
-    #[arg(short, long, value_name = "DIR")]
+    #[arg(short, long, value_name = "DIR", global = true)]
     pub model_dir: Option<PathBuf>,
 
     /// Disable v3 models
-    #[arg(short = '3', long)]
+    #[arg(short = '3', long, global = true)]
     pub skip_v3: bool,
 
     /// Disable v2 models
-    #[arg(short = '2', long)]
+    #[arg(short = '2', long, global = true)]
     pub skip_v2: bool,
 
     /// Disable v1 models
-    #[arg(short = '1', long)]
+    #[arg(short = '1', long, global = true)]
     pub skip_v1: bool,
 
     /// Disable Stachelhaus lookups
-    #[arg(short = 'S', long)]
+    #[arg(short = 'S', long, global = true)]
     pub skip_stachelhaus: bool,
 
+    /// Only run this prediction category, e.g. ThreeClusterV3, can be repeated
+    #[arg(long = "category", value_name = "CATEGORY", value_parser = PredictionCategory::from_str, global = true)]
+    pub categories: Vec<PredictionCategory>,
+
     /// Disable printing new-style AA34 Stachelhaus results
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub skip_new_stachelhaus_output: bool,
+
+    /// List the top Stachelhaus matches per domain with their signatures and scores
+    #[arg(long, global = true)]
+    pub stachelhaus_detail: bool,
+
+    /// Report ambiguous Stachelhaus matches as alternative substrates, e.g. "Val|Leu"
+    #[arg(long, global = true)]
+    pub stachelhaus_alternatives: bool,
+
+    /// How to compare signatures for Stachelhaus lookups
+    #[arg(long, value_enum, global = true)]
+    pub stachelhaus_scoring: Option<StachelhausScoring>,
+
+    /// Output format for the results
+    #[arg(long, value_enum, global = true)]
+    pub output_format: Option<OutputFormat>,
+
+    /// Order of the domains in the output [default: input]
+    #[arg(long, value_enum, global = true)]
+    pub sort_by: Option<SortBy>,
+
+    /// Input format of the signature file
+    #[arg(long, value_enum, global = true)]
+    pub input_format: Option<InputFormat>,
+
+    /// Overrides the config file settings for the GrsA reference used for signature extraction
+    #[arg(long, value_name = "FILE", global = true)]
+    pub extraction_reference: Option<PathBuf>,
+
+    /// Method used to extract signatures from protein sequences
+    #[arg(long, value_enum, global = true)]
+    pub extraction_method: Option<ExtractionMethod>,
+
+    /// Overrides the config file settings for the AMP-binding profile HMM used by hmmalign
+    #[arg(long, value_name = "FILE", global = true)]
+    pub hmm_profile: Option<PathBuf>,
+
+    /// Load models from the binary model cache, (re)building it if it's out of date
+    #[arg(long, global = true)]
+    pub cache_models: bool,
+
+    /// Fail instead of warning about unknown dirs and non-model files in the model dir
+    #[arg(long, global = true)]
+    pub strict_models: bool,
+
+    /// Overrides the config file settings for the model cache file
+    #[arg(long, value_name = "FILE", global = true)]
+    pub model_cache: Option<PathBuf>,
+
+    /// Skip invalid signature lines with a warning instead of aborting
+    #[arg(long, global = true)]
+    pub skip_invalid: bool,
+
+    /// Uppercase input signatures and strip whitespace from them
+    #[arg(long, global = true)]
+    pub lenient: bool,
+
+    /// How to check the residues of input signatures [default: off]
+    #[arg(long, value_enum, global = true)]
+    pub residue_check: Option<ResidueCheck>,
+
+    /// Only report SVM predictions scoring above this threshold [default: 0.0]
+    #[arg(long, allow_negative_numbers = true, global = true)]
+    pub min_score: Option<f64>,
+
+    /// Only report predictions of CATEGORY scoring above SCORE, overrides --min-score
+    #[arg(
+        long = "category-min-score",
+        value_name = "CATEGORY=SCORE",
+        value_parser = parse_category_min_score,
+        global = true
+    )]
+    pub category_min_scores: Vec<(PredictionCategory, f64)>,
+
+    /// Flag domains whose two best predictions of a category score within DELTA, or whose
+    /// Stachelhaus and SVM predictions disagree
+    #[arg(long, value_name = "DELTA", global = true)]
+    pub ambiguity_delta: Option<f64>,
+
+    /// Add a column summarizing how well Stachelhaus and the best SVM predictions agree
+    #[arg(long, global = true)]
+    pub summary: bool,
+
+    /// Compare this category in the summary, can be repeated
+    /// [default: Stachelhaus, SingleV3, SingleV2]
+    #[arg(
+        long = "summary-category",
+        value_name = "CATEGORY",
+        value_parser = PredictionCategory::from_str,
+        global = true
+    )]
+    pub summary_categories: Vec<PredictionCategory>,
+
+    /// Report all SVM predictions, including those with negative scores
+    #[arg(
+        long,
+        conflicts_with_all = ["min_score", "category_min_scores"],
+        global = true
+    )]
+    pub include_negative_scores: bool,
+
+    /// Start the TSV output with comment lines describing the run
+    #[arg(long, global = true)]
+    pub metadata_header: bool,
+
+    /// Write version, config, model set and start time of the run to FILE as JSON
+    #[arg(long, value_name = "FILE", global = true)]
+    pub metadata_file: Option<PathBuf>,
+
+    /// Platt-scaling parameters per model, to report calibrated probabilities
+    #[arg(long, value_name = "FILE", global = true)]
+    pub calibration_file: Option<PathBuf>,
+
+    /// Report the per-position contributions to the predictions of linear models in JSON output
+    #[arg(long, global = true)]
+    pub attributions: bool,
+
+    /// Only report domains whose best call is one of these comma-separated substrates
+    #[arg(
+        long = "only-substrate",
+        value_name = "SUBSTRATES",
+        value_delimiter = ',',
+        global = true
+    )]
+    pub only_substrates: Vec<String>,
+
+    /// Match --only-substrate against the best call of CATEGORY only [default: any category]
+    #[arg(
+        long,
+        value_name = "CATEGORY",
+        value_parser = PredictionCategory::from_str,
+        global = true
+    )]
+    pub substrate_category: Option<PredictionCategory>,
+
+    /// Report the aa34 Hamming distance to the closest Stachelhaus reference signature
+    #[arg(long, global = true)]
+    pub novelty: bool,
+
+    /// List the K most similar Stachelhaus reference signatures, whatever their aa10 score
+    #[arg(long, value_name = "K", global = true)]
+    pub nearest_references: Option<usize>,
+
+    /// Add a Knn category, voting over the nearest Stachelhaus references in feature space
+    #[arg(long, global = true)]
+    pub knn: bool,
+
+    /// Number of references voting in the Knn category [default: 5]
+    #[arg(long, value_name = "K", global = true)]
+    pub knn_neighbours: Option<usize>,
+
+    /// Add a Forest category, predicted by the tree ensemble in this JSON file
+    #[arg(long, value_name = "FILE", global = true)]
+    pub forest_model: Option<PathBuf>,
+
+    /// Add a Pwm category, scored against the position weight matrices in the model dir
+    #[arg(long, global = true)]
+    pub pwm: bool,
+
+    /// Only load the SVM models of these comma-separated substrates [default: all]
+    #[arg(
+        long = "model-substrate",
+        value_name = "SUBSTRATES",
+        value_delimiter = ',',
+        global = true
+    )]
+    pub model_substrates: Vec<String>,
+
+    /// Comma-separated 1-based aa34 positions of the aa10 code, the conserved K is appended to
+    /// nine positions [default: the NRPSPredictor2 positions]
+    #[arg(long, value_name = "POSITIONS", value_delimiter = ',', global = true)]
+    pub aa10_positions: Vec<usize>,
+
+    /// How to encode gaps in signatures for the SVMs
+    #[arg(long, value_enum, global = true)]
+    pub gap_encoding: Option<ResiduePolicy>,
+
+    /// How to encode the ambiguous residues X, B, Z and U for the SVMs
+    #[arg(long, value_enum, global = true)]
+    pub ambiguous_residues: Option<ResiduePolicy>,
+
+    /// TOML file overriding the property tables of the wold and rausch encodings
+    #[arg(long, value_name = "FILE", global = true)]
+    pub encoding_tables: Option<PathBuf>,
+
+    /// Fraction of a mismatch a gap aligned to a residue costs in Stachelhaus lookups [default: 1]
+    #[arg(long, value_name = "PENALTY", global = true)]
+    pub stachelhaus_gap_penalty: Option<f64>,
+
+    /// Increase logging verbosity, repeat for more details
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Decrease logging verbosity, repeat to only show errors
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub quiet: u8,
+}
+
+#[cfg(feature = "cli")]
+impl Cli {
+    /// The subcommand to run, `predict` for the `nrps-rs SIGNATURES` shorthand
+    ///
+    /// Predict options before another subcommand are an error instead of being ignored.
+    pub fn take_command(&mut self) -> Result<Command, clap::Error> {
+        match (self.command.take(), self.signatures.take()) {
+            (Some(_), _) if self.predict != PredictArgs::default() => Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--output, --dry-run, --stream and --explain only apply to predict, \
+                 pass them after the subcommand",
+            )),
+            (Some(command), _) => Ok(command),
+            (None, Some(signatures)) => Ok(Command::Predict {
+                signatures,
+                options: self.predict.clone(),
+            }),
+            (None, None) => Err(Cli::command().error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "a signature file or subcommand is required",
+            )),
+        }
+    }
+}
+
+/// Prefix of the environment variables overriding config file settings
+const ENV_PREFIX: &str = "NRPS_";
+
+/// The environment variables for `Config::update_from_env`, skipping ones that aren't UTF-8
+pub fn env_vars() -> Vec<(String, String)> {
+    let mut vars = Vec::new();
+    for (key, value) in env::vars_os() {
+        match (key.into_string(), value.into_string()) {
+            (Ok(key), Ok(value)) => vars.push((key, value)),
+            (Ok(key), Err(_)) if key.starts_with(ENV_PREFIX) => {
+                log::warn!("Ignoring {key}, its value isn't valid UTF-8");
+            }
+            _ => (),
+        }
+    }
+    vars
 }
 
 #[derive(Debug, Deserialize)]
 struct ParsedConfig {
     pub model_dir: Option<String>,
+    #[serde(alias = "stach_signatures")]
     pub stachelhaus_signatures: Option<String>,
+    pub extra_stachelhaus_signatures: Option<Vec<String>>,
     pub count: Option<usize>,
+    pub ties: Option<Ties>,
     pub fungal: Option<bool>,
     pub skip_v3: Option<bool>,
     pub skip_v2: Option<bool>,
     pub skip_v1: Option<bool>,
     pub skip_stachelhaus: Option<bool>,
+    pub categories: Option<Vec<PredictionCategory>>,
+    pub column_order: Option<Vec<PredictionCategory>>,
     pub skip_new_stachelhaus_output: Option<bool>,
+    pub stachelhaus_detail: Option<bool>,
+    pub stachelhaus_alternatives: Option<bool>,
+    pub stachelhaus_scoring: Option<StachelhausScoring>,
+    pub output_format: Option<OutputFormat>,
+    pub sort_by: Option<SortBy>,
+    pub input_format: Option<InputFormat>,
+    pub extraction_reference: Option<String>,
+    pub extraction_method: Option<ExtractionMethod>,
+    pub hmm_profile: Option<String>,
+    pub cache_models: Option<bool>,
+    pub strict_models: Option<bool>,
+    pub model_cache: Option<String>,
+    pub skip_invalid: Option<bool>,
+    pub lenient: Option<bool>,
+    pub residue_check: Option<ResidueCheck>,
+    pub min_score: Option<f64>,
+    pub category_min_scores: Option<BTreeMap<PredictionCategory, f64>>,
+    pub ambiguity_delta: Option<f64>,
+    pub summary: Option<bool>,
+    pub summary_categories: Option<Vec<PredictionCategory>>,
+    pub weights: Option<BTreeMap<PredictionCategory, f64>>,
+    pub include_negative_scores: Option<bool>,
+    pub metadata_header: Option<bool>,
+    pub metadata_file: Option<String>,
+    pub calibration_file: Option<String>,
+    pub attributions: Option<bool>,
+    pub only_substrates: Option<Vec<String>>,
+    pub substrate_category: Option<PredictionCategory>,
+    pub novelty: Option<bool>,
+    pub nearest_references: Option<usize>,
+    pub knn: Option<bool>,
+    pub knn_neighbours: Option<usize>,
+    pub forest_model: Option<String>,
+    pub pwm: Option<bool>,
+    pub model_substrates: Option<Vec<String>>,
+    pub aa10_positions: Option<Vec<usize>>,
+    pub gap_encoding: Option<ResiduePolicy>,
+    pub ambiguous_residues: Option<ResiduePolicy>,
+    pub encoding_tables: Option<String>,
+    pub stachelhaus_gap_penalty: Option<f64>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Config {
     model_dir: PathBuf,
     stachelhaus_signatures: PathBuf,
+    #[serde(skip)]
     stach_sig_derived: bool,
+    /// Signature files merged into the main Stachelhaus signatures
+    pub extra_stachelhaus_signatures: Vec<PathBuf>,
     pub count: usize,
+    /// Ties with the last of the `count` results are reported beyond `count` by default
+    pub ties: Ties,
     pub fungal: bool,
     pub skip_v3: bool,
     pub skip_v2: bool,
     pub skip_v1: bool,
     pub skip_stachelhaus: bool,
+    /// Only run these categories, replaces the `skip_*` and `fungal` settings if set
+    #[serde(rename = "categories")]
+    pub enabled_categories: Option<Vec<PredictionCategory>>,
+    /// Categories reported in the output and their order, all enabled ones if not set
+    pub column_order: Option<Vec<PredictionCategory>>,
     pub skip_new_stachelhaus_output: bool,
+    /// Report the best `count` Stachelhaus matches instead of only the best one
+    pub stachelhaus_detail: bool,
+    /// Combine the substrates of tied best Stachelhaus matches into one call
+    pub stachelhaus_alternatives: bool,
+    pub stachelhaus_scoring: StachelhausScoring,
+    pub output_format: OutputFormat,
+    /// Domains are reported in input order by default
+    pub sort_by: SortBy,
+    pub input_format: InputFormat,
+    extraction_reference: Option<PathBuf>,
+    pub extraction_method: ExtractionMethod,
+    hmm_profile: Option<PathBuf>,
+    pub cache_models: bool,
+    model_cache: Option<PathBuf>,
+    /// Unknown dirs and non-model files in the model dir are errors instead of warnings
+    pub strict_models: bool,
+    pub skip_invalid: bool,
+    /// Uppercase input signatures and strip whitespace from them before parsing
+    pub lenient: bool,
+    pub residue_check: ResidueCheck,
+    /// SVM predictions need to score above this to be reported
+    pub min_score: f64,
+    /// Per-category thresholds overriding `min_score`
+    pub category_min_scores: BTreeMap<PredictionCategory, f64>,
+    /// Flag ambiguous domains in the output if set, see `ADomain::ambiguity`
+    pub ambiguity_delta: Option<f64>,
+    /// Report the agreement of the `summary_categories` predictions
+    pub summary: bool,
+    pub summary_categories: Vec<PredictionCategory>,
+    /// Per-category vote weights in the summary, `consensus::DEFAULT_WEIGHT` if not set
+    pub weights: BTreeMap<PredictionCategory, f64>,
+    /// Start the TSV output with the run metadata as comment lines
+    pub metadata_header: bool,
+    /// Write the run metadata to this file as JSON
+    pub metadata_file: Option<PathBuf>,
+    /// Platt-scaling parameters per model, see `svm::calibration`
+    pub calibration_file: Option<PathBuf>,
+    /// Attribute linear model predictions to signature positions, see `predictions::Attribution`
+    pub attributions: bool,
+    /// Only report domains with one of these substrates as best call, all domains if empty
+    pub only_substrates: Vec<String>,
+    /// Category whose best call is matched against `only_substrates`, any reported one if not set
+    pub substrate_category: Option<PredictionCategory>,
+    /// Distance to the closest Stachelhaus reference signature, see `ADomain::novelty`
+    pub novelty: bool,
+    /// Number of nearest Stachelhaus references to list, see `ADomain::nearest_references`
+    pub nearest_references: Option<usize>,
+    /// Predict the Knn category, see `predictors::knn`
+    pub knn: bool,
+    pub knn_neighbours: usize,
+    /// Tree ensemble predicting the Forest category, see `predictors::forest`
+    pub forest_model: Option<PathBuf>,
+    /// Predict the Pwm category, see `predictors::pwm`
+    pub pwm: bool,
+    /// Only SVM models of these substrates are loaded, all if empty, see `is_wanted_model`
+    pub model_substrates: Vec<String>,
+    /// 1-based aa34 positions of the aa10 code, the NRPSPredictor2 ones if empty, see
+    /// `StachelhausDb::set_aa10_positions`
+    pub aa10_positions: Vec<usize>,
+    pub gap_encoding: ResiduePolicy,
+    pub ambiguous_residues: ResiduePolicy,
+    /// Overrides of the wold and rausch property tables, see `encodings::tables`
+    pub encoding_tables: Option<PathBuf>,
+    /// Fraction of a mismatch a gap aligned to a residue costs, see `StachelhausDb::gap_penalty`
+    pub stachelhaus_gap_penalty: f64,
+    /// Custom categories in the model dir, see `discover_custom_categories`
+    pub custom_categories: Vec<PredictionCategory>,
+    /// Reported in the JSON output if set, see `ModelSetInfo::from_config`
+    #[serde(skip)]
+    pub model_set: Option<ModelSetInfo>,
+}
+
+/// Config file looked up in the working directory
+pub const CONFIG_FILE: &str = "nrps.toml";
+
+/// Model dir of system-wide installations
+pub const SYSTEM_MODEL_DIR: &str = "/usr/share/nrps-rs/models";
+
+/// The config file to use, `cli_config`, `$NRPS_CONFIG`, `nrps.toml` in the working directory or
+/// `$XDG_CONFIG_HOME/nrps-rs/config.toml`, in that order
+///
+/// A missing file given explicitly is an error, `None` means no implicit candidate exists.
+pub fn find_config_file(cli_config: Option<&Path>) -> Result<Option<PathBuf>, NrpsError> {
+    let explicit = cli_config
+        .map(Path::to_path_buf)
+        .or_else(|| env::var_os("NRPS_CONFIG").map(PathBuf::from));
+    if let Some(path) = explicit {
+        if !path.exists() {
+            return Err(NrpsError::ConfigFileError(path));
+        }
+        return Ok(Some(path));
+    }
+
+    let mut candidates = Vec::from([env::current_dir().unwrap_or_default().join(CONFIG_FILE)]);
+    if let Some(dir) = xdg_dir(env::var_os("XDG_CONFIG_HOME"), ".config") {
+        candidates.push(dir.join("nrps-rs").join("config.toml"));
+    }
+    Ok(candidates.into_iter().find(|path| path.is_file()))
+}
+
+/// `data/models` in the working directory, `$XDG_DATA_HOME/nrps-rs/models` or
+/// `/usr/share/nrps-rs/models`, whichever exists first
+fn default_model_dir() -> PathBuf {
+    // There is no working directory on wasm32, fall back to a relative path
+    let local = env::current_dir()
+        .unwrap_or_default()
+        .join("data")
+        .join("models");
+    let mut candidates = Vec::from([local.clone()]);
+    if let Some(dir) = xdg_dir(env::var_os("XDG_DATA_HOME"), ".local/share") {
+        candidates.push(dir.join("nrps-rs").join("models"));
+    }
+    candidates.push(PathBuf::from(SYSTEM_MODEL_DIR));
+    candidates
+        .into_iter()
+        .find(|dir| dir.is_dir())
+        .unwrap_or(local)
+}
+
+/// An XDG base dir from its environment variable `value`, or `fallback` relative to `$HOME`
+fn xdg_dir(value: Option<OsString>, fallback: &str) -> Option<PathBuf> {
+    match value.map(PathBuf::from) {
+        // The spec says relative paths are invalid and should be ignored
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)),
+    }
+}
+
+/// Parse a `CATEGORY=SCORE` threshold, e.g. `SingleV2=0.5`
+#[cfg(feature = "cli")]
+fn parse_category_min_score(value: &str) -> Result<(PredictionCategory, f64), String> {
+    let (category, score) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected CATEGORY=SCORE, got `{value}`"))?;
+    let score = score
+        .parse()
+        .map_err(|_| format!("invalid score `{score}`"))?;
+    Ok((category.parse()?, score))
+}
+
+/// Parse a fraction of identical positions between 0 and 1
+#[cfg(feature = "cli")]
+fn parse_identity(value: &str) -> Result<f64, String> {
+    match value.parse() {
+        Ok(identity) if (0.0..=1.0).contains(&identity) => Ok(identity),
+        _ => Err(format!("expected a number between 0 and 1, got `{value}`")),
+    }
 }
 
 fn set_stach_from_model_dir(model_dir: &Path) -> PathBuf {
@@ -95,26 +794,80 @@ fn set_stach_from_model_dir(model_dir: &Path) -> PathBuf {
 
 impl Config {
     pub fn new() -> Self {
-        let mut model_dir: PathBuf;
-        model_dir = env::current_dir().unwrap();
-        model_dir.push("data");
-        model_dir.push("models");
+        let model_dir = default_model_dir();
         let stachelhaus_signatures = set_stach_from_model_dir(&model_dir);
 
         Config {
             model_dir,
             stachelhaus_signatures,
             stach_sig_derived: true,
+            extra_stachelhaus_signatures: Vec::new(),
             count: 1,
+            ties: Ties::Include,
             fungal: false,
             skip_v3: false,
             skip_v2: false,
             skip_v1: false,
             skip_stachelhaus: false,
+            enabled_categories: None,
+            column_order: None,
             skip_new_stachelhaus_output: false,
+            stachelhaus_detail: false,
+            stachelhaus_alternatives: false,
+            stachelhaus_scoring: StachelhausScoring::Identity,
+            output_format: OutputFormat::Tsv,
+            sort_by: SortBy::Input,
+            input_format: InputFormat::Signatures,
+            extraction_reference: None,
+            extraction_method: ExtractionMethod::Auto,
+            hmm_profile: None,
+            cache_models: false,
+            model_cache: None,
+            strict_models: false,
+            skip_invalid: false,
+            lenient: false,
+            residue_check: ResidueCheck::Off,
+            min_score: 0.0,
+            category_min_scores: BTreeMap::new(),
+            ambiguity_delta: None,
+            summary: false,
+            summary_categories: DEFAULT_SUMMARY_CATEGORIES.to_vec(),
+            weights: BTreeMap::new(),
+            metadata_header: false,
+            metadata_file: None,
+            calibration_file: None,
+            attributions: false,
+            only_substrates: Vec::new(),
+            substrate_category: None,
+            novelty: false,
+            nearest_references: None,
+            knn: false,
+            knn_neighbours: 5,
+            forest_model: None,
+            pwm: false,
+            model_substrates: Vec::new(),
+            aa10_positions: Vec::new(),
+            gap_encoding: ResiduePolicy::Native,
+            ambiguous_residues: ResiduePolicy::Native,
+            encoding_tables: None,
+            stachelhaus_gap_penalty: DEFAULT_GAP_PENALTY,
+            custom_categories: Vec::new(),
+            model_set: None,
         }
     }
 
+    /// Parse a TOML config, unset options keep their defaults
+    pub fn from_toml<R>(mut reader: R) -> Result<Self, NrpsError>
+    where
+        R: Read,
+    {
+        let mut raw_config = String::new();
+        reader.read_to_string(&mut raw_config)?;
+        let parsed_config: ParsedConfig = toml::from_str(&raw_config)?;
+        check_weights(&parsed_config)?;
+        Ok(Config::from(parsed_config))
+    }
+
     pub fn model_dir(&self) -> &PathBuf {
         &self.model_dir
     }
@@ -126,6 +879,11 @@ impl Config {
         }
     }
 
+    /// Model set version declared in the model dir manifest, if any
+    pub fn model_set_version(&self) -> Option<String> {
+        manifest::model_set_version(&self.model_dir).ok().flatten()
+    }
+
     pub fn stachelhaus_signatures(&self) -> &PathBuf {
         &self.stachelhaus_signatures
     }
@@ -135,7 +893,56 @@ impl Config {
         self.stachelhaus_signatures = stachelhaus_signatures;
     }
 
+    /// GrsA reference sequence for signature extraction, defaults to `grsA.fasta` in the model dir
+    pub fn extraction_reference(&self) -> PathBuf {
+        match &self.extraction_reference {
+            Some(reference) => reference.clone(),
+            None => self.model_dir.join("grsA.fasta"),
+        }
+    }
+
+    pub fn set_extraction_reference(&mut self, extraction_reference: PathBuf) {
+        self.extraction_reference = Some(extraction_reference);
+    }
+
+    /// AMP-binding profile HMM, defaults to `AMP-binding.hmm` in the model dir
+    pub fn hmm_profile(&self) -> PathBuf {
+        match &self.hmm_profile {
+            Some(profile) => profile.clone(),
+            None => self.model_dir.join("AMP-binding.hmm"),
+        }
+    }
+
+    pub fn set_hmm_profile(&mut self, hmm_profile: PathBuf) {
+        self.hmm_profile = Some(hmm_profile);
+    }
+
+    /// Binary model cache file, defaults to `nrps-rs.cache` in the model dir
+    pub fn model_cache(&self) -> PathBuf {
+        match &self.model_cache {
+            Some(cache) => cache.clone(),
+            None => self.model_dir.join("nrps-rs.cache"),
+        }
+    }
+
+    pub fn set_model_cache(&mut self, model_cache: PathBuf) {
+        self.model_cache = Some(model_cache);
+    }
+
     pub fn categories(&self) -> Vec<PredictionCategory> {
+        if let Some(enabled) = &self.enabled_categories {
+            let mut categories: Vec<PredictionCategory> = PredictionCategory::ALL
+                .into_iter()
+                .filter(|category| enabled.contains(category))
+                .collect();
+            for category in enabled.iter().filter(|category| category.is_custom()) {
+                if !categories.contains(category) {
+                    categories.push(category.clone());
+                }
+            }
+            return categories;
+        }
+
         let mut categories: Vec<PredictionCategory> = Vec::with_capacity(12);
         if !self.skip_v3 {
             categories.extend_from_slice(&[
@@ -150,6 +957,18 @@ impl Config {
             categories.push(PredictionCategory::Stachelhaus);
         }
 
+        if self.knn {
+            categories.push(PredictionCategory::Knn);
+        }
+
+        if self.forest_model.is_some() {
+            categories.push(PredictionCategory::Forest);
+        }
+
+        if self.pwm {
+            categories.push(PredictionCategory::Pwm);
+        }
+
         if !self.skip_v2 {
             categories.extend_from_slice(&[
                 PredictionCategory::ThreeClusterV2,
@@ -170,8 +989,92 @@ impl Config {
             ]);
         }
 
+        categories.extend_from_slice(&self.custom_categories);
+
         categories
     }
+
+    /// Enable the custom categories found in the model dir
+    ///
+    /// Custom categories named in the settings need models in the model dir, so typos are
+    /// reported instead of becoming empty categories.
+    pub fn discover_custom_categories(&mut self) -> Result<(), NrpsError> {
+        self.custom_categories = custom_categories(self.model_dir())?;
+        let named = self
+            .enabled_categories
+            .iter()
+            .chain(self.column_order.iter())
+            .flatten()
+            .chain(self.summary_categories.iter())
+            .chain(self.category_min_scores.keys())
+            .chain(self.weights.keys())
+            .chain(self.substrate_category.iter());
+        for category in named {
+            if category.is_custom() && !self.custom_categories.contains(category) {
+                return Err(NrpsError::CategoryError(format!(
+                    "no models for `{category}` in {}",
+                    self.model_dir.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The enabled categories reported in the output, in `column_order` if set
+    pub fn output_categories(&self) -> Vec<PredictionCategory> {
+        let enabled = self.categories();
+        match &self.column_order {
+            Some(order) => order
+                .iter()
+                .filter(|category| enabled.contains(category))
+                .cloned()
+                .collect(),
+            None => enabled,
+        }
+    }
+
+    /// The enabled categories compared in the summary column
+    pub fn enabled_summary_categories(&self) -> Vec<PredictionCategory> {
+        let enabled = self.categories();
+        self.summary_categories
+            .iter()
+            .filter(|category| enabled.contains(category))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether Stachelhaus lookups are enabled
+    pub fn stachelhaus_enabled(&self) -> bool {
+        self.categories().contains(&PredictionCategory::Stachelhaus)
+    }
+
+    /// Whether the Knn category is predicted
+    pub fn knn_enabled(&self) -> bool {
+        self.categories().contains(&PredictionCategory::Knn)
+    }
+
+    /// Whether the Forest category is predicted
+    pub fn forest_enabled(&self) -> bool {
+        self.categories().contains(&PredictionCategory::Forest)
+    }
+
+    /// Whether the Pwm category is predicted
+    pub fn pwm_enabled(&self) -> bool {
+        self.categories().contains(&PredictionCategory::Pwm)
+    }
+
+    /// How the SVMs encode gaps and ambiguous residues, with the `encoding_tables` loaded
+    pub fn encoding_options(&self) -> Result<EncodingOptions, NrpsError> {
+        let tables = match &self.encoding_tables {
+            Some(path) => EncodingTables::from_file(path)?,
+            None => EncodingTables::default(),
+        };
+        Ok(EncodingOptions {
+            gaps: self.gap_encoding,
+            ambiguous: self.ambiguous_residues,
+            tables,
+        })
+    }
 }
 
 impl Default for Config {
@@ -180,85 +1083,541 @@ impl Default for Config {
     }
 }
 
+/// Weights need to be finite and not negative
+fn check_weights(item: &ParsedConfig) -> Result<(), NrpsError> {
+    let Some(weights) = &item.weights else {
+        return Ok(());
+    };
+    for (category, weight) in weights.iter() {
+        if !weight.is_finite() || *weight < 0.0 {
+            return Err(NrpsError::WeightError(format!(
+                "invalid weight {weight} for {category}"
+            )));
+        }
+    }
+    Ok(())
+}
+
 impl From<ParsedConfig> for Config {
     fn from(item: ParsedConfig) -> Self {
         let mut config = Config::new();
+        config.update(item);
+        config
+    }
+}
 
+impl Config {
+    /// Override the settings given in `item`
+    fn update(&mut self, item: ParsedConfig) {
         if let Some(dir_str) = item.model_dir {
-            config.set_model_dir(PathBuf::from(dir_str));
+            self.set_model_dir(PathBuf::from(dir_str));
         }
 
         if let Some(file_name) = item.stachelhaus_signatures {
-            config.set_stachelhaus_signatures(PathBuf::from(file_name));
+            self.set_stachelhaus_signatures(PathBuf::from(file_name));
+        }
+
+        if let Some(file_names) = item.extra_stachelhaus_signatures {
+            self.extra_stachelhaus_signatures = file_names.into_iter().map(PathBuf::from).collect();
         }
 
         if let Some(count) = item.count {
-            config.count = count;
+            self.count = count;
+        }
+
+        if let Some(ties) = item.ties {
+            self.ties = ties;
         }
 
         if let Some(skip_v3) = item.skip_v3 {
-            config.skip_v3 = skip_v3;
+            self.skip_v3 = skip_v3;
         }
 
         if let Some(skip_v2) = item.skip_v2 {
-            config.skip_v2 = skip_v2;
+            self.skip_v2 = skip_v2;
         }
 
         if let Some(skip_v1) = item.skip_v1 {
-            config.skip_v1 = skip_v1;
+            self.skip_v1 = skip_v1;
         }
 
         if let Some(skip_stachelhaus) = item.skip_stachelhaus {
-            config.skip_stachelhaus = skip_stachelhaus;
+            self.skip_stachelhaus = skip_stachelhaus;
+        }
+
+        if let Some(categories) = item.categories {
+            self.enabled_categories = Some(categories);
+        }
+
+        if let Some(column_order) = item.column_order {
+            self.column_order = Some(column_order);
         }
 
         if let Some(skip_new_stach) = item.skip_new_stachelhaus_output {
-            config.skip_new_stachelhaus_output = skip_new_stach;
+            self.skip_new_stachelhaus_output = skip_new_stach;
+        }
+
+        if let Some(stachelhaus_detail) = item.stachelhaus_detail {
+            self.stachelhaus_detail = stachelhaus_detail;
+        }
+
+        if let Some(alternatives) = item.stachelhaus_alternatives {
+            self.stachelhaus_alternatives = alternatives;
+        }
+
+        if let Some(scoring) = item.stachelhaus_scoring {
+            self.stachelhaus_scoring = scoring;
         }
 
         if let Some(fungal) = item.fungal {
-            config.fungal = fungal;
+            self.fungal = fungal;
         }
 
-        config
+        if let Some(output_format) = item.output_format {
+            self.output_format = output_format;
+        }
+
+        if let Some(sort_by) = item.sort_by {
+            self.sort_by = sort_by;
+        }
+
+        if let Some(input_format) = item.input_format {
+            self.input_format = input_format;
+        }
+
+        if let Some(reference) = item.extraction_reference {
+            self.set_extraction_reference(PathBuf::from(reference));
+        }
+
+        if let Some(method) = item.extraction_method {
+            self.extraction_method = method;
+        }
+
+        if let Some(profile) = item.hmm_profile {
+            self.set_hmm_profile(PathBuf::from(profile));
+        }
+
+        if let Some(cache_models) = item.cache_models {
+            self.cache_models = cache_models;
+        }
+
+        if let Some(strict_models) = item.strict_models {
+            self.strict_models = strict_models;
+        }
+
+        if let Some(model_cache) = item.model_cache {
+            self.set_model_cache(PathBuf::from(model_cache));
+        }
+
+        if let Some(skip_invalid) = item.skip_invalid {
+            self.skip_invalid = skip_invalid;
+        }
+
+        if let Some(lenient) = item.lenient {
+            self.lenient = lenient;
+        }
+
+        if let Some(residue_check) = item.residue_check {
+            self.residue_check = residue_check;
+        }
+
+        if let Some(min_score) = item.min_score {
+            self.min_score = min_score;
+        }
+
+        if let Some(category_min_scores) = item.category_min_scores {
+            self.category_min_scores.extend(category_min_scores);
+        }
+
+        if let Some(ambiguity_delta) = item.ambiguity_delta {
+            self.ambiguity_delta = Some(ambiguity_delta);
+        }
+
+        if let Some(summary) = item.summary {
+            self.summary = summary;
+        }
+
+        if let Some(summary_categories) = item.summary_categories {
+            self.summary_categories = summary_categories;
+        }
+
+        if let Some(weights) = item.weights {
+            self.weights.extend(weights);
+        }
+
+        if let Some(true) = item.include_negative_scores {
+            self.min_score = f64::NEG_INFINITY;
+            self.category_min_scores.clear();
+        }
+
+        if let Some(metadata_header) = item.metadata_header {
+            self.metadata_header = metadata_header;
+        }
+
+        if let Some(metadata_file) = item.metadata_file {
+            self.metadata_file = Some(PathBuf::from(metadata_file));
+        }
+
+        if let Some(calibration_file) = item.calibration_file {
+            self.calibration_file = Some(PathBuf::from(calibration_file));
+        }
+
+        if let Some(attributions) = item.attributions {
+            self.attributions = attributions;
+        }
+
+        if let Some(only_substrates) = item.only_substrates {
+            self.only_substrates = only_substrates;
+        }
+
+        if let Some(category) = item.substrate_category {
+            self.substrate_category = Some(category);
+        }
+
+        if let Some(novelty) = item.novelty {
+            self.novelty = novelty;
+        }
+
+        if let Some(nearest_references) = item.nearest_references {
+            self.nearest_references = Some(nearest_references);
+        }
+
+        if let Some(knn) = item.knn {
+            self.knn = knn;
+        }
+
+        if let Some(knn_neighbours) = item.knn_neighbours {
+            self.knn_neighbours = knn_neighbours;
+        }
+
+        if let Some(forest_model) = item.forest_model {
+            self.forest_model = Some(PathBuf::from(forest_model));
+        }
+
+        if let Some(pwm) = item.pwm {
+            self.pwm = pwm;
+        }
+
+        if let Some(model_substrates) = item.model_substrates {
+            self.model_substrates = model_substrates;
+        }
+
+        if let Some(aa10_positions) = item.aa10_positions {
+            self.aa10_positions = aa10_positions;
+        }
+
+        if let Some(gap_encoding) = item.gap_encoding {
+            self.gap_encoding = gap_encoding;
+        }
+
+        if let Some(ambiguous_residues) = item.ambiguous_residues {
+            self.ambiguous_residues = ambiguous_residues;
+        }
+
+        if let Some(encoding_tables) = item.encoding_tables {
+            self.encoding_tables = Some(PathBuf::from(encoding_tables));
+        }
+
+        if let Some(penalty) = item.stachelhaus_gap_penalty {
+            self.stachelhaus_gap_penalty = penalty;
+        }
+    }
+
+    /// Override settings from `NRPS_`-prefixed environment variables like `NRPS_MODEL_DIR`
+    pub fn update_from_env<I>(&mut self, vars: I) -> Result<(), NrpsError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let parsed: ParsedConfig = envy::prefixed(ENV_PREFIX).from_iter(vars)?;
+        check_weights(&parsed)?;
+        self.update(parsed);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Config {
+    /// Override the settings given on the command line
+    pub fn apply_cli_overrides(&mut self, args: &Cli) {
+        if let Some(md) = &args.model_dir {
+            self.model_dir = md.clone();
+            self.stachelhaus_signatures = set_stach_from_model_dir(&self.model_dir);
+        }
+        if let Some(stach) = &args.stachelhaus_signatures {
+            self.stachelhaus_signatures = stach.clone();
+        }
+        // Extra signature files add to the ones from the config file
+        self.extra_stachelhaus_signatures
+            .extend(args.extra_stachelhaus_signatures.iter().cloned());
+        if let Some(mut count_val) = args.count {
+            if count_val < 1 {
+                count_val = 1;
+            }
+            self.count = count_val;
+        }
+        if let Some(ties) = args.ties {
+            self.ties = ties;
+        }
+
+        // Flags can only switch settings on, so they don't reset the config file and environment
+        self.fungal |= args.fungal;
+
+        self.skip_v3 |= args.skip_v3;
+        self.skip_v2 |= args.skip_v2;
+        self.skip_v1 |= args.skip_v1;
+        self.skip_stachelhaus |= args.skip_stachelhaus;
+        self.skip_new_stachelhaus_output |= args.skip_new_stachelhaus_output;
+
+        if !args.categories.is_empty() {
+            self.enabled_categories = Some(args.categories.clone());
+        }
+
+        if args.stachelhaus_detail {
+            self.stachelhaus_detail = true;
+        }
+
+        if args.stachelhaus_alternatives {
+            self.stachelhaus_alternatives = true;
+        }
+
+        if let Some(scoring) = args.stachelhaus_scoring {
+            self.stachelhaus_scoring = scoring;
+        }
+
+        if let Some(output_format) = args.output_format {
+            self.output_format = output_format;
+        }
+        if let Some(sort_by) = args.sort_by {
+            self.sort_by = sort_by;
+        }
+
+        if let Some(input_format) = args.input_format {
+            self.input_format = input_format;
+        }
+
+        if let Some(reference) = &args.extraction_reference {
+            self.set_extraction_reference(reference.clone());
+        }
+
+        if let Some(method) = args.extraction_method {
+            self.extraction_method = method;
+        }
+
+        if let Some(profile) = &args.hmm_profile {
+            self.set_hmm_profile(profile.clone());
+        }
+
+        if args.cache_models {
+            self.cache_models = true;
+        }
+
+        self.strict_models |= args.strict_models;
+
+        if let Some(model_cache) = &args.model_cache {
+            self.set_model_cache(model_cache.clone());
+        }
+
+        if args.skip_invalid {
+            self.skip_invalid = true;
+        }
+
+        if args.lenient {
+            self.lenient = true;
+        }
+
+        if let Some(residue_check) = args.residue_check {
+            self.residue_check = residue_check;
+        }
+
+        if let Some(min_score) = args.min_score {
+            self.min_score = min_score;
+        }
+
+        self.category_min_scores
+            .extend(args.category_min_scores.iter().cloned());
+
+        if let Some(ambiguity_delta) = args.ambiguity_delta {
+            self.ambiguity_delta = Some(ambiguity_delta);
+        }
+
+        self.summary |= args.summary;
+
+        if !args.summary_categories.is_empty() {
+            self.summary_categories = args.summary_categories.clone();
+        }
+
+        if args.include_negative_scores {
+            self.min_score = f64::NEG_INFINITY;
+            self.category_min_scores.clear();
+        }
+
+        if args.metadata_header {
+            self.metadata_header = true;
+        }
+
+        if let Some(metadata_file) = &args.metadata_file {
+            self.metadata_file = Some(metadata_file.clone());
+        }
+
+        if let Some(calibration_file) = &args.calibration_file {
+            self.calibration_file = Some(calibration_file.clone());
+        }
+
+        self.attributions |= args.attributions;
+
+        if !args.only_substrates.is_empty() {
+            self.only_substrates = args.only_substrates.clone();
+        }
+
+        if let Some(category) = &args.substrate_category {
+            self.substrate_category = Some(category.clone());
+        }
+
+        self.novelty |= args.novelty;
+
+        if let Some(nearest_references) = args.nearest_references {
+            self.nearest_references = Some(nearest_references);
+        }
+
+        self.knn |= args.knn;
+
+        if let Some(knn_neighbours) = args.knn_neighbours {
+            self.knn_neighbours = knn_neighbours;
+        }
+
+        if let Some(forest_model) = &args.forest_model {
+            self.forest_model = Some(forest_model.clone());
+        }
+
+        self.pwm |= args.pwm;
+
+        if !args.model_substrates.is_empty() {
+            self.model_substrates = args.model_substrates.clone();
+        }
+
+        if !args.aa10_positions.is_empty() {
+            self.aa10_positions = args.aa10_positions.clone();
+        }
+
+        if let Some(gap_encoding) = args.gap_encoding {
+            self.gap_encoding = gap_encoding;
+        }
+
+        if let Some(ambiguous_residues) = args.ambiguous_residues {
+            self.ambiguous_residues = ambiguous_residues;
+        }
+
+        if let Some(encoding_tables) = &args.encoding_tables {
+            self.encoding_tables = Some(encoding_tables.clone());
+        }
+
+        if let Some(penalty) = args.stachelhaus_gap_penalty {
+            self.stachelhaus_gap_penalty = penalty;
+        }
     }
 }
 
-pub fn parse_config<R>(mut reader: R, args: &Cli) -> Result<Config, NrpsError>
+/// Read the config file from `reader`, then apply the environment `vars` and command line overrides
+#[cfg(feature = "cli")]
+pub fn parse_config<R, I>(reader: R, args: &Cli, vars: I) -> Result<Config, NrpsError>
 where
     R: Read,
+    I: IntoIterator<Item = (String, String)>,
 {
-    let mut raw_config = String::new();
-    reader.read_to_string(&mut raw_config)?;
-    let parsed_config: ParsedConfig = toml::from_str(&raw_config)?;
-    let mut config = Config::from(parsed_config);
-    if let Some(md) = &args.model_dir {
-        config.model_dir = md.clone();
-        config.stachelhaus_signatures = set_stach_from_model_dir(&config.model_dir);
-    }
-    if let Some(stach) = &args.stachelhaus_signatures {
-        config.stachelhaus_signatures = stach.clone();
+    let mut config = Config::from_toml(reader)?;
+    config.update_from_env(vars)?;
+    config.apply_cli_overrides(args);
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xdg_dir() {
+        assert_eq!(
+            xdg_dir(Some(OsString::from("/xdg/config")), ".config"),
+            Some(PathBuf::from("/xdg/config"))
+        );
+        let fallback = env::var_os("HOME").map(|home| PathBuf::from(home).join(".config"));
+        assert_eq!(xdg_dir(None, ".config"), fallback);
+        assert_eq!(
+            xdg_dir(Some(OsString::from("relative")), ".config"),
+            fallback
+        );
     }
-    if let Some(mut count_val) = args.count {
-        if count_val < 1 {
-            count_val = 1;
-        }
-        config.count = count_val;
+
+    #[test]
+    fn test_find_config_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("custom.toml");
+        assert!(matches!(
+            find_config_file(Some(&path)),
+            Err(NrpsError::ConfigFileError(missing)) if missing == path
+        ));
+
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(find_config_file(Some(&path)).unwrap(), Some(path));
     }
 
-    config.fungal = args.fungal;
+    #[test]
+    fn test_update_from_env() {
+        let vars = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        let mut config = Config::from_toml("count = 3\nfungal = true".as_bytes()).unwrap();
+        config
+            .update_from_env(vars(&[
+                ("NRPS_MODEL_DIR", "/models"),
+                ("NRPS_COUNT", "5"),
+                ("NRPS_SKIP_V1", "true"),
+                ("NRPS_RESIDUE_CHECK", "strict"),
+                ("NRPS_EXTRA_STACHELHAUS_SIGNATURES", "a.tsv,b.tsv"),
+                ("NRPS_BUNDLED_MODEL_DIR", "ignored"),
+                ("MODEL_DIR", "ignored"),
+            ]))
+            .unwrap();
+        assert_eq!(config.model_dir(), Path::new("/models"));
+        assert_eq!(
+            config.stachelhaus_signatures(),
+            Path::new("/models/signatures.tsv")
+        );
+        assert_eq!(config.count, 5);
+        assert!(config.fungal);
+        assert!(config.skip_v1);
+        assert_eq!(config.residue_check, ResidueCheck::Strict);
+        assert_eq!(
+            config.extra_stachelhaus_signatures,
+            [PathBuf::from("a.tsv"), PathBuf::from("b.tsv")]
+        );
 
-    config.skip_v3 = args.skip_v3;
-    config.skip_v2 = args.skip_v2;
-    config.skip_v1 = args.skip_v1;
-    config.skip_stachelhaus = args.skip_stachelhaus;
-    config.skip_new_stachelhaus_output = args.skip_new_stachelhaus_output;
+        config
+            .update_from_env(vars(&[
+                ("NRPS_STACH_SIGNATURES", "sigs.tsv"),
+                ("NRPS_CATEGORIES", "Stachelhaus,SingleV3"),
+            ]))
+            .unwrap();
+        assert_eq!(config.stachelhaus_signatures(), Path::new("sigs.tsv"));
+        assert_eq!(
+            config.categories(),
+            [
+                PredictionCategory::SingleV3,
+                PredictionCategory::Stachelhaus
+            ]
+        );
 
-    Ok(config)
+        assert!(config
+            .update_from_env(vars(&[("NRPS_COUNT", "many")]))
+            .is_err());
+    }
 }
 
-#[cfg(test)]
-mod tests {
+#[cfg(all(test, feature = "cli"))]
+mod cli_tests {
     use super::*;
 
     use rstest::{fixture, rstest};
@@ -266,17 +1625,63 @@ mod tests {
     #[fixture]
     fn args() -> Cli {
         Cli {
-            signatures: PathBuf::from("foo.sig"),
+            command: None,
+            signatures: Some(PathBuf::from("foo.sig")),
             count: None,
+            ties: None,
             fungal: false,
             config: None,
+            predict: PredictArgs::default(),
             stachelhaus_signatures: None,
+            extra_stachelhaus_signatures: Vec::new(),
             model_dir: None,
             skip_v3: false,
             skip_v2: false,
             skip_v1: false,
             skip_stachelhaus: false,
+            categories: Vec::new(),
             skip_new_stachelhaus_output: false,
+            stachelhaus_detail: false,
+            stachelhaus_alternatives: false,
+            stachelhaus_scoring: None,
+            output_format: None,
+            sort_by: None,
+            input_format: None,
+            extraction_reference: None,
+            extraction_method: None,
+            hmm_profile: None,
+            cache_models: false,
+            model_cache: None,
+            strict_models: false,
+            skip_invalid: false,
+            lenient: false,
+            residue_check: None,
+            min_score: None,
+            category_min_scores: Vec::new(),
+            ambiguity_delta: None,
+            summary: false,
+            summary_categories: Vec::new(),
+            include_negative_scores: false,
+            metadata_header: false,
+            metadata_file: None,
+            calibration_file: None,
+            attributions: false,
+            only_substrates: Vec::new(),
+            substrate_category: None,
+            novelty: false,
+            nearest_references: None,
+            knn: false,
+            knn_neighbours: None,
+            forest_model: None,
+            pwm: false,
+            model_substrates: Vec::new(),
+            aa10_positions: Vec::new(),
+            gap_encoding: None,
+            ambiguous_residues: None,
+            encoding_tables: None,
+            stachelhaus_gap_penalty: None,
+            verbose: 0,
+            quiet: 0,
         }
     }
 
@@ -286,7 +1691,7 @@ mod tests {
         expected.set_model_dir(PathBuf::from("/foo"));
         expected.set_stachelhaus_signatures(PathBuf::from("/foo/signatures.tsv"));
         expected.stach_sig_derived = true;
-        let got = parse_config("model_dir = '/foo'".as_bytes(), &args).unwrap();
+        let got = parse_config("model_dir = '/foo'".as_bytes(), &args, []).unwrap();
         assert_eq!(expected, got);
     }
 
@@ -302,10 +1707,22 @@ mod tests {
         expected.set_model_dir(model_dir);
         expected.set_stachelhaus_signatures(stach);
         expected.stach_sig_derived = true;
-        let got = parse_config("".as_bytes(), &args).unwrap();
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
         assert_eq!(expected, got);
     }
 
+    #[rstest]
+    fn test_env_overrides(mut args: Cli) {
+        let vars = [("NRPS_COUNT".to_string(), "5".to_string())];
+        let got = parse_config("count = 2".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.count, 2);
+        let got = parse_config("count = 2".as_bytes(), &args, vars.clone()).unwrap();
+        assert_eq!(got.count, 5);
+        args.count = Some(7);
+        let got = parse_config("count = 2".as_bytes(), &args, vars).unwrap();
+        assert_eq!(got.count, 7);
+    }
+
     #[rstest]
     fn test_stach_extra(args: Cli) {
         let mut model_dir = env::current_dir().unwrap();
@@ -321,6 +1738,7 @@ mod tests {
         let got = parse_config(
             "stachelhaus_signatures = '/foo/signatures.tsv'".as_bytes(),
             &args,
+            [],
         )
         .unwrap();
         assert_eq!(expected, got);
@@ -338,7 +1756,7 @@ mod tests {
         expected.set_stachelhaus_signatures(stach);
         expected.stach_sig_derived = true;
 
-        let got = parse_config("".as_bytes(), &args).unwrap();
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
         assert_eq!(expected, got);
     }
 
@@ -353,7 +1771,7 @@ mod tests {
         expected.set_stachelhaus_signatures(stach.clone());
         expected.stach_sig_derived = true;
 
-        let got = parse_config("model_dir = '/foo'".as_bytes(), &args).unwrap();
+        let got = parse_config("model_dir = '/foo'".as_bytes(), &args, []).unwrap();
         assert_eq!(expected, got);
     }
 
@@ -372,18 +1790,74 @@ mod tests {
         let got = parse_config(
             "stachelhaus_signatures = '/baz/signatures.tsv'".as_bytes(),
             &args,
+            [],
+        )
+        .unwrap();
+        assert_eq!(expected, got);
+    }
+
+    #[rstest]
+    fn test_extra_stach(mut args: Cli) {
+        args.extra_stachelhaus_signatures = Vec::from([PathBuf::from("/cli/extra.tsv")]);
+
+        let mut expected = Config::new();
+        expected.extra_stachelhaus_signatures = Vec::from([
+            PathBuf::from("/foo/extra.tsv"),
+            PathBuf::from("/cli/extra.tsv"),
+        ]);
+
+        let got = parse_config(
+            "extra_stachelhaus_signatures = ['/foo/extra.tsv']".as_bytes(),
+            &args,
+            [],
         )
         .unwrap();
         assert_eq!(expected, got);
     }
 
+    #[rstest]
+    fn test_stachelhaus_detail(mut args: Cli) {
+        let mut expected = Config::new();
+        expected.stachelhaus_detail = true;
+        let got = parse_config("stachelhaus_detail = true".as_bytes(), &args, []).unwrap();
+        assert_eq!(expected, got);
+
+        args.stachelhaus_detail = true;
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(expected, got);
+    }
+
+    #[rstest]
+    fn test_stachelhaus_alternatives(mut args: Cli) {
+        let mut expected = Config::new();
+        expected.stachelhaus_alternatives = true;
+        let got = parse_config("stachelhaus_alternatives = true".as_bytes(), &args, []).unwrap();
+        assert_eq!(expected, got);
+
+        args.stachelhaus_alternatives = true;
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(expected, got);
+    }
+
+    #[rstest]
+    fn test_stachelhaus_scoring(mut args: Cli) {
+        let mut expected = Config::new();
+        expected.stachelhaus_scoring = StachelhausScoring::Blosum62;
+        let got = parse_config("stachelhaus_scoring = 'blosum62'".as_bytes(), &args, []).unwrap();
+        assert_eq!(expected, got);
+
+        args.stachelhaus_scoring = Some(StachelhausScoring::Identity);
+        let got = parse_config("stachelhaus_scoring = 'blosum62'".as_bytes(), &args, []).unwrap();
+        assert_eq!(Config::new(), got);
+    }
+
     #[rstest]
     fn test_skip_v3(mut args: Cli) {
         args.skip_v3 = true;
 
         let mut expected = Config::new();
         expected.skip_v3 = true;
-        let got = parse_config("".as_bytes(), &args).unwrap();
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
         assert_eq!(expected, got);
     }
 
@@ -393,7 +1867,7 @@ mod tests {
 
         let mut expected = Config::new();
         expected.skip_v2 = true;
-        let got = parse_config("".as_bytes(), &args).unwrap();
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
         assert_eq!(expected, got);
     }
 
@@ -403,7 +1877,7 @@ mod tests {
 
         let mut expected = Config::new();
         expected.skip_v1 = true;
-        let got = parse_config("".as_bytes(), &args).unwrap();
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
         assert_eq!(expected, got);
     }
 
@@ -413,7 +1887,656 @@ mod tests {
 
         let mut expected = Config::new();
         expected.skip_stachelhaus = true;
-        let got = parse_config("".as_bytes(), &args).unwrap();
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(expected, got);
+    }
+
+    #[rstest]
+    fn test_categories(mut args: Cli) {
+        let got = parse_config(
+            "categories = ['Stachelhaus', 'ThreeClusterV3']".as_bytes(),
+            &args,
+            [],
+        )
+        .unwrap();
+        assert_eq!(
+            got.categories(),
+            [
+                PredictionCategory::ThreeClusterV3,
+                PredictionCategory::Stachelhaus
+            ]
+        );
+        assert!(got.stachelhaus_enabled());
+
+        args.categories = Vec::from([PredictionCategory::SingleV2]);
+        let got = parse_config("categories = ['Stachelhaus']".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.categories(), [PredictionCategory::SingleV2]);
+        assert!(!got.stachelhaus_enabled());
+
+        let cli = Cli::try_parse_from([
+            "nrps-rs",
+            "--category",
+            "SingleV3",
+            "--category",
+            "ThreeClusterFungalV2",
+            "foo.sig",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.categories,
+            [
+                PredictionCategory::SingleV3,
+                PredictionCategory::ThreeClusterFungalV2
+            ]
+        );
+        assert!(Cli::try_parse_from(["nrps-rs", "--category", "SingleV4", "foo.sig"]).is_err());
+    }
+
+    #[rstest]
+    fn test_column_order(args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.output_categories(), got.categories());
+
+        let got = parse_config(
+            "column_order = ['SingleV2', 'Stachelhaus', 'SingleV3']\nskip_v3 = true".as_bytes(),
+            &args,
+            [],
+        )
+        .unwrap();
+        assert_eq!(
+            got.output_categories(),
+            [
+                PredictionCategory::SingleV2,
+                PredictionCategory::Stachelhaus
+            ]
+        );
+    }
+
+    #[rstest]
+    fn test_metadata(mut args: Cli) {
+        let got = parse_config("metadata_file = 'run.json'".as_bytes(), &args, []).unwrap();
+        assert!(!got.metadata_header);
+        assert_eq!(got.metadata_file, Some(PathBuf::from("run.json")));
+
+        args.metadata_header = true;
+        args.metadata_file = Some(PathBuf::from("other.json"));
+        let got = parse_config("metadata_file = 'run.json'".as_bytes(), &args, []).unwrap();
+        assert!(got.metadata_header);
+        assert_eq!(got.metadata_file, Some(PathBuf::from("other.json")));
+    }
+
+    #[rstest]
+    fn test_attributions(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(!got.attributions);
+
+        let got = parse_config("attributions = true".as_bytes(), &args, []).unwrap();
+        assert!(got.attributions);
+
+        args.attributions = true;
+        let got = parse_config("attributions = false".as_bytes(), &args, []).unwrap();
+        assert!(got.attributions);
+    }
+
+    #[rstest]
+    fn test_only_substrates(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(got.only_substrates.is_empty());
+        assert_eq!(got.substrate_category, None);
+
+        let got = parse_config(
+            "only_substrates = ['val']\nsubstrate_category = 'SingleV3'".as_bytes(),
+            &args,
+            [],
+        )
+        .unwrap();
+        assert_eq!(got.only_substrates, ["val"]);
+        assert_eq!(got.substrate_category, Some(PredictionCategory::SingleV3));
+
+        args.only_substrates = Vec::from(["leu".to_string(), "ile".to_string()]);
+        let got = parse_config("only_substrates = ['val']".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.only_substrates, ["leu", "ile"]);
+    }
+
+    #[rstest]
+    fn test_novelty(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(!got.novelty);
+
+        let got = parse_config("novelty = true".as_bytes(), &args, []).unwrap();
+        assert!(got.novelty);
+
+        args.novelty = true;
+        let got = parse_config("novelty = false".as_bytes(), &args, []).unwrap();
+        assert!(got.novelty);
+    }
+
+    #[rstest]
+    fn test_nearest_references(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.nearest_references, None);
+
+        let got = parse_config("nearest_references = 3".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.nearest_references, Some(3));
+
+        args.nearest_references = Some(5);
+        let got = parse_config("nearest_references = 3".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.nearest_references, Some(5));
+    }
+
+    #[rstest]
+    fn test_knn(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(!got.knn);
+        assert_eq!(got.knn_neighbours, 5);
+        assert!(!got.categories().contains(&PredictionCategory::Knn));
+
+        let got = parse_config("knn = true\nknn_neighbours = 3".as_bytes(), &args, []).unwrap();
+        assert!(got.knn);
+        assert_eq!(got.knn_neighbours, 3);
+        let categories = got.categories();
+        let stachelhaus = categories
+            .iter()
+            .position(|category| *category == PredictionCategory::Stachelhaus)
+            .unwrap();
+        assert_eq!(categories[stachelhaus + 1], PredictionCategory::Knn);
+
+        args.knn = true;
+        args.knn_neighbours = Some(7);
+        let got = parse_config("knn = false\nknn_neighbours = 3".as_bytes(), &args, []).unwrap();
+        assert!(got.knn);
+        assert_eq!(got.knn_neighbours, 7);
+    }
+
+    #[rstest]
+    fn test_forest_model(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.forest_model, None);
+        assert!(!got.forest_enabled());
+
+        let got = parse_config("forest_model = 'rf.json'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.forest_model, Some(PathBuf::from("rf.json")));
+        assert!(got.forest_enabled());
+
+        args.forest_model = Some(PathBuf::from("other.json"));
+        let got = parse_config("forest_model = 'rf.json'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.forest_model, Some(PathBuf::from("other.json")));
+    }
+
+    #[rstest]
+    fn test_pwm(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(!got.pwm_enabled());
+
+        let got = parse_config("pwm = true".as_bytes(), &args, []).unwrap();
+        assert!(got.pwm_enabled());
+        let categories = got.categories();
+        let stachelhaus = categories
+            .iter()
+            .position(|c| *c == PredictionCategory::Stachelhaus);
+        assert_eq!(
+            categories[stachelhaus.unwrap() + 1],
+            PredictionCategory::Pwm
+        );
+
+        args.pwm = true;
+        let got = parse_config("pwm = false".as_bytes(), &args, []).unwrap();
+        assert!(got.pwm);
+    }
+
+    #[rstest]
+    fn test_model_substrates(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(got.model_substrates.is_empty());
+
+        let got = parse_config("model_substrates = ['val', 'leu']".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.model_substrates, ["val", "leu"]);
+
+        args.model_substrates = Vec::from(["ile".to_string()]);
+        let got = parse_config("model_substrates = ['val']".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.model_substrates, ["ile"]);
+    }
+
+    #[rstest]
+    fn test_aa10_positions(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(got.aa10_positions.is_empty());
+
+        let toml = "aa10_positions = [6, 7, 10, 13, 15, 17, 22, 30, 31]";
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.aa10_positions, [6, 7, 10, 13, 15, 17, 22, 30, 31]);
+
+        args.aa10_positions = Vec::from([1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.aa10_positions, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+    }
+
+    #[rstest]
+    fn test_gaps(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.gap_encoding, ResiduePolicy::Native);
+        assert_eq!(got.stachelhaus_gap_penalty, 1.0);
+
+        let toml = "gap_encoding = 'mean'\nstachelhaus_gap_penalty = 0.5";
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.gap_encoding, ResiduePolicy::Mean);
+        assert_eq!(got.stachelhaus_gap_penalty, 0.5);
+
+        args.gap_encoding = Some(ResiduePolicy::Native);
+        args.stachelhaus_gap_penalty = Some(0.0);
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.gap_encoding, ResiduePolicy::Native);
+        assert_eq!(got.stachelhaus_gap_penalty, 0.0);
+    }
+
+    #[rstest]
+    fn test_ambiguous_residues(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.encoding_options().unwrap(), EncodingOptions::default());
+
+        let toml = "ambiguous_residues = 'zero'\ngap_encoding = 'error'";
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(
+            got.encoding_options().unwrap(),
+            EncodingOptions {
+                gaps: ResiduePolicy::Error,
+                ambiguous: ResiduePolicy::Zero,
+                ..Default::default()
+            }
+        );
+
+        args.ambiguous_residues = Some(ResiduePolicy::Mean);
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.ambiguous_residues, ResiduePolicy::Mean);
+    }
+
+    #[rstest]
+    fn test_weights(args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(got.weights.is_empty());
+
+        let toml = "[weights]\nStachelhaus = 2.0\nSingleV2 = 0";
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(
+            got.weights,
+            BTreeMap::from([
+                (PredictionCategory::Stachelhaus, 2.0),
+                (PredictionCategory::SingleV2, 0.0)
+            ])
+        );
+
+        for toml in [
+            "[weights]\nStachelhaus = -1.0",
+            "[weights]\nStachelhaus = inf",
+            "[weights]\nStachelhaus = nan",
+            "[weights]\nFoo = 1.0",
+        ] {
+            assert!(parse_config(toml.as_bytes(), &args, []).is_err(), "{toml}");
+        }
+    }
+
+    #[rstest]
+    fn test_custom_category_names(mut args: Cli) {
+        use std::fs;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        for dir_name in ["MY_CLI_SET", "MY_TOML_SET"] {
+            fs::create_dir_all(model_dir.join(dir_name)).unwrap();
+            fs::write(model_dir.join(dir_name).join("[leu].mdl"), "").unwrap();
+        }
+
+        // Custom category names parse without the model dir, discovery checks they have models
+        let cli = Cli::try_parse_from(["nrps-rs", "--category", "MY_CLI_SET", "foo.sig"]).unwrap();
+        let custom = PredictionCategory::custom("MY_CLI_SET").unwrap();
+        assert_eq!(cli.categories, Vec::from([custom.clone()]));
+        args.categories = cli.categories;
+        args.model_dir = Some(model_dir.join("missing"));
+        let mut got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.enabled_categories, Some(Vec::from([custom.clone()])));
+        assert!(matches!(
+            got.discover_custom_categories(),
+            Err(NrpsError::CategoryError(_))
+        ));
+
+        args.model_dir = Some(model_dir.clone());
+        let toml = "categories = ['MY_TOML_SET']";
+        let mut got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        got.discover_custom_categories().unwrap();
+        assert_eq!(got.enabled_categories, Some(Vec::from([custom])));
+        assert_eq!(got.custom_categories.len(), 2);
+
+        args.categories.clear();
+        let toml = "categories = ['MY_OTHER_SET']";
+        let mut got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert!(matches!(
+            got.discover_custom_categories(),
+            Err(NrpsError::CategoryError(_))
+        ));
+    }
+
+    #[rstest]
+    fn test_encoding_tables(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.encoding_tables, None);
+
+        let toml = "encoding_tables = 'tables.toml'";
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.encoding_tables, Some(PathBuf::from("tables.toml")));
+        assert!(matches!(
+            got.encoding_options(),
+            Err(NrpsError::FileError { .. })
+        ));
+
+        args.encoding_tables = Some(PathBuf::from("other.toml"));
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.encoding_tables, Some(PathBuf::from("other.toml")));
+    }
+
+    #[rstest]
+    fn test_calibration_file(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.calibration_file, None);
+
+        let got = parse_config("calibration_file = 'platt.tsv'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.calibration_file, Some(PathBuf::from("platt.tsv")));
+
+        args.calibration_file = Some(PathBuf::from("other.tsv"));
+        let got = parse_config("calibration_file = 'platt.tsv'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.calibration_file, Some(PathBuf::from("other.tsv")));
+    }
+
+    #[rstest]
+    fn test_output_format(mut args: Cli) {
+        let mut expected = Config::new();
+        expected.output_format = OutputFormat::Json;
+        let got = parse_config("output_format = 'json'".as_bytes(), &args, []).unwrap();
+        assert_eq!(expected, got);
+
+        args.output_format = Some(OutputFormat::Tsv);
+        expected.output_format = OutputFormat::Tsv;
+        let got = parse_config("output_format = 'json'".as_bytes(), &args, []).unwrap();
         assert_eq!(expected, got);
     }
+
+    #[rstest]
+    fn test_input_format(mut args: Cli) {
+        let mut expected = Config::new();
+        expected.input_format = InputFormat::Fasta;
+        expected.set_extraction_reference(PathBuf::from("/foo/ref.fasta"));
+        args.extraction_reference = Some(PathBuf::from("/foo/ref.fasta"));
+        let got = parse_config("input_format = 'fasta'".as_bytes(), &args, []).unwrap();
+        assert_eq!(expected, got);
+        assert_eq!(got.extraction_reference(), PathBuf::from("/foo/ref.fasta"));
+    }
+
+    #[rstest]
+    fn test_lenient(mut args: Cli) {
+        let got = parse_config("lenient = true".as_bytes(), &args, []).unwrap();
+        assert!(got.lenient);
+
+        args.lenient = true;
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(got.lenient);
+    }
+
+    #[rstest]
+    fn test_residue_check(mut args: Cli) {
+        let got = parse_config("residue_check = 'strict'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.residue_check, ResidueCheck::Strict);
+
+        args.residue_check = Some(ResidueCheck::Lenient);
+        let got = parse_config("residue_check = 'strict'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.residue_check, ResidueCheck::Lenient);
+    }
+
+    #[rstest]
+    fn test_sort_by(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.sort_by, SortBy::Input);
+
+        let got = parse_config("sort_by = 'score'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.sort_by, SortBy::Score);
+
+        args.sort_by = Some(SortBy::Name);
+        let got = parse_config("sort_by = 'score'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.sort_by, SortBy::Name);
+    }
+
+    #[rstest]
+    fn test_ties(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.ties, Ties::Include);
+
+        let got = parse_config("ties = 'exclude'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.ties, Ties::Exclude);
+
+        args.ties = Some(Ties::Include);
+        let got = parse_config("ties = 'exclude'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.ties, Ties::Include);
+    }
+
+    #[rstest]
+    fn test_fungal_flag_keeps_config(args: Cli) {
+        let got = parse_config("fungal = true".as_bytes(), &args, []).unwrap();
+        assert!(got.fungal);
+    }
+
+    #[rstest]
+    fn test_model_cache(mut args: Cli) {
+        let got = parse_config("model_dir = '/foo'".as_bytes(), &args, []).unwrap();
+        assert!(!got.cache_models);
+        assert_eq!(got.model_cache(), PathBuf::from("/foo/nrps-rs.cache"));
+
+        args.cache_models = true;
+        args.model_cache = Some(PathBuf::from("/tmp/models.cache"));
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(got.cache_models);
+        assert_eq!(got.model_cache(), PathBuf::from("/tmp/models.cache"));
+    }
+
+    #[rstest]
+    fn test_strict_models(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(!got.strict_models);
+
+        let got = parse_config("strict_models = true".as_bytes(), &args, []).unwrap();
+        assert!(got.strict_models);
+
+        args.strict_models = true;
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(got.strict_models);
+    }
+
+    #[rstest]
+    fn test_skip_invalid(mut args: Cli) {
+        let got = parse_config("skip_invalid = true".as_bytes(), &args, []).unwrap();
+        assert!(got.skip_invalid);
+
+        args.skip_invalid = true;
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(got.skip_invalid);
+    }
+
+    #[rstest]
+    fn test_min_score(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.min_score, 0.0);
+
+        let got = parse_config("min_score = -0.5".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.min_score, -0.5);
+
+        let got = parse_config("include_negative_scores = true".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.min_score, f64::NEG_INFINITY);
+
+        args.min_score = Some(0.25);
+        let got = parse_config("min_score = -0.5".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.min_score, 0.25);
+
+        args.min_score = None;
+        args.include_negative_scores = true;
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.min_score, f64::NEG_INFINITY);
+
+        let cli = Cli::try_parse_from(["nrps-rs", "--min-score", "-1.5", "foo.sig"]).unwrap();
+        assert_eq!(cli.min_score, Some(-1.5));
+    }
+
+    #[rstest]
+    fn test_category_min_scores(mut args: Cli) {
+        let toml = "min_score = 0.1\ncategory_min_scores = { SingleV2 = 0.5, SingleV3 = -1.0 }";
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.min_score, 0.1);
+        assert_eq!(
+            got.category_min_scores,
+            BTreeMap::from([
+                (PredictionCategory::SingleV3, -1.0),
+                (PredictionCategory::SingleV2, 0.5)
+            ])
+        );
+
+        args.category_min_scores = Vec::from([(PredictionCategory::SingleV2, 0.75)]);
+        let got = parse_config(toml.as_bytes(), &args, []).unwrap();
+        assert_eq!(got.category_min_scores[&PredictionCategory::SingleV2], 0.75);
+        assert_eq!(got.category_min_scores[&PredictionCategory::SingleV3], -1.0);
+
+        assert!(parse_config("category_min_scores = { Foo = 0.5 }".as_bytes(), &args, []).is_err());
+
+        let cli = Cli::try_parse_from([
+            "nrps-rs",
+            "--category-min-score",
+            "LargeClusterV1=-0.5",
+            "foo.sig",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.category_min_scores,
+            [(PredictionCategory::LargeClusterV1, -0.5)]
+        );
+        assert!(
+            Cli::try_parse_from(["nrps-rs", "--category-min-score", "Foo=1", "foo.sig"]).is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["nrps-rs", "--category-min-score", "SingleV2", "foo.sig"])
+                .is_err()
+        );
+    }
+
+    #[rstest]
+    fn test_ambiguity_delta(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.ambiguity_delta, None);
+
+        let got = parse_config("ambiguity_delta = 0.1".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.ambiguity_delta, Some(0.1));
+
+        args.ambiguity_delta = Some(0.25);
+        let got = parse_config("ambiguity_delta = 0.1".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.ambiguity_delta, Some(0.25));
+    }
+
+    #[rstest]
+    fn test_summary(mut args: Cli) {
+        let got = parse_config("".as_bytes(), &args, []).unwrap();
+        assert!(!got.summary);
+        assert_eq!(got.summary_categories, DEFAULT_SUMMARY_CATEGORIES);
+
+        let got = parse_config("summary = true\nskip_v2 = true".as_bytes(), &args, []).unwrap();
+        assert!(got.summary);
+        assert_eq!(
+            got.enabled_summary_categories(),
+            [
+                PredictionCategory::Stachelhaus,
+                PredictionCategory::SingleV3
+            ]
+        );
+
+        args.summary = true;
+        args.summary_categories = Vec::from([PredictionCategory::LargeClusterV3]);
+        let got = parse_config("summary_categories = ['SingleV2']".as_bytes(), &args, []).unwrap();
+        assert!(got.summary);
+        assert_eq!(got.summary_categories, [PredictionCategory::LargeClusterV3]);
+    }
+
+    #[rstest]
+    fn test_extraction_method(mut args: Cli) {
+        let got = parse_config("model_dir = '/foo'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.extraction_method, ExtractionMethod::Auto);
+        assert_eq!(got.hmm_profile(), PathBuf::from("/foo/AMP-binding.hmm"));
+
+        let got = parse_config("extraction_method = 'hmmer'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.extraction_method, ExtractionMethod::Hmmer);
+
+        args.extraction_method = Some(ExtractionMethod::Alignment);
+        args.hmm_profile = Some(PathBuf::from("/tmp/PF00501.hmm"));
+        let got = parse_config("extraction_method = 'hmmer'".as_bytes(), &args, []).unwrap();
+        assert_eq!(got.extraction_method, ExtractionMethod::Alignment);
+        assert_eq!(got.hmm_profile(), PathBuf::from("/tmp/PF00501.hmm"));
+    }
+
+    #[test]
+    fn test_cli_subcommand() {
+        let cli = Cli::try_parse_from(["nrps-rs", "--model-dir", "/foo", "build-cache"]).unwrap();
+        assert_eq!(cli.command, Some(Command::BuildCache));
+        assert_eq!(cli.model_dir, Some(PathBuf::from("/foo")));
+
+        let cli = Cli::try_parse_from(["nrps-rs", "predict", "-3", "foo.sig"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::Predict {
+                signatures: PathBuf::from("foo.sig"),
+                options: PredictArgs::default(),
+            })
+        );
+        assert!(cli.skip_v3);
+
+        let cli = Cli::try_parse_from([
+            "nrps-rs",
+            "-C",
+            "nrps.toml",
+            "predict",
+            "--dry-run",
+            "foo.sig",
+        ])
+        .unwrap();
+        assert_eq!(cli.config, Some(PathBuf::from("nrps.toml")));
+        assert!(matches!(
+            cli.command,
+            Some(Command::Predict { options, .. }) if options.dry_run
+        ));
+
+        // Predict options don't apply to other subcommands
+        assert!(Cli::try_parse_from(["nrps-rs", "version-info", "--dry-run"]).is_err());
+        for args in [
+            &["nrps-rs", "--stream", "version-info"][..],
+            &["nrps-rs", "-o", "out.tsv", "version-info"],
+            &["nrps-rs", "--dry-run", "predict", "foo.sig"],
+        ] {
+            let mut cli = Cli::try_parse_from(args).unwrap();
+            assert!(cli.take_command().is_err(), "{args:?}");
+        }
+
+        let cli = Cli::try_parse_from(["nrps-rs", "-c", "2", "foo.sig"]).unwrap();
+        assert_eq!(cli.command, None);
+        assert_eq!(cli.signatures, Some(PathBuf::from("foo.sig")));
+        assert_eq!(cli.count, Some(2));
+
+        let mut cli = Cli::try_parse_from(["nrps-rs", "--explain", "bpsA", "foo.sig"]).unwrap();
+        let Command::Predict { options, .. } = cli.take_command().unwrap() else {
+            panic!("not the predict subcommand");
+        };
+        assert_eq!(options.explain, Some("bpsA".to_string()));
+
+        assert!(Cli::try_parse_from(["nrps-rs"]).is_err());
+        assert!(Cli::try_parse_from(["nrps-rs", "predict"]).is_err());
+    }
+
+    #[test]
+    fn test_cli_verbosity() {
+        let cli = Cli::try_parse_from(["nrps-rs", "-vv", "foo.sig"]).unwrap();
+        assert_eq!((cli.verbose, cli.quiet), (2, 0));
+
+        let cli = Cli::try_parse_from(["nrps-rs", "validate-models", "-q"]).unwrap();
+        assert_eq!((cli.verbose, cli.quiet), (0, 1));
+    }
 }
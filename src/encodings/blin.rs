@@ -1,11 +1,16 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
+//! The rausch features of each residue followed by its wold features.
+
 use super::rausch;
 use super::wold;
 
+/// Features per residue
+pub const FEATURES: usize = rausch::FEATURES + wold::FEATURES;
+
 pub fn encode(sequence: &str) -> Vec<f64> {
-    let capacity = sequence.len() * 3;
+    let capacity = sequence.len() * FEATURES;
     let encodeded: Vec<f64> = Vec::with_capacity(capacity);
     sequence
         .chars()
@@ -17,8 +22,26 @@ pub fn encode(sequence: &str) -> Vec<f64> {
 }
 
 pub fn encode_one(c: char) -> Vec<f64> {
-    let mut encoded: Vec<f64> = Vec::with_capacity(15);
+    let mut encoded: Vec<f64> = Vec::with_capacity(FEATURES);
     encoded.append(&mut rausch::encode_one(c));
     encoded.append(&mut wold::encode_one(c));
     encoded
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blin_encoder() {
+        for c in "ACDEFGHIKLMNPQRSTVWYX-".chars() {
+            let got = encode_one(c);
+            assert_eq!(got.len(), FEATURES);
+            assert_eq!(got[..rausch::FEATURES], rausch::encode_one(c));
+            assert_eq!(got[rausch::FEATURES..], wold::encode_one(c));
+        }
+        let got = encode("LD");
+        assert_eq!(got.len(), 2 * FEATURES);
+        assert_eq!(got[FEATURES..], encode_one('D'));
+    }
+}
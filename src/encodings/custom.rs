@@ -0,0 +1,101 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Registry for residue encodings provided by library users.
+//!
+//! Registered encodings are referred to by name as `FeatureEncoding::Custom`, model dirs using
+//! them declare the name in their `manifest.toml`.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use crate::errors::NrpsError;
+
+use super::FeatureEncoding;
+
+pub trait CustomEncoding: Send + Sync {
+    /// Encode an aa34 signature, the result must have `dimensions()` values
+    fn encode(&self, sequence: &str) -> Vec<f64>;
+    /// Length of the feature vector for an aa34 signature
+    fn dimensions(&self) -> usize;
+}
+
+static REGISTRY: RwLock<BTreeMap<&'static str, Arc<dyn CustomEncoding>>> =
+    RwLock::new(BTreeMap::new());
+
+/// Register `encoding` as `name`, replacing any encoding previously registered under that name
+pub fn register_encoding(
+    name: &str,
+    encoding: Arc<dyn CustomEncoding>,
+) -> Result<FeatureEncoding, NrpsError> {
+    if FeatureEncoding::builtin(name).is_some() {
+        return Err(NrpsError::EncodingError(format!(
+            "Can't replace built-in encoding `{name}`"
+        )));
+    }
+
+    let mut registry = REGISTRY.write().unwrap();
+    // Names are leaked once, so FeatureEncoding can stay Copy
+    let key = match registry.get_key_value(name) {
+        Some((key, _)) => *key,
+        None => Box::leak(name.to_string().into_boxed_str()),
+    };
+    registry.insert(key, encoding);
+    Ok(FeatureEncoding::Custom(key))
+}
+
+/// Look up a registered encoding by name
+pub fn registered_encoding(name: &str) -> Option<FeatureEncoding> {
+    let registry = REGISTRY.read().unwrap();
+    registry
+        .get_key_value(name)
+        .map(|(key, _)| FeatureEncoding::Custom(key))
+}
+
+pub(crate) fn get(name: &str) -> Option<Arc<dyn CustomEncoding>> {
+    REGISTRY.read().unwrap().get(name).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::encodings::{encode, ResidueEncoder};
+    use crate::predictors::predictions::PredictionCategory;
+
+    struct Hydrophobic;
+
+    impl CustomEncoding for Hydrophobic {
+        fn encode(&self, sequence: &str) -> Vec<f64> {
+            sequence
+                .chars()
+                .map(|c| if "AILMFVW".contains(c) { 1.0 } else { 0.0 })
+                .collect()
+        }
+
+        fn dimensions(&self) -> usize {
+            34
+        }
+    }
+
+    #[test]
+    fn test_register_encoding() {
+        let encoding = register_encoding("test-hydrophobic", Arc::new(Hydrophobic)).unwrap();
+        assert_eq!(encoding, FeatureEncoding::Custom("test-hydrophobic"));
+        assert_eq!(registered_encoding("test-hydrophobic"), Some(encoding));
+        assert_eq!(
+            FeatureEncoding::from_name("test-hydrophobic"),
+            Some(encoding)
+        );
+        assert_eq!(encoding.dimensions(), 34);
+        assert_eq!(
+            encode("AC", &encoding, &PredictionCategory::SingleV3),
+            [1.0, 0.0]
+        );
+        assert_eq!(encoding.features(), 1);
+        assert_eq!(encoding.encode_one('A'), [1.0]);
+
+        assert!(register_encoding("wold", Arc::new(Hydrophobic)).is_err());
+        assert!(registered_encoding("test-unregistered").is_none());
+    }
+}
@@ -1,17 +1,224 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
+//! Feature encodings of signatures for the SVMs.
+//!
+//! Each residue encodes to `FEATURES` values of its encoding module, e.g. `wold::FEATURES`. An aa34
+//! signature vector is made up of the features of each residue in sequence order, so feature `f`
+//! of residue `i` is at `i * FEATURES + f`. The rausch models of the v1 categories use the legacy
+//! layout grouping the vector by feature instead, at `f * AA34_LENGTH + i`, see
+//! `is_legacy_layout`. `ResidueEncoder` gives access to the features of single residues.
+//! Overridden property tables are passed in `EncodingOptions`, see `tables`.
+
 pub mod blin;
+pub mod custom;
 pub mod rausch;
+pub mod reduced;
+pub mod tables;
 pub mod wold;
 
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::NrpsError;
 use crate::predictors::predictions::PredictionCategory;
 
-#[derive(Debug)]
+use tables::EncodingTables;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
 pub enum FeatureEncoding {
     Blin,
     Rausch,
     Wold,
+    /// One-hot physicochemical classes, see `reduced`
+    Reduced,
+    /// An encoding registered with `custom::register_encoding`
+    #[cfg_attr(feature = "cli", value(skip))]
+    Custom(&'static str),
+}
+
+/// Residues of an aa34 signature
+pub const AA34_LENGTH: usize = 34;
+
+/// Per-residue features of an encoding, the building blocks of the signature vectors
+pub trait ResidueEncoder {
+    /// Number of features per residue
+    fn features(&self) -> usize;
+    /// The features of residue `c`
+    fn encode_one(&self, c: char) -> Vec<f64>;
+    /// The features of each residue of `sequence`, in sequence order
+    fn encode_sequence(&self, sequence: &str) -> Vec<f64> {
+        sequence.chars().flat_map(|c| self.encode_one(c)).collect()
+    }
+}
+
+impl ResidueEncoder for FeatureEncoding {
+    fn features(&self) -> usize {
+        match self {
+            FeatureEncoding::Blin => blin::FEATURES,
+            FeatureEncoding::Rausch => rausch::FEATURES,
+            FeatureEncoding::Wold => wold::FEATURES,
+            FeatureEncoding::Reduced => reduced::FEATURES,
+            FeatureEncoding::Custom(_) => self.dimensions() / AA34_LENGTH,
+        }
+    }
+
+    fn encode_one(&self, c: char) -> Vec<f64> {
+        match self {
+            FeatureEncoding::Blin => blin::encode_one(c),
+            FeatureEncoding::Rausch => rausch::encode_one(c),
+            FeatureEncoding::Wold => wold::encode_one(c),
+            FeatureEncoding::Reduced => reduced::encode_one(c),
+            FeatureEncoding::Custom(name) => custom::get(name)
+                .map(|e| e.encode(&c.to_string()))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// How gaps or ambiguous residues in signatures are encoded, see `EncodingOptions`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ResiduePolicy {
+    /// The values of each encoding, like NRPSPredictor2. Rausch features of gaps and ambiguous
+    /// residues are the unnormalised feature means
+    #[default]
+    Native,
+    /// Impute the mean features of the 20 standard amino acids
+    Mean,
+    /// Set all features to 0, the feature means of normalised encodings
+    Zero,
+    /// Refuse to encode signatures containing them
+    Error,
+}
+
+/// `X` for any amino acid, `B` for D or N, `Z` for E or Q and `U` for selenocysteine
+pub const AMBIGUOUS_RESIDUES: &str = "XBZU";
+const STANDARD_RESIDUES: &str = "ACDEFGHIKLMNPQRSTVWY";
+
+/// How gaps and ambiguous residues are encoded for the SVMs, and with which property tables
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EncodingOptions {
+    /// Policy for `-` gaps
+    pub gaps: ResiduePolicy,
+    /// Policy for the `AMBIGUOUS_RESIDUES`
+    pub ambiguous: ResiduePolicy,
+    /// Property tables overriding the built-in ones
+    pub tables: EncodingTables,
+}
+
+impl EncodingOptions {
+    fn policy(&self, residue: char) -> ResiduePolicy {
+        match residue {
+            '-' => self.gaps,
+            c if AMBIGUOUS_RESIDUES.contains(c) => self.ambiguous,
+            _ => ResiduePolicy::Native,
+        }
+    }
+}
+
+/// Serialised form of `FeatureEncoding`, custom encodings are stored by name
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "FeatureEncoding")]
+enum EncodingRepr {
+    Blin,
+    Rausch,
+    Wold,
+    Custom(String),
+    // Added after `Custom` to keep the variant indices of existing model caches
+    Reduced,
+}
+
+impl Serialize for FeatureEncoding {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        EncodingRepr::from(*self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FeatureEncoding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        FeatureEncoding::try_from(EncodingRepr::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl From<FeatureEncoding> for EncodingRepr {
+    fn from(encoding: FeatureEncoding) -> Self {
+        match encoding {
+            FeatureEncoding::Blin => EncodingRepr::Blin,
+            FeatureEncoding::Rausch => EncodingRepr::Rausch,
+            FeatureEncoding::Wold => EncodingRepr::Wold,
+            FeatureEncoding::Reduced => EncodingRepr::Reduced,
+            FeatureEncoding::Custom(name) => EncodingRepr::Custom(name.to_string()),
+        }
+    }
+}
+
+impl TryFrom<EncodingRepr> for FeatureEncoding {
+    type Error = NrpsError;
+
+    fn try_from(repr: EncodingRepr) -> Result<Self, Self::Error> {
+        match repr {
+            EncodingRepr::Blin => Ok(FeatureEncoding::Blin),
+            EncodingRepr::Rausch => Ok(FeatureEncoding::Rausch),
+            EncodingRepr::Wold => Ok(FeatureEncoding::Wold),
+            EncodingRepr::Reduced => Ok(FeatureEncoding::Reduced),
+            EncodingRepr::Custom(name) => custom::registered_encoding(&name)
+                .ok_or_else(|| NrpsError::EncodingError(format!("Unregistered encoding `{name}`"))),
+        }
+    }
+}
+
+impl FeatureEncoding {
+    /// Length of the feature vector for an aa34 signature
+    pub fn dimensions(&self) -> usize {
+        match self {
+            FeatureEncoding::Blin => blin::FEATURES * AA34_LENGTH,
+            FeatureEncoding::Rausch => rausch::FEATURES * AA34_LENGTH,
+            FeatureEncoding::Wold => wold::FEATURES * AA34_LENGTH,
+            FeatureEncoding::Reduced => reduced::FEATURES * AA34_LENGTH,
+            FeatureEncoding::Custom(name) => custom::get(name).map_or(0, |e| e.dimensions()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeatureEncoding::Blin => "blin",
+            FeatureEncoding::Rausch => "rausch",
+            FeatureEncoding::Wold => "wold",
+            FeatureEncoding::Reduced => "reduced",
+            FeatureEncoding::Custom(name) => name,
+        }
+    }
+
+    /// The encoding of models with `dimensions` features that don't declare one
+    pub fn from_dimensions(dimensions: usize) -> Option<Self> {
+        match dimensions {
+            102 => Some(FeatureEncoding::Wold),
+            408 => Some(FeatureEncoding::Rausch),
+            510 => Some(FeatureEncoding::Blin),
+            _ => None,
+        }
+    }
+
+    fn builtin(name: &str) -> Option<Self> {
+        [
+            FeatureEncoding::Blin,
+            FeatureEncoding::Rausch,
+            FeatureEncoding::Wold,
+            FeatureEncoding::Reduced,
+        ]
+        .into_iter()
+        .find(|encoding| encoding.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Find a built-in or registered encoding by name
+    pub fn from_name(name: &str) -> Option<Self> {
+        FeatureEncoding::builtin(name).or_else(|| custom::registered_encoding(name))
+    }
 }
 
 pub fn encode(
@@ -19,21 +226,145 @@ pub fn encode(
     encoding: &FeatureEncoding,
     category: &PredictionCategory,
 ) -> Vec<f64> {
-    let legacy_categories = &[
-        PredictionCategory::LargeClusterV1,
-        PredictionCategory::SmallClusterV1,
-    ];
     match encoding {
         FeatureEncoding::Blin => blin::encode(sequence),
         FeatureEncoding::Rausch => {
-            if legacy_categories.contains(category) {
+            if is_legacy_layout(encoding, category) {
                 rausch::legacy_encode(sequence)
             } else {
                 rausch::encode(sequence)
             }
         }
         FeatureEncoding::Wold => wold::encode(sequence),
+        FeatureEncoding::Reduced => reduced::encode(sequence),
+        FeatureEncoding::Custom(name) => custom::get(name)
+            .map(|e| e.encode(sequence))
+            .unwrap_or_default(),
+    }
+}
+
+/// Encode `sequence` like `encode()`, using the property tables in `tables`
+pub fn encode_with_tables(
+    sequence: &str,
+    encoding: &FeatureEncoding,
+    category: &PredictionCategory,
+    tables: &EncodingTables,
+) -> Vec<f64> {
+    if !tables.overrides(encoding) {
+        return encode(sequence, encoding, category);
+    }
+    let residues: Vec<Vec<f64>> = sequence
+        .chars()
+        .map(|c| tables.encode_one(encoding, c))
+        .collect();
+    if !is_legacy_layout(encoding, category) {
+        return residues.concat();
+    }
+    let width = residues.first().map_or(0, Vec::len);
+    (0..width)
+        .flat_map(|feature| residues.iter().map(move |values| values[feature]))
+        .collect()
+}
+
+/// Encode `sequence` like `encode()`, with gaps, ambiguous residues and property tables as set in
+/// `options`
+pub fn encode_with_options(
+    sequence: &str,
+    encoding: &FeatureEncoding,
+    category: &PredictionCategory,
+    options: &EncodingOptions,
+) -> Result<Vec<f64>, NrpsError> {
+    let mut features = encode_with_tables(sequence, encoding, category, &options.tables);
+    let len = sequence.chars().count();
+    let native =
+        options.gaps == ResiduePolicy::Native && options.ambiguous == ResiduePolicy::Native;
+    if native || len == 0 {
+        return Ok(features);
+    }
+    let width = features.len() / len;
+    let legacy = is_legacy_layout(encoding, category);
+    let zeros = vec![0.0; width];
+    let mut means = None;
+    for (position, c) in sequence.chars().enumerate() {
+        let values = match options.policy(c) {
+            ResiduePolicy::Native => continue,
+            ResiduePolicy::Mean => {
+                means.get_or_insert_with(|| mean_features(encoding, category, &options.tables))
+            }
+            ResiduePolicy::Zero => &zeros,
+            ResiduePolicy::Error => {
+                return Err(NrpsError::InvalidResidue {
+                    position: position + 1,
+                    char: c,
+                })
+            }
+        };
+        for (feature, value) in values.iter().enumerate().take(width) {
+            let index = if legacy {
+                feature * len + position
+            } else {
+                position * width + feature
+            };
+            features[index] = *value;
+        }
     }
+    Ok(features)
+}
+
+/// The mean features of the 20 standard amino acids
+fn mean_features(
+    encoding: &FeatureEncoding,
+    category: &PredictionCategory,
+    tables: &EncodingTables,
+) -> Vec<f64> {
+    let mut means: Vec<f64> = Vec::new();
+    for residue in STANDARD_RESIDUES.chars() {
+        let features = encode_with_tables(&residue.to_string(), encoding, category, tables);
+        means.resize(features.len(), 0.0);
+        for (mean, value) in means.iter_mut().zip(features) {
+            *mean += value / STANDARD_RESIDUES.len() as f64;
+        }
+    }
+    means
+}
+
+/// The features of each residue of `sequence`, in sequence order
+///
+/// For legacy layouts this is the same as the features of each residue, before regrouping.
+pub fn encode_residues(
+    sequence: &str,
+    encoding: &FeatureEncoding,
+    category: &PredictionCategory,
+    tables: &EncodingTables,
+) -> Vec<(char, Vec<f64>)> {
+    sequence
+        .chars()
+        .map(|c| {
+            (
+                c,
+                encode_with_tables(&c.to_string(), encoding, category, tables),
+            )
+        })
+        .collect()
+}
+
+/// Features in SVMlight notation, `1:value 2:value ...`
+pub fn format_features(features: &[f64]) -> String {
+    features
+        .iter()
+        .enumerate()
+        .map(|(i, value)| format!("{}:{value}", i + 1))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Legacy layouts group the vector by feature instead of by residue
+pub fn is_legacy_layout(encoding: &FeatureEncoding, category: &PredictionCategory) -> bool {
+    let legacy_categories = &[
+        PredictionCategory::LargeClusterV1,
+        PredictionCategory::SmallClusterV1,
+    ];
+    matches!(encoding, FeatureEncoding::Rausch) && legacy_categories.contains(category)
 }
 
 pub fn get_value(map: &phf::Map<char, f64>, c: char, mean: f64, stdev: f64, use_mean: bool) -> f64 {
@@ -64,6 +395,114 @@ mod tests {
     const TEST_MEAN: f64 = 2.0;
     const TEST_STDEV: f64 = 2.0;
 
+    #[test]
+    fn test_encode_residues() {
+        let sequence = "LDA-";
+        let category = PredictionCategory::SingleV3;
+        for encoding in [FeatureEncoding::Wold, FeatureEncoding::Rausch] {
+            let residues = encode_residues(sequence, &encoding, &category, &Default::default());
+            assert_eq!(residues.len(), 4);
+            assert_eq!(residues[1].0, 'D');
+            let joined: Vec<f64> = residues.into_iter().flat_map(|(_, f)| f).collect();
+            assert_eq!(joined, encode(sequence, &encoding, &category));
+        }
+        assert_eq!(format_features(&[0.5, -1.0]), "1:0.5 2:-1");
+    }
+
+    #[test]
+    fn test_residue_encoder() {
+        let sequence = "LDAX-";
+        let category = PredictionCategory::SingleV3;
+        for encoding in [
+            FeatureEncoding::Blin,
+            FeatureEncoding::Rausch,
+            FeatureEncoding::Wold,
+            FeatureEncoding::Reduced,
+        ] {
+            assert_eq!(encoding.features() * AA34_LENGTH, encoding.dimensions());
+            assert_eq!(encoding.encode_one('L').len(), encoding.features());
+            assert_eq!(
+                encoding.encode_sequence(sequence),
+                encode(sequence, &encoding, &category)
+            );
+        }
+
+        // Feature f of residue i is at i * FEATURES + f, or f * len + i in the legacy layout
+        let encoding = FeatureEncoding::Rausch;
+        let legacy = encode(sequence, &encoding, &PredictionCategory::LargeClusterV1);
+        for (i, c) in sequence.chars().enumerate() {
+            for (f, value) in encoding.encode_one(c).into_iter().enumerate() {
+                assert_eq!(legacy[f * sequence.len() + i], value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_with_options() {
+        let sequence = "L-AX";
+        let gaps = EncodingOptions {
+            gaps: ResiduePolicy::Zero,
+            ..Default::default()
+        };
+        let ambiguous = EncodingOptions {
+            ambiguous: ResiduePolicy::Mean,
+            ..Default::default()
+        };
+        for (encoding, category) in [
+            (FeatureEncoding::Wold, PredictionCategory::SingleV3),
+            (FeatureEncoding::Rausch, PredictionCategory::SingleV2),
+            (FeatureEncoding::Rausch, PredictionCategory::LargeClusterV1),
+        ] {
+            let encode = |sequence, options: &EncodingOptions| {
+                encode_with_options(sequence, &encoding, &category, options).unwrap()
+            };
+            let native = super::encode(sequence, &encoding, &category);
+            assert_eq!(encode(sequence, &EncodingOptions::default()), native);
+
+            // Only the features of the gap or the X change, per residue with any layout
+            let residues = |features: Vec<f64>| -> Vec<Vec<f64>> {
+                let width = features.len() / 4;
+                (0..4)
+                    .map(|position| {
+                        (0..width)
+                            .map(|feature| match is_legacy_layout(&encoding, &category) {
+                                true => features[feature * 4 + position],
+                                false => features[position * width + feature],
+                            })
+                            .collect()
+                    })
+                    .collect()
+            };
+            let native = residues(native);
+            let gapless = residues(encode(sequence, &gaps));
+            assert!(gapless[1].iter().all(|value| *value == 0.0));
+            assert_eq!(gapless[0], native[0]);
+            assert_eq!(gapless[3], native[3]);
+
+            let imputed = residues(encode(sequence, &ambiguous));
+            assert_eq!(
+                imputed[3],
+                mean_features(&encoding, &category, &Default::default())
+            );
+            assert_eq!(imputed[..3], native[..3]);
+        }
+
+        let errors = EncodingOptions {
+            gaps: ResiduePolicy::Error,
+            ambiguous: ResiduePolicy::Error,
+            ..Default::default()
+        };
+        let category = PredictionCategory::SingleV3;
+        assert!(matches!(
+            encode_with_options(sequence, &FeatureEncoding::Wold, &category, &errors),
+            Err(NrpsError::InvalidResidue {
+                position: 2,
+                char: '-'
+            })
+        ));
+        assert!(encode_with_options("LA", &FeatureEncoding::Wold, &category, &errors).is_ok());
+    }
+
     #[test]
     fn test_get_value() {
         assert_approx_eq!(get_value(&TEST_MAP, 'A', TEST_MEAN, TEST_STDEV, true), -1.0);
@@ -3,6 +3,7 @@
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static ALPHA_HELIX_MAP: phf::Map<char, f64> = phf_map! {
     'A' => 1.42,
@@ -39,3 +40,13 @@ pub fn get(c: char) -> f64 {
         true,
     )
 }
+
+pub fn table() -> PropertyTable {
+    PropertyTable::from_map(
+        "alpha_helix",
+        &ALPHA_HELIX_MAP,
+        ALPHA_HELIX_MEAN,
+        ALPHA_HELIX_STDEV,
+        true,
+    )
+}
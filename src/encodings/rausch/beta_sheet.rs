@@ -3,6 +3,7 @@
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static BETA_SHEET_MAP: phf::Map<char, f64> = phf_map! {
     'A' => 0.83,
@@ -39,3 +40,13 @@ pub fn get(c: char) -> f64 {
         true,
     )
 }
+
+pub fn table() -> PropertyTable {
+    PropertyTable::from_map(
+        "beta_sheet",
+        &BETA_SHEET_MAP,
+        BETA_SHEET_MEAN,
+        BETA_SHEET_STDEV,
+        true,
+    )
+}
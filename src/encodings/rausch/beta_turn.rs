@@ -3,6 +3,7 @@
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static BETA_TURN_MAP: phf::Map<char, f64> = phf_map! {
     'A' => 0.74,
@@ -39,3 +40,13 @@ pub fn get(c: char) -> f64 {
         true,
     )
 }
+
+pub fn table() -> PropertyTable {
+    PropertyTable::from_map(
+        "beta_turn",
+        &BETA_TURN_MAP,
+        BETA_TURN_MEAN,
+        BETA_TURN_STDEV,
+        true,
+    )
+}
@@ -3,6 +3,7 @@
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static HYDROPHOBICITY_NEU2_MAP: phf::Map<char, f64> = phf_map! {
     'A' => -0.25,
@@ -39,3 +40,13 @@ pub fn get(c: char) -> f64 {
         true,
     )
 }
+
+pub fn table() -> PropertyTable {
+    PropertyTable::from_map(
+        "hydrophobicity_neu2",
+        &HYDROPHOBICITY_NEU2_MAP,
+        HYDROPHOBICITY_NEU2_MEAN,
+        HYDROPHOBICITY_NEU2_STDEV,
+        true,
+    )
+}
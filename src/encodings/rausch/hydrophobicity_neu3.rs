@@ -3,6 +3,7 @@
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static HYDROPHOBICITY_NEU3_MAP: phf::Map<char, f64> = phf_map! {
     'A' => 0.25,
@@ -39,3 +40,13 @@ pub fn get(c: char) -> f64 {
         true,
     )
 }
+
+pub fn table() -> PropertyTable {
+    PropertyTable::from_map(
+        "hydrophobicity_neu3",
+        &HYDROPHOBICITY_NEU3_MAP,
+        HYDROPHOBICITY_NEU3_MEAN,
+        HYDROPHOBICITY_NEU3_STDEV,
+        true,
+    )
+}
@@ -3,6 +3,7 @@
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static ISOELECTRIC_MAP: phf::Map<char, f64> = phf_map! {
     'A' => 6.00,
@@ -39,3 +40,13 @@ pub fn get(c: char) -> f64 {
         true,
     )
 }
+
+pub fn table() -> PropertyTable {
+    PropertyTable::from_map(
+        "isoelectric",
+        &ISOELECTRIC_MAP,
+        ISOELECTRIC_MEAN,
+        ISOELECTRIC_STDEV,
+        true,
+    )
+}
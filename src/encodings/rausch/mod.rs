@@ -14,8 +14,13 @@ mod polar_radzicka;
 mod polar_zimmerman;
 mod volume;
 
+use super::tables::PropertyTable;
+
+/// Features per residue
+pub const FEATURES: usize = 12;
+
 pub fn encode(sequence: &str) -> Vec<f64> {
-    let capacity = sequence.len() * 12;
+    let capacity = sequence.len() * FEATURES;
     let encoded: Vec<f64> = Vec::with_capacity(capacity);
     sequence
         .chars()
@@ -44,17 +49,35 @@ pub fn encode_one(c: char) -> Vec<f64> {
     ]
 }
 
+/// The property tables, in feature order
+pub(super) fn tables() -> Vec<PropertyTable> {
+    vec![
+        hydrogenbond::table(),
+        hydrophobicity_neu1::table(),
+        hydrophobicity_neu2::table(),
+        hydrophobicity_neu3::table(),
+        polar_zimmerman::table(),
+        polar_radzicka::table(),
+        polar_grantham::table(),
+        volume::table(),
+        beta_turn::table(),
+        beta_sheet::table(),
+        alpha_helix::table(),
+        isoelectric::table(),
+    ]
+}
+
 pub fn legacy_encode(sequence: &str) -> Vec<f64> {
-    let capacity = sequence.len() * 12;
+    let capacity = sequence.len() * FEATURES;
     let mut encoded: Vec<f64> = Vec::with_capacity(capacity);
 
-    let mut array: Vec<Vec<f64>> = Vec::with_capacity(12);
+    let mut array: Vec<Vec<f64>> = Vec::with_capacity(FEATURES);
 
     for c in sequence.chars() {
         array.push(encode_one(c));
     }
 
-    for i in 0_usize..12 {
+    for i in 0_usize..FEATURES {
         for a in array.iter().take(sequence.len()) {
             encoded.push(a[i]);
         }
@@ -103,19 +126,28 @@ mod tests {
         for (c, expected) in DATA.entries() {
             let query = c.to_string();
             let got = encode(&query);
-            dbg!(&got);
+            assert_eq!(got.len(), FEATURES);
             for (i, value) in got.iter().enumerate() {
                 assert_approx_eq!(value.clone(), expected[i]);
             }
         }
     }
 
+    #[test]
+    fn test_rausch_encode_sequence() {
+        let got = encode("AC");
+        assert_eq!(got.len(), 2 * FEATURES);
+        for (value, expected) in got.iter().zip(DATA[&'A'].iter().chain(DATA[&'C'].iter())) {
+            assert_approx_eq!(*value, *expected);
+        }
+    }
+
     #[test]
     fn test_rausch_legacy_concatenation() {
         for (c, expected) in LEGACY_CONCAT_DATA.entries() {
             let query = c.to_string();
             let got = legacy_encode(&query);
-            dbg!(&got);
+            assert_eq!(got.len(), query.len() * FEATURES);
             for (i, value) in got.iter().enumerate() {
                 assert_approx_eq!(value.clone(), expected[i]);
             }
@@ -3,6 +3,7 @@
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static POLAR_GRANTHAM_MAP: phf::Map<char, f64> = phf_map! {
     'A' => 8.10,
@@ -39,3 +40,13 @@ pub fn get(c: char) -> f64 {
         true,
     )
 }
+
+pub fn table() -> PropertyTable {
+    PropertyTable::from_map(
+        "polar_grantham",
+        &POLAR_GRANTHAM_MAP,
+        POLAR_GRANTHAM_MEAN,
+        POLAR_GRANTHAM_STDEV,
+        true,
+    )
+}
@@ -3,6 +3,7 @@
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static POLAR_RADZICKA_MAP: phf::Map<char, f64> = phf_map! {
     'A' => -0.06,
@@ -39,3 +40,13 @@ pub fn get(c: char) -> f64 {
         true,
     )
 }
+
+pub fn table() -> PropertyTable {
+    PropertyTable::from_map(
+        "polar_radzicka",
+        &POLAR_RADZICKA_MAP,
+        POLAR_RADZICKA_MEAN,
+        POLAR_RADZICKA_STDEV,
+        true,
+    )
+}
@@ -0,0 +1,108 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Reduced alphabet encoding, one-hot over three physicochemical classes.
+//!
+//! Residues are grouped by hydrophobicity as in the composition/transition/distribution
+//! descriptors of Dubchak et al. (1995): polar (RKEDQN), neutral (GASTPHY) and hydrophobic
+//! (CLVIMFW). Gaps and unknown residues encode as all zeros.
+
+use phf::phf_map;
+
+/// Features per residue
+pub const FEATURES: usize = 3;
+
+pub fn encode(sequence: &str) -> Vec<f64> {
+    let capacity = sequence.len() * FEATURES;
+    let encoded: Vec<f64> = Vec::with_capacity(capacity);
+    sequence
+        .chars()
+        .map(encode_one)
+        .fold(encoded, |mut acc, mut part| {
+            acc.append(&mut part);
+            acc
+        })
+}
+
+pub fn encode_one(c: char) -> Vec<f64> {
+    let mut encoded = vec![0.0; FEATURES];
+    if let Some(class) = CLASS_MAP.get(&c) {
+        encoded[*class] = 1.0;
+    }
+    encoded
+}
+
+static CLASS_MAP: phf::Map<char, usize> = phf_map! {
+    'R' => 0,
+    'K' => 0,
+    'E' => 0,
+    'D' => 0,
+    'Q' => 0,
+    'N' => 0,
+    'G' => 1,
+    'A' => 1,
+    'S' => 1,
+    'T' => 1,
+    'P' => 1,
+    'H' => 1,
+    'Y' => 1,
+    'C' => 2,
+    'L' => 2,
+    'V' => 2,
+    'I' => 2,
+    'M' => 2,
+    'F' => 2,
+    'W' => 2,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    static DATA: phf::Map<char, [f64; 3]> = phf_map! {
+        'A' => [0.0, 1.0, 0.0, ],
+        'C' => [0.0, 0.0, 1.0, ],
+        'D' => [1.0, 0.0, 0.0, ],
+        'E' => [1.0, 0.0, 0.0, ],
+        'F' => [0.0, 0.0, 1.0, ],
+        'G' => [0.0, 1.0, 0.0, ],
+        'H' => [0.0, 1.0, 0.0, ],
+        'I' => [0.0, 0.0, 1.0, ],
+        'K' => [1.0, 0.0, 0.0, ],
+        'L' => [0.0, 0.0, 1.0, ],
+        'M' => [0.0, 0.0, 1.0, ],
+        'N' => [1.0, 0.0, 0.0, ],
+        'P' => [0.0, 1.0, 0.0, ],
+        'Q' => [1.0, 0.0, 0.0, ],
+        'R' => [1.0, 0.0, 0.0, ],
+        'S' => [0.0, 1.0, 0.0, ],
+        'T' => [0.0, 1.0, 0.0, ],
+        'V' => [0.0, 0.0, 1.0, ],
+        'W' => [0.0, 0.0, 1.0, ],
+        'X' => [0.0, 0.0, 0.0, ],
+        'Y' => [0.0, 1.0, 0.0, ],
+        '-' => [0.0, 0.0, 0.0, ],
+    };
+
+    #[test]
+    fn test_reduced_encoder() {
+        for (c, expected) in DATA.entries() {
+            let query = c.to_string();
+            let got = encode(&query);
+            assert_eq!(got.len(), FEATURES);
+            for (i, value) in got.iter().enumerate() {
+                assert_approx_eq!(value.clone(), expected[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reduced_encode_sequence() {
+        let got = encode("LDA-");
+        assert_eq!(
+            got,
+            [0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]
+        );
+    }
+}
@@ -0,0 +1,357 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Residue property tables of the wold and rausch encodings, and runtime overrides of them.
+//!
+//! Each feature of these encodings is a residue property, normalised with the mean and standard
+//! deviation of the property. Models trained on re-normalised or different property values need
+//! the same tables, which can be overridden from a TOML file with one table per property:
+//!
+//! ```toml
+//! [wold.hydrophobicity]
+//! mean = 0.0
+//! stdev = 2.5
+//! values = { A = 0.1, R = 2.9 }
+//! ```
+//!
+//! Keys and residues missing from the file keep their built-in values. The loaded
+//! `EncodingTables` are passed to the encoding functions with the `EncodingOptions`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::NrpsError;
+
+use super::{normalise, rausch, wold, FeatureEncoding, ResidueEncoder};
+
+/// A residue property, features are its values normalised to `(value - mean) / stdev`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyTable {
+    pub name: String,
+    pub mean: f64,
+    pub stdev: f64,
+    pub values: BTreeMap<char, f64>,
+    /// Residues without a value get the unnormalised mean instead of the normalised 0
+    pub unknown_as_mean: bool,
+}
+
+impl PropertyTable {
+    pub fn from_map(
+        name: &str,
+        map: &phf::Map<char, f64>,
+        mean: f64,
+        stdev: f64,
+        unknown_as_mean: bool,
+    ) -> Self {
+        PropertyTable {
+            name: name.to_string(),
+            mean,
+            stdev,
+            values: map.entries().map(|(c, value)| (*c, *value)).collect(),
+            unknown_as_mean,
+        }
+    }
+
+    /// The feature value of residue `c`
+    pub fn get(&self, c: char) -> f64 {
+        match self.values.get(&c) {
+            Some(value) => normalise(*value, self.mean, self.stdev),
+            None if self.unknown_as_mean => self.mean,
+            None => normalise(0.0, self.mean, self.stdev),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PropertyOverride {
+    mean: Option<f64>,
+    stdev: Option<f64>,
+    #[serde(default)]
+    values: BTreeMap<char, f64>,
+}
+
+/// Serialised form of `PropertyTable`, TOML keys need to be strings
+#[derive(Serialize)]
+struct PropertyRepr {
+    mean: f64,
+    stdev: f64,
+    values: BTreeMap<String, f64>,
+}
+
+/// Property tables used instead of the built-in ones, by encoding name
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EncodingTables {
+    overrides: BTreeMap<&'static str, Arc<Vec<PropertyTable>>>,
+}
+
+impl EncodingTables {
+    /// Read the overrides in `path`, see the module docs for the format
+    pub fn from_file(path: &Path) -> Result<Self, NrpsError> {
+        let text = fs::read_to_string(path).map_err(|source| NrpsError::FileError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let overrides = parse_overrides(&text).map_err(|err| match err {
+            NrpsError::EncodingTableError(msg) => {
+                NrpsError::EncodingTableError(format!("{}: {msg}", path.display()))
+            }
+            err => err,
+        })?;
+        let mut tables = EncodingTables::default();
+        for (encoding, encoding_tables) in overrides {
+            log::info!("Using {} tables from {}", encoding.name(), path.display());
+            tables.set(&encoding, encoding_tables)?;
+        }
+        Ok(tables)
+    }
+
+    /// Use `tables` for `encoding` instead of the built-in ones
+    pub fn set(
+        &mut self,
+        encoding: &FeatureEncoding,
+        tables: Vec<PropertyTable>,
+    ) -> Result<(), NrpsError> {
+        let builtin = builtin_tables(encoding).ok_or_else(|| {
+            NrpsError::EncodingTableError(format!("{} has no property tables", encoding.name()))
+        })?;
+        let names = |tables: &[PropertyTable]| -> Vec<String> {
+            tables.iter().map(|table| table.name.to_string()).collect()
+        };
+        if names(&tables) != names(&builtin) {
+            return Err(NrpsError::EncodingTableError(format!(
+                "expected the {} tables {}",
+                encoding.name(),
+                names(&builtin).join(", ")
+            )));
+        }
+        self.overrides.insert(encoding.name(), Arc::new(tables));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    /// The tables overriding those of `encoding`, if any
+    pub fn get(&self, encoding: &FeatureEncoding) -> Option<&[PropertyTable]> {
+        self.overrides
+            .get(encoding.name())
+            .map(|tables| tables.as_slice())
+    }
+
+    /// The tables `encoding` uses, overridden or built-in
+    pub fn tables(&self, encoding: &FeatureEncoding) -> Option<Vec<PropertyTable>> {
+        match self.get(encoding) {
+            Some(tables) => Some(tables.to_vec()),
+            None => builtin_tables(encoding),
+        }
+    }
+
+    /// Whether `encoding` encodes differently with these tables, blin is made up of rausch and wold
+    pub fn overrides(&self, encoding: &FeatureEncoding) -> bool {
+        match encoding {
+            FeatureEncoding::Blin => {
+                self.overrides(&FeatureEncoding::Rausch) || self.overrides(&FeatureEncoding::Wold)
+            }
+            encoding => self.overrides.contains_key(encoding.name()),
+        }
+    }
+
+    /// The features of residue `c` in `encoding`, like `ResidueEncoder::encode_one()`
+    pub fn encode_one(&self, encoding: &FeatureEncoding, c: char) -> Vec<f64> {
+        if let Some(tables) = self.get(encoding) {
+            return tables.iter().map(|table| table.get(c)).collect();
+        }
+        match encoding {
+            FeatureEncoding::Blin => {
+                let mut encoded = self.encode_one(&FeatureEncoding::Rausch, c);
+                encoded.append(&mut self.encode_one(&FeatureEncoding::Wold, c));
+                encoded
+            }
+            encoding => encoding.encode_one(c),
+        }
+    }
+}
+
+/// The built-in tables of `encoding` in feature order, `None` if it isn't table based
+pub fn builtin_tables(encoding: &FeatureEncoding) -> Option<Vec<PropertyTable>> {
+    match encoding {
+        FeatureEncoding::Wold => Some(wold::tables()),
+        FeatureEncoding::Rausch => Some(rausch::tables()),
+        _ => None,
+    }
+}
+
+/// Apply the overrides in `text` to the built-in tables, see the module docs for the format
+pub fn parse_overrides(
+    text: &str,
+) -> Result<Vec<(FeatureEncoding, Vec<PropertyTable>)>, NrpsError> {
+    let parsed: BTreeMap<String, BTreeMap<String, PropertyOverride>> = toml::from_str(text)
+        .map_err(|err| NrpsError::EncodingTableError(err.message().to_string()))?;
+    let mut result = Vec::with_capacity(parsed.len());
+    for (encoding_name, overrides) in parsed {
+        let error = |msg: String| NrpsError::EncodingTableError(format!("{encoding_name}: {msg}"));
+        let Some((encoding, mut tables)) = FeatureEncoding::from_name(&encoding_name)
+            .and_then(|encoding| Some((encoding, builtin_tables(&encoding)?)))
+        else {
+            return Err(error("no property tables to override".to_string()));
+        };
+        for (name, property) in overrides {
+            let Some(table) = tables.iter_mut().find(|table| table.name == name) else {
+                return Err(error(format!("unknown property `{name}`")));
+            };
+            if let Some(mean) = property.mean {
+                table.mean = mean;
+            }
+            if let Some(stdev) = property.stdev {
+                table.stdev = stdev;
+            }
+            table.values.extend(property.values);
+            if !table.mean.is_finite() || !table.stdev.is_finite() || table.stdev <= 0.0 {
+                return Err(error(format!("{name}: invalid mean or stdev")));
+            }
+            if let Some((c, _)) = table.values.iter().find(|(_, value)| !value.is_finite()) {
+                return Err(error(format!("{name}: invalid value of `{c}`")));
+            }
+        }
+        result.push((encoding, tables));
+    }
+    Ok(result)
+}
+
+/// `tables` in the override file format
+pub fn format_tables(
+    encoding: &FeatureEncoding,
+    tables: &[PropertyTable],
+) -> Result<String, NrpsError> {
+    let properties: BTreeMap<&str, PropertyRepr> = tables
+        .iter()
+        .map(|table| {
+            let repr = PropertyRepr {
+                mean: table.mean,
+                stdev: table.stdev,
+                values: table
+                    .values
+                    .iter()
+                    .map(|(c, value)| (c.to_string(), *value))
+                    .collect(),
+            };
+            (table.name.as_str(), repr)
+        })
+        .collect();
+    let file = BTreeMap::from([(encoding.name(), properties)]);
+    toml::to_string(&file).map_err(|err| NrpsError::EncodingTableError(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::encodings::{blin, encode, encode_with_tables};
+    use crate::predictors::predictions::PredictionCategory;
+
+    #[test]
+    fn test_builtin_tables() {
+        let category = PredictionCategory::SingleV3;
+        let legacy = PredictionCategory::LargeClusterV1;
+        for (encoding, count) in [(FeatureEncoding::Wold, 3), (FeatureEncoding::Rausch, 12)] {
+            let tables = builtin_tables(&encoding).unwrap();
+            assert_eq!(tables.len(), count);
+            // Encoding with the tables matches the built-in encoding
+            let mut overrides = EncodingTables::default();
+            overrides.set(&encoding, tables).unwrap();
+            for category in [category.clone(), legacy.clone()] {
+                let expected = encode("LDX-", &encoding, &category);
+                let got = encode_with_tables("LDX-", &encoding, &category, &overrides);
+                assert_eq!(got.len(), expected.len());
+                for (got, expected) in got.iter().zip(expected) {
+                    assert_approx_eq!(*got, expected);
+                }
+            }
+        }
+        assert!(builtin_tables(&FeatureEncoding::Blin).is_none());
+    }
+
+    #[test]
+    fn test_parse_overrides() {
+        let text = "[wold.size]\nmean = 1.0\nstdev = 2.0\nvalues = { A = 3.0 }\n";
+        let overrides = parse_overrides(text).unwrap();
+        assert_eq!(overrides.len(), 1);
+        let (encoding, tables) = &overrides[0];
+        assert_eq!(*encoding, FeatureEncoding::Wold);
+        assert_eq!(tables[1].name, "size");
+        assert_eq!(tables[1].get('A'), 1.0);
+        assert_eq!(tables[1].get('X'), -0.5);
+        // Other residues and tables keep their values
+        assert_eq!(tables[1].values[&'R'], 2.52);
+        assert_eq!(tables[0], builtin_tables(encoding).unwrap()[0]);
+
+        // The printed tables parse to the same tables
+        let reparsed = parse_overrides(&format_tables(encoding, tables).unwrap()).unwrap();
+        assert_eq!(&reparsed[0].1, tables);
+
+        for invalid in [
+            "[blin.size]\nmean = 1.0",
+            "[wold.volume]\nmean = 1.0",
+            "[wold.size]\nstdev = 0.0",
+            "[wold.size]\nmedian = 1.0",
+            "[wold.size]\nvalues = { AB = 1.0 }",
+            "[wold.size]\nvalues = { A = nan }",
+        ] {
+            assert!(
+                matches!(
+                    parse_overrides(invalid),
+                    Err(NrpsError::EncodingTableError(_))
+                ),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_encoding_tables() {
+        let category = PredictionCategory::SingleV3;
+        let wold = FeatureEncoding::Wold;
+        let mut overrides = EncodingTables::default();
+        assert!(overrides.is_empty());
+        assert_eq!(overrides.tables(&wold), builtin_tables(&wold));
+
+        let mut tables = builtin_tables(&wold).unwrap();
+        tables[0].values.insert('A', 10.0);
+        overrides.set(&wold, tables.clone()).unwrap();
+        assert_eq!(overrides.get(&wold), Some(tables.as_slice()));
+        assert!(overrides.overrides(&wold));
+        assert!(!overrides.overrides(&FeatureEncoding::Rausch));
+        assert_eq!(overrides.encode_one(&wold, 'A')[0], tables[0].get('A'));
+        assert_eq!(
+            encode_with_tables("LA", &wold, &category, &overrides)[3],
+            tables[0].get('A')
+        );
+
+        // Blin uses the overridden wold tables after the built-in rausch ones
+        let blin = FeatureEncoding::Blin;
+        assert!(overrides.overrides(&blin));
+        let features = overrides.encode_one(&blin, 'A');
+        assert_eq!(features[..rausch::FEATURES], rausch::encode_one('A'));
+        assert_eq!(
+            features[rausch::FEATURES..],
+            overrides.encode_one(&wold, 'A')
+        );
+        let encoded = encode_with_tables("LA", &blin, &category, &overrides);
+        assert_eq!(encoded[blin::FEATURES..], features);
+        assert_ne!(encoded, encode("LA", &blin, &category));
+
+        tables.pop();
+        assert!(overrides.set(&wold, tables).is_err());
+        assert!(overrides
+            .set(&FeatureEncoding::Reduced, Vec::new())
+            .is_err());
+    }
+}
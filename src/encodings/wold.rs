@@ -4,9 +4,13 @@
 use phf::phf_map;
 
 use super::get_value;
+use super::tables::PropertyTable;
+
+/// Features per residue
+pub const FEATURES: usize = 3;
 
 pub fn encode(sequence: &str) -> Vec<f64> {
-    let capacity = sequence.len() * 3;
+    let capacity = sequence.len() * FEATURES;
     let encodeded: Vec<f64> = Vec::with_capacity(capacity);
     sequence
         .chars()
@@ -37,6 +41,27 @@ pub fn encode_one(c: char) -> Vec<f64> {
     ]
 }
 
+/// The property tables, in feature order
+pub(super) fn tables() -> Vec<PropertyTable> {
+    vec![
+        PropertyTable::from_map(
+            "hydrophobicity",
+            &HYDROPHOBICITY_MAP,
+            HYDROPHOBICITY_MEAN,
+            HYDROPHOBICITY_STDEV,
+            false,
+        ),
+        PropertyTable::from_map("size", &SIZE_MAP, SIZE_MEAN, SIZE_STDEV, false),
+        PropertyTable::from_map(
+            "polarity_charge",
+            &POLARITY_CHARGE_MAP,
+            POLARITY_CHARGE_MEAN,
+            POLARITY_CHARGE_STDEV,
+            false,
+        ),
+    ]
+}
+
 static HYDROPHOBICITY_MAP: phf::Map<char, f64> = phf_map! {
     'A' => 0.07,
     'R' => 2.88,
@@ -148,6 +173,7 @@ mod tests {
         for (c, expected) in DATA.entries() {
             let query = c.to_string();
             let got = encode(&query);
+            assert_eq!(got.len(), FEATURES);
             for (i, value) in got.iter().enumerate() {
                 assert_approx_eq!(value.clone(), expected[i]);
             }
@@ -3,6 +3,7 @@
 
 use std::io;
 use std::num;
+use std::path::PathBuf;
 
 use thiserror::Error;
 use toml;
@@ -10,24 +11,215 @@ use walkdir;
 
 #[derive(Error, Debug)]
 pub enum NrpsError {
+    #[error("Invalid aa10 positions: {0}")]
+    Aa10PositionError(String),
+    #[error("Model cache error `{0}`")]
+    CacheError(String),
+    #[error("Calibration file error `{0}`")]
+    CalibrationError(String),
+    #[error("Invalid category: {0}")]
+    CategoryError(String),
     #[error("Error parsing config")]
     ConfigError(#[from] toml::de::Error),
+    #[error("Config file `{}` not found", .0.display())]
+    ConfigFileError(PathBuf),
     #[error("Invalid result count: `{0}`")]
     CountError(usize),
     #[error("Dimension mismatch: `{first}` vs. `{second}`")]
     DimensionMismatch { first: usize, second: usize },
     #[error("Dir error")]
     DirError(#[from] walkdir::Error),
+    #[error("Error parsing config from environment: {0}")]
+    EnvConfigError(#[from] envy::Error),
+    #[error("Encoding error `{0}`")]
+    EncodingError(String),
+    #[error("Invalid encoding tables: {0}")]
+    EncodingTableError(String),
+    #[error("Signature extraction error `{0}`")]
+    ExtractionError(String),
+    #[error("FASTA error `{0}`")]
+    FastaError(String),
+    #[error("Forest model error `{0}`")]
+    ForestError(String),
+    #[error("Failed to read `{}`: {source}", .path.display())]
+    FileError { path: PathBuf, source: io::Error },
     #[error("Error parsing float")]
     FloatParserError(#[from] num::ParseFloatError),
     #[error("Error parsing int")]
     IntParserError(#[from] num::ParseIntError),
     #[error("Invalid feature line `{0}`")]
     InvalidFeatureLine(String),
+    #[error("JSON error")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Invalid Stachelhaus gap penalty `{0}`, expected a value from 0 to 1")]
+    GapPenaltyError(f64),
+    #[error("GenBank error `{0}`")]
+    GenbankError(String),
+    #[error("Invalid input `{}`, line {line}: {source}", .path.display())]
+    InputParseError {
+        path: PathBuf,
+        line: usize,
+        source: Box<NrpsError>,
+    },
+    #[error("Invalid residue `{char}` at position {position}")]
+    InvalidResidue { position: usize, char: char },
     #[error("IO error")]
     Io(#[from] io::Error),
+    #[error("Model dir error `{0}`")]
+    ModelDirError(String),
+    #[error("Model file `{}` has {found} features, but the {encoding} encoding has {expected}", .path.display())]
+    ModelDimensionError {
+        path: PathBuf,
+        encoding: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Model `{name}` has {found} features, but the {encoding} encoding has {expected}")]
+    ModelEncodingError {
+        name: String,
+        encoding: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("Invalid model file `{}`, line {line}: {source}", .path.display())]
+    ModelParseError {
+        path: PathBuf,
+        line: usize,
+        source: Box<NrpsError>,
+    },
+    #[error("PWM error `{0}`")]
+    PwmError(String),
+    #[error("Server error `{0}`")]
+    ServerError(String),
     #[error("Signature error `{0}`")]
     SignatureError(String),
+    #[error("Invalid snapshot line `{0}`")]
+    SnapshotError(String),
     #[error("Stachelhaus signature file error `{0}`")]
     SignatureFileError(String),
+    #[error("Model declares {declared} support vectors, but has {found}")]
+    SupportVectorCountMismatch { declared: usize, found: usize },
+    #[error("Training error `{0}`")]
+    TrainingError(String),
+    #[error("Not supported for kernel type `{0}`")]
+    UnsupportedKernel(String),
+    #[error("Invalid summary weights: {0}")]
+    WeightError(String),
+}
+
+/// What an error is about, the command line tool exits with a distinct code for each
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Invalid config file, environment or options
+    Config,
+    /// Unreadable or malformed input signatures or sequences
+    Input,
+    /// Missing or broken model dir, model files or Stachelhaus signatures
+    Model,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Config => 2,
+            ErrorKind::Input => 3,
+            ErrorKind::Model => 4,
+        }
+    }
+}
+
+impl NrpsError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            NrpsError::Aa10PositionError(_)
+            | NrpsError::CalibrationError(_)
+            | NrpsError::CategoryError(_)
+            | NrpsError::ConfigError(_)
+            | NrpsError::ConfigFileError(_)
+            | NrpsError::CountError(_)
+            | NrpsError::EnvConfigError(_)
+            | NrpsError::EncodingTableError(_)
+            | NrpsError::GapPenaltyError(_)
+            | NrpsError::WeightError(_) => ErrorKind::Config,
+            NrpsError::ExtractionError(_)
+            | NrpsError::FastaError(_)
+            | NrpsError::FileError { .. }
+            | NrpsError::GenbankError(_)
+            | NrpsError::InputParseError { .. }
+            | NrpsError::InvalidResidue { .. }
+            | NrpsError::SignatureError(_) => ErrorKind::Input,
+            NrpsError::CacheError(_)
+            | NrpsError::DimensionMismatch { .. }
+            | NrpsError::DirError(_)
+            | NrpsError::EncodingError(_)
+            | NrpsError::ForestError(_)
+            | NrpsError::InvalidFeatureLine(_)
+            | NrpsError::ModelDimensionError { .. }
+            | NrpsError::ModelEncodingError { .. }
+            | NrpsError::ModelDirError(_)
+            | NrpsError::ModelParseError { .. }
+            | NrpsError::PwmError(_)
+            | NrpsError::SignatureFileError(_)
+            | NrpsError::SupportVectorCountMismatch { .. }
+            | NrpsError::UnsupportedKernel(_) => ErrorKind::Model,
+            NrpsError::FloatParserError(_)
+            | NrpsError::IntParserError(_)
+            | NrpsError::JsonError(_)
+            | NrpsError::Io(_)
+            | NrpsError::ServerError(_)
+            | NrpsError::SnapshotError(_)
+            | NrpsError::TrainingError(_) => ErrorKind::Other,
+        }
+    }
+
+    /// The error message, followed by the messages of its causes that it doesn't include yet
+    pub fn report(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(err) = source {
+            let text = err.to_string();
+            if !message.contains(&text) {
+                message.push_str(&format!(": {text}"));
+            }
+            source = err.source();
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind() {
+        let err = NrpsError::ModelParseError {
+            path: PathBuf::from("[leu].mdl"),
+            line: 3,
+            source: Box::new(NrpsError::InvalidFeatureLine("bad".to_string())),
+        };
+        assert_eq!(err.kind(), ErrorKind::Model);
+        assert_eq!(err.kind().exit_code(), 4);
+        assert_eq!(
+            NrpsError::SignatureError("short".to_string()).kind(),
+            ErrorKind::Input
+        );
+        assert_eq!(NrpsError::CountError(0).kind().exit_code(), 2);
+    }
+
+    #[test]
+    fn test_report() {
+        let err = NrpsError::Io(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(err.report(), "IO error: no such file");
+
+        // Sources already part of the message aren't repeated
+        let err = NrpsError::ModelParseError {
+            path: PathBuf::from("[leu].mdl"),
+            line: 3,
+            source: Box::new(NrpsError::InvalidFeatureLine("bad".to_string())),
+        };
+        assert_eq!(err.report(), err.to_string());
+    }
 }
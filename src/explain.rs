@@ -0,0 +1,134 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Score breakdowns of the reported predictions of a domain.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::predictors::predictions::{ADomain, PredictionCategory};
+use crate::predictors::store::ModelStore;
+use crate::svm::models::Explanation;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelExplanation {
+    pub name: String,
+    pub category: PredictionCategory,
+    pub explanation: Explanation,
+}
+
+/// Explain the SVM predictions reported for `domain`, in output category order
+pub fn explain_domain(
+    config: &Config,
+    store: &ModelStore,
+    domain: &ADomain,
+) -> Result<Vec<ModelExplanation>, NrpsError> {
+    let mut explanations = Vec::new();
+    if domain.aa34.is_empty() {
+        return Ok(explanations);
+    }
+    for category in config.output_categories() {
+        let models = store.models_for(&category)?;
+        for pred in domain
+            .get_best_n(&category, config.count, config.ties)
+            .iter()
+        {
+            // Predictions from older snapshots don't have model ids
+            let Some(model) = models.iter().find(|model| match &pred.model {
+                Some(id) => *id == model.id(),
+                None => model.name == pred.name,
+            }) else {
+                continue;
+            };
+            explanations.push(ModelExplanation {
+                name: model.name.to_string(),
+                category: category.clone(),
+                explanation: model.predict_explain(&domain.aa34)?,
+            });
+        }
+    }
+    Ok(explanations)
+}
+
+/// One `Model Category Support vector yalpha Kernel Contribution` row per support vector
+///
+/// Each model starts with a comment line giving its score, and ends with a `bias` row.
+pub fn to_text(explanations: &[ModelExplanation]) -> String {
+    let mut output = "Model\tCategory\tSupport vector\tyalpha\tKernel\tContribution\n".to_string();
+    for model in explanations.iter() {
+        let explanation = &model.explanation;
+        output.push_str(&format!(
+            "# {} ({}): score {:.4} from {} support vectors\n",
+            model.name,
+            model.category,
+            explanation.score,
+            explanation.contributions.len()
+        ));
+        for sv in explanation.contributions.iter() {
+            output.push_str(&format!(
+                "{}\t{}\t{}\t{:.4}\t{:.4}\t{:.4}\n",
+                model.name,
+                model.category,
+                sv.index + 1,
+                sv.yalpha,
+                sv.kernel_value,
+                sv.contribution
+            ));
+        }
+        output.push_str(&format!(
+            "{}\t{}\tbias\tN/A\tN/A\t{:.4}\n",
+            model.name, model.category, -explanation.bias
+        ));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::predictors::predictions::Prediction;
+    use crate::svm::models::SVMlightModel;
+    use crate::testing::svmlight_model;
+
+    #[test]
+    fn test_explain_domain() {
+        let model = |name: &str| {
+            SVMlightModel::from_handle(
+                svmlight_model(102).as_bytes(),
+                name.to_string(),
+                PredictionCategory::SingleV3,
+            )
+            .unwrap()
+        };
+        let store = ModelStore::from_models(Vec::from([model("leu"), model("val")]));
+        let mut config = Config::new();
+        config.enabled_categories = Some(Vec::from([PredictionCategory::SingleV3]));
+
+        let mut domain = ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        );
+        domain.add(
+            PredictionCategory::SingleV3,
+            Prediction {
+                name: "leu".to_string(),
+                score: 1.0,
+                probability: None,
+                model: None,
+            },
+        );
+
+        let got = explain_domain(&config, &store, &domain).unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "leu");
+        assert_eq!(got[0].explanation.contributions.len(), 2);
+
+        let text = to_text(&got);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[1].starts_with("# leu (SingleV3): score "));
+        assert_eq!(lines[4], "leu\tSingleV3\tbias\tN/A\tN/A\t-0.5000");
+    }
+}
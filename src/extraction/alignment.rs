@@ -0,0 +1,216 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+/// Cost of opening a gap, including its first residue
+const GAP_OPEN: i32 = 11;
+/// Cost of every further residue in a gap
+const GAP_EXTEND: i32 = 1;
+
+const NEG_INF: i32 = i32::MIN / 2;
+
+const BLOSUM62_ALPHABET: &[u8; 24] = b"ARNDCQEGHILKMFPSTWYVBZX*";
+
+#[rustfmt::skip]
+const BLOSUM62: [[i8; 24]; 24] = [
+    // A   R   N   D   C   Q   E   G   H   I   L   K   M   F   P   S   T   W   Y   V   B   Z   X   *
+    [  4, -1, -2, -2,  0, -1, -1,  0, -2, -1, -1, -1, -1, -2, -1,  1,  0, -3, -2,  0, -2, -1,  0, -4], // A
+    [ -1,  5,  0, -2, -3,  1,  0, -2,  0, -3, -2,  2, -1, -3, -2, -1, -1, -3, -2, -3, -1,  0, -1, -4], // R
+    [ -2,  0,  6,  1, -3,  0,  0,  0,  1, -3, -3,  0, -2, -3, -2,  1,  0, -4, -2, -3,  3,  0, -1, -4], // N
+    [ -2, -2,  1,  6, -3,  0,  2, -1, -1, -3, -4, -1, -3, -3, -1,  0, -1, -4, -3, -3,  4,  1, -1, -4], // D
+    [  0, -3, -3, -3,  9, -3, -4, -3, -3, -1, -1, -3, -1, -2, -3, -1, -1, -2, -2, -1, -3, -3, -2, -4], // C
+    [ -1,  1,  0,  0, -3,  5,  2, -2,  0, -3, -2,  1,  0, -3, -1,  0, -1, -2, -1, -2,  0,  3, -1, -4], // Q
+    [ -1,  0,  0,  2, -4,  2,  5, -2,  0, -3, -3,  1, -2, -3, -1,  0, -1, -3, -2, -2,  1,  4, -1, -4], // E
+    [  0, -2,  0, -1, -3, -2, -2,  6, -2, -4, -4, -2, -3, -3, -2,  0, -2, -2, -3, -3, -1, -2, -1, -4], // G
+    [ -2,  0,  1, -1, -3,  0,  0, -2,  8, -3, -3, -1, -2, -1, -2, -1, -2, -2,  2, -3,  0,  0, -1, -4], // H
+    [ -1, -3, -3, -3, -1, -3, -3, -4, -3,  4,  2, -3,  1,  0, -3, -2, -1, -3, -1,  3, -3, -3, -1, -4], // I
+    [ -1, -2, -3, -4, -1, -2, -3, -4, -3,  2,  4, -2,  2,  0, -3, -2, -1, -2, -1,  1, -4, -3, -1, -4], // L
+    [ -1,  2,  0, -1, -3,  1,  1, -2, -1, -3, -2,  5, -1, -3, -1,  0, -1, -3, -2, -2,  0,  1, -1, -4], // K
+    [ -1, -1, -2, -3, -1,  0, -2, -3, -2,  1,  2, -1,  5,  0, -2, -1, -1, -1, -1,  1, -3, -1, -1, -4], // M
+    [ -2, -3, -3, -3, -2, -3, -3, -3, -1,  0,  0, -3,  0,  6, -4, -2, -2,  1,  3, -1, -3, -3, -1, -4], // F
+    [ -1, -2, -2, -1, -3, -1, -1, -2, -2, -3, -3, -1, -2, -4,  7, -1, -1, -4, -3, -2, -2, -1, -2, -4], // P
+    [  1, -1,  1,  0, -1,  0,  0,  0, -1, -2, -2,  0, -1, -2, -1,  4,  1, -3, -2, -2,  0,  0,  0, -4], // S
+    [  0, -1,  0, -1, -1, -1, -1, -2, -2, -1, -1, -1, -1, -2, -1,  1,  5, -2, -2,  0, -1, -1,  0, -4], // T
+    [ -3, -3, -4, -4, -2, -2, -3, -2, -2, -3, -2, -3, -1,  1, -4, -3, -2, 11,  2, -3, -4, -3, -2, -4], // W
+    [ -2, -2, -2, -3, -2, -1, -2, -3,  2, -1, -1, -2, -1,  3, -3, -2, -2,  2,  7, -1, -3, -2, -1, -4], // Y
+    [  0, -3, -3, -3, -1, -2, -2, -3, -3,  3,  1, -2,  1, -1, -2, -2,  0, -3, -1,  4, -3, -2, -1, -4], // V
+    [ -2, -1,  3,  4, -3,  0,  1, -1,  0, -3, -4,  0, -3, -3, -2,  0, -1, -4, -3, -3,  4,  1, -1, -4], // B
+    [ -1,  0,  0,  1, -3,  3,  4, -2,  0, -3, -3,  1, -1, -3, -1,  0, -1, -3, -2, -2,  1,  4, -1, -4], // Z
+    [  0, -1, -1, -1, -2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -2,  0,  0, -2, -1, -1, -1, -1, -1, -4], // X
+    [ -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4, -4,  1], // *
+];
+
+fn blosum62_index(residue: u8) -> usize {
+    let residue = residue.to_ascii_uppercase();
+    BLOSUM62_ALPHABET
+        .iter()
+        .position(|r| *r == residue)
+        .unwrap_or(22) // X
+}
+
+/// BLOSUM62 substitution score, unknown residues are scored like `X`
+pub fn blosum62(a: u8, b: u8) -> i32 {
+    BLOSUM62[blosum62_index(a)][blosum62_index(b)] as i32
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// Query and reference residue aligned
+    Match,
+    /// Reference residue aligned to a gap in the query
+    Left,
+    /// Query residue aligned to a gap in the reference
+    Up,
+}
+
+/// Align `query` against `reference` with BLOSUM62 and affine gaps, end gaps are free on both
+/// sequences
+///
+/// Returns the aligned query index for every reference position, or `None` if the
+/// reference position is aligned to a gap.
+pub fn align(query: &[u8], reference: &[u8]) -> Vec<Option<usize>> {
+    let rows = query.len() + 1;
+    let cols = reference.len() + 1;
+    let query: Vec<usize> = query.iter().map(|r| blosum62_index(*r)).collect();
+    let reference: Vec<usize> = reference.iter().map(|r| blosum62_index(*r)).collect();
+
+    // Gotoh's three matrices, the first row and column are 0 for free leading end gaps
+    let mut matches = vec![NEG_INF; rows * cols];
+    let mut lefts = vec![NEG_INF; rows * cols];
+    let mut ups = vec![NEG_INF; rows * cols];
+    for i in 0..rows {
+        matches[i * cols] = 0;
+    }
+    matches[..cols].fill(0);
+
+    let best_of = |m: &[i32], l: &[i32], u: &[i32], idx: usize| m[idx].max(l[idx]).max(u[idx]);
+
+    for i in 1..rows {
+        for j in 1..cols {
+            let idx = i * cols + j;
+            let diagonal = idx - cols - 1;
+            let left = idx - 1;
+            let up = idx - cols;
+
+            let substitution = BLOSUM62[query[i - 1]][reference[j - 1]] as i32;
+            matches[idx] = best_of(&matches, &lefts, &ups, diagonal) + substitution;
+            lefts[idx] = (matches[left].max(ups[left]) - GAP_OPEN).max(lefts[left] - GAP_EXTEND);
+            ups[idx] = (matches[up].max(lefts[up]) - GAP_OPEN).max(ups[up] - GAP_EXTEND);
+        }
+    }
+
+    // End gaps are free, so start the traceback at the best cell of the last row or column
+    let mut best = (rows - 1, cols - 1);
+    let mut best_score = i32::MIN;
+    let last_column = (0..rows).map(|i| (i, cols - 1));
+    let last_row = (0..cols).map(|j| (rows - 1, j));
+    for (i, j) in last_column.chain(last_row) {
+        let score = best_of(&matches, &lefts, &ups, i * cols + j);
+        if score > best_score {
+            best_score = score;
+            best = (i, j);
+        }
+    }
+
+    let state_of = |idx: usize, score: i32| {
+        if matches[idx] == score {
+            State::Match
+        } else if lefts[idx] == score {
+            State::Left
+        } else {
+            State::Up
+        }
+    };
+
+    let mut mapping = vec![None; reference.len()];
+    let (mut i, mut j) = best;
+    let mut state = state_of(i * cols + j, best_score);
+    while i > 0 && j > 0 {
+        let idx = i * cols + j;
+        match state {
+            State::Match => {
+                mapping[j - 1] = Some(i - 1);
+                let score = matches[idx] - BLOSUM62[query[i - 1]][reference[j - 1]] as i32;
+                i -= 1;
+                j -= 1;
+                state = state_of(i * cols + j, score);
+            }
+            State::Left => {
+                let left = idx - 1;
+                if lefts[idx] != lefts[left] - GAP_EXTEND {
+                    state = state_of(left, lefts[idx] + GAP_OPEN);
+                }
+                j -= 1;
+            }
+            State::Up => {
+                let up = idx - cols;
+                if ups[idx] != ups[up] - GAP_EXTEND {
+                    state = state_of(up, ups[idx] + GAP_OPEN);
+                }
+                i -= 1;
+            }
+        }
+    }
+
+    mapping
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blosum62() {
+        for a in BLOSUM62_ALPHABET.iter() {
+            for b in BLOSUM62_ALPHABET.iter() {
+                assert_eq!(blosum62(*a, *b), blosum62(*b, *a));
+            }
+        }
+        assert_eq!(blosum62(b'W', b'W'), 11);
+        assert_eq!(blosum62(b'l', b'I'), 2);
+        assert_eq!(blosum62(b'U', b'A'), blosum62(b'X', b'A'));
+    }
+
+    #[test]
+    fn test_align_affine_gap() {
+        // A single long gap is cheaper than two short ones
+        let reference = b"MKVLAGHTWYPRESCFDNQ";
+        let query = b"MKVLAWYPRESCFDNQ";
+        let got = align(query, reference);
+        assert_eq!(got[4], Some(4));
+        assert_eq!(got[5], None);
+        assert_eq!(got[6], None);
+        assert_eq!(got[7], None);
+        assert_eq!(got[8], Some(5));
+    }
+
+    #[test]
+    fn test_align_identical() {
+        let seq = b"MKVLAGHT";
+        let got = align(seq, seq);
+        let expected: Vec<Option<usize>> = (0..seq.len()).map(Some).collect();
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn test_align_free_end_gaps() {
+        let reference = b"AAAAMKVLAGHTCCCC";
+        let query = b"MKVLAGHT";
+        let got = align(query, reference);
+        assert_eq!(got[3], None);
+        assert_eq!(got[4], Some(0));
+        assert_eq!(got[11], Some(7));
+        assert_eq!(got[12], None);
+    }
+
+    #[test]
+    fn test_align_deletion() {
+        let reference = b"MKVLAGHTWYPRES";
+        let query = b"MKVLAGWYPRES";
+        let got = align(query, reference);
+        assert_eq!(got[5], Some(5));
+        assert_eq!(got[6], None);
+        assert_eq!(got[7], None);
+        assert_eq!(got[8], Some(6));
+        assert_eq!(got[13], Some(11));
+    }
+}
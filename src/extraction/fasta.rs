@@ -0,0 +1,92 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+use std::io::BufRead;
+
+use crate::errors::NrpsError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FastaRecord {
+    pub id: String,
+    pub sequence: String,
+}
+
+pub fn parse_fasta<R>(reader: R) -> Result<Vec<FastaRecord>, NrpsError>
+where
+    R: BufRead,
+{
+    let mut records = Vec::new();
+    let mut current: Option<FastaRecord> = None;
+
+    for line_res in reader.lines() {
+        let line = line_res?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('>') {
+            if let Some(record) = current.take() {
+                records.push(record);
+            }
+            let id = header.split_whitespace().next().unwrap_or_default();
+            if id.is_empty() {
+                return Err(NrpsError::FastaError(format!(
+                    "Empty FASTA header `{line}`"
+                )));
+            }
+            current = Some(FastaRecord {
+                id: id.to_string(),
+                sequence: String::new(),
+            });
+            continue;
+        }
+
+        match current.as_mut() {
+            Some(record) => record.sequence.extend(
+                line.chars()
+                    .filter(|c| !c.is_whitespace() && *c != '*')
+                    .map(|c| c.to_ascii_uppercase()),
+            ),
+            None => {
+                return Err(NrpsError::FastaError(format!(
+                    "Sequence data before first header: `{line}`"
+                )))
+            }
+        }
+    }
+
+    if let Some(record) = current.take() {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fasta() {
+        let data = ">seq1 some description\nMKVL\nacd*\n\n>seq2\nGGG\n";
+        let got = parse_fasta(data.as_bytes()).unwrap();
+        let expected = Vec::from([
+            FastaRecord {
+                id: "seq1".to_string(),
+                sequence: "MKVLACD".to_string(),
+            },
+            FastaRecord {
+                id: "seq2".to_string(),
+                sequence: "GGG".to_string(),
+            },
+        ]);
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn test_parse_fasta_errors() {
+        assert!(parse_fasta("MKVL\n>seq1\nMKVL".as_bytes()).is_err());
+        assert!(parse_fasta(">\nMKVL".as_bytes()).is_err());
+    }
+}
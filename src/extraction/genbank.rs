@@ -0,0 +1,222 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Minimal GenBank feature table parser, just enough to find annotated A domains.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::errors::NrpsError;
+
+use super::fasta::FastaRecord;
+
+const FEATURE_INDENT: usize = 5;
+const QUALIFIER_INDENT: usize = 21;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenbankFeature {
+    pub kind: String,
+    pub location: String,
+    pub qualifiers: Vec<(String, String)>,
+}
+
+impl GenbankFeature {
+    pub fn qualifier(&self, key: &str) -> Option<&str> {
+        self.qualifiers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn is_amp_binding(&self) -> bool {
+        ["aSDomain", "domain"]
+            .iter()
+            .any(|key| self.qualifier(key) == Some("AMP-binding"))
+    }
+}
+
+/// Parse the features of all records in a GenBank file
+pub fn parse_genbank_features<R>(reader: R) -> Result<Vec<GenbankFeature>, NrpsError>
+where
+    R: BufRead,
+{
+    let mut features = Vec::new();
+    let mut in_features = false;
+    let mut current: Option<GenbankFeature> = None;
+
+    for (i, line_res) in reader.lines().enumerate() {
+        let line = line_res?;
+        let line = line.trim_end();
+
+        if !in_features {
+            in_features = line.starts_with("FEATURES");
+            continue;
+        }
+
+        // Any non-indented line ends the feature table
+        if !line.is_empty() && !line.starts_with(' ') {
+            features.extend(current.take());
+            in_features = false;
+            continue;
+        }
+
+        let indent = line.len() - line.trim_start().len();
+        let content = line.trim_start();
+        if content.is_empty() {
+            continue;
+        }
+
+        if indent == FEATURE_INDENT {
+            features.extend(current.take());
+            let mut parts = content.split_whitespace();
+            current = Some(GenbankFeature {
+                kind: parts.next().unwrap_or_default().to_string(),
+                location: parts.collect::<Vec<_>>().join(""),
+                qualifiers: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some(feature) = current.as_mut() else {
+            return Err(NrpsError::GenbankError(format!(
+                "line {}: qualifier outside of a feature",
+                i + 1
+            )));
+        };
+
+        if indent < QUALIFIER_INDENT {
+            return Err(NrpsError::GenbankError(format!(
+                "line {}: unexpected indentation in `{content}`",
+                i + 1
+            )));
+        }
+
+        if let Some(qualifier) = content.strip_prefix('/') {
+            let (key, value) = qualifier.split_once('=').unwrap_or((qualifier, ""));
+            feature
+                .qualifiers
+                .push((key.to_string(), value.to_string()));
+        } else if let Some((key, value)) = feature.qualifiers.last_mut() {
+            // Translations are wrapped without spaces, free text with them
+            if key != "translation" {
+                value.push(' ');
+            }
+            value.push_str(content);
+        } else {
+            // Wrapped location
+            feature.location.push_str(content);
+        }
+    }
+    features.extend(current.take());
+
+    for feature in features.iter_mut() {
+        for (_, value) in feature.qualifiers.iter_mut() {
+            *value = value.trim_matches('"').to_string();
+        }
+    }
+
+    Ok(features)
+}
+
+/// Collect the sequences of all A domains, named after their locus tag and index in the gene
+pub fn amp_binding_records(features: &[GenbankFeature]) -> Result<Vec<FastaRecord>, NrpsError> {
+    let mut records = Vec::new();
+    let mut domain_counts: HashMap<String, usize> = HashMap::new();
+
+    for feature in features.iter().filter(|f| f.is_amp_binding()) {
+        let locus = ["locus_tag", "gene", "protein_id"]
+            .iter()
+            .find_map(|key| feature.qualifier(key))
+            .unwrap_or("unknown");
+
+        let Some(translation) = feature.qualifier("translation") else {
+            return Err(NrpsError::GenbankError(format!(
+                "AMP-binding domain at {} in {locus} has no translation",
+                feature.location
+            )));
+        };
+
+        let count = domain_counts.entry(locus.to_string()).or_insert(0);
+        *count += 1;
+        records.push(FastaRecord {
+            id: format!("{locus}_A{count}"),
+            sequence: translation
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(|c| c.to_ascii_uppercase())
+                .collect(),
+        });
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENBANK: &str = "\
+LOCUS       test                     120 bp    DNA     linear   UNK 01-JAN-1980
+FEATURES             Location/Qualifiers
+     CDS             1..120
+                     /locus_tag=\"nrpsA\"
+                     /product=\"non-ribosomal peptide
+                     synthetase\"
+                     /translation=\"MKVLAGHTWY
+                     PRES\"
+     aSDomain        complement(join(1..30,
+                     40..60))
+                     /aSDomain=\"AMP-binding\"
+                     /locus_tag=\"nrpsA\"
+                     /translation=\"MKVL
+                     AGHT\"
+     aSDomain        61..90
+                     /aSDomain=\"PCP\"
+                     /locus_tag=\"nrpsA\"
+                     /translation=\"WYPR\"
+     aSDomain        91..120
+                     /aSDomain=\"AMP-binding\"
+                     /locus_tag=\"nrpsA\"
+                     /translation=\"wypres\"
+ORIGIN
+        1 atgaaagtgc
+//
+";
+
+    #[test]
+    fn test_parse_genbank_features() {
+        let features = parse_genbank_features(GENBANK.as_bytes()).unwrap();
+        assert_eq!(features.len(), 4);
+        assert_eq!(features[0].kind, "CDS");
+        assert_eq!(
+            features[0].qualifier("product"),
+            Some("non-ribosomal peptide synthetase")
+        );
+        assert_eq!(features[0].qualifier("translation"), Some("MKVLAGHTWYPRES"));
+        assert_eq!(features[1].location, "complement(join(1..30,40..60))");
+        assert_eq!(features[2].qualifier("aSDomain"), Some("PCP"));
+    }
+
+    #[test]
+    fn test_amp_binding_records() {
+        let features = parse_genbank_features(GENBANK.as_bytes()).unwrap();
+        let records = amp_binding_records(&features).unwrap();
+        assert_eq!(
+            records,
+            Vec::from([
+                FastaRecord {
+                    id: "nrpsA_A1".to_string(),
+                    sequence: "MKVLAGHT".to_string(),
+                },
+                FastaRecord {
+                    id: "nrpsA_A2".to_string(),
+                    sequence: "WYPRES".to_string(),
+                },
+            ])
+        );
+
+        let missing = GENBANK.replace("                     /translation=\"wypres\"\n", "");
+        let features = parse_genbank_features(missing.as_bytes()).unwrap();
+        assert!(amp_binding_records(&features).is_err());
+    }
+}
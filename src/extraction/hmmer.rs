@@ -0,0 +1,184 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Signature extraction by aligning the query and the GrsA reference to the AMP-binding
+//! profile HMM with `hmmalign`, then reading the signature positions off the shared columns.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::errors::NrpsError;
+use crate::predictors::predictions::ADomain;
+
+use super::fasta::{parse_fasta, FastaRecord};
+use super::A34_POSITIONS;
+
+pub const HMMALIGN: &str = "hmmalign";
+
+/// Check if `hmmalign` can be run
+pub fn hmmalign_available() -> bool {
+    Command::new(HMMALIGN)
+        .arg("-h")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Find the profile HMM, falling back to the bundled one if `profile` doesn't exist
+pub fn resolve_profile(profile: &Path) -> Result<Option<PathBuf>, NrpsError> {
+    if profile.exists() {
+        return Ok(Some(profile.to_path_buf()));
+    }
+
+    #[cfg(feature = "bundled-models")]
+    if let Some(data) = crate::bundled::BUNDLED_HMM_PROFILE {
+        let path =
+            std::env::temp_dir().join(format!("nrps-rs-{}-AMP-binding.hmm", std::process::id()));
+        std::fs::write(&path, data)?;
+        return Ok(Some(path));
+    }
+
+    Ok(None)
+}
+
+/// Run `hmmalign` on the reference and all records, returning the aligned sequences in input order
+pub fn hmmalign(
+    profile: &Path,
+    reference: &str,
+    records: &[FastaRecord],
+) -> Result<Vec<FastaRecord>, NrpsError> {
+    let mut input = format!(">reference\n{reference}\n");
+    for (i, record) in records.iter().enumerate() {
+        // Use the index as name, hmmalign doesn't like duplicate names
+        input.push_str(&format!(">q{i}\n{}\n", record.sequence));
+    }
+
+    let mut child = Command::new(HMMALIGN)
+        .args(["--outformat", "afa"])
+        .arg(profile)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| NrpsError::ExtractionError(format!("Failed to run {HMMALIGN}: {e}")))?;
+
+    // Write from a separate thread so a full stdout pipe can't deadlock us
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer
+        .join()
+        .map_err(|_| NrpsError::ExtractionError(format!("Failed to write to {HMMALIGN}")))??;
+
+    if !output.status.success() {
+        return Err(NrpsError::ExtractionError(format!(
+            "{HMMALIGN} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let aligned = parse_fasta(output.stdout.as_slice())?;
+    if aligned.len() != records.len() + 1 {
+        return Err(NrpsError::ExtractionError(format!(
+            "Expected {} aligned sequences from {HMMALIGN}, got {}",
+            records.len() + 1,
+            aligned.len()
+        )));
+    }
+    Ok(aligned)
+}
+
+/// Read the residues at the 1-based reference `positions` from an aligned query
+pub fn signature_from_msa(
+    aligned_query: &str,
+    aligned_reference: &str,
+    positions: &[usize],
+) -> Result<String, NrpsError> {
+    if aligned_query.len() != aligned_reference.len() {
+        return Err(NrpsError::ExtractionError(format!(
+            "Aligned lengths differ: {} vs. {}",
+            aligned_query.len(),
+            aligned_reference.len()
+        )));
+    }
+
+    let mut residues = vec!['-'; positions.len()];
+    let mut found = vec![false; positions.len()];
+    let mut ref_pos = 0;
+    for (r, q) in aligned_reference.chars().zip(aligned_query.chars()) {
+        if is_gap(r) {
+            continue;
+        }
+        ref_pos += 1;
+        for (i, pos) in positions.iter().enumerate() {
+            if *pos == ref_pos {
+                found[i] = true;
+                if !is_gap(q) {
+                    residues[i] = q;
+                }
+            }
+        }
+    }
+
+    if let Some(i) = found.iter().position(|f| !f) {
+        return Err(NrpsError::ExtractionError(format!(
+            "Reference position {} outside of reference sequence",
+            positions[i]
+        )));
+    }
+    if residues.iter().all(|c| *c == '-') {
+        return Err(NrpsError::ExtractionError(format!(
+            "No signature positions covered by `{}`",
+            aligned_query.replace(['-', '.'], "")
+        )));
+    }
+
+    Ok(residues.into_iter().collect())
+}
+
+fn is_gap(c: char) -> bool {
+    c == '-' || c == '.'
+}
+
+pub fn domains_from_records(
+    records: &[FastaRecord],
+    reference: &str,
+    profile: &Path,
+) -> Result<Vec<ADomain>, NrpsError> {
+    if records.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let aligned = hmmalign(profile, reference, records)?;
+    let aligned_reference = &aligned[0].sequence;
+
+    let mut domains = Vec::with_capacity(records.len());
+    for (record, aligned_query) in records.iter().zip(aligned[1..].iter()) {
+        let aa34 = signature_from_msa(&aligned_query.sequence, aligned_reference, &A34_POSITIONS)?;
+        domains.push(ADomain::new(record.id.to_string(), aa34));
+    }
+    Ok(domains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_from_msa() {
+        // Reference positions 1..8, with an insert column after position 2
+        let reference = "MK.VLAGHT";
+        let query = "-KWVL-GHT";
+        assert_eq!(
+            signature_from_msa(query, reference, &[1, 2, 3, 5, 8]).unwrap(),
+            "-KV-T"
+        );
+
+        assert!(signature_from_msa(query, reference, &[9]).is_err());
+        assert!(signature_from_msa("---------", reference, &[1, 2]).is_err());
+        assert!(signature_from_msa("MK", reference, &[1, 2]).is_err());
+    }
+}
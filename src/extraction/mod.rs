@@ -0,0 +1,213 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+pub mod alignment;
+pub mod fasta;
+pub mod genbank;
+pub mod hmmer;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, ExtractionMethod};
+use crate::errors::NrpsError;
+use crate::predictors::predictions::ADomain;
+use alignment::align;
+use fasta::{parse_fasta, FastaRecord};
+use genbank::{amp_binding_records, parse_genbank_features};
+
+/// The 8 Å signature positions in GrsA (PheA) numbering, 1-based
+pub const A34_POSITIONS: [usize; 34] = [
+    210, 213, 214, 230, 234, 235, 236, 237, 238, 239, 240, 243, 278, 279, 299, 300, 301, 302, 303,
+    320, 321, 322, 323, 324, 325, 326, 327, 328, 329, 330, 331, 332, 333, 334,
+];
+
+/// Extract the aa34 signature of `sequence` by aligning it to the `reference` sequence
+pub fn extract_signature(
+    sequence: &str,
+    reference: &str,
+    positions: &[usize],
+) -> Result<String, NrpsError> {
+    if let Some(pos) = positions.iter().find(|p| **p == 0 || **p > reference.len()) {
+        return Err(NrpsError::ExtractionError(format!(
+            "Reference position {pos} outside of reference sequence"
+        )));
+    }
+
+    let query = sequence.as_bytes();
+    let mapping = align(query, reference.as_bytes());
+
+    let mut signature = String::with_capacity(positions.len());
+    let mut aligned = 0;
+    for pos in positions.iter() {
+        match mapping[pos - 1] {
+            Some(idx) => {
+                signature.push(query[idx] as char);
+                aligned += 1;
+            }
+            None => signature.push('-'),
+        }
+    }
+
+    if aligned == 0 {
+        return Err(NrpsError::ExtractionError(format!(
+            "No signature positions covered by `{sequence}`"
+        )));
+    }
+
+    Ok(signature)
+}
+
+pub fn load_reference(path: &Path) -> Result<String, NrpsError> {
+    if !path.exists() {
+        return Err(NrpsError::ExtractionError(format!(
+            "Reference file '{}' doesn't exist",
+            path.display()
+        )));
+    }
+    let reader = BufReader::new(File::open(path)?);
+    match parse_fasta(reader)?.into_iter().next() {
+        Some(record) => Ok(record.sequence),
+        None => Err(NrpsError::ExtractionError(format!(
+            "No sequence in reference file '{}'",
+            path.display()
+        ))),
+    }
+}
+
+pub fn domains_from_records(
+    records: &[FastaRecord],
+    reference: &str,
+) -> Result<Vec<ADomain>, NrpsError> {
+    let mut domains = Vec::with_capacity(records.len());
+    for record in records.iter() {
+        let aa34 = extract_signature(&record.sequence, reference, &A34_POSITIONS)?;
+        domains.push(ADomain::new(record.id.to_string(), aa34));
+    }
+    Ok(domains)
+}
+
+/// Extract the signatures of `records` with the configured extraction method
+pub fn extract_domains(
+    config: &Config,
+    records: &[FastaRecord],
+) -> Result<Vec<ADomain>, NrpsError> {
+    let reference = load_reference(&config.extraction_reference())?;
+
+    let profile = match config.extraction_method {
+        ExtractionMethod::Alignment => None,
+        ExtractionMethod::Hmmer => match hmmer::resolve_profile(&config.hmm_profile())? {
+            Some(profile) => Some(profile),
+            None => {
+                return Err(NrpsError::ExtractionError(format!(
+                    "Profile HMM '{}' doesn't exist",
+                    config.hmm_profile().display()
+                )))
+            }
+        },
+        ExtractionMethod::Auto => {
+            if hmmer::hmmalign_available() {
+                hmmer::resolve_profile(&config.hmm_profile())?
+            } else {
+                None
+            }
+        }
+    };
+
+    match profile {
+        Some(profile) => {
+            log::debug!("Extracting signatures with {}", hmmer::HMMALIGN);
+            hmmer::domains_from_records(records, &reference, &profile)
+        }
+        None => {
+            log::debug!("Extracting signatures with the built-in alignment");
+            domains_from_records(records, &reference)
+        }
+    }
+}
+
+pub fn parse_fasta_domains(
+    config: &Config,
+    fasta_file: PathBuf,
+) -> Result<Vec<ADomain>, NrpsError> {
+    let records = parse_fasta(open_input(&fasta_file)?)?;
+    extract_domains(config, &records)
+}
+
+/// Extract the signatures of the AMP-binding domains annotated in a GenBank file
+pub fn parse_genbank_domains(
+    config: &Config,
+    genbank_file: PathBuf,
+) -> Result<Vec<ADomain>, NrpsError> {
+    let features = parse_genbank_features(open_input(&genbank_file)?)?;
+    extract_domains(config, &amp_binding_records(&features)?)
+}
+
+fn open_input(path: &Path) -> Result<Box<dyn BufRead>, NrpsError> {
+    if path.as_os_str() == "-" {
+        return Ok(Box::new(BufReader::new(io::stdin())));
+    }
+    if !path.exists() {
+        let err = format!("'{}' doesn't exist", path.display());
+        return Err(NrpsError::SignatureFileError(err));
+    }
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+pub fn parse_fasta_domains_from_reader<R>(
+    reader: R,
+    reference: &str,
+) -> Result<Vec<ADomain>, NrpsError>
+where
+    R: BufRead,
+{
+    domains_from_records(&parse_fasta(reader)?, reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deterministic pseudo-random protein sequence
+    fn reference(len: usize) -> String {
+        let alphabet = b"ACDEFGHIKLMNPQRSTVWY";
+        let mut state: u32 = 42;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                alphabet[((state >> 16) % 20) as usize] as char
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_signature() {
+        let reference = reference(400);
+        let expected: String = A34_POSITIONS
+            .iter()
+            .map(|p| reference.as_bytes()[p - 1] as char)
+            .collect();
+
+        // Domain fragment with an insertion upstream of the signature positions
+        let query = format!("{}GGGG{}", &reference[150..200], &reference[200..380]);
+        let got = extract_signature(&query, &reference, &A34_POSITIONS).unwrap();
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn test_extract_signature_errors() {
+        let reference = reference(100);
+        assert!(extract_signature(&reference, &reference, &A34_POSITIONS).is_err());
+    }
+
+    #[test]
+    fn test_parse_fasta_domains_from_reader() {
+        let reference = reference(400);
+        let data = format!(">dom1\n{}\n", &reference[100..]);
+        let got = parse_fasta_domains_from_reader(data.as_bytes(), &reference).unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "dom1");
+        assert_eq!(got[0].aa34.len(), 34);
+    }
+}
@@ -0,0 +1,202 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! C API, see `include/nrps_rs.h` for the generated header.
+
+use std::any::Any;
+use std::ffi::{c_char, CStr, CString};
+use std::fs::File;
+use std::panic::{self, UnwindSafe};
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::output::{format_results, OutputFormat};
+use crate::run_on_strings;
+
+/// Result of a prediction run
+///
+/// On success, `data` holds the predictions as JSON, otherwise it holds the error message.
+/// Free with `nrps_result_free`.
+#[repr(C)]
+pub struct NrpsResult {
+    pub success: bool,
+    pub data: *mut c_char,
+}
+
+impl NrpsResult {
+    fn new(success: bool, data: String) -> Self {
+        // Interior NUL bytes can't be represented, so drop them
+        let data = CString::new(data.replace('\0', "")).unwrap_or_default();
+        NrpsResult {
+            success,
+            data: data.into_raw(),
+        }
+    }
+}
+
+unsafe fn predict_internal(
+    signatures: *const *const c_char,
+    n: usize,
+    config_path: *const c_char,
+) -> Result<String, NrpsError> {
+    let mut config = if config_path.is_null() {
+        Config::new()
+    } else {
+        let path = CStr::from_ptr(config_path).to_string_lossy().to_string();
+        Config::from_toml(File::open(path)?)?
+    };
+    config.output_format = OutputFormat::Json;
+    config.discover_custom_categories()?;
+
+    if signatures.is_null() && n > 0 {
+        return Err(NrpsError::SignatureError("NULL signatures".to_string()));
+    }
+
+    let mut lines = Vec::with_capacity(n);
+    for i in 0..n {
+        let line = *signatures.add(i);
+        if line.is_null() {
+            return Err(NrpsError::SignatureError(format!("NULL signature at {i}")));
+        }
+        lines.push(CStr::from_ptr(line).to_string_lossy().to_string());
+    }
+
+    let domains = run_on_strings(&config, lines)?;
+    format_results(&config, &domains)
+}
+
+/// Unwinding into C is undefined behaviour, so panics in `f` become failed results
+fn catch_panics<F>(f: F) -> NrpsResult
+where
+    F: FnOnce() -> Result<String, NrpsError> + UnwindSafe,
+{
+    match panic::catch_unwind(f) {
+        Ok(Ok(json)) => NrpsResult::new(true, json),
+        Ok(Err(err)) => NrpsResult::new(false, err.to_string()),
+        Err(payload) => NrpsResult::new(
+            false,
+            format!("Internal error: {}", panic_message(&payload)),
+        ),
+    }
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(msg) => msg,
+        None => payload
+            .downcast_ref::<String>()
+            .map_or("unknown panic", String::as_str),
+    }
+}
+
+/// Run predictions on `n` signature lines (`signature<TAB>name`)
+///
+/// `config_path` points to a TOML config file, or is NULL to use the default config. Internal
+/// errors are reported as failed results too.
+///
+/// # Safety
+///
+/// `signatures` must point to `n` valid NUL-terminated strings, `config_path` must be NULL
+/// or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn nrps_predict(
+    signatures: *const *const c_char,
+    n: usize,
+    config_path: *const c_char,
+) -> NrpsResult {
+    catch_panics(|| predict_internal(signatures, n, config_path))
+}
+
+/// Free the data of a result returned by `nrps_predict`
+///
+/// # Safety
+///
+/// `result` must have been returned by `nrps_predict` and not been freed before.
+#[no_mangle]
+pub unsafe extern "C" fn nrps_result_free(result: NrpsResult) {
+    if !result.data.is_null() {
+        drop(CString::from_raw(result.data));
+    }
+}
+
+/// Version of the nrps-rs library, the returned string is static and must not be freed
+#[no_mangle]
+pub extern "C" fn nrps_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+    use std::ptr;
+
+    fn result_data(result: &NrpsResult) -> String {
+        unsafe { CStr::from_ptr(result.data).to_string_lossy().to_string() }
+    }
+
+    #[test]
+    fn test_nrps_predict() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let config_file = model_dir.join("nrps.toml");
+        fs::write(
+            &config_file,
+            format!(
+                "model_dir = '{}'\nskip_stachelhaus = true\n",
+                model_dir.display()
+            ),
+        )
+        .unwrap();
+
+        let line = CString::new("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA").unwrap();
+        let lines = [line.as_ptr()];
+        let config_path = CString::new(config_file.display().to_string()).unwrap();
+
+        let result = unsafe { nrps_predict(lines.as_ptr(), 1, config_path.as_ptr()) };
+        assert!(result.success);
+        let json: serde_json::Value = serde_json::from_str(&result_data(&result)).unwrap();
+        assert_eq!(json[0]["name"], "bpsA");
+        unsafe { nrps_result_free(result) };
+
+        let invalid = CString::new("INVALID\tbpsA").unwrap();
+        let lines = [invalid.as_ptr()];
+        let result = unsafe { nrps_predict(lines.as_ptr(), 1, config_path.as_ptr()) };
+        assert!(!result.success);
+        assert!(result_data(&result).contains("INVALID"));
+        unsafe { nrps_result_free(result) };
+
+        let result = unsafe { nrps_predict(ptr::null(), 1, config_path.as_ptr()) };
+        assert!(!result.success);
+        unsafe { nrps_result_free(result) };
+    }
+
+    #[test]
+    fn test_catch_panics() {
+        let result = catch_panics(|| Ok("[]".to_string()));
+        assert!(result.success);
+        unsafe { nrps_result_free(result) };
+
+        let result = catch_panics(|| panic!("model {} exploded", 1));
+        assert!(!result.success);
+        assert_eq!(result_data(&result), "Internal error: model 1 exploded");
+        unsafe { nrps_result_free(result) };
+    }
+
+    #[test]
+    fn test_nrps_version() {
+        let version = unsafe { CStr::from_ptr(nrps_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_header_up_to_date() {
+        let generated = concat!(env!("OUT_DIR"), "/nrps_rs.h");
+        assert!(
+            include_str!(concat!(env!("OUT_DIR"), "/nrps_rs.h"))
+                == include_str!("../include/nrps_rs.h"),
+            "include/nrps_rs.h is out of date, copy {generated} over it"
+        );
+    }
+}
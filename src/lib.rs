@@ -1,49 +1,297 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod benchmark;
+#[cfg(feature = "bundled-models")]
+pub mod bundled;
+pub mod cluster;
+pub mod compression;
 pub mod config;
 pub mod encodings;
 pub mod errors;
+pub mod explain;
+pub mod extraction;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod metadata;
+pub mod output;
+pub mod plan;
 pub mod predictors;
+#[cfg(feature = "pyo3")]
+pub mod python;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod svm;
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-use std::fs::File;
+use std::collections::HashMap;
 use std::io::{self, BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use config::{InputFormat, ResidueCheck};
 use errors::NrpsError;
+use extraction::{parse_fasta_domains, parse_genbank_domains};
+pub use output::{format_results, write_results};
 use predictors::predictions::ADomain;
-use predictors::stachelhaus::predict_stachelhaus;
-use predictors::{load_models, Predictor};
+use predictors::stachelhaus::extract_aa10;
+use predictors::{load_backends, OptionalBackends, PredictionBackend, SvmPredictor};
 
 pub fn run_on_file(
     config: &config::Config,
     signature_file: PathBuf,
 ) -> Result<Vec<ADomain>, NrpsError> {
-    let mut domains = parse_domains(signature_file)?;
+    let mut domains = read_input(config, signature_file)?;
     run(config, &mut domains)?;
     Ok(domains)
 }
 
+/// Parse and check the domains in `signature_file`, in the configured input format
+pub fn read_input(
+    config: &config::Config,
+    signature_file: PathBuf,
+) -> Result<Vec<ADomain>, NrpsError> {
+    let mut domains = match config.input_format {
+        InputFormat::Signatures | InputFormat::Aa10 => {
+            let (domains, warnings) =
+                read_domains(signature_file, config.skip_invalid, line_parser(config))?;
+            for warning in warnings.iter() {
+                log::warn!("Skipping invalid signature: {warning}");
+            }
+            if config.input_format == InputFormat::Aa10 {
+                log::info!("Only running Stachelhaus lookups, the SVM models need aa34 signatures");
+            }
+            domains
+        }
+        InputFormat::Fasta => parse_fasta_domains(config, signature_file)?,
+        InputFormat::Genbank => parse_genbank_domains(config, signature_file)?,
+    };
+    if config.residue_check != ResidueCheck::Off {
+        domains = check_domains(domains, config.residue_check, config.skip_invalid)?;
+    }
+    Ok(domains)
+}
+
+/// Check the residues of all `domains`, with `skip_invalid` dropping invalid ones with a warning
+fn check_domains(
+    domains: Vec<ADomain>,
+    check: ResidueCheck,
+    skip_invalid: bool,
+) -> Result<Vec<ADomain>, NrpsError> {
+    let mut checked = Vec::with_capacity(domains.len());
+    for mut domain in domains {
+        match check_domain(&mut domain, check) {
+            Ok(()) => checked.push(domain),
+            Err(err) if skip_invalid => log::warn!("Skipping {}: {err}", domain.name),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(checked)
+}
+
+/// Check the signature of `domain`, in lenient mode unknown residues are replaced by `X`
+pub fn check_domain(domain: &mut ADomain, check: ResidueCheck) -> Result<(), NrpsError> {
+    if domain.aa34.is_empty() {
+        domain.aa10 = check_residues(&domain.aa10, check)?;
+    } else {
+        domain.aa34 = check_residues(&domain.aa34, check)?;
+        domain.aa10 = extract_aa10(&domain.aa34)?;
+    }
+    Ok(())
+}
+
+/// Check that `signature` only has amino acids, `X` and `-` gaps, returns the checked signature
+///
+/// Signatures made up of gaps only carry no information and are rejected unless `check` is off.
+pub fn check_residues(signature: &str, check: ResidueCheck) -> Result<String, NrpsError> {
+    const RESIDUES: &str = "ACDEFGHIKLMNPQRSTVWYX-";
+    if check != ResidueCheck::Off && signature.chars().all(|c| c == '-') {
+        return Err(NrpsError::SignatureError(format!(
+            "{signature} only consists of gaps"
+        )));
+    }
+    signature
+        .chars()
+        .enumerate()
+        .map(|(i, c)| match check {
+            _ if RESIDUES.contains(c) => Ok(c),
+            ResidueCheck::Off => Ok(c),
+            ResidueCheck::Lenient => Ok('X'),
+            ResidueCheck::Strict => Err(NrpsError::InvalidResidue {
+                position: i + 1,
+                char: c,
+            }),
+        })
+        .collect()
+}
+
 pub fn run(config: &config::Config, domains: &mut [ADomain]) -> Result<(), NrpsError> {
-    if !config.skip_stachelhaus {
-        predict_stachelhaus(config, domains)?;
+    run_backends(&load_backends(config)?, domains)
+}
+
+/// Models and Stachelhaus signatures loaded once, to predict any number of batches of domains
+///
+/// Unlike `run()`, which loads everything on every call, this is meant to be kept around, e.g.
+/// by servers or in notebooks.
+#[derive(Debug)]
+pub struct NrpsPredictor {
+    config: config::Config,
+    backends: OptionalBackends,
+    svm: SvmPredictor,
+}
+
+impl NrpsPredictor {
+    /// Load all models and Stachelhaus signatures enabled in `config`
+    pub fn new(config: &config::Config) -> Result<Self, NrpsError> {
+        let backends = OptionalBackends::from_config(config)?;
+        let svm = SvmPredictor::from_config(config)?;
+        svm.store.load_all()?;
+
+        Ok(NrpsPredictor {
+            config: config.clone(),
+            backends,
+            svm,
+        })
+    }
+
+    pub fn config(&self) -> &config::Config {
+        &self.config
+    }
+
+    pub fn model_count(&self) -> usize {
+        self.svm.store.model_count()
+    }
+
+    /// Reload the models and Stachelhaus signatures, e.g. after the model files were updated
+    ///
+    /// On errors the predictor keeps using the previously loaded data.
+    pub fn reload(&mut self) -> Result<usize, NrpsError> {
+        let backends = OptionalBackends::from_config(&self.config)?;
+        let count = self.svm.store.reload()?;
+        self.backends = backends;
+        Ok(count)
+    }
+
+    /// Add the Stachelhaus and SVM predictions to `domains`
+    pub fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+        let mut backends = self.backends.backends();
+        backends.push(&self.svm);
+        predict_deduplicated(&backends, domains)
+    }
+}
+
+/// Run `backends` on `domains` in order
+///
+/// Each distinct signature is only predicted once, domains sharing a signature get copies of
+/// the same predictions.
+pub fn run_backends(
+    backends: &[Box<dyn PredictionBackend>],
+    domains: &mut [ADomain],
+) -> Result<(), NrpsError> {
+    let backends: Vec<&dyn PredictionBackend> = backends.iter().map(|b| b.as_ref()).collect();
+    predict_deduplicated(&backends, domains)
+}
+
+fn predict_deduplicated(
+    backends: &[&dyn PredictionBackend],
+    domains: &mut [ADomain],
+) -> Result<(), NrpsError> {
+    let mut unique_index: HashMap<&str, usize> = HashMap::with_capacity(domains.len());
+    let mut unique: Vec<ADomain> = Vec::new();
+    let mut mapping: Vec<usize> = Vec::with_capacity(domains.len());
+    for domain in domains.iter() {
+        let index = *unique_index.entry(domain.signature()).or_insert_with(|| {
+            unique.push(match domain.aa34.is_empty() {
+                true => ADomain::from_aa10(domain.name.to_string(), domain.aa10.to_string()),
+                false => ADomain::new(domain.name.to_string(), domain.aa34.to_string()),
+            });
+            unique.len() - 1
+        });
+        mapping.push(index);
     }
 
-    let models = load_models(config)?;
-    let predictor = Predictor { models };
-    predictor.predict(domains)?;
+    if unique.len() == domains.len() {
+        for backend in backends.iter() {
+            backend.predict(domains)?;
+        }
+        return Ok(());
+    }
+
+    log::debug!(
+        "Predicting {} distinct signatures for {} domains",
+        unique.len(),
+        domains.len()
+    );
+    for backend in backends.iter() {
+        backend.predict(&mut unique)?;
+    }
+    for (domain, index) in domains.iter_mut().zip(mapping) {
+        domain.add_predictions_from(&unique[index]);
+    }
     Ok(())
 }
 
+/// Run predictions on signature lines from `reader`, calling `callback` for each predicted domain
+///
+/// Models and Stachelhaus signatures are loaded once up front, domains are parsed and predicted
+/// one line at a time, so memory use doesn't grow with the input size and results are available
+/// as soon as their line was read. An error returned by `callback` stops the run.
+pub fn run_streaming<R, F>(
+    config: &config::Config,
+    reader: R,
+    mut callback: F,
+) -> Result<usize, NrpsError>
+where
+    R: BufRead,
+    F: FnMut(ADomain) -> Result<(), NrpsError>,
+{
+    let backends = load_backends(config)?;
+
+    let parse = line_parser(config);
+    let mut count = 0;
+    for (i, line_res) in reader.lines().enumerate() {
+        let line = line_res?.trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+
+        let parsed = parse(line).and_then(|mut domain| {
+            if config.residue_check != ResidueCheck::Off {
+                check_domain(&mut domain, config.residue_check)?;
+            }
+            Ok(domain)
+        });
+        let mut domain = match parsed {
+            Ok(domain) => domain,
+            Err(err) if config.skip_invalid => {
+                log::warn!("Skipping invalid signature: line {}: {err}", i + 1);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        run_backends(&backends, std::slice::from_mut(&mut domain))?;
+        callback(domain)?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
 pub fn run_on_strings(
     config: &config::Config,
     lines: Vec<String>,
 ) -> Result<Vec<ADomain>, NrpsError> {
     let mut domains = Vec::with_capacity(lines.len());
 
+    let parse = line_parser(config);
     for line in lines.iter() {
-        domains.push(parse_domain(line.to_string())?);
+        domains.push(parse(line.to_string())?);
+    }
+    if config.residue_check != ResidueCheck::Off {
+        domains = check_domains(domains, config.residue_check, false)?;
     }
 
     run(config, &mut domains)?;
@@ -52,103 +300,128 @@ pub fn run_on_strings(
 }
 
 pub fn print_results(config: &config::Config, domains: &[ADomain]) -> Result<(), NrpsError> {
-    if config.count < 1 {
-        return Err(NrpsError::CountError(config.count));
-    }
-
-    let categories = config.categories();
+    write_results(config, domains, io::stdout().lock())
+}
 
-    let cat_strings: Vec<String> = categories.iter().map(|c| format!("{c:?}")).collect();
+/// Parse the domains in `signature_file`
+///
+/// With `skip_invalid`, malformed lines are returned as warnings instead of aborting the parse.
+pub fn parse_domains(
+    signature_file: PathBuf,
+    skip_invalid: bool,
+) -> Result<(Vec<ADomain>, Vec<String>), NrpsError> {
+    read_domains(signature_file, skip_invalid, parse_domain)
+}
 
-    let mut headers: Vec<String> = Vec::with_capacity(3);
+/// Parse the domains in `signature_file`, with aa10 Stachelhaus codes instead of aa34 signatures
+pub fn parse_aa10_domains(
+    signature_file: PathBuf,
+    skip_invalid: bool,
+) -> Result<(Vec<ADomain>, Vec<String>), NrpsError> {
+    read_domains(signature_file, skip_invalid, parse_aa10_domain)
+}
 
-    headers.push("Name\t8A signature\tStachelhaus signature".to_string());
-    if !config.skip_stachelhaus && !config.skip_new_stachelhaus_output {
-        headers.push(
-            [
-                "Full Stachelhaus match",
-                "AA10 score",
-                "AA10 signature matched",
-                "AA34 score",
-            ]
-            .join("\t")
-            .to_string(),
-        );
-    }
-    headers.push(cat_strings.join("\t"));
-    println!("{}", headers.join("\t"));
+type LineParser = fn(String) -> Result<ADomain, NrpsError>;
 
-    for domain in domains.iter() {
-        let mut best_predictions: Vec<String> = Vec::new();
-        for cat in categories.iter() {
-            let mut best = domain
-                .get_best_n(cat, config.count)
-                .iter()
-                .fold("".to_string(), |acc, new| {
-                    format!("{acc}|{}({:.2})", new.name, new.score)
-                })
-                .trim_matches('|')
-                .to_string();
-            if best.is_empty() {
-                best = "N/A".to_string();
-            }
-            best_predictions.push(best)
-        }
-        let mut line: Vec<String> = Vec::with_capacity(5);
-        line.push(domain.name.to_string());
-        line.push(domain.aa34.to_string());
-        line.push(domain.aa10.to_string());
-        if !config.skip_stachelhaus && !config.skip_new_stachelhaus_output {
-            line.push(domain.stach_predictions.to_table());
-        }
-        line.push(best_predictions.join("\t"));
-        println!("{}", line.join("\t"));
+/// The signature line parser for the configured input format and leniency
+fn line_parser(config: &config::Config) -> LineParser {
+    match (config.input_format == InputFormat::Aa10, config.lenient) {
+        (false, false) => parse_domain,
+        (false, true) => |line| parse_domain(normalize_signature(line)),
+        (true, false) => parse_aa10_domain,
+        (true, true) => |line| parse_aa10_domain(normalize_signature(line)),
     }
-
-    Ok(())
 }
 
-pub fn parse_domains(signature_file: PathBuf) -> Result<Vec<ADomain>, NrpsError> {
-    if signature_file == PathBuf::from("-") {
-        let reader = BufReader::new(io::stdin());
-        return parse_domains_from_reader(reader);
+/// Uppercase the signature column of `line` and strip any whitespace from it
+pub fn normalize_signature(line: String) -> String {
+    let (signature, rest) = match line.split_once('\t') {
+        Some((signature, rest)) => (signature, Some(rest)),
+        None => (line.as_str(), None),
+    };
+    let mut normalized: String = signature
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if let Some(rest) = rest {
+        normalized.push('\t');
+        normalized.push_str(rest);
     }
+    normalized
+}
 
-    if !signature_file.exists() {
-        let err = format!("'{}' doesn't exist", signature_file.display());
-        return Err(NrpsError::SignatureFileError(err));
+fn read_domains(
+    signature_file: PathBuf,
+    skip_invalid: bool,
+    parse: LineParser,
+) -> Result<(Vec<ADomain>, Vec<String>), NrpsError> {
+    if signature_file.as_os_str() == "-" {
+        let reader = compression::decompress(BufReader::new(io::stdin()))?;
+        return parse_lines(reader, &signature_file, skip_invalid, parse);
     }
 
-    let handle = File::open(signature_file)?;
-    let reader = BufReader::new(handle);
-
-    parse_domains_from_reader(reader)
+    let reader = compression::open(&signature_file).map_err(|source| NrpsError::FileError {
+        path: signature_file.clone(),
+        source,
+    })?;
+    parse_lines(reader, &signature_file, skip_invalid, parse)
 }
 
-fn parse_domains_from_reader<R>(reader: R) -> Result<Vec<ADomain>, NrpsError>
+/// Parse the signature lines from `reader`, errors are reported with `source` and the line number
+fn parse_lines<R>(
+    reader: R,
+    source: &Path,
+    skip_invalid: bool,
+    parse: LineParser,
+) -> Result<(Vec<ADomain>, Vec<String>), NrpsError>
 where
     R: BufRead,
 {
     let mut domains = Vec::new();
-
-    for line_res in reader.lines() {
-        let line = line_res?.trim().to_string();
+    let mut warnings = Vec::new();
+
+    for (i, line_res) in reader.lines().enumerate() {
+        let in_context = |err: NrpsError| NrpsError::InputParseError {
+            path: source.to_path_buf(),
+            line: i + 1,
+            source: Box::new(err),
+        };
+        let line = line_res.map_err(|err| in_context(err.into()))?;
+        let line = line.trim().to_string();
         if line.is_empty() {
             continue;
         }
 
-        domains.push(parse_domain(line)?);
+        match parse(line) {
+            Ok(domain) => domains.push(domain),
+            Err(err) if skip_invalid => warnings.push(format!("line {}: {err}", i + 1)),
+            Err(err) => return Err(in_context(err)),
+        }
     }
 
-    Ok(domains)
+    Ok((domains, warnings))
 }
 
 pub fn parse_domain(line: String) -> Result<ADomain, NrpsError> {
+    parse_domain_line(line, 34, ADomain::new)
+}
+
+/// Parse a line like `parse_domain()` does, but with an aa10 code instead of the aa34 signature
+pub fn parse_aa10_domain(line: String) -> Result<ADomain, NrpsError> {
+    parse_domain_line(line, 10, ADomain::from_aa10)
+}
+
+fn parse_domain_line(
+    line: String,
+    signature_len: usize,
+    new_domain: fn(String, String) -> ADomain,
+) -> Result<ADomain, NrpsError> {
     let parts: Vec<&str> = line.split('\t').collect();
     if parts.len() < 2 {
         return Err(NrpsError::SignatureError(line));
     }
-    if parts[0].len() != 34 {
+    if !parts[0].is_ascii() || parts[0].len() != signature_len {
         return Err(NrpsError::SignatureError(line));
     }
 
@@ -156,13 +429,112 @@ pub fn parse_domain(line: String) -> Result<ADomain, NrpsError> {
         2 => parts[1].to_string(),
         _ => format!("{}_{}", parts[2], parts[1]),
     };
-    Ok(ADomain::new(name, parts[0].to_string()))
+    let mut domain = new_domain(name, parts[0].to_string());
+
+    // Optional metadata columns: protein ID, gene, module index and coordinates
+    let column = |i: usize| {
+        parts
+            .get(i)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty() && *value != "-")
+    };
+    domain.protein_id = column(3).map(str::to_string);
+    domain.gene = column(4).map(str::to_string);
+    if let Some(index) = column(5) {
+        domain.module_index =
+            Some(index.parse().map_err(|_| {
+                NrpsError::SignatureError(format!("invalid module index in {line}"))
+            })?);
+    }
+    if let Some(coords) = column(6) {
+        domain.coords = Some(
+            coords
+                .parse()
+                .map_err(|err| NrpsError::SignatureError(format!("{err} in {line}")))?,
+        );
+    }
+    Ok(domain)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use encodings::FeatureEncoding;
+    use predictors::predictions::{Coordinates, Prediction, PredictionCategory};
+    use svm::models::{KernelParameters, KernelType, SVMlightModel};
+    use svm::vectors::SupportVectors;
+
+    use proptest::prelude::*;
+
+    #[derive(Debug)]
+    struct ConstantBackend(&'static str);
+
+    impl PredictionBackend for ConstantBackend {
+        fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+            for domain in domains.iter_mut() {
+                domain.add(
+                    PredictionCategory::SingleV3,
+                    Prediction {
+                        name: self.0.to_string(),
+                        score: 1.0,
+                        probability: None,
+                        model: None,
+                    },
+                );
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_run_backends() {
+        let backends: Vec<Box<dyn PredictionBackend>> = Vec::from([
+            Box::new(ConstantBackend("leu")) as _,
+            Box::new(ConstantBackend("val")) as _,
+        ]);
+        let mut domains = [ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        )];
+        run_backends(&backends, &mut domains).unwrap();
+        assert_eq!(domains[0].get_all(&PredictionCategory::SingleV3).len(), 2);
+    }
+
+    #[derive(Debug)]
+    struct CountingBackend(Rc<Cell<usize>>);
+
+    impl PredictionBackend for CountingBackend {
+        fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+            self.0.set(self.0.get() + domains.len());
+            ConstantBackend("leu").predict(domains)
+        }
+    }
+
+    #[test]
+    fn test_run_backends_dedup() {
+        let predicted = Rc::new(Cell::new(0));
+        let backends: Vec<Box<dyn PredictionBackend>> =
+            Vec::from([Box::new(CountingBackend(predicted.clone())) as _]);
+        let mut domains = [
+            ("bpsA_A1", "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW"),
+            ("bpsA_A2", "LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW"),
+            ("bpsB_A1", "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW"),
+        ]
+        .map(|(name, aa34)| ADomain::new(name.to_string(), aa34.to_string()));
+
+        run_backends(&backends, &mut domains).unwrap();
+
+        assert_eq!(predicted.get(), 2);
+        assert_eq!(domains[2].name, "bpsB_A1");
+        for domain in domains.iter() {
+            assert_eq!(domain.get_all(&PredictionCategory::SingleV3).len(), 1);
+        }
+    }
+
     #[test]
     fn test_parse_domains() {
         let two_parts = BufReader::new("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA_A1".as_bytes());
@@ -180,13 +552,296 @@ mod tests {
             "LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW".to_string(),
         )]);
 
-        let got_two = parse_domains_from_reader(two_parts).unwrap();
+        let (got_two, _) = parse_lines(two_parts, Path::new("-"), false, parse_domain).unwrap();
         assert_eq!(expected_two, got_two);
 
-        let got_three = parse_domains_from_reader(three_parts).unwrap();
+        let (got_three, _) = parse_lines(three_parts, Path::new("-"), false, parse_domain).unwrap();
         assert_eq!(expected_three, got_three);
 
-        let got_error = parse_domains_from_reader(too_short);
-        assert!(got_error.is_err());
+        let got_error = parse_lines(too_short, Path::new("sigs.tsv"), false, parse_domain);
+        assert!(matches!(
+            got_error,
+            Err(NrpsError::InputParseError { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_run_streaming() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+
+        let mut config = config::Config::new();
+        config.set_model_dir(model_dir);
+        config.skip_stachelhaus = true;
+
+        let input = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA_A1\n\n\
+                     LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW\tHpg\tCAC48361.1.A1\n";
+        let mut names = Vec::new();
+        let count = run_streaming(&config, input.as_bytes(), |domain| {
+            names.push(domain.name);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(names, ["bpsA_A1", "CAC48361.1.A1_Hpg"]);
+
+        assert!(run_streaming(&config, "INVALID\tfoo".as_bytes(), |_| Ok(())).is_err());
+        config.skip_invalid = true;
+        let input = "INVALID\tfoo\nLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA_A1\n";
+        assert_eq!(
+            run_streaming(&config, input.as_bytes(), |_| Ok(())).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_nrps_predictor() {
+        fn is_send_sync<T: Send + Sync>(_: &T) {}
+
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let single = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        std::fs::create_dir_all(&single).unwrap();
+        let mut handle = std::fs::File::create(single.join("[leu].mdl")).unwrap();
+        SVMlightModel::new(
+            "leu".to_string(),
+            PredictionCategory::SingleV2,
+            SupportVectors::default(),
+            -1.0,
+            FeatureEncoding::Wold,
+            KernelType::Linear,
+            KernelParameters::default(),
+        )
+        .write(&mut handle, 0)
+        .unwrap();
+
+        let mut config = config::Config::new();
+        config.set_model_dir(model_dir.clone());
+        config.skip_stachelhaus = true;
+        let predictor = NrpsPredictor::new(&config).unwrap();
+        is_send_sync(&predictor);
+        assert_eq!(predictor.model_count(), 1);
+
+        for _ in 0..2 {
+            let mut domains = [
+                ADomain::new(
+                    "a".to_string(),
+                    "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+                ),
+                ADomain::new(
+                    "b".to_string(),
+                    "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+                ),
+            ];
+            predictor.predict(&mut domains).unwrap();
+            for domain in domains.iter() {
+                let got = domain.get_all(&PredictionCategory::SingleV2);
+                assert_eq!(got.len(), 1);
+                assert_eq!(got[0].score, 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_domain_metadata() {
+        let got = parse_domain(
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tA1\tbpsA\tCAA76550.1\tbpsA\t1\t120..530"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(got.name, "bpsA_A1");
+        assert_eq!(got.protein_id.as_deref(), Some("CAA76550.1"));
+        assert_eq!(got.gene.as_deref(), Some("bpsA"));
+        assert_eq!(got.module_index, Some(1));
+        assert_eq!(
+            got.coords,
+            Some(Coordinates {
+                start: 120,
+                end: 530
+            })
+        );
+
+        // Empty and `-` columns are left unset
+        let got = parse_domain(
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tA1\tbpsA\t-\t\t\t120-530".to_string(),
+        )
+        .unwrap();
+        assert!(got.protein_id.is_none() && got.gene.is_none() && got.module_index.is_none());
+        assert_eq!(
+            got.coords,
+            Some(Coordinates {
+                start: 120,
+                end: 530
+            })
+        );
+
+        for case in [
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tA1\tbpsA\t-\t-\tfirst",
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tA1\tbpsA\t-\t-\t1\t120",
+        ] {
+            assert!(parse_domain(case.to_string()).is_err(), "{case}");
+        }
+    }
+
+    #[test]
+    fn test_parse_aa10_domain() {
+        let got = parse_aa10_domain("DAFYLGMMCK\tA1\tbpsA\tCAA76550.1".to_string()).unwrap();
+        assert_eq!(got.name, "bpsA_A1");
+        assert_eq!(got.aa10, "DAFYLGMMCK");
+        assert!(got.aa34.is_empty());
+        assert_eq!(got.protein_id.as_deref(), Some("CAA76550.1"));
+
+        for case in [
+            "DAFYLGMMCK",
+            "DAFYLGMMC\tshort",
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\taa34",
+        ] {
+            assert!(parse_aa10_domain(case.to_string()).is_err(), "{case}");
+        }
+    }
+
+    #[test]
+    fn test_predict_aa10_domains() {
+        let mut domains = [
+            ADomain::from_aa10("a".to_string(), "DAFYLGMMCK".to_string()),
+            ADomain::new(
+                "b".to_string(),
+                "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+            ),
+            ADomain::from_aa10("c".to_string(), "DAFYLGMMCK".to_string()),
+        ];
+        let backends: Vec<Box<dyn PredictionBackend>> =
+            Vec::from([Box::new(ConstantBackend("leu")) as Box<dyn PredictionBackend>]);
+        run_backends(&backends, &mut domains).unwrap();
+        // The aa10 code and the aa34 signature it came from don't get merged
+        assert_eq!(domains[0].name, "a");
+        assert!(domains[0].aa34.is_empty() && !domains[1].aa34.is_empty());
+        for domain in domains.iter() {
+            assert_eq!(domain.get_all(&PredictionCategory::SingleV3).len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_normalize_signature() {
+        assert_eq!(
+            normalize_signature("ldasfdaslf emyllTGGDRN MYGPTEATMCATW\tbpsA A1".to_string()),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA A1"
+        );
+        assert_eq!(normalize_signature("dafy lgmmck".to_string()), "DAFYLGMMCK");
+
+        let mut config = config::Config::new();
+        let line = "ldasfdaslfemyllTGGDRNMYGPTEATMC ATW\tbpsA".to_string();
+        assert!(line_parser(&config)(line.clone()).is_err());
+        config.lenient = true;
+        let got = line_parser(&config)(line).unwrap();
+        assert_eq!(got.aa34, "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW");
+    }
+
+    #[test]
+    fn test_check_residues() {
+        let valid = "LDASFDASLFEMYLLTGGDRNMYGPTEATMC-XW";
+        for check in [
+            ResidueCheck::Off,
+            ResidueCheck::Strict,
+            ResidueCheck::Lenient,
+        ] {
+            assert_eq!(check_residues(valid, check).unwrap(), valid);
+        }
+
+        let invalid = "LDASFDASLFEMYLLTGGDRNMYGPTEATMC*JW";
+        assert_eq!(check_residues(invalid, ResidueCheck::Off).unwrap(), invalid);
+        assert_eq!(
+            check_residues(invalid, ResidueCheck::Lenient).unwrap(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCXXW"
+        );
+        assert!(matches!(
+            check_residues(invalid, ResidueCheck::Strict),
+            Err(NrpsError::InvalidResidue {
+                position: 32,
+                char: '*'
+            })
+        ));
+
+        let gaps = "-".repeat(34);
+        assert_eq!(check_residues(&gaps, ResidueCheck::Off).unwrap(), gaps);
+        assert!(check_residues(&gaps, ResidueCheck::Lenient).is_err());
+        assert!(check_residues(&gaps, ResidueCheck::Strict).is_err());
+    }
+
+    #[test]
+    fn test_check_domains() {
+        let domains = Vec::from([
+            ADomain::new(
+                "valid".to_string(),
+                "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+            ),
+            ADomain::new(
+                "invalid".to_string(),
+                "LDASFDASLFEMYLLTGGDRNMYGPTEAJMCATW".to_string(),
+            ),
+        ]);
+        assert!(check_domains(domains.clone(), ResidueCheck::Strict, false).is_err());
+
+        let got = check_domains(domains.clone(), ResidueCheck::Strict, true).unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "valid");
+
+        let got = check_domains(domains, ResidueCheck::Lenient, false).unwrap();
+        assert_eq!(got[1].aa34, "LDASFDASLFEMYLLTGGDRNMYGPTEAXMCATW");
+        assert_eq!(got[1].aa10, extract_aa10(&got[1].aa34).unwrap());
+    }
+
+    #[test]
+    fn test_parse_domain_malformed() {
+        let cases = [
+            "",
+            "\t",
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW",
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCAT\tshort",
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATWW\tlong",
+            // 34 bytes, but not 34 residues
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMC\u{e9}A\tutf8",
+        ];
+        for case in cases.iter() {
+            assert!(parse_domain(case.to_string()).is_err(), "{case}");
+        }
+    }
+
+    #[test]
+    fn test_parse_domains_skip_invalid() {
+        let input = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA_A1\n\
+                     garbage\n\
+                     LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW\tHpg\tCAC48361.1.A1\n";
+        let err =
+            parse_lines(input.as_bytes(), Path::new("sigs.tsv"), false, parse_domain).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid input `sigs.tsv`, line 2: Signature error `garbage`"
+        );
+
+        let (domains, warnings) =
+            parse_lines(input.as_bytes(), Path::new("sigs.tsv"), true, parse_domain).unwrap();
+        assert_eq!(domains.len(), 2);
+        assert_eq!(warnings, Vec::from(["line 2: Signature error `garbage`"]));
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_domain_never_panics(line in "\\PC*") {
+            let _ = parse_domain(line.clone());
+            let _ = parse_aa10_domain(normalize_signature(line));
+        }
+
+        #[test]
+        fn test_parse_domain_columns(
+            signature in "[A-Z-]{33,35}|[A-Z\u{e9}]{33,34}",
+            columns in prop::collection::vec("[ -~\u{e9}]{0,8}", 0..8),
+        ) {
+            let line = std::iter::once(signature).chain(columns).collect::<Vec<_>>().join("\t");
+            if let Ok(domain) = parse_domain(line) {
+                prop_assert_eq!(domain.aa34.len(), 34);
+                prop_assert_eq!(domain.aa10.len(), 10);
+            }
+        }
     }
 }
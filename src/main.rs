@@ -3,49 +3,555 @@
 
 use std::env;
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+#[cfg(feature = "server")]
+use std::time::Duration;
 
 use clap::Parser;
+use log::LevelFilter;
 
-use nrps_rs::config::{parse_config, Cli};
-use nrps_rs::{print_results, run_on_file};
+use nrps_rs::benchmark::{benchmark_models, cross_validate, format_reports};
+use nrps_rs::cluster::{cluster_domains, format_clusters, predict_representatives};
+use nrps_rs::config::{
+    env_vars, find_config_file, parse_config, Cli, Command, Config, InputFormat, PredictArgs,
+};
+use nrps_rs::encodings::tables::format_tables;
+use nrps_rs::encodings::{encode_residues, encode_with_tables, format_features, FeatureEncoding};
+use nrps_rs::errors::NrpsError;
+use nrps_rs::explain::{explain_domain, to_text};
+use nrps_rs::extraction::{parse_fasta_domains, parse_genbank_domains};
+use nrps_rs::metadata::RunMetadata;
+use nrps_rs::output::{matches_substrates, sort_domains, OutputFormat, SortBy, StreamWriter};
+use nrps_rs::plan::RunPlan;
+use nrps_rs::predictors::cache::build_cache;
+use nrps_rs::predictors::manifest::ModelSetInfo;
+use nrps_rs::predictors::predictions::{ADomain, PredictionCategory};
+use nrps_rs::predictors::pwm;
+use nrps_rs::predictors::store::ModelStore;
+use nrps_rs::predictors::validation::validate_model_dir;
+#[cfg(feature = "server")]
+use nrps_rs::server::PredictionServer;
+use nrps_rs::svm::train::{parse_training_data, train_models, write_models, TrainingParameters};
+use nrps_rs::{
+    check_residues, compression, print_results, read_input, run_on_file, run_streaming,
+    write_results,
+};
 
 fn main() {
     let cli = Cli::parse();
-    let mut config_file: PathBuf;
+    init_logging(cli.verbose, cli.quiet);
 
-    if let Some(file) = &cli.config {
-        config_file = file.clone();
-    } else {
-        config_file = env::current_dir().unwrap();
-        config_file.push("nrps.toml");
+    if let Err(err) = run(cli) {
+        log::error!("{}", err.report());
+        process::exit(err.kind().exit_code());
     }
+}
 
-    eprintln!("Running on {}", cli.signatures.display());
+fn run(mut cli: Cli) -> Result<(), NrpsError> {
+    let command = cli.take_command().unwrap_or_else(|err| err.exit());
 
-    let config = if config_file.exists() {
-        eprintln!("Using config from {}", config_file.display());
-        parse_config(File::open(config_file).unwrap(), &cli).unwrap()
-    } else {
-        eprintln!("Using default config");
-        parse_config("".as_bytes(), &cli).unwrap()
+    let mut config = match find_config_file(cli.config.as_deref())? {
+        Some(config_file) => {
+            log::info!("Using config from {}", config_file.display());
+            parse_config(open(&config_file)?, &cli, env_vars())?
+        }
+        None => {
+            log::info!("Using default config");
+            parse_config("".as_bytes(), &cli, env_vars())?
+        }
     };
 
-    eprintln!("Printing the best {} hit(s)", &config.count);
-    eprintln!("Model dir is {}", &config.model_dir().display());
+    config.discover_custom_categories()?;
+
+    if config.attributions && config.output_format != OutputFormat::Json {
+        log::warn!("Attributions are only reported in JSON output");
+    }
+    if config.summary {
+        let summary_categories = config.enabled_summary_categories();
+        for category in config.weights.keys() {
+            if !summary_categories.contains(category) {
+                log::warn!("Weight for {category} is unused, it isn't an enabled summary category");
+            }
+        }
+    }
+    if config.novelty && !config.stachelhaus_enabled() {
+        log::warn!("Novelty is only computed with Stachelhaus predictions enabled");
+    }
+    if config.nearest_references.is_some() && !config.stachelhaus_enabled() {
+        log::warn!("Nearest references are only listed with Stachelhaus predictions enabled");
+    }
+
+    // Record which models produced the results, TSV output only reports the version
+    if config.output_format == OutputFormat::Json {
+        config.model_set = ModelSetInfo::from_config(&config).ok();
+    }
+
+    match command {
+        Command::Predict {
+            signatures,
+            options,
+        } if options.dry_run => dry_run(&config, signatures),
+        Command::Predict {
+            signatures,
+            options:
+                PredictArgs {
+                    output,
+                    explain: Some(domain),
+                    ..
+                },
+        } => explain(&config, signatures, output, &domain),
+        Command::Predict {
+            signatures,
+            options,
+        } => predict(&config, signatures, options.output, options.stream),
+        Command::ValidateModels => validate_models(&config),
+        Command::ExtractSignatures { sequences } => extract_signatures(&config, sequences),
+        Command::Encode {
+            signature,
+            encoding,
+            per_residue,
+        } => encode_signature(
+            &config,
+            &signature,
+            encoding,
+            training_category(&cli.categories),
+            per_residue,
+        ),
+        Command::EncodingTables { encoding } => print_tables(&config, encoding),
+        Command::Cluster {
+            signatures,
+            min_identity,
+            output,
+        } => cluster(&config, signatures, output, min_identity),
+        Command::VersionInfo => version_info(&config),
+        Command::BuildCache => {
+            log::info!("Model dir is {}", &config.model_dir().display());
+            let count = build_cache(&config)?;
+            log::info!(
+                "Wrote {count} models to {}",
+                &config.model_cache().display()
+            );
+            Ok(())
+        }
+        Command::Train {
+            training_data,
+            output_dir,
+            training,
+        } => train(
+            training_data,
+            output_dir,
+            &training.parameters(training_category(&cli.categories)),
+        ),
+        Command::BuildPwms {
+            training_data,
+            output_dir,
+        } => build_pwms(training_data, output_dir),
+        Command::Benchmark {
+            labeled_data,
+            folds,
+            leave_one_out,
+            training,
+        } => benchmark(
+            &config,
+            labeled_data,
+            folds,
+            leave_one_out,
+            &training.parameters(training_category(&cli.categories)),
+        ),
+        #[cfg(feature = "server")]
+        Command::Serve { address, watch } => serve(&config, &address, watch),
+    }
+}
+
+fn open(path: &Path) -> Result<File, NrpsError> {
+    File::open(path).map_err(|source| NrpsError::FileError {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>, NrpsError> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|source| NrpsError::FileError {
+            path: path.to_path_buf(),
+            source,
+        })
+}
 
-    if !config.skip_stachelhaus {
-        eprintln!(
+/// Models are trained and signatures encoded for the first `--category`, SingleV3 by default
+fn training_category(categories: &[PredictionCategory]) -> PredictionCategory {
+    if categories.len() > 1 {
+        log::warn!("Only one category is supported, using {}", categories[0]);
+    }
+    categories
+        .first()
+        .cloned()
+        .unwrap_or(PredictionCategory::SingleV3)
+}
+
+fn version_info(config: &Config) -> Result<(), NrpsError> {
+    println!("nrps-rs {}", env!("CARGO_PKG_VERSION"));
+    match ModelSetInfo::from_config(config) {
+        Ok(info) => {
+            println!("Model dir\t{}", config.model_dir().display());
+            if let Some(version) = info.version {
+                println!("Model set version\t{version}");
+            }
+            println!("Model set hash\t{}", info.hash);
+        }
+        Err(err) => log::warn!("No model set info: {err}"),
+    }
+    Ok(())
+}
+
+fn init_logging(verbose: u8, quiet: u8) {
+    let level = match i16::from(verbose) - i16::from(quiet) {
+        i16::MIN..=-2 => LevelFilter::Error,
+        -1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_target(false)
+        .format_timestamp(None)
+        .parse_default_env()
+        .init();
+}
+
+fn predict(
+    config: &Config,
+    signatures: PathBuf,
+    output: Option<PathBuf>,
+    stream: bool,
+) -> Result<(), NrpsError> {
+    log::info!("Running on {}", signatures.display());
+
+    log::info!("Printing the best {} hit(s)", &config.count);
+    log::info!("Model dir is {}", &config.model_dir().display());
+
+    if config.stachelhaus_enabled() {
+        log::info!(
             "Stachelhaus signatures from {}",
             &config.stachelhaus_signatures().display()
         );
     }
 
-    let domains = run_on_file(&config, cli.signatures).unwrap();
-    print_results(&config, &domains).unwrap();
+    let metadata = if config.metadata_header || config.metadata_file.is_some() {
+        Some(RunMetadata::new(config)?)
+    } else {
+        None
+    };
+    // JSON can't have comment lines, but the results carry the model set info already
+    let header = match &metadata {
+        Some(metadata) if config.metadata_header && config.output_format != OutputFormat::Json => {
+            metadata.to_header()?
+        }
+        _ => String::new(),
+    };
+
+    let stream = match config.input_format {
+        InputFormat::Signatures | InputFormat::Aa10 => stream,
+        format if stream => {
+            log::warn!("Can't stream {format:?} input, predicting all domains at once");
+            false
+        }
+        _ => false,
+    };
+    if stream && config.sort_by != SortBy::Input {
+        log::warn!("Streamed results are written in input order, ignoring the sort order");
+    }
+    if stream {
+        match output {
+            Some(output_file) => {
+                log::info!("Writing results to {}", output_file.display());
+                let mut handle = create(&output_file)?;
+                handle.write_all(header.as_bytes())?;
+                predict_streaming(config, &signatures, handle)?;
+            }
+            None => {
+                let mut handle = io::stdout().lock();
+                handle.write_all(header.as_bytes())?;
+                predict_streaming(config, &signatures, handle)?;
+            }
+        }
+        if let (Some(metadata), Some(metadata_file)) = (metadata, &config.metadata_file) {
+            log::info!("Writing run metadata to {}", metadata_file.display());
+            metadata.write_json(create(metadata_file)?)?;
+        }
+        return Ok(());
+    }
+
+    let mut domains = run_on_file(config, signatures)?;
+    domains.retain(|domain| matches_substrates(config, domain));
+    sort_domains(config, &mut domains);
+    match output {
+        Some(output_file) => {
+            log::info!("Writing results to {}", output_file.display());
+            let mut handle = create(&output_file)?;
+            handle.write_all(header.as_bytes())?;
+            write_results(config, &domains, handle)?;
+        }
+        None => {
+            print!("{header}");
+            print_results(config, &domains)?;
+        }
+    }
+
+    if let (Some(metadata), Some(metadata_file)) = (metadata, &config.metadata_file) {
+        log::info!("Writing run metadata to {}", metadata_file.display());
+        metadata.write_json(create(metadata_file)?)?;
+    }
+    Ok(())
+}
+
+/// Predict and write the domains one line at a time, `-` reads from stdin
+fn predict_streaming<W: Write>(
+    config: &Config,
+    signatures: &Path,
+    writer: W,
+) -> Result<(), NrpsError> {
+    let reader = if signatures.as_os_str() == "-" {
+        compression::decompress(BufReader::new(io::stdin()))?
+    } else {
+        compression::open(signatures).map_err(|source| NrpsError::FileError {
+            path: signatures.to_path_buf(),
+            source,
+        })?
+    };
+    let mut writer = StreamWriter::new(config, writer)?;
+    let count = run_streaming(config, reader, |domain| {
+        match matches_substrates(config, &domain) {
+            true => writer.write(&domain),
+            false => Ok(()),
+        }
+    })?;
+    writer.finish()?;
+    log::info!("Predicted {count} domain(s)");
+    Ok(())
+}
+
+fn cluster(
+    config: &Config,
+    signatures: PathBuf,
+    output: Option<PathBuf>,
+    min_identity: f64,
+) -> Result<(), NrpsError> {
+    if !matches!(config.output_format, OutputFormat::Tsv | OutputFormat::Json) {
+        log::warn!("Clusters are written as TSV or JSON, using TSV");
+    }
+    log::info!("Clustering {}", signatures.display());
+    let mut domains = read_input(config, signatures)?;
+    let clusters = cluster_domains(&domains, min_identity);
+    log::info!(
+        "Predicting {} representative(s) of {} domain(s)",
+        clusters.len(),
+        domains.len()
+    );
+    predict_representatives(config, &mut domains, &clusters)?;
+
+    let text = format_clusters(config, &clusters, &domains)?;
+    match output {
+        Some(output_file) => create(&output_file)?.write_all(text.as_bytes())?,
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+fn explain(
+    config: &Config,
+    signatures: PathBuf,
+    output: Option<PathBuf>,
+    name: &str,
+) -> Result<(), NrpsError> {
+    let domains: Vec<ADomain> = run_on_file(config, signatures)?
+        .into_iter()
+        .filter(|domain| domain.name == name)
+        .collect();
+    if domains.is_empty() {
+        return Err(NrpsError::SignatureError(format!("No domain named {name}")));
+    }
+    log::info!("Explaining the predictions of {name}");
+
+    let store = ModelStore::new(config);
+    let mut text = String::new();
+    for domain in domains.iter() {
+        text.push_str(&to_text(&explain_domain(config, &store, domain)?));
+    }
+    match output {
+        Some(output_file) => create(&output_file)?.write_all(text.as_bytes())?,
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+fn dry_run(config: &Config, signatures: PathBuf) -> Result<(), NrpsError> {
+    log::info!("Dry run, checking the setup without predicting");
+    let plan = RunPlan::new(config, signatures)?;
+    for category in plan.empty_categories() {
+        log::warn!("No model files for {category}");
+    }
+    print!("{}", plan.to_text());
+    Ok(())
+}
+
+fn validate_models(config: &Config) -> Result<(), NrpsError> {
+    log::info!("Model dir is {}", &config.model_dir().display());
+    let report = validate_model_dir(config.model_dir())?;
+    if let Some(version) = &report.version {
+        println!("Model set version\t{version}");
+    }
+    for (category, count) in report.counts.iter() {
+        println!("{category}\t{count}");
+    }
+    println!("Total\t{}", report.total());
+
+    if !report.is_ok() {
+        for issue in report.issues.iter() {
+            log::error!("{}: {}", issue.path.display(), issue.message);
+        }
+        return Err(NrpsError::ModelDirError(format!(
+            "Found {} invalid model(s)",
+            report.issues.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+fn serve(config: &Config, address: &str, watch: Option<u64>) -> Result<(), NrpsError> {
+    log::info!("Model dir is {}", &config.model_dir().display());
+    let mut server = PredictionServer::new(config)?;
+    log::info!("Loaded {} models", server.model_count());
+    if let Some(seconds) = watch {
+        log::info!("Checking for model changes every {seconds}s");
+        server = server.watch(Duration::from_secs(seconds));
+    }
+    log::info!("Listening on http://{address}");
+    server.serve(address)
+}
+
+fn train(
+    training_data: PathBuf,
+    output_dir: PathBuf,
+    params: &TrainingParameters,
+) -> Result<(), NrpsError> {
+    log::info!("Training on {}", training_data.display());
+    let samples = parse_training_data(open(&training_data)?)?;
+    log::info!(
+        "Training {:?} models with {:?} encoding on {} samples",
+        params.kernel_type,
+        params.encoding,
+        samples.len()
+    );
+    let models = train_models(&samples, params)?;
+    write_models(&models, &output_dir, samples.len())?;
+    log::info!("Wrote {} models to {}", models.len(), output_dir.display());
+    Ok(())
+}
+
+fn build_pwms(training_data: PathBuf, output_dir: PathBuf) -> Result<(), NrpsError> {
+    let samples = parse_training_data(open(&training_data)?)?;
+    let matrices = pwm::build_pwms(
+        samples
+            .iter()
+            .map(|sample| (sample.aa34.as_str(), sample.substrate.as_str())),
+    )?;
+    pwm::write_pwms(&matrices, &output_dir)?;
+    log::info!(
+        "Wrote {} PWMs from {} samples to {}",
+        matrices.len(),
+        samples.len(),
+        output_dir.join(pwm::PWM_DIR).display()
+    );
+    Ok(())
+}
+
+fn benchmark(
+    config: &Config,
+    labeled_data: PathBuf,
+    folds: Option<usize>,
+    leave_one_out: bool,
+    params: &TrainingParameters,
+) -> Result<(), NrpsError> {
+    log::info!("Benchmarking on {}", labeled_data.display());
+    let samples = parse_training_data(open(&labeled_data)?)?;
+    let folds = if leave_one_out {
+        Some(samples.len())
+    } else {
+        folds
+    };
+
+    let reports = match folds {
+        Some(folds) => {
+            log::info!(
+                "Running {folds}-fold cross-validation on {} samples",
+                samples.len()
+            );
+            Vec::from([cross_validate(&samples, params, folds)?])
+        }
+        None => {
+            log::info!("Model dir is {}", &config.model_dir().display());
+            benchmark_models(config, &samples)?
+        }
+    };
+    println!("{}", format_reports(&reports));
+    Ok(())
+}
+
+fn encode_signature(
+    config: &Config,
+    signature: &str,
+    encoding: FeatureEncoding,
+    category: PredictionCategory,
+    per_residue: bool,
+) -> Result<(), NrpsError> {
+    let signature = check_residues(signature, config.residue_check)?;
+    let tables = config.encoding_options()?.tables;
+    if !per_residue {
+        let features = encode_with_tables(&signature, &encoding, &category, &tables);
+        log::info!(
+            "{} features with {} encoding",
+            features.len(),
+            encoding.name()
+        );
+        println!("{}", format_features(&features));
+        return Ok(());
+    }
+    for (i, (residue, features)) in encode_residues(&signature, &encoding, &category, &tables)
+        .iter()
+        .enumerate()
+    {
+        let values: Vec<String> = features.iter().map(f64::to_string).collect();
+        println!("{}\t{residue}\t{}", i + 1, values.join("\t"));
+    }
+    Ok(())
+}
+
+fn print_tables(config: &Config, encoding: FeatureEncoding) -> Result<(), NrpsError> {
+    let tables = config
+        .encoding_options()?
+        .tables
+        .tables(&encoding)
+        .ok_or_else(|| {
+            NrpsError::EncodingTableError(format!("{} has no property tables", encoding.name()))
+        })?;
+    print!("{}", format_tables(&encoding, &tables)?);
+    Ok(())
 }
 
-#[cfg(test)]
-extern crate assert_approx_eq;
-#[cfg(test)]
-extern crate rstest;
+fn extract_signatures(config: &Config, sequences: PathBuf) -> Result<(), NrpsError> {
+    log::info!("Extracting signatures from {}", sequences.display());
+    let domains = match config.input_format {
+        InputFormat::Genbank => parse_genbank_domains(config, sequences),
+        _ => parse_fasta_domains(config, sequences),
+    };
+    for domain in domains?.iter() {
+        println!("{}\t{}", domain.aa34, domain.name);
+    }
+    Ok(())
+}
@@ -0,0 +1,221 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Run metadata for reproducible pipelines: tool version, config, model set and start time.
+
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "serde")]
+use serde::Deserialize;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::predictors::manifest::{category_manifest, ModelSetInfo};
+use crate::predictors::predictions::PredictionCategory;
+use crate::predictors::{extract_name, is_model_file, is_wanted_model};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct ModelCount {
+    pub category: PredictionCategory,
+    pub models: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunMetadata<'a> {
+    pub nrps_rs_version: &'static str,
+    /// Start of the run, as RFC 3339 UTC timestamp
+    pub started_at: String,
+    pub model_set: Option<ModelSetInfo>,
+    /// Number of model files per enabled category
+    pub model_counts: Vec<ModelCount>,
+    pub config: &'a Config,
+}
+
+impl<'a> RunMetadata<'a> {
+    /// Collect the metadata for a run starting now
+    pub fn new(config: &'a Config) -> Result<Self, NrpsError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Ok(RunMetadata {
+            nrps_rs_version: env!("CARGO_PKG_VERSION"),
+            started_at: utc_timestamp(now),
+            model_set: ModelSetInfo::from_config(config).ok(),
+            model_counts: model_counts(config)?,
+            config,
+        })
+    }
+
+    /// The metadata as `# key: value` comment lines, the config as a single JSON line
+    pub fn to_header(&self) -> Result<String, NrpsError> {
+        let mut header = format!("# nrps-rs version: {}\n", self.nrps_rs_version);
+        header.push_str(&format!("# Started at: {}\n", self.started_at));
+        if let Some(model_set) = &self.model_set {
+            if let Some(version) = &model_set.version {
+                header.push_str(&format!("# Model set version: {version}\n"));
+            }
+            header.push_str(&format!("# Model set hash: {}\n", model_set.hash));
+        }
+        let counts: Vec<String> = self
+            .model_counts
+            .iter()
+            .map(|count| format!("{}={}", count.category, count.models))
+            .collect();
+        header.push_str(&format!("# Models: {}\n", counts.join(", ")));
+        header.push_str(&format!(
+            "# Config: {}\n",
+            serde_json::to_string(self.config)?
+        ));
+        Ok(header)
+    }
+
+    /// Write the metadata as JSON side-car file
+    pub fn write_json<W>(&self, mut writer: W) -> Result<(), NrpsError>
+    where
+        W: Write,
+    {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Count the model files of the enabled categories, without parsing them
+pub fn model_counts(config: &Config) -> Result<Vec<ModelCount>, NrpsError> {
+    let mut counts: Vec<ModelCount> = config
+        .categories()
+        .into_iter()
+        .filter(PredictionCategory::has_models)
+        .map(|category| ModelCount {
+            category,
+            models: 0,
+        })
+        .collect();
+    let mut add = |category: PredictionCategory, models: usize| {
+        if let Some(count) = counts.iter_mut().find(|count| count.category == category) {
+            count.models += models;
+        }
+    };
+
+    #[cfg(feature = "bundled-models")]
+    if !config.model_dir().exists() {
+        for (dir_name, file_name, _) in crate::bundled::BUNDLED_MODELS.iter() {
+            let name = extract_name(std::path::Path::new(file_name));
+            if !is_wanted_model(&name, &config.model_substrates) {
+                continue;
+            }
+            if let Some(category) = PredictionCategory::from_dir_name(dir_name) {
+                add(category, 1);
+            }
+        }
+        return Ok(counts);
+    }
+
+    if !config.model_dir().is_dir() {
+        return Ok(counts);
+    }
+    for category_dir_res in WalkDir::new(config.model_dir()).min_depth(1).max_depth(1) {
+        let category_dir = category_dir_res?;
+        if !category_dir.path().is_dir() {
+            continue;
+        }
+        let Some(category) = category_manifest(category_dir.path())?
+            .category
+            .or_else(|| {
+                PredictionCategory::from_dir_name(&category_dir.file_name().to_string_lossy())
+            })
+        else {
+            continue;
+        };
+        let mut models = 0;
+        for model_file_res in WalkDir::new(category_dir.path()).min_depth(1).max_depth(1) {
+            let model_file = model_file_res?;
+            if is_model_file(model_file.path())
+                && is_wanted_model(&extract_name(model_file.path()), &config.model_substrates)
+            {
+                models += 1;
+            }
+        }
+        add(category, models);
+    }
+    Ok(counts)
+}
+
+/// Format seconds since the epoch as RFC 3339 UTC timestamp
+fn utc_timestamp(secs: u64) -> String {
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(utc_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(utc_timestamp(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn test_run_metadata() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let single = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        fs::create_dir_all(&single).unwrap();
+        fs::write(single.join("[leu].mdl"), "model").unwrap();
+        fs::write(single.join("[val].mdl.gz"), "model").unwrap();
+        fs::write(single.join("README"), "not a model").unwrap();
+
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.clone());
+        config.skip_v3 = true;
+        config.skip_v1 = true;
+        let metadata = RunMetadata::new(&config).unwrap();
+        let single_v2 = metadata
+            .model_counts
+            .iter()
+            .find(|count| count.category == PredictionCategory::SingleV2)
+            .unwrap();
+        assert_eq!(single_v2.models, 2);
+        assert!(!metadata
+            .model_counts
+            .iter()
+            .any(|count| count.category == PredictionCategory::SingleV3));
+
+        let header = metadata.to_header().unwrap();
+        assert!(header.lines().all(|line| line.starts_with("# ")));
+        assert!(header.contains("SingleV2=2"));
+        assert!(header.contains("# Model set hash: fnv1a64:"));
+
+        let mut json = Vec::new();
+        metadata.write_json(&mut json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed["nrps_rs_version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(parsed["config"]["skip_v3"], true);
+    }
+}
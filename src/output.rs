@@ -0,0 +1,1058 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+use std::io::Write;
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::predictors::consensus::Consensus;
+use crate::predictors::manifest::ModelSetInfo;
+use crate::predictors::predictions::{
+    ADomain, Ambiguity, Attribution, Coordinates, Prediction, PredictionCategory, StachPrediction,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Tsv,
+    Json,
+    /// Tab-separated, one row per domain, category and prediction
+    Long,
+    /// The NRPSPredictor2 report layout, as parsed by antiSMASH
+    Legacy,
+}
+
+/// Order of the domains in the output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Input,
+    Name,
+    /// Highest best-prediction score of the reported categories first
+    Score,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct CategoryResult {
+    pub category: PredictionCategory,
+    pub predictions: Vec<Prediction>,
+    /// Set if predictions tied with the last one were added beyond the count
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ties_included: bool,
+    /// Per-position contributions to the predictions, only set if attributions are enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributions: Vec<Attribution>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResultRow<'a> {
+    pub name: &'a str,
+    pub aa34: &'a str,
+    pub aa10: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protein_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gene: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coords: Option<Coordinates>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stachelhaus: Option<Vec<StachPrediction>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stachelhaus_matches: Option<Vec<StachPrediction>>,
+    pub predictions: Vec<CategoryResult>,
+    /// Agreement of the summary categories, only set if the summary is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Only set if an ambiguity delta is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ambiguity: Option<Ambiguity>,
+    /// Only set if novelty is enabled, see `ADomain::novelty`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub novelty: Option<usize>,
+    /// Only set if nearest references are enabled, see `ADomain::nearest_references`
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub nearest_references: &'a [StachPrediction],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_set: Option<&'a ModelSetInfo>,
+}
+
+impl<'a> ResultRow<'a> {
+    pub fn new(config: &'a Config, domain: &'a ADomain) -> Self {
+        let stachelhaus = if config.stachelhaus_enabled() && !config.skip_new_stachelhaus_output {
+            Some(domain.stach_predictions.get_best())
+        } else {
+            None
+        };
+        let stachelhaus_matches = if config.stachelhaus_enabled() && config.stachelhaus_detail {
+            Some(
+                domain
+                    .stach_predictions
+                    .get_best_n(config.count, config.ties)
+                    .predictions,
+            )
+        } else {
+            None
+        };
+
+        let predictions = config
+            .output_categories()
+            .iter()
+            .map(|cat| {
+                let best = domain.get_best_n(cat, config.count, config.ties);
+                let attributions = best
+                    .iter()
+                    .filter_map(|pred| domain.attribution(cat, &pred.name))
+                    .cloned()
+                    .collect();
+                CategoryResult {
+                    category: cat.clone(),
+                    predictions: best.predictions,
+                    ties_included: best.ties_included,
+                    attributions,
+                }
+            })
+            .collect();
+
+        ResultRow {
+            name: &domain.name,
+            aa34: &domain.aa34,
+            aa10: &domain.aa10,
+            protein_id: domain.protein_id.as_deref(),
+            gene: domain.gene.as_deref(),
+            module_index: domain.module_index,
+            coords: domain.coords,
+            stachelhaus,
+            stachelhaus_matches,
+            predictions,
+            summary: config.summary.then(|| summary(config, domain)),
+            ambiguity: config
+                .ambiguity_delta
+                .map(|delta| domain.ambiguity(&config.output_categories(), delta)),
+            novelty: domain.novelty,
+            nearest_references: &domain.nearest_references,
+            model_set: config.model_set.as_ref(),
+        }
+    }
+}
+
+/// How well the predictions of the enabled summary categories agree, e.g. `weak: split Val/Leu`
+fn summary(config: &Config, domain: &ADomain) -> String {
+    Consensus::weighted(
+        domain,
+        &config.enabled_summary_categories(),
+        &config.weights,
+    )
+    .agreement()
+    .to_string()
+}
+
+/// Sort `domains` as configured, domains that compare equal keep their input order
+pub fn sort_domains(config: &Config, domains: &mut [ADomain]) {
+    match config.sort_by {
+        SortBy::Input => {}
+        SortBy::Name => domains.sort_by(|a, b| a.name.cmp(&b.name)),
+        SortBy::Score => {
+            let categories = config.output_categories();
+            // Domains without any predictions go last
+            let best_score = |domain: &ADomain| {
+                categories
+                    .iter()
+                    .flat_map(|category| domain.get_best(category))
+                    .map(|pred| pred.score)
+                    .fold(f64::NEG_INFINITY, f64::max)
+            };
+            domains.sort_by(|a, b| best_score(b).total_cmp(&best_score(a)));
+        }
+    }
+}
+
+/// Whether the best call of `domain` is one of the configured `only_substrates`
+///
+/// Only the best call of `substrate_category` is checked if set, otherwise those of all reported
+/// categories. Names are compared case-insensitively, combined calls like `Val|Leu` match if any
+/// of their substrates does.
+pub fn matches_substrates(config: &Config, domain: &ADomain) -> bool {
+    if config.only_substrates.is_empty() {
+        return true;
+    }
+    let categories = match &config.substrate_category {
+        Some(category) => Vec::from([category.clone()]),
+        None => config.output_categories(),
+    };
+    categories
+        .iter()
+        .flat_map(|category| domain.get_best(category))
+        .flat_map(|pred| {
+            pred.name
+                .split(['|', ','])
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .any(|substrate| {
+            config
+                .only_substrates
+                .iter()
+                .any(|wanted| wanted.eq_ignore_ascii_case(&substrate))
+        })
+}
+
+/// Structured per-domain results, in input order
+pub fn result_rows<'a>(
+    config: &'a Config,
+    domains: &'a [ADomain],
+) -> impl Iterator<Item = ResultRow<'a>> + 'a {
+    domains.iter().map(|domain| ResultRow::new(config, domain))
+}
+
+/// Format the results according to the configured output format
+pub fn format_results(config: &Config, domains: &[ADomain]) -> Result<String, NrpsError> {
+    if config.count < 1 {
+        return Err(NrpsError::CountError(config.count));
+    }
+
+    match config.output_format {
+        OutputFormat::Tsv => Ok(to_tsv(config, domains)),
+        OutputFormat::Json => Ok(format!("{}\n", to_json(config, domains)?)),
+        OutputFormat::Long => Ok(to_long(config, domains)),
+        OutputFormat::Legacy => Ok(to_legacy(config, domains)),
+    }
+}
+
+/// Write the results according to the configured output format
+pub fn write_results<W>(
+    config: &Config,
+    domains: &[ADomain],
+    mut writer: W,
+) -> Result<(), NrpsError>
+where
+    W: Write,
+{
+    writer.write_all(format_results(config, domains)?.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the results one domain at a time, flushing after each, for use in pipes
+///
+/// The header is written with the first domain, so TSV output only gets the metadata columns if
+/// the first domain has metadata. JSON is written as JSON Lines, one compact object per domain.
+pub struct StreamWriter<'a, W: Write> {
+    config: &'a Config,
+    writer: W,
+    /// Set once the header is written, whether the TSV rows have metadata columns
+    with_metadata: Option<bool>,
+}
+
+impl<'a, W: Write> StreamWriter<'a, W> {
+    pub fn new(config: &'a Config, writer: W) -> Result<Self, NrpsError> {
+        if config.count < 1 {
+            return Err(NrpsError::CountError(config.count));
+        }
+        Ok(StreamWriter {
+            config,
+            writer,
+            with_metadata: None,
+        })
+    }
+
+    pub fn write(&mut self, domain: &ADomain) -> Result<(), NrpsError> {
+        self.write_header(domain.has_metadata())?;
+        let config = self.config;
+        let row = match config.output_format {
+            OutputFormat::Tsv => tsv_row(config, domain, self.with_metadata.unwrap_or_default()),
+            OutputFormat::Json => {
+                format!(
+                    "{}\n",
+                    serde_json::to_string(&ResultRow::new(config, domain))?
+                )
+            }
+            OutputFormat::Long => long_rows(config, domain),
+            OutputFormat::Legacy => legacy_row(config, domain),
+        };
+        self.writer.write_all(row.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the header if there were no domains, and return the writer
+    pub fn finish(mut self) -> Result<W, NrpsError> {
+        self.write_header(false)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self, with_metadata: bool) -> Result<(), NrpsError> {
+        if self.with_metadata.is_some() {
+            return Ok(());
+        }
+        self.with_metadata = Some(with_metadata);
+        let header = match self.config.output_format {
+            OutputFormat::Tsv => tsv_header(self.config, with_metadata),
+            OutputFormat::Json => String::new(),
+            OutputFormat::Long => long_header(self.config),
+            OutputFormat::Legacy => legacy_header(),
+        };
+        self.writer.write_all(header.as_bytes())?;
+        Ok(())
+    }
+}
+
+pub fn to_json(config: &Config, domains: &[ADomain]) -> Result<String, NrpsError> {
+    let results: Vec<ResultRow> = result_rows(config, domains).collect();
+    Ok(serde_json::to_string_pretty(&results)?)
+}
+
+/// Tidy table with one `Name Category Rank Substrate Score` row per prediction
+///
+/// With a calibration file configured, a `Probability` column is added.
+pub fn to_long(config: &Config, domains: &[ADomain]) -> String {
+    let mut output = long_header(config);
+    for domain in domains.iter() {
+        output.push_str(&long_rows(config, domain));
+    }
+    output
+}
+
+fn long_header(config: &Config) -> String {
+    let mut output = String::new();
+    if let Some(version) = config.model_set_version() {
+        output.push_str(&format!("# Model set version: {version}\n"));
+    }
+    output.push_str("Name\tCategory\tRank\tSubstrate\tScore");
+    if config.calibration_file.is_some() {
+        output.push_str("\tProbability");
+    }
+    output.push('\n');
+    output
+}
+
+fn long_rows(config: &Config, domain: &ADomain) -> String {
+    let calibrated = config.calibration_file.is_some();
+    let mut output = String::new();
+    for cat in config.output_categories().iter() {
+        for (rank, pred) in domain
+            .get_best_n(cat, config.count, config.ties)
+            .iter()
+            .enumerate()
+        {
+            output.push_str(&format!(
+                "{}\t{cat}\t{}\t{}\t{:.2}",
+                domain.name,
+                rank + 1,
+                pred.name,
+                pred.score
+            ));
+            if calibrated {
+                match pred.probability {
+                    Some(probability) => output.push_str(&format!("\t{probability:.2}")),
+                    None => output.push_str("\tN/A"),
+                }
+            }
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// Columns of the NRPSPredictor2 report and the categories filling them
+const LEGACY_COLUMNS: [(&str, PredictionCategory); 7] = [
+    ("3class-pred", PredictionCategory::ThreeClusterV2),
+    ("large-class-pred", PredictionCategory::LargeClusterV2),
+    ("small-class-pred", PredictionCategory::SmallClusterV2),
+    ("single-class-pred", PredictionCategory::SingleV2),
+    ("nearest stachelhaus code", PredictionCategory::Stachelhaus),
+    (
+        "NRPS1pred-large-class-pred",
+        PredictionCategory::LargeClusterV1,
+    ),
+    (
+        "NRPS2pred-large-class-pred",
+        PredictionCategory::SmallClusterV1,
+    ),
+];
+
+/// The NRPSPredictor2 report, so existing parsers keep working
+///
+/// NRPSPredictor2 only had v1 and v2 models, v3 results aren't reported. The applicability domain
+/// and Pfam score aren't computed, they always get NRPSPredictor2's "not available" values.
+pub fn to_legacy(config: &Config, domains: &[ADomain]) -> String {
+    let mut output = legacy_header();
+    for domain in domains.iter() {
+        output.push_str(&legacy_row(config, domain));
+    }
+    output
+}
+
+fn legacy_header() -> String {
+    let mut headers = Vec::from(["#sequence-id", "8A-signature", "stachelhaus-code"]);
+    headers.extend(LEGACY_COLUMNS.iter().map(|(header, _)| *header));
+    headers.extend(["outside applicability domain?", "coords", "pfam-score"]);
+
+    let mut output = headers.join("\t");
+    output.push('\n');
+    output
+}
+
+fn legacy_row(config: &Config, domain: &ADomain) -> String {
+    let enabled = config.categories();
+    let mut line = Vec::from([
+        domain.name.to_string(),
+        domain.aa34.to_string(),
+        domain.aa10.to_string(),
+    ]);
+    for (_, category) in LEGACY_COLUMNS.iter() {
+        let best: Vec<String> = if enabled.contains(category) {
+            domain
+                .get_best(category)
+                .into_iter()
+                .map(|pred| pred.name)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if best.is_empty() {
+            line.push("N/A".to_string());
+        } else {
+            line.push(best.join("|"));
+        }
+    }
+    line.push("0".to_string());
+    line.push(match domain.coords {
+        Some(coords) => format!("{}:{}", coords.start, coords.end),
+        None => "-1:-1".to_string(),
+    });
+    line.push("-1.0".to_string());
+    let mut output = line.join("\t");
+    output.push('\n');
+    output
+}
+
+pub fn to_tsv(config: &Config, domains: &[ADomain]) -> String {
+    // Only add the metadata columns if the input had any
+    let with_metadata = domains.iter().any(ADomain::has_metadata);
+    let mut output = tsv_header(config, with_metadata);
+    for domain in domains.iter() {
+        output.push_str(&tsv_row(config, domain, with_metadata));
+    }
+    output
+}
+
+pub(crate) fn tsv_header(config: &Config, with_metadata: bool) -> String {
+    let categories = config.output_categories();
+
+    let cat_strings: Vec<String> = categories
+        .iter()
+        .map(PredictionCategory::to_string)
+        .collect();
+
+    let mut headers: Vec<String> = Vec::with_capacity(3);
+    headers.push("Name".to_string());
+    if with_metadata {
+        headers.push("Protein ID\tGene\tModule\tCoordinates".to_string());
+    }
+    headers.push("8A signature\tStachelhaus signature".to_string());
+    if config.stachelhaus_enabled() && !config.skip_new_stachelhaus_output {
+        headers.push(
+            [
+                "Full Stachelhaus match",
+                "AA10 score",
+                "AA10 signature matched",
+                "AA34 score",
+                "Evidence level",
+                "Reference IDs",
+            ]
+            .join("\t")
+            .to_string(),
+        );
+    }
+    if config.stachelhaus_enabled() && config.stachelhaus_detail {
+        headers.push("Stachelhaus matches".to_string());
+    }
+    headers.push(cat_strings.join("\t"));
+    if config.summary {
+        headers.push("Summary".to_string());
+    }
+    if config.ambiguity_delta.is_some() {
+        headers.push("Ambiguous".to_string());
+    }
+    if config.stachelhaus_enabled() && config.novelty {
+        headers.push("Novelty".to_string());
+    }
+    if config.stachelhaus_enabled() && config.nearest_references.is_some() {
+        headers.push("Nearest references".to_string());
+    }
+
+    let mut output = String::new();
+    if let Some(version) = config.model_set_version() {
+        output.push_str(&format!("# Model set version: {version}\n"));
+    }
+    output.push_str(&headers.join("\t"));
+    output.push('\n');
+    output
+}
+
+pub(crate) fn tsv_row(config: &Config, domain: &ADomain, with_metadata: bool) -> String {
+    let categories = config.output_categories();
+    let mut best_predictions: Vec<String> = Vec::new();
+    for cat in categories.iter() {
+        let mut best = domain
+            .get_best_n(cat, config.count, config.ties)
+            .iter()
+            .fold("".to_string(), |acc, new| match new.probability {
+                Some(probability) => {
+                    format!("{acc}|{}({:.2};p={probability:.2})", new.name, new.score)
+                }
+                None => format!("{acc}|{}({:.2})", new.name, new.score),
+            })
+            .trim_matches('|')
+            .to_string();
+        if best.is_empty() {
+            best = "N/A".to_string();
+        }
+        best_predictions.push(best)
+    }
+    let mut line: Vec<String> = Vec::with_capacity(5);
+    line.push(domain.name.to_string());
+    if with_metadata {
+        let or_na = |value: Option<String>| value.unwrap_or_else(|| "N/A".to_string());
+        line.push(or_na(domain.protein_id.clone()));
+        line.push(or_na(domain.gene.clone()));
+        line.push(or_na(domain.module_index.map(|index| index.to_string())));
+        line.push(or_na(domain.coords.map(|coords| coords.to_string())));
+    }
+    // Domains from aa10-only input have no aa34 signature
+    if domain.aa34.is_empty() {
+        line.push("N/A".to_string());
+    } else {
+        line.push(domain.aa34.to_string());
+    }
+    line.push(domain.aa10.to_string());
+    if config.stachelhaus_enabled() && !config.skip_new_stachelhaus_output {
+        line.push(domain.stach_predictions.to_table());
+    }
+    if config.stachelhaus_enabled() && config.stachelhaus_detail {
+        line.push(
+            domain
+                .stach_predictions
+                .to_detail(config.count, config.ties),
+        );
+    }
+    line.push(best_predictions.join("\t"));
+    if config.summary {
+        line.push(summary(config, domain));
+    }
+    if let Some(delta) = config.ambiguity_delta {
+        line.push(domain.ambiguity(&categories, delta).to_string());
+    }
+    if config.stachelhaus_enabled() && config.novelty {
+        line.push(
+            domain
+                .novelty
+                .map_or_else(|| "N/A".to_string(), |novelty| novelty.to_string()),
+        );
+    }
+    if config.stachelhaus_enabled() && config.nearest_references.is_some() {
+        let references: Vec<String> = domain
+            .nearest_references
+            .iter()
+            .map(StachPrediction::to_detail)
+            .collect();
+        if references.is_empty() {
+            line.push("N/A".to_string());
+        } else {
+            line.push(references.join("|"));
+        }
+    }
+    let mut output = line.join("\t");
+    output.push('\n');
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    use rstest::rstest;
+
+    fn config() -> Config {
+        let mut config = Config::new();
+        config.skip_v1 = true;
+        config.skip_v2 = true;
+        config.skip_stachelhaus = true;
+        config
+    }
+
+    fn domain() -> ADomain {
+        let mut domain = ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        );
+        domain.add(
+            PredictionCategory::SingleV3,
+            Prediction {
+                name: "leu".to_string(),
+                score: 0.5,
+                probability: None,
+                model: None,
+            },
+        );
+        domain
+    }
+
+    #[test]
+    fn test_result_rows() {
+        let config = config();
+        let domains = [domain()];
+        let rows: Vec<ResultRow> = result_rows(&config, &domains).collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "bpsA");
+        assert!(rows[0].stachelhaus.is_none());
+        assert_eq!(rows[0].predictions.len(), 4);
+        assert_eq!(
+            rows[0].predictions[3].category,
+            PredictionCategory::SingleV3
+        );
+        assert_eq!(rows[0].predictions[3].predictions[0].name, "leu");
+    }
+
+    #[test]
+    fn test_format_results() {
+        let mut config = config();
+        let expected = "Name\t8A signature\tStachelhaus signature\t\
+                        ThreeClusterV3\tLargeClusterV3\tSmallClusterV3\tSingleV3\n\
+                        bpsA\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tDAFYLGMMCK\t\
+                        N/A\tN/A\tN/A\tleu(0.50)\n";
+        assert_eq!(format_results(&config, &[domain()]).unwrap(), expected);
+
+        config.count = 0;
+        assert!(format_results(&config, &[domain()]).is_err());
+    }
+
+    #[test]
+    fn test_to_long() {
+        let mut config = config();
+        config.count = 2;
+        let mut domain = domain();
+        domain.add(
+            PredictionCategory::SingleV3,
+            Prediction {
+                name: "val".to_string(),
+                score: 0.25,
+                probability: None,
+                model: None,
+            },
+        );
+        domain.add(
+            PredictionCategory::ThreeClusterV3,
+            Prediction {
+                name: "hydrophobic-aliphatic".to_string(),
+                score: 1.0,
+                probability: None,
+                model: None,
+            },
+        );
+        let other = ADomain::new(
+            "bpsB".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        );
+
+        let expected = "Name\tCategory\tRank\tSubstrate\tScore\n\
+                        bpsA\tThreeClusterV3\t1\thydrophobic-aliphatic\t1.00\n\
+                        bpsA\tSingleV3\t1\tleu\t0.50\n\
+                        bpsA\tSingleV3\t2\tval\t0.25\n";
+        assert_eq!(to_long(&config, &[domain.clone(), other]), expected);
+
+        config.count = 1;
+        config.calibration_file = Some(PathBuf::from("platt.tsv"));
+        config.enabled_categories = Some(Vec::from([PredictionCategory::SingleV3]));
+        domain.add(
+            PredictionCategory::SingleV3,
+            Prediction {
+                name: "ile".to_string(),
+                score: 2.0,
+                probability: Some(0.875),
+                model: None,
+            },
+        );
+        let expected = "Name\tCategory\tRank\tSubstrate\tScore\tProbability\n\
+                        bpsA\tSingleV3\t1\tile\t2.00\t0.88\n";
+        assert_eq!(to_long(&config, &[domain.clone()]), expected);
+        assert!(to_tsv(&config, &[domain]).contains("ile(2.00;p=0.88)"));
+    }
+
+    #[test]
+    fn test_to_legacy() {
+        let mut config = config();
+        config.skip_v2 = false;
+        let mut domain = domain();
+        domain.add(
+            PredictionCategory::SingleV2,
+            Prediction {
+                name: "leu".to_string(),
+                score: 0.5,
+                probability: None,
+                model: None,
+            },
+        );
+        domain.coords = Some(Coordinates {
+            start: 10,
+            end: 420,
+        });
+
+        let expected = "#sequence-id\t8A-signature\tstachelhaus-code\t3class-pred\t\
+                        large-class-pred\tsmall-class-pred\tsingle-class-pred\t\
+                        nearest stachelhaus code\tNRPS1pred-large-class-pred\t\
+                        NRPS2pred-large-class-pred\toutside applicability domain?\tcoords\t\
+                        pfam-score\n\
+                        bpsA\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tDAFYLGMMCK\t\
+                        N/A\tN/A\tN/A\tleu\tN/A\tN/A\tN/A\t0\t10:420\t-1.0\n";
+        assert_eq!(to_legacy(&config, &[domain]), expected);
+    }
+
+    #[test]
+    fn test_to_tsv_model_set_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        std::fs::write(model_dir.join("manifest.toml"), "version = \"2.0\"\n").unwrap();
+
+        let mut config = config();
+        config.set_model_dir(model_dir);
+        let got = to_tsv(&config, &[domain()]);
+        assert!(got.starts_with("# Model set version: 2.0\nName\t"));
+    }
+
+    #[test]
+    fn test_write_results() {
+        let config = config();
+        let mut buffer = Vec::new();
+        write_results(&config, &[domain()], &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format_results(&config, &[domain()]).unwrap()
+        );
+    }
+
+    #[rstest]
+    #[case(OutputFormat::Tsv)]
+    #[case(OutputFormat::Long)]
+    #[case(OutputFormat::Legacy)]
+    fn test_stream_writer(#[case] format: OutputFormat) {
+        let mut config = config();
+        config.output_format = format;
+        let domains = [domain(), domain()];
+
+        let mut writer = StreamWriter::new(&config, Vec::new()).unwrap();
+        for domain in domains.iter() {
+            writer.write(domain).unwrap();
+        }
+        let got = String::from_utf8(writer.finish().unwrap()).unwrap();
+        assert_eq!(got, format_results(&config, &domains).unwrap());
+
+        let empty = StreamWriter::new(&config, Vec::new()).unwrap();
+        let got = String::from_utf8(empty.finish().unwrap()).unwrap();
+        assert_eq!(got, format_results(&config, &[]).unwrap());
+    }
+
+    #[test]
+    fn test_stream_writer_json_lines() {
+        let mut config = config();
+        config.output_format = OutputFormat::Json;
+        let mut writer = StreamWriter::new(&config, Vec::new()).unwrap();
+        writer.write(&domain()).unwrap();
+        writer.write(&domain()).unwrap();
+        let got = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let lines: Vec<serde_json::Value> = got
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["name"], "bpsA");
+    }
+
+    #[test]
+    fn test_sort_domains() {
+        let mut config = config();
+        let named = |name: &str, score: Option<f64>| {
+            let mut domain = ADomain::new(
+                name.to_string(),
+                "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+            );
+            if let Some(score) = score {
+                domain.add(
+                    PredictionCategory::SingleV3,
+                    Prediction {
+                        name: "leu".to_string(),
+                        score,
+                        probability: None,
+                        model: None,
+                    },
+                );
+            }
+            domain
+        };
+        let mut domains = Vec::from([
+            named("b", Some(0.5)),
+            named("c", None),
+            named("a", Some(0.25)),
+            named("d", Some(1.5)),
+        ]);
+        let names = |domains: &[ADomain]| -> Vec<String> {
+            domains.iter().map(|domain| domain.name.clone()).collect()
+        };
+
+        sort_domains(&config, &mut domains);
+        assert_eq!(names(&domains), ["b", "c", "a", "d"]);
+
+        config.sort_by = SortBy::Score;
+        sort_domains(&config, &mut domains);
+        assert_eq!(names(&domains), ["d", "b", "a", "c"]);
+
+        config.sort_by = SortBy::Name;
+        sort_domains(&config, &mut domains);
+        assert_eq!(names(&domains), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_matches_substrates() {
+        let mut config = config();
+        config.skip_stachelhaus = false;
+        let mut domain = domain();
+        domain.add(
+            PredictionCategory::Stachelhaus,
+            Prediction {
+                name: "Val|Ile".to_string(),
+                score: 1.0,
+                probability: None,
+                model: None,
+            },
+        );
+        assert!(matches_substrates(&config, &domain));
+
+        config.only_substrates = Vec::from(["LEU".to_string()]);
+        assert!(matches_substrates(&config, &domain));
+        config.only_substrates = Vec::from(["ile".to_string(), "phe".to_string()]);
+        assert!(matches_substrates(&config, &domain));
+
+        config.substrate_category = Some(PredictionCategory::SingleV3);
+        assert!(!matches_substrates(&config, &domain));
+        config.only_substrates = Vec::from(["phe".to_string()]);
+        config.substrate_category = None;
+        assert!(!matches_substrates(&config, &domain));
+    }
+
+    #[test]
+    fn test_to_json() {
+        let config = config();
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &[domain()]).unwrap()).unwrap();
+        let expected = serde_json::json!([{
+            "name": "bpsA",
+            "aa34": "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW",
+            "aa10": "DAFYLGMMCK",
+            "predictions": [
+                {"category": "ThreeClusterV3", "predictions": []},
+                {"category": "LargeClusterV3", "predictions": []},
+                {"category": "SmallClusterV3", "predictions": []},
+                {"category": "SingleV3", "predictions": [{"name": "leu", "score": 0.5}]},
+            ],
+        }]);
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut config = config();
+        config.summary = true;
+        let tsv = to_tsv(&config, &[domain()]);
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert!(lines[0].ends_with("\tSingleV3\tSummary"));
+        assert!(lines[1].ends_with("\tleu(0.50)\tweak: only leu predicted"));
+
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &[domain()]).unwrap()).unwrap();
+        assert_eq!(got[0]["summary"], "weak: only leu predicted");
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        let mut config = config();
+        config.ambiguity_delta = Some(0.25);
+        let mut domain = domain();
+        domain.add(
+            PredictionCategory::SingleV3,
+            Prediction {
+                name: "val".to_string(),
+                score: 0.375,
+                probability: None,
+                model: None,
+            },
+        );
+
+        let tsv = to_tsv(&config, &[domain.clone()]);
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert!(lines[0].ends_with("\tSingleV3\tAmbiguous"));
+        assert!(lines[1].ends_with("\tleu(0.50)\tSingleV3"));
+
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &[domain]).unwrap()).unwrap();
+        assert_eq!(
+            got[0]["ambiguity"],
+            serde_json::json!({"close_calls": ["SingleV3"], "stachelhaus_disagrees": false})
+        );
+    }
+
+    #[test]
+    fn test_novelty() {
+        let mut config = config();
+        config.skip_stachelhaus = false;
+        config.skip_new_stachelhaus_output = true;
+        config.novelty = true;
+        let mut domain = domain();
+        domain.novelty = Some(3);
+        let aa10_only = ADomain::from_aa10("aa10".to_string(), "DAFYLGMMCK".to_string());
+
+        let tsv = to_tsv(&config, &[domain.clone(), aa10_only]);
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert!(lines[0].ends_with("\tSingleV3\tStachelhaus\tNovelty"));
+        assert!(lines[1].ends_with("\tleu(0.50)\tN/A\t3"));
+        assert!(lines[2].ends_with("\tN/A\tN/A"));
+
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &[domain]).unwrap()).unwrap();
+        assert_eq!(got[0]["novelty"], 3);
+    }
+
+    #[test]
+    fn test_nearest_references() {
+        let mut config = config();
+        config.skip_stachelhaus = false;
+        config.skip_new_stachelhaus_output = true;
+        config.nearest_references = Some(1);
+        let mut domain = domain();
+        domain.nearest_references = Vec::from([StachPrediction {
+            name: "Leu".to_string(),
+            aa10_score: 1.0,
+            aa10_sig: "DAFYLGMMCK".to_string(),
+            aa34_score: 0.5,
+            aa34_sig: "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+            all_substrates: "Leu".to_string(),
+            ids: Vec::from(["BGC0000311".to_string()]),
+            evidence: None,
+        }]);
+
+        let tsv = to_tsv(&config, &[domain.clone(), domain.clone()]);
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert!(lines[0].ends_with("\tStachelhaus\tNearest references"));
+        assert!(lines[1]
+            .ends_with("\tLeu:DAFYLGMMCK:LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW:1.00:0.50:BGC0000311"));
+
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &[domain]).unwrap()).unwrap();
+        assert_eq!(got[0]["nearest_references"][0]["name"], "Leu");
+    }
+
+    #[test]
+    fn test_stachelhaus_detail() {
+        let mut config = config();
+        config.skip_stachelhaus = false;
+        config.skip_new_stachelhaus_output = true;
+        config.stachelhaus_detail = true;
+        config.count = 2;
+
+        let mut domain = domain();
+        for (name, aa34_score) in [("Leu", 1.0), ("Val", 0.5), ("Ile", 0.25)] {
+            domain.stach_predictions.add(StachPrediction {
+                name: name.to_string(),
+                aa10_score: 1.0,
+                aa10_sig: "DAFYLGMMCK".to_string(),
+                aa34_score,
+                aa34_sig: "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+                all_substrates: name.to_string(),
+                ids: Vec::from([format!("BGC_{name}")]),
+                evidence: None,
+            });
+        }
+
+        let tsv = to_tsv(&config, std::slice::from_ref(&domain));
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert!(lines[0].contains("\tStachelhaus matches\t"));
+        assert!(lines[1].contains(
+            "\tLeu:DAFYLGMMCK:LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW:1.00:1.00:BGC_Leu|\
+             Val:DAFYLGMMCK:LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW:1.00:0.50:BGC_Val\t"
+        ));
+
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &[domain]).unwrap()).unwrap();
+        let names: Vec<&str> = got[0]["stachelhaus_matches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Leu", "Val"]);
+        assert!(got[0].get("stachelhaus").is_none());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let config = config();
+        let mut with_metadata = domain();
+        with_metadata.protein_id = Some("CAA76550.1".to_string());
+        with_metadata.module_index = Some(1);
+        with_metadata.coords = Some(Coordinates {
+            start: 120,
+            end: 530,
+        });
+        let domains = [with_metadata, domain()];
+
+        let tsv = to_tsv(&config, &domains);
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert!(lines[0].starts_with("Name\tProtein ID\tGene\tModule\tCoordinates\t8A signature"));
+        assert!(lines[1].starts_with("bpsA\tCAA76550.1\tN/A\t1\t120-530\tLDASF"));
+        assert!(lines[2].starts_with("bpsA\tN/A\tN/A\tN/A\tN/A\tLDASF"));
+
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &domains).unwrap()).unwrap();
+        assert_eq!(got[0]["protein_id"], "CAA76550.1");
+        assert_eq!(got[0]["module_index"], 1);
+        assert_eq!(
+            got[0]["coords"],
+            serde_json::json!({"start": 120, "end": 530})
+        );
+        assert!(got[0].get("gene").is_none());
+        assert!(got[1].get("protein_id").is_none());
+    }
+
+    #[test]
+    fn test_to_json_model_set() {
+        let mut config = config();
+        config.model_set = Some(ModelSetInfo {
+            nrps_rs_version: "0.2.1".to_string(),
+            version: None,
+            hash: "fnv1a64:0123456789abcdef".to_string(),
+        });
+        let got: serde_json::Value =
+            serde_json::from_str(&to_json(&config, &[domain()]).unwrap()).unwrap();
+        assert_eq!(
+            got[0]["model_set"],
+            serde_json::json!({
+                "nrps_rs_version": "0.2.1",
+                "version": null,
+                "hash": "fnv1a64:0123456789abcdef",
+            })
+        );
+    }
+}
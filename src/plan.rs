@@ -0,0 +1,128 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! What a prediction run would do, without loading the models or predicting anything.
+
+use std::path::PathBuf;
+
+use crate::config::{Config, InputFormat};
+use crate::errors::NrpsError;
+use crate::metadata::{model_counts, ModelCount};
+use crate::predictors::predictions::PredictionCategory;
+use crate::predictors::stachelhaus::parse_stachelhaus_sigs;
+use crate::read_input;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunPlan {
+    pub input: PathBuf,
+    pub input_format: InputFormat,
+    /// Number of domains parsed from the input
+    pub domains: usize,
+    pub model_dir: PathBuf,
+    /// Number of model files per enabled SVM category
+    pub model_counts: Vec<ModelCount>,
+    /// The Stachelhaus signature file and its number of signatures, if Stachelhaus is enabled
+    pub stachelhaus: Option<(PathBuf, usize)>,
+    pub count: usize,
+}
+
+impl RunPlan {
+    /// Check the config, model dir, Stachelhaus signatures and input of a run on `signature_file`
+    pub fn new(config: &Config, signature_file: PathBuf) -> Result<Self, NrpsError> {
+        let stachelhaus = if config.stachelhaus_enabled() {
+            let signatures = parse_stachelhaus_sigs(config)?;
+            Some((config.stachelhaus_signatures().clone(), signatures.len()))
+        } else {
+            None
+        };
+        let domains = read_input(config, signature_file.clone())?.len();
+
+        Ok(RunPlan {
+            input: signature_file,
+            input_format: config.input_format,
+            domains,
+            model_dir: config.model_dir().clone(),
+            model_counts: model_counts(config)?,
+            stachelhaus,
+            count: config.count,
+        })
+    }
+
+    /// Enabled SVM categories without any model files
+    pub fn empty_categories(&self) -> Vec<PredictionCategory> {
+        self.model_counts
+            .iter()
+            .filter(|count| count.models == 0)
+            .map(|count| count.category.clone())
+            .collect()
+    }
+
+    /// The plan as tab-separated `key\tvalue` lines
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Input\t{}\n", self.input.display());
+        text.push_str(&format!("Input format\t{:?}\n", self.input_format));
+        text.push_str(&format!("Domains\t{}\n", self.domains));
+        text.push_str(&format!("Model dir\t{}\n", self.model_dir.display()));
+        for count in self.model_counts.iter() {
+            text.push_str(&format!("Models\t{}\t{}\n", count.category, count.models));
+        }
+        match &self.stachelhaus {
+            Some((path, signatures)) => text.push_str(&format!(
+                "Stachelhaus signatures\t{}\t{signatures}\n",
+                path.display()
+            )),
+            None => text.push_str("Stachelhaus signatures\tdisabled\n"),
+        }
+        text.push_str(&format!("Results per category\t{}\n", self.count));
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn test_run_plan() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let single = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        fs::create_dir_all(&single).unwrap();
+        fs::write(single.join("[leu].mdl"), "not parsed").unwrap();
+        fs::write(
+            model_dir.join("signatures.tsv"),
+            "DAWFLGNVVK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tleu\tleu\tQ70AZ9\n",
+        )
+        .unwrap();
+        let input = model_dir.join("input.sigs");
+        fs::write(
+            &input,
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA_A1\n\
+             LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA_A2\n",
+        )
+        .unwrap();
+
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.clone());
+        config.skip_v3 = true;
+        config.skip_v1 = true;
+        let plan = RunPlan::new(&config, input.clone()).unwrap();
+        assert_eq!(plan.domains, 2);
+        assert_eq!(plan.stachelhaus.as_ref().unwrap().1, 1);
+        assert!(plan
+            .empty_categories()
+            .contains(&PredictionCategory::ThreeClusterV2));
+        assert!(!plan
+            .empty_categories()
+            .contains(&PredictionCategory::SingleV2));
+
+        let text = plan.to_text();
+        assert!(text.contains("Domains\t2\n"));
+        assert!(text.contains("Models\tSingleV2\t1\n"));
+
+        fs::write(&input, "too short\tbpsA_A1\n").unwrap();
+        assert!(RunPlan::new(&config, input).is_err());
+    }
+}
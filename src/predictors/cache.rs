@@ -0,0 +1,233 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use super::load_models_with_report;
+use super::predictions::PredictionCategory;
+use crate::config::Config;
+use crate::encodings::FeatureEncoding;
+use crate::errors::NrpsError;
+use crate::svm::models::{KernelParameters, KernelType, SVMlightModel};
+use crate::svm::vectors::{to_f64, SupportVectors};
+
+const MAGIC: &[u8; 8] = b"NRPSRSC3";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedModel {
+    name: String,
+    category: PredictionCategory,
+    dimensions: usize,
+    values: Vec<f64>,
+    yalphas: Vec<f64>,
+    bias: f64,
+    encoding: FeatureEncoding,
+    kernel_type: KernelType,
+    params: KernelParameters,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ModelCache {
+    version: String,
+    models: Vec<CachedModel>,
+}
+
+impl From<&SVMlightModel> for CachedModel {
+    fn from(model: &SVMlightModel) -> Self {
+        CachedModel {
+            name: model.name.to_string(),
+            category: model.category.clone(),
+            dimensions: model.vectors.dimensions(),
+            values: to_f64(model.vectors.values()).into_owned(),
+            yalphas: model.vectors.yalphas().to_vec(),
+            bias: model.bias,
+            encoding: model.encoding,
+            kernel_type: model.kernel_type,
+            params: model.params,
+        }
+    }
+}
+
+impl TryFrom<CachedModel> for SVMlightModel {
+    type Error = NrpsError;
+
+    fn try_from(cached: CachedModel) -> Result<Self, Self::Error> {
+        let vectors = SupportVectors::from_parts(cached.dimensions, cached.values, cached.yalphas)?;
+        Ok(SVMlightModel::new(
+            cached.name,
+            cached.category,
+            vectors,
+            cached.bias,
+            cached.encoding,
+            cached.kernel_type,
+            cached.params,
+        ))
+    }
+}
+
+pub fn write_cache<W>(writer: W, models: &[SVMlightModel]) -> Result<(), NrpsError>
+where
+    W: Write,
+{
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(MAGIC)?;
+    let cache = ModelCache {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        models: models.iter().map(CachedModel::from).collect(),
+    };
+    bincode::serialize_into(&mut writer, &cache)
+        .map_err(|e| NrpsError::CacheError(e.to_string()))?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_cache<R>(reader: R) -> Result<Vec<SVMlightModel>, NrpsError>
+where
+    R: Read,
+{
+    let mut reader = BufReader::new(reader);
+    let mut magic = [0_u8; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(NrpsError::CacheError("Not a model cache file".to_string()));
+    }
+    let cache: ModelCache =
+        bincode::deserialize_from(reader).map_err(|e| NrpsError::CacheError(e.to_string()))?;
+    if cache.version != env!("CARGO_PKG_VERSION") {
+        return Err(NrpsError::CacheError(format!(
+            "Cache was built by nrps-rs {}",
+            cache.version
+        )));
+    }
+    cache
+        .models
+        .into_iter()
+        .map(SVMlightModel::try_from)
+        .collect()
+}
+
+/// Parse all models in the model dir and write them to the configured cache file
+pub fn build_cache(config: &Config) -> Result<usize, NrpsError> {
+    let models = load_checked(config)?;
+    write_cache(File::create(config.model_cache())?, &models)?;
+    Ok(models.len())
+}
+
+fn load_checked(config: &Config) -> Result<Vec<SVMlightModel>, NrpsError> {
+    let (models, report) = load_models_with_report(config.model_dir(), None)?;
+    report.warn();
+    report.check(config.strict_models)?;
+    Ok(models)
+}
+
+/// Load models from the cache if it's newer than the model files, otherwise parse and cache them
+pub fn load_or_build(config: &Config) -> Result<Vec<SVMlightModel>, NrpsError> {
+    let cache_file = config.model_cache();
+    let categories = config.categories();
+
+    let cached = if is_fresh(&cache_file, config.model_dir())? {
+        Some(read_cache(File::open(&cache_file)?)?)
+    } else {
+        None
+    };
+
+    let models = match cached {
+        Some(models) => models,
+        None => {
+            let models = load_checked(config)?;
+            // The cache is an optimisation, a read-only model dir shouldn't break predictions
+            if let Err(err) = File::create(&cache_file)
+                .map_err(NrpsError::from)
+                .and_then(|handle| write_cache(handle, &models))
+            {
+                log::warn!(
+                    "Failed to write model cache {}: {err}",
+                    cache_file.display()
+                );
+                let _ = fs::remove_file(&cache_file);
+            }
+            models
+        }
+    };
+
+    Ok(models
+        .into_iter()
+        .filter(|m| categories.contains(&m.category))
+        .collect())
+}
+
+fn is_fresh(cache_file: &Path, model_dir: &Path) -> Result<bool, NrpsError> {
+    let cache_time = match fs::metadata(cache_file).and_then(|m| m.modified()) {
+        Ok(time) => time,
+        Err(_) => return Ok(false),
+    };
+
+    Ok(cache_time >= newest_model_file(model_dir, cache_file)?)
+}
+
+/// Modification time of the newest file or dir in `model_dir`, ignoring the cache file
+pub(crate) fn newest_model_file(
+    model_dir: &Path,
+    cache_file: &Path,
+) -> Result<SystemTime, NrpsError> {
+    let mut newest = SystemTime::UNIX_EPOCH;
+    for entry_res in WalkDir::new(model_dir).max_depth(2) {
+        let entry = entry_res?;
+        if entry.path() == cache_file {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        if modified > newest {
+            newest = modified;
+        }
+    }
+    Ok(newest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svm::vectors::SupportVector;
+
+    fn model() -> SVMlightModel {
+        let vectors = Vec::from([
+            SupportVector::new(Vec::from([1.0, 0.5, 0.0]), 1.0),
+            SupportVector::new(Vec::from([0.0, -0.5, 2.0]), -0.5),
+        ]);
+        SVMlightModel::new(
+            "leu".to_string(),
+            PredictionCategory::SingleV2,
+            vectors.try_into().unwrap(),
+            0.25,
+            FeatureEncoding::Wold,
+            KernelType::RBF,
+            KernelParameters::default(),
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut buffer = Vec::new();
+        write_cache(&mut buffer, &[model()]).unwrap();
+        let got = read_cache(buffer.as_slice()).unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "leu");
+        assert_eq!(got[0].category, PredictionCategory::SingleV2);
+        assert_eq!(got[0].vectors.len(), 2);
+        assert_eq!(got[0].vectors.get(1), model().vectors.get(1));
+        assert_eq!(got[0].bias, 0.25);
+        assert_eq!(got[0].kernel_type, KernelType::RBF);
+    }
+
+    #[test]
+    fn test_invalid_cache() {
+        assert!(read_cache("NOTACACHEFILE".as_bytes()).is_err());
+        assert!(read_cache("NRPSRSC1garbage".as_bytes()).is_err());
+    }
+}
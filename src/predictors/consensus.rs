@@ -0,0 +1,244 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Agreement between the best predictions of several categories.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::predictors::predictions::{ADomain, PredictionCategory};
+
+/// Categories compared by default, Stachelhaus and the single substrate SVMs
+pub const DEFAULT_SUMMARY_CATEGORIES: [PredictionCategory; 3] = [
+    PredictionCategory::Stachelhaus,
+    PredictionCategory::SingleV3,
+    PredictionCategory::SingleV2,
+];
+
+/// Weight of categories without a configured weight
+pub const DEFAULT_WEIGHT: f64 = 1.0;
+
+/// Allowed difference of weight sums, so e.g. `0.1 + 0.2` still agrees with `0.3`
+const WEIGHT_TOLERANCE: f64 = 1e-9;
+
+/// The best substrates of one category
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Vote {
+    pub category: PredictionCategory,
+    pub substrates: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default = "default_weight"))]
+    pub weight: f64,
+}
+
+#[cfg(feature = "serde")]
+fn default_weight() -> f64 {
+    DEFAULT_WEIGHT
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Agreement {
+    /// All categories agree, and there are at least two of them
+    Strong(String),
+    /// Categories with more than half of the total weight agree
+    Moderate(String),
+    /// No majority, or only a single category with predictions
+    Weak(Vec<String>),
+    /// None of the categories has predictions
+    None,
+}
+
+impl fmt::Display for Agreement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Agreement::Strong(substrate) => write!(f, "strong: all agree on {substrate}"),
+            Agreement::Moderate(substrate) => write!(f, "moderate: majority for {substrate}"),
+            Agreement::Weak(substrates) if substrates.len() == 1 => {
+                write!(f, "weak: only {} predicted", substrates[0])
+            }
+            Agreement::Weak(substrates) => write!(f, "weak: split {}", substrates.join("/")),
+            Agreement::None => write!(f, "N/A"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Consensus {
+    pub votes: Vec<Vote>,
+}
+
+impl Consensus {
+    /// Collect the best predictions of `categories`, categories without predictions don't vote
+    pub fn new(domain: &ADomain, categories: &[PredictionCategory]) -> Self {
+        Consensus::weighted(domain, categories, &BTreeMap::new())
+    }
+
+    /// Like `new`, but categories vote with their weight in `weights`, `DEFAULT_WEIGHT` if unset
+    ///
+    /// Categories weighted 0 don't vote.
+    pub fn weighted(
+        domain: &ADomain,
+        categories: &[PredictionCategory],
+        weights: &BTreeMap<PredictionCategory, f64>,
+    ) -> Self {
+        let votes = categories
+            .iter()
+            .map(|category| Vote {
+                category: category.clone(),
+                substrates: domain
+                    .get_best(category)
+                    .iter()
+                    .flat_map(|pred| pred.name.split(['|', ',']))
+                    .map(str::to_string)
+                    .collect(),
+                weight: weights.get(category).copied().unwrap_or(DEFAULT_WEIGHT),
+            })
+            .filter(|vote| !vote.substrates.is_empty() && vote.weight > 0.0)
+            .collect();
+        Consensus { votes }
+    }
+
+    /// Substrates with the summed weight of their votes, in order of first appearance
+    ///
+    /// Names are compared case-insensitively, the first spelling seen is kept.
+    pub fn tally(&self) -> Vec<(String, f64)> {
+        let mut tally: Vec<(String, f64)> = Vec::new();
+        for vote in self.votes.iter() {
+            let mut seen: Vec<String> = Vec::with_capacity(vote.substrates.len());
+            for substrate in vote.substrates.iter() {
+                let key = substrate.to_lowercase();
+                if seen.contains(&key) {
+                    continue;
+                }
+                seen.push(key);
+                match tally
+                    .iter_mut()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(substrate))
+                {
+                    Some((_, total)) => *total += vote.weight,
+                    None => tally.push((substrate.to_string(), vote.weight)),
+                }
+            }
+        }
+        tally
+    }
+
+    pub fn agreement(&self) -> Agreement {
+        let tally = self.tally();
+        let Some(max) = tally.iter().map(|(_, weight)| *weight).reduce(f64::max) else {
+            return Agreement::None;
+        };
+        let leaders: Vec<String> = tally
+            .iter()
+            .filter(|(_, weight)| *weight >= max - WEIGHT_TOLERANCE)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        let total: f64 = self.votes.iter().map(|vote| vote.weight).sum();
+        if leaders.len() == 1 && self.votes.len() > 1 {
+            if max >= total - WEIGHT_TOLERANCE {
+                return Agreement::Strong(leaders[0].clone());
+            }
+            if max * 2.0 > total + WEIGHT_TOLERANCE {
+                return Agreement::Moderate(leaders[0].clone());
+            }
+        }
+        Agreement::Weak(tally.into_iter().map(|(name, _)| name).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::predictors::predictions::Prediction;
+
+    fn domain(calls: &[(PredictionCategory, &str)]) -> ADomain {
+        let mut domain = ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        );
+        for (category, name) in calls {
+            domain.add(
+                category.clone(),
+                Prediction {
+                    name: name.to_string(),
+                    score: 1.0,
+                    probability: None,
+                    model: None,
+                },
+            );
+        }
+        domain
+    }
+
+    fn summary(calls: &[(PredictionCategory, &str)]) -> String {
+        Consensus::new(&domain(calls), &DEFAULT_SUMMARY_CATEGORIES)
+            .agreement()
+            .to_string()
+    }
+
+    #[test]
+    fn test_agreement() {
+        use PredictionCategory::*;
+
+        assert_eq!(summary(&[]), "N/A");
+        assert_eq!(summary(&[(SingleV3, "val")]), "weak: only val predicted");
+        assert_eq!(
+            summary(&[(Stachelhaus, "Val"), (SingleV3, "val"), (SingleV2, "val")]),
+            "strong: all agree on Val"
+        );
+        assert_eq!(
+            summary(&[(Stachelhaus, "Val"), (SingleV3, "val"), (SingleV2, "leu")]),
+            "moderate: majority for Val"
+        );
+        assert_eq!(
+            summary(&[(Stachelhaus, "Val"), (SingleV3, "leu")]),
+            "weak: split Val/leu"
+        );
+        // Ties count for every tied substrate
+        assert_eq!(
+            summary(&[(Stachelhaus, "Val|Leu"), (SingleV3, "leu")]),
+            "strong: all agree on Leu"
+        );
+        // Categories that aren't compared don't vote
+        assert_eq!(
+            summary(&[(SingleV3, "val"), (LargeClusterV3, "leu")]),
+            "weak: only val predicted"
+        );
+    }
+
+    #[test]
+    fn test_weighted_agreement() {
+        use PredictionCategory::*;
+
+        let calls = [(Stachelhaus, "Val"), (SingleV3, "leu"), (SingleV2, "leu")];
+        let summary = |weights: &[(PredictionCategory, f64)]| {
+            Consensus::weighted(
+                &domain(&calls),
+                &DEFAULT_SUMMARY_CATEGORIES,
+                &BTreeMap::from_iter(weights.iter().cloned()),
+            )
+            .agreement()
+            .to_string()
+        };
+
+        assert_eq!(summary(&[]), "moderate: majority for leu");
+        assert_eq!(summary(&[(Stachelhaus, 3.0)]), "moderate: majority for Val");
+        assert_eq!(summary(&[(Stachelhaus, 2.0)]), "weak: split Val/leu");
+        // Categories weighted 0 don't vote
+        assert_eq!(summary(&[(Stachelhaus, 0.0)]), "strong: all agree on leu");
+        assert_eq!(
+            summary(&[(SingleV3, 0.0), (SingleV2, 0.0)]),
+            "weak: only Val predicted"
+        );
+        assert_eq!(
+            summary(&[(SingleV3, 0.1), (SingleV2, 0.2), (Stachelhaus, 0.3)]),
+            "weak: split Val/leu"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Tree ensembles, e.g. scikit-learn random forests retrained on new data.
+//!
+//! Forest files are JSON objects with the feature `encoding` name, the `classes` and the `trees`,
+//! each a list of `nodes` starting with the root. A node is either a split
+//! `{"feature": 3, "threshold": 0.5, "left": 1, "right": 2}`, going left if the (0-based) feature
+//! is at most the threshold like scikit-learn does, or a leaf `{"value": [..]}` with a weight per
+//! class. Each class scores its share of the leaf weights, averaged over all trees.
+
+use std::io::Read;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::compression;
+use crate::config::Config;
+use crate::encodings::{encode, FeatureEncoding};
+use crate::errors::NrpsError;
+
+use super::predictions::{ADomain, Prediction, PredictionCategory};
+use super::{with_aa34, PredictionBackend};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Node {
+    Split {
+        feature: usize,
+        threshold: f64,
+        left: usize,
+        right: usize,
+    },
+    Leaf {
+        value: Vec<f64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Tree {
+    pub nodes: Vec<Node>,
+}
+
+impl Tree {
+    /// The leaf weights `features` end up at
+    fn leaf(&self, features: &[f64]) -> &[f64] {
+        let mut node = &self.nodes[0];
+        loop {
+            match node {
+                Node::Split {
+                    feature,
+                    threshold,
+                    left,
+                    right,
+                } => {
+                    let next = if features[*feature] <= *threshold {
+                        left
+                    } else {
+                        right
+                    };
+                    node = &self.nodes[*next];
+                }
+                Node::Leaf { value } => return value,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ForestFile {
+    encoding: String,
+    classes: Vec<String>,
+    trees: Vec<Tree>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RandomForest {
+    pub encoding: FeatureEncoding,
+    pub classes: Vec<String>,
+    pub trees: Vec<Tree>,
+    /// Only classes scoring above this are reported
+    pub min_score: f64,
+}
+
+impl RandomForest {
+    /// Load the configured forest file, with the configured threshold for the Forest category
+    pub fn from_config(config: &Config) -> Result<Self, NrpsError> {
+        let Some(path) = &config.forest_model else {
+            return Err(NrpsError::ForestError(
+                "The Forest category needs a forest model file".to_string(),
+            ));
+        };
+        let mut forest = RandomForest::from_file(path)?;
+        forest.min_score = config
+            .category_min_scores
+            .get(&PredictionCategory::Forest)
+            .copied()
+            .unwrap_or(config.min_score);
+        Ok(forest)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, NrpsError> {
+        let handle = compression::open(path).map_err(|source| NrpsError::FileError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        RandomForest::parse(handle).map_err(|err| match err {
+            NrpsError::ForestError(msg) => {
+                NrpsError::ForestError(format!("{}: {msg}", path.display()))
+            }
+            err => err,
+        })
+    }
+
+    pub fn parse<R: Read>(handle: R) -> Result<Self, NrpsError> {
+        let file: ForestFile = serde_json::from_reader(handle)
+            .map_err(|err| NrpsError::ForestError(err.to_string()))?;
+        let Some(encoding) = FeatureEncoding::from_name(&file.encoding) else {
+            return Err(NrpsError::ForestError(format!(
+                "unknown encoding `{}`",
+                file.encoding
+            )));
+        };
+        if file.classes.is_empty() || file.trees.is_empty() {
+            return Err(NrpsError::ForestError("no classes or no trees".to_string()));
+        }
+        for (i, tree) in file.trees.iter().enumerate() {
+            check_tree(tree, encoding.dimensions(), file.classes.len())
+                .map_err(|msg| NrpsError::ForestError(format!("tree {}: {msg}", i + 1)))?;
+        }
+        Ok(RandomForest {
+            encoding,
+            classes: file.classes,
+            trees: file.trees,
+            min_score: 0.0,
+        })
+    }
+
+    /// The score of each class for an encoded signature
+    pub fn class_scores(&self, features: &[f64]) -> Vec<f64> {
+        let mut scores = vec![0.0; self.classes.len()];
+        for tree in self.trees.iter() {
+            let value = tree.leaf(features);
+            let total: f64 = value.iter().sum();
+            if total <= 0.0 {
+                continue;
+            }
+            for (score, weight) in scores.iter_mut().zip(value.iter()) {
+                *score += weight / total;
+            }
+        }
+        scores
+            .into_iter()
+            .map(|score| score / self.trees.len() as f64)
+            .collect()
+    }
+}
+
+impl PredictionBackend for RandomForest {
+    fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+        for domain in with_aa34(domains) {
+            let features = encode(&domain.aa34, &self.encoding, &PredictionCategory::Forest);
+            for (class, score) in self.classes.iter().zip(self.class_scores(&features)) {
+                if score > self.min_score {
+                    domain.add(
+                        PredictionCategory::Forest,
+                        Prediction {
+                            name: class.to_string(),
+                            score,
+                            probability: None,
+                            model: None,
+                        },
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Children need to come after their parent, so every walk down the tree ends at a leaf
+fn check_tree(tree: &Tree, dimensions: usize, classes: usize) -> Result<(), String> {
+    if tree.nodes.is_empty() {
+        return Err("no nodes".to_string());
+    }
+    for (i, node) in tree.nodes.iter().enumerate() {
+        match node {
+            Node::Split {
+                feature,
+                left,
+                right,
+                ..
+            } => {
+                if *feature >= dimensions {
+                    return Err(format!(
+                        "node {i}: feature {feature} out of {dimensions} features"
+                    ));
+                }
+                for child in [left, right] {
+                    if *child <= i || *child >= tree.nodes.len() {
+                        return Err(format!("node {i}: invalid child {child}"));
+                    }
+                }
+            }
+            Node::Leaf { value } if value.len() != classes => {
+                return Err(format!(
+                    "node {i}: expected {classes} leaf values, got {}",
+                    value.len()
+                ));
+            }
+            Node::Leaf { .. } => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    // Feature 2 is set if the first residue is hydrophobic, like L but not A
+    const FOREST: &str = r#"{
+        "encoding": "reduced",
+        "classes": ["leu", "val"],
+        "trees": [
+            {"nodes": [
+                {"feature": 2, "threshold": 0.5, "left": 1, "right": 2},
+                {"value": [0.0, 2.0]},
+                {"value": [3.0, 1.0]}
+            ]},
+            {"nodes": [{"value": [1.0, 1.0]}]}
+        ]
+    }"#;
+
+    #[test]
+    fn test_forest_predict() {
+        let forest = RandomForest::parse(FOREST.as_bytes()).unwrap();
+        let mut domains = [
+            ADomain::new(
+                "bpsA".to_string(),
+                "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+            ),
+            ADomain::new(
+                "other".to_string(),
+                "ADASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+            ),
+        ];
+        forest.predict(&mut domains).unwrap();
+
+        assert_eq!(domains[0].get_all(&PredictionCategory::Forest).len(), 2);
+        let got = domains[0].get_best(&PredictionCategory::Forest);
+        assert_eq!(got[0].name, "leu");
+        assert_approx_eq!(got[0].score, 0.625);
+
+        let got = domains[1].get_best(&PredictionCategory::Forest);
+        assert_eq!(got[0].name, "val");
+        assert_approx_eq!(got[0].score, 0.75);
+    }
+
+    #[test]
+    fn test_forest_parse_errors() {
+        for (old, new) in [
+            ("\"reduced\"", "\"unknown\""),
+            ("\"feature\": 2", "\"feature\": 102"),
+            ("\"left\": 1", "\"left\": 0"),
+            ("\"right\": 2", "\"right\": 3"),
+            ("[3.0, 1.0]", "[3.0]"),
+            ("[\"leu\", \"val\"]", "[]"),
+        ] {
+            let text = FOREST.replace(old, new);
+            let err = RandomForest::parse(text.as_bytes()).unwrap_err();
+            assert!(matches!(err, NrpsError::ForestError(_)), "{old} -> {new}");
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! k-nearest-neighbour predictions from the Stachelhaus reference signatures.
+//!
+//! Signatures are compared by the Euclidean distance of their Wold-encoded aa34 signatures. Each
+//! of the `k` nearest references votes for its substrate with weight `1 / (1 + distance)`, a
+//! substrate scores its share of all votes.
+
+use crate::config::Config;
+use crate::encodings::{encode, FeatureEncoding};
+use crate::errors::NrpsError;
+
+use super::predictions::{ADomain, Prediction, PredictionCategory};
+use super::stachelhaus::{parse_stachelhaus_sigs, StachelhausSignature};
+use super::{with_aa34, PredictionBackend};
+
+const ENCODING: FeatureEncoding = FeatureEncoding::Wold;
+
+#[derive(Debug)]
+pub struct KnnPredictor {
+    /// Substrate and encoded aa34 signature of each reference
+    references: Vec<(String, Vec<f64>)>,
+    /// Number of references voting
+    pub k: usize,
+}
+
+impl KnnPredictor {
+    pub fn new(signatures: &[StachelhausSignature], k: usize) -> Self {
+        let references = signatures
+            .iter()
+            .map(|sig| (sig.winner.to_string(), encode_signature(&sig.aa34)))
+            .collect();
+        KnnPredictor { references, k }
+    }
+
+    /// Encode the configured Stachelhaus signatures
+    pub fn from_config(config: &Config) -> Result<Self, NrpsError> {
+        Ok(KnnPredictor::new(
+            &parse_stachelhaus_sigs(config)?,
+            config.knn_neighbours,
+        ))
+    }
+
+    /// Substrates voted for by the `k` nearest references, with their share of the votes
+    pub fn vote(&self, aa34: &str) -> Vec<Prediction> {
+        let query = encode_signature(aa34);
+        let mut distances: Vec<(f64, &str)> = self
+            .references
+            .iter()
+            .map(|(substrate, features)| (distance(&query, features), substrate.as_str()))
+            .collect();
+        distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut votes: Vec<(&str, f64)> = Vec::new();
+        for (distance, substrate) in distances.into_iter().take(self.k) {
+            let weight = 1.0 / (1.0 + distance);
+            match votes.iter_mut().find(|(name, _)| *name == substrate) {
+                Some((_, total)) => *total += weight,
+                None => votes.push((substrate, weight)),
+            }
+        }
+        let total: f64 = votes.iter().map(|(_, weight)| weight).sum();
+        votes
+            .into_iter()
+            .map(|(substrate, weight)| Prediction {
+                name: substrate.to_string(),
+                score: weight / total,
+                probability: None,
+                model: None,
+            })
+            .collect()
+    }
+}
+
+impl PredictionBackend for KnnPredictor {
+    fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+        for domain in with_aa34(domains) {
+            for pred in self.vote(&domain.aa34) {
+                domain.add(PredictionCategory::Knn, pred);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encode_signature(aa34: &str) -> Vec<f64> {
+    encode(aa34, &ENCODING, &PredictionCategory::Knn)
+}
+
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| (x - y).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::predictors::stachelhaus::extract_aa10;
+
+    fn sig(aa34: &str, winner: &str) -> StachelhausSignature {
+        StachelhausSignature {
+            aa10: extract_aa10(aa34).unwrap(),
+            aa34: aa34.to_string(),
+            all: winner.to_string(),
+            winner: winner.to_string(),
+            ids: format!("BGC_{winner}"),
+            evidence: None,
+        }
+    }
+
+    #[test]
+    fn test_knn_predict() {
+        let leu = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let signatures = [
+            sig(leu, "Leu"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATA", "Leu"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCAAA", "Val"),
+            sig("LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW", "Hpg"),
+        ];
+        let predictor = KnnPredictor::new(&signatures, 3);
+        let mut domains = [
+            ADomain::new("bpsA".to_string(), leu.to_string()),
+            ADomain::from_aa10("aa10".to_string(), "DAFYLGMMCK".to_string()),
+        ];
+        predictor.predict(&mut domains).unwrap();
+
+        let got = domains[0].get_all(&PredictionCategory::Knn);
+        assert_eq!(got.len(), 2);
+        let total: f64 = got.iter().map(|pred| pred.score).sum();
+        assert_approx_eq!(total, 1.0);
+        let best = domains[0].get_best(&PredictionCategory::Knn);
+        assert_eq!(best[0].name, "Leu");
+        assert!(best[0].score > 0.5);
+        assert!(domains[1].get_all(&PredictionCategory::Knn).is_empty());
+
+        // With a single neighbour, an exact match gets all the votes
+        let predictor = KnnPredictor::new(&signatures, 1);
+        let got = predictor.vote(leu);
+        assert_eq!(got.len(), 1);
+        assert_approx_eq!(got[0].score, 1.0);
+    }
+}
@@ -0,0 +1,369 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Optional `manifest.toml` files declaring what's otherwise inferred from dir names and models.
+//!
+//! The manifest in the model dir itself declares the model set version, the manifests in the
+//! category dirs declare the category, encoding and kernel of the models in that dir.
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::config::Config;
+use crate::encodings::FeatureEncoding;
+use crate::errors::NrpsError;
+use crate::svm::models::{KernelType, SVMlightModel};
+
+use super::is_model_file;
+use super::predictions::PredictionCategory;
+
+pub const MANIFEST_FILE: &str = "manifest.toml";
+
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// Version of the model set, model dir manifest only
+    pub version: Option<String>,
+    /// Category of the models, e.g. `SingleV2`, instead of deriving it from the dir name
+    pub category: Option<PredictionCategory>,
+    /// Name of the built-in or registered encoding all models in the dir use
+    pub encoding: Option<String>,
+    /// Kernel type all models in the dir are expected to use
+    pub kernel: Option<String>,
+}
+
+impl Manifest {
+    /// Read the manifest of `dir`, if there is one
+    pub fn from_dir(dir: &Path) -> Result<Option<Self>, NrpsError> {
+        let path = dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(Self::parse(&fs::read_to_string(&path)?, &path)?))
+    }
+
+    /// Parse the contents of the manifest at `path`
+    pub fn parse(text: &str, path: &Path) -> Result<Self, NrpsError> {
+        toml::from_str(text).map_err(|e| {
+            NrpsError::ModelDirError(format!("Invalid manifest {}: {e}", path.display()))
+        })
+    }
+
+    /// Check the declared encoding and kernel exist
+    pub fn validate(&self) -> Result<(), NrpsError> {
+        self.encoding()?;
+        self.kernel()?;
+        Ok(())
+    }
+
+    /// The declared model set version, for the manifest of the model dir itself
+    pub fn model_set_version(self, model_dir: &Path) -> Result<Option<String>, NrpsError> {
+        if self.category.is_some() || self.encoding.is_some() || self.kernel.is_some() {
+            return Err(NrpsError::ModelDirError(format!(
+                "Only `version` can be set in {}",
+                model_dir.join(MANIFEST_FILE).display()
+            )));
+        }
+        Ok(self.version)
+    }
+
+    pub fn encoding(&self) -> Result<Option<FeatureEncoding>, NrpsError> {
+        let Some(name) = &self.encoding else {
+            return Ok(None);
+        };
+        match FeatureEncoding::from_name(name) {
+            Some(encoding) => Ok(Some(encoding)),
+            None => Err(NrpsError::EncodingError(format!(
+                "Unknown encoding `{name}`, custom encodings need to be registered first"
+            ))),
+        }
+    }
+
+    pub fn kernel(&self) -> Result<Option<KernelType>, NrpsError> {
+        let Some(name) = &self.kernel else {
+            return Ok(None);
+        };
+        match KernelType::from_name(name) {
+            Some(kernel) => Ok(Some(kernel)),
+            None => Err(NrpsError::ModelDirError(format!(
+                "Unknown kernel type `{name}` in manifest"
+            ))),
+        }
+    }
+
+    /// Check that `model` matches the declared kernel
+    pub fn check_model(&self, model: &SVMlightModel) -> Result<(), NrpsError> {
+        if let Some(kernel) = self.kernel()? {
+            if model.kernel_type != kernel {
+                return Err(NrpsError::ModelDirError(format!(
+                    "{} uses a {:?} kernel, but the manifest declares {kernel:?}",
+                    model.name, model.kernel_type
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the manifest of a category dir, validating the declared values
+pub fn category_manifest(dir: &Path) -> Result<Manifest, NrpsError> {
+    let manifest = Manifest::from_dir(dir)?.unwrap_or_default();
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+/// The model set version declared in the manifest of `model_dir`, if any
+pub fn model_set_version(model_dir: &Path) -> Result<Option<String>, NrpsError> {
+    match Manifest::from_dir(model_dir)? {
+        Some(manifest) => manifest.model_set_version(model_dir),
+        None => Ok(None),
+    }
+}
+
+/// Identifies the model set that produced a result
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct ModelSetInfo {
+    pub nrps_rs_version: String,
+    /// Version declared in the model dir manifest
+    pub version: Option<String>,
+    /// Hash of the models, Stachelhaus signatures and manifests
+    pub hash: String,
+}
+
+impl ModelSetInfo {
+    pub fn from_dir(model_dir: &Path) -> Result<Self, NrpsError> {
+        if !model_dir.is_dir() {
+            return Err(NrpsError::ModelDirError(format!(
+                "Model dir '{}' doesn't exist",
+                model_dir.display()
+            )));
+        }
+        Ok(ModelSetInfo {
+            nrps_rs_version: env!("CARGO_PKG_VERSION").to_string(),
+            version: model_set_version(model_dir)?,
+            hash: model_set_hash(model_dir)?,
+        })
+    }
+
+    /// Info on the configured model dir, or on the bundled models if that doesn't exist
+    pub fn from_config(config: &Config) -> Result<Self, NrpsError> {
+        #[cfg(feature = "bundled-models")]
+        if !config.model_dir().exists() {
+            let mut hasher = Fnv1a::new();
+            for (dir_name, file_name, data) in crate::bundled::BUNDLED_MODELS.iter() {
+                hasher.add_file(&format!("{dir_name}/{file_name}"), data);
+            }
+            if let Some(data) = crate::bundled::BUNDLED_SIGNATURES {
+                hasher.add_file("signatures.tsv", data);
+            }
+            return Ok(ModelSetInfo {
+                nrps_rs_version: env!("CARGO_PKG_VERSION").to_string(),
+                version: None,
+                hash: hasher.hex(),
+            });
+        }
+
+        ModelSetInfo::from_dir(config.model_dir())
+    }
+}
+
+/// Hash all files that influence the predictions, in a stable order
+///
+/// This identifies a model set, it's not meant to be cryptographically secure.
+pub fn model_set_hash(model_dir: &Path) -> Result<String, NrpsError> {
+    let mut hasher = Fnv1a::new();
+    for entry_res in WalkDir::new(model_dir)
+        .min_depth(1)
+        .max_depth(2)
+        .sort_by_file_name()
+    {
+        let entry = entry_res?;
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy();
+        let relevant = is_model_file(path)
+            || file_name == MANIFEST_FILE
+            || (file_name == "signatures.tsv" && entry.depth() == 1);
+        if !relevant || !path.is_file() {
+            continue;
+        }
+        let relative: Vec<String> = path
+            .strip_prefix(model_dir)
+            .unwrap_or(path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        hasher.add_file(&relative.join("/"), &fs::read(path)?);
+    }
+    Ok(hasher.hex())
+}
+
+/// 64 bit FNV-1a, stable across platforms and Rust versions unlike `DefaultHasher`
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for byte in data.iter() {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn add_file(&mut self, name: &str, data: &[u8]) {
+        self.update(name.as_bytes());
+        self.update(&[0]);
+        self.update(&(data.len() as u64).to_le_bytes());
+        self.update(data);
+    }
+
+    fn hex(&self) -> String {
+        format!("fnv1a64:{:016x}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::encodings::custom::{register_encoding, CustomEncoding};
+    use crate::predictors::load_models_from_dir;
+    use crate::testing::svmlight_model;
+
+    struct Presence;
+
+    impl CustomEncoding for Presence {
+        fn encode(&self, sequence: &str) -> Vec<f64> {
+            sequence
+                .chars()
+                .map(|c| if c == '-' { 0.0 } else { 1.0 })
+                .collect()
+        }
+
+        fn dimensions(&self) -> usize {
+            34
+        }
+    }
+
+    #[test]
+    fn test_manifest_encoding() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let category_dir = model_dir.join("NRPS3_SINGLE_CLUSTER");
+        fs::create_dir_all(&category_dir).unwrap();
+        fs::write(category_dir.join("[leu].mdl"), svmlight_model(34)).unwrap();
+
+        assert!(load_models_from_dir(&model_dir, None).is_err());
+
+        fs::write(
+            category_dir.join(MANIFEST_FILE),
+            "encoding = \"test-presence\"\n",
+        )
+        .unwrap();
+        assert!(category_manifest(&category_dir).is_err());
+
+        let encoding = register_encoding("test-presence", Arc::new(Presence)).unwrap();
+        assert_eq!(
+            category_manifest(&category_dir)
+                .unwrap()
+                .encoding()
+                .unwrap(),
+            Some(encoding)
+        );
+        let models = load_models_from_dir(&model_dir, None).unwrap();
+        assert_eq!(models[0].encoding, encoding);
+        let signature = format!("A-{}", "A".repeat(32));
+        assert_eq!(models[0].predict_seq(&signature).unwrap(), 0.5);
+
+        fs::write(category_dir.join(MANIFEST_FILE), "encoding = \"wold\"\n").unwrap();
+        assert!(load_models_from_dir(&model_dir, None).is_err());
+    }
+
+    #[test]
+    fn test_manifest_category_and_kernel() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let category_dir = model_dir.join("my_models");
+        fs::create_dir_all(&category_dir).unwrap();
+        fs::write(category_dir.join("[leu].mdl"), svmlight_model(102)).unwrap();
+
+        // Unknown dir names are skipped without a manifest
+        assert!(load_models_from_dir(&model_dir, None).unwrap().is_empty());
+
+        fs::write(
+            category_dir.join(MANIFEST_FILE),
+            "category = \"SingleV2\"\nkernel = \"linear\"\n",
+        )
+        .unwrap();
+        let models = load_models_from_dir(&model_dir, None).unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].category, PredictionCategory::SingleV2);
+
+        fs::write(category_dir.join(MANIFEST_FILE), "kernel = \"rbf\"\n").unwrap();
+        fs::rename(&category_dir, model_dir.join("NRPS2_SINGLE_CLUSTER")).unwrap();
+        assert!(load_models_from_dir(&model_dir, None).is_err());
+
+        fs::write(
+            model_dir.join("NRPS2_SINGLE_CLUSTER").join(MANIFEST_FILE),
+            "kernel = \"quantum\"\n",
+        )
+        .unwrap();
+        assert!(load_models_from_dir(&model_dir, None).is_err());
+    }
+
+    #[test]
+    fn test_model_set_version() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        assert_eq!(model_set_version(&model_dir).unwrap(), None);
+
+        fs::write(model_dir.join(MANIFEST_FILE), "version = \"2.0\"\n").unwrap();
+        assert_eq!(
+            model_set_version(&model_dir).unwrap(),
+            Some("2.0".to_string())
+        );
+
+        fs::write(model_dir.join(MANIFEST_FILE), "encoding = \"wold\"\n").unwrap();
+        assert!(model_set_version(&model_dir).is_err());
+        fs::write(model_dir.join(MANIFEST_FILE), "versoin = \"2.0\"\n").unwrap();
+        assert!(model_set_version(&model_dir).is_err());
+    }
+
+    #[test]
+    fn test_model_set_hash() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let category_dir = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        fs::create_dir_all(&category_dir).unwrap();
+        fs::write(category_dir.join("[leu].mdl"), "model").unwrap();
+
+        let first = model_set_hash(&model_dir).unwrap();
+        assert!(first.starts_with("fnv1a64:"));
+        assert_eq!(first.len(), "fnv1a64:".len() + 16);
+
+        // Files that don't affect the predictions don't change the hash
+        fs::write(model_dir.join("nrps-rs.cache"), "cache").unwrap();
+        fs::write(category_dir.join("README"), "notes").unwrap();
+        assert_eq!(model_set_hash(&model_dir).unwrap(), first);
+
+        fs::write(category_dir.join("[leu].mdl"), "other model").unwrap();
+        let second = model_set_hash(&model_dir).unwrap();
+        assert_ne!(second, first);
+
+        fs::write(model_dir.join(MANIFEST_FILE), "version = \"2.0\"\n").unwrap();
+        let info = ModelSetInfo::from_dir(&model_dir).unwrap();
+        assert_eq!(info.version, Some("2.0".to_string()));
+        assert_ne!(info.hash, second);
+        assert_eq!(info.nrps_rs_version, env!("CARGO_PKG_VERSION"));
+
+        assert!(ModelSetInfo::from_dir(&model_dir.join("missing")).is_err());
+    }
+}
@@ -1,94 +1,485 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+pub mod cache;
+pub mod consensus;
+pub mod forest;
+pub mod knn;
+pub mod manifest;
 pub mod predictions;
+pub mod pwm;
 pub mod stachelhaus;
+pub mod store;
+pub mod validation;
 
-use std::fs::File;
-use std::path::Path;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
 use walkdir::WalkDir;
 
+use crate::compression::uncompressed_path;
 use crate::config::Config;
+use crate::encodings::{encode_with_options, is_legacy_layout, EncodingOptions, FeatureEncoding};
 use crate::errors::NrpsError;
-use crate::svm::models::SVMlightModel;
-use predictions::{ADomain, Prediction, PredictionCategory};
+use crate::svm::models::{KernelType, SVMlightModel};
+use crate::svm::vectors::FeatureVector;
+use forest::RandomForest;
+use knn::KnnPredictor;
+use manifest::{category_manifest, model_set_version, MANIFEST_FILE};
+use predictions::{ADomain, Attribution, Prediction, PredictionCategory};
+use pwm::{PwmPredictor, PWM_DIR};
+use stachelhaus::StachelhausDb;
+use store::ModelStore;
+
+/// A source of predictions, `run()` runs all configured backends in order
+pub trait PredictionBackend: Debug {
+    /// Add this backend's predictions to `domains`
+    fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError>;
+}
+
+/// The domains with an aa34 signature, domains from aa10-only input can't be encoded
+pub(crate) fn with_aa34(domains: &mut [ADomain]) -> impl Iterator<Item = &mut ADomain> {
+    domains.iter_mut().filter(|domain| !domain.aa34.is_empty())
+}
+
+impl<T: PredictionBackend + ?Sized> PredictionBackend for Arc<T> {
+    fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+        self.as_ref().predict(domains)
+    }
+}
 
 #[derive(Debug)]
-pub struct Predictor {
-    pub models: Vec<SVMlightModel>,
-}
-
-impl Predictor {
-    pub fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
-        for model in self.models.iter() {
-            for domain in domains.iter_mut() {
-                let score = model.predict_seq(&domain.aa34)?;
-                if score > 0.0 {
-                    let pred = Prediction {
-                        name: model.name.to_string(),
-                        score,
+pub struct SvmPredictor {
+    pub store: ModelStore,
+    /// Only predictions scoring above this are reported
+    pub min_score: f64,
+    /// Per-category thresholds overriding `min_score`
+    pub category_min_scores: BTreeMap<PredictionCategory, f64>,
+    /// Attribute the predictions of linear models to the signature positions
+    pub attributions: bool,
+    /// How gaps and ambiguous residues are encoded
+    pub encoding_options: EncodingOptions,
+}
+
+impl SvmPredictor {
+    /// Models are only loaded once their category is predicted
+    pub fn from_config(config: &Config) -> Result<Self, NrpsError> {
+        Ok(SvmPredictor {
+            store: ModelStore::new(config),
+            min_score: config.min_score,
+            category_min_scores: config.category_min_scores.clone(),
+            attributions: config.attributions,
+            encoding_options: config.encoding_options()?,
+        })
+    }
+
+    /// The threshold predictions of `category` need to score above
+    pub fn min_score_for(&self, category: &PredictionCategory) -> f64 {
+        self.category_min_scores
+            .get(category)
+            .copied()
+            .unwrap_or(self.min_score)
+    }
+
+    /// Predict only `categories`, models of other categories aren't loaded
+    pub fn predict_categories(
+        &self,
+        domains: &mut [ADomain],
+        categories: &[PredictionCategory],
+    ) -> Result<(), NrpsError> {
+        // Models sharing an encoding and layout all see the same feature vectors, domains from
+        // aa10-only input don't have any
+        let mut features: HashMap<(FeatureEncoding, bool), Vec<Option<FeatureVector>>> =
+            HashMap::new();
+        for category in categories.iter() {
+            let models = self.store.models_for(category)?;
+            let min_score = self.min_score_for(category);
+            let start = Instant::now();
+            for model in models.iter() {
+                let key = (
+                    model.encoding,
+                    is_legacy_layout(&model.encoding, &model.category),
+                );
+                let vectors = match features.entry(key) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(
+                        domains
+                            .iter()
+                            .map(|domain| {
+                                if domain.aa34.is_empty() {
+                                    return Ok(None);
+                                }
+                                let encoded = encode_with_options(
+                                    &domain.aa34,
+                                    &model.encoding,
+                                    &model.category,
+                                    &self.encoding_options,
+                                )?;
+                                Ok(Some(FeatureVector::new(encoded)))
+                            })
+                            .collect::<Result<Vec<_>, NrpsError>>()?,
+                    ),
+                };
+                for (domain, vector) in domains.iter_mut().zip(vectors.iter()) {
+                    let Some(vector) = vector else {
+                        continue;
                     };
-                    domain.add(model.category, pred);
+                    let score = model.predict(vector)?;
+                    if score > min_score {
+                        if self.attributions
+                            && model.kernel_type == KernelType::Linear
+                            && !model.vectors.is_empty()
+                        {
+                            domain.attributions.push(Attribution {
+                                category: model.category.clone(),
+                                model: model.name.to_string(),
+                                positions: model.position_contributions_with_options(
+                                    &domain.aa34,
+                                    &self.encoding_options,
+                                )?,
+                            });
+                        }
+                        let pred = Prediction {
+                            name: model.name.to_string(),
+                            score,
+                            probability: model.calibration.map(|platt| platt.probability(score)),
+                            model: Some(model.id()),
+                        };
+                        domain.add(model.category.clone(), pred);
+                    }
                 }
             }
+            if !models.is_empty() {
+                log::debug!("Predicted {category} in {:.2?}", start.elapsed());
+            }
         }
         Ok(())
     }
 }
 
+impl PredictionBackend for SvmPredictor {
+    fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+        self.predict_categories(domains, &self.store.categories())
+    }
+}
+
+/// The backends besides the SVMs, loaded if enabled in the config
+#[derive(Debug, Default)]
+pub struct OptionalBackends {
+    pub stachelhaus: Option<StachelhausDb>,
+    pub knn: Option<KnnPredictor>,
+    pub forest: Option<RandomForest>,
+    pub pwm: Option<PwmPredictor>,
+}
+
+impl OptionalBackends {
+    pub fn from_config(config: &Config) -> Result<Self, NrpsError> {
+        Ok(OptionalBackends {
+            stachelhaus: config
+                .stachelhaus_enabled()
+                .then(|| StachelhausDb::from_config(config))
+                .transpose()?,
+            knn: config
+                .knn_enabled()
+                .then(|| KnnPredictor::from_config(config))
+                .transpose()?,
+            forest: config
+                .forest_enabled()
+                .then(|| RandomForest::from_config(config))
+                .transpose()?,
+            pwm: config
+                .pwm_enabled()
+                .then(|| PwmPredictor::from_config(config))
+                .transpose()?,
+        })
+    }
+
+    /// The loaded backends, in prediction order
+    pub fn backends(&self) -> Vec<&dyn PredictionBackend> {
+        let mut backends: Vec<&dyn PredictionBackend> = Vec::with_capacity(4);
+        if let Some(stachelhaus) = &self.stachelhaus {
+            backends.push(stachelhaus);
+        }
+        if let Some(knn) = &self.knn {
+            backends.push(knn);
+        }
+        if let Some(forest) = &self.forest {
+            backends.push(forest);
+        }
+        if let Some(pwm) = &self.pwm {
+            backends.push(pwm);
+        }
+        backends
+    }
+
+    fn into_boxed(self) -> Vec<Box<dyn PredictionBackend>> {
+        let mut backends: Vec<Box<dyn PredictionBackend>> = Vec::with_capacity(5);
+        if let Some(stachelhaus) = self.stachelhaus {
+            backends.push(Box::new(stachelhaus));
+        }
+        if let Some(knn) = self.knn {
+            backends.push(Box::new(knn));
+        }
+        if let Some(forest) = self.forest {
+            backends.push(Box::new(forest));
+        }
+        if let Some(pwm) = self.pwm {
+            backends.push(Box::new(pwm));
+        }
+        backends
+    }
+}
+
+/// The backends enabled by `config`, the SVMs last
+pub fn load_backends(config: &Config) -> Result<Vec<Box<dyn PredictionBackend>>, NrpsError> {
+    let mut backends = OptionalBackends::from_config(config)?.into_boxed();
+    backends.push(Box::new(SvmPredictor::from_config(config)?));
+    Ok(backends)
+}
+
 pub fn load_models(config: &Config) -> Result<Vec<SVMlightModel>, NrpsError> {
+    #[cfg(feature = "bundled-models")]
+    if !config.model_dir().exists() {
+        let mut models = crate::bundled::load_bundled_models(&config.categories())?;
+        models.retain(|model| is_wanted_model(&model.name, &config.model_substrates));
+        return Ok(models);
+    }
+
+    if config.cache_models {
+        let mut models = cache::load_or_build(config)?;
+        models.retain(|model| is_wanted_model(&model.name, &config.model_substrates));
+        return Ok(models);
+    }
+
+    let (models, report) = load_models_for_substrates(
+        config.model_dir(),
+        Some(&config.categories()),
+        &config.model_substrates,
+    )?;
+    report.warn();
+    report.check(config.strict_models)?;
+    Ok(models)
+}
+
+/// Dirs and files in a model dir that weren't loaded
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LoadReport {
+    /// Dirs that are neither a known nor a valid custom category
+    pub unknown_dirs: Vec<PathBuf>,
+    /// Files in loaded category dirs that aren't model files
+    pub skipped_files: Vec<PathBuf>,
+}
+
+impl LoadReport {
+    pub fn is_clean(&self) -> bool {
+        self.unknown_dirs.is_empty() && self.skipped_files.is_empty()
+    }
+
+    /// Log a warning for every skipped dir and file
+    pub fn warn(&self) {
+        for dir in self.unknown_dirs.iter() {
+            log::warn!("Skipping unknown model dir {}", dir.display());
+        }
+        for file in self.skipped_files.iter() {
+            log::warn!("Skipping non-model file {}", file.display());
+        }
+    }
+
+    /// Fail if anything was skipped and `strict` is set
+    pub fn check(&self, strict: bool) -> Result<(), NrpsError> {
+        if !strict || self.is_clean() {
+            return Ok(());
+        }
+        let skipped: Vec<String> = self
+            .unknown_dirs
+            .iter()
+            .chain(self.skipped_files.iter())
+            .map(|path| path.display().to_string())
+            .collect();
+        Err(NrpsError::ModelDirError(format!(
+            "Unknown model dirs or files: {}",
+            skipped.join(", ")
+        )))
+    }
+}
+
+/// Hidden files and manifests aren't reported as skipped
+pub(crate) fn is_expected_extra(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        name == MANIFEST_FILE || name == PWM_DIR || name.to_string_lossy().starts_with('.')
+    })
+}
+
+/// Load all models from `model_dir`, optionally restricted to the given categories
+///
+/// Skipped dirs and files are logged as warnings, see `load_models_with_report`.
+pub fn load_models_from_dir(
+    model_dir: &Path,
+    categories: Option<&[PredictionCategory]>,
+) -> Result<Vec<SVMlightModel>, NrpsError> {
+    let (models, report) = load_models_with_report(model_dir, categories)?;
+    report.warn();
+    Ok(models)
+}
+
+/// Load all models from `model_dir`, also reporting the dirs and files that were skipped
+pub fn load_models_with_report(
+    model_dir: &Path,
+    categories: Option<&[PredictionCategory]>,
+) -> Result<(Vec<SVMlightModel>, LoadReport), NrpsError> {
+    load_models_for_substrates(model_dir, categories, &[])
+}
+
+/// Like `load_models_with_report`, but only parse the models of `substrates`, all if empty
+///
+/// See `is_wanted_model` for how model names are matched.
+pub fn load_models_for_substrates(
+    model_dir: &Path,
+    categories: Option<&[PredictionCategory]>,
+    substrates: &[String],
+) -> Result<(Vec<SVMlightModel>, LoadReport), NrpsError> {
+    if !model_dir.is_dir() {
+        return Err(NrpsError::ModelDirError(format!(
+            "Model dir '{}' doesn't exist",
+            model_dir.display()
+        )));
+    }
     let mut models = Vec::with_capacity(1000);
+    let mut report = LoadReport::default();
 
-    for category_dir_res in WalkDir::new(config.model_dir())
+    if let Some(version) = model_set_version(model_dir)? {
+        log::debug!("Model set version {version}");
+    }
+
+    for category_dir_res in WalkDir::new(model_dir)
         .min_depth(1)
         .max_depth(1)
         .sort_by_file_name()
     {
         let category_dir = category_dir_res?;
-        let category = match category_dir.file_name().to_str().unwrap() {
-            "NRPS3_THREE_CLUSTER" => PredictionCategory::ThreeClusterV3,
-            "NRPS3_LARGE_CLUSTER" => PredictionCategory::LargeClusterV3,
-            "NRPS3_SMALL_CLUSTER" => PredictionCategory::SmallClusterV3,
-            "NRPS3_SINGLE_CLUSTER" => PredictionCategory::SingleV3,
-            "NRPS2_THREE_CLUSTER" => PredictionCategory::ThreeClusterV2,
-            "NRPS2_THREE_CLUSTER_FUNGAL" => PredictionCategory::ThreeClusterFungalV2,
-            "NRPS2_LARGE_CLUSTER" => PredictionCategory::LargeClusterV2,
-            "NRPS2_SMALL_CLUSTER" => PredictionCategory::SmallClusterV2,
-            "NRPS2_SINGLE_CLUSTER" => PredictionCategory::SingleV2,
-            "NRPS1_LARGE_CLUSTER" => PredictionCategory::LargeClusterV1,
-            "NRPS1_SMALL_CLUSTER" => PredictionCategory::SmallClusterV1,
-            _ => continue,
+        if !category_dir.path().is_dir() {
+            continue;
+        }
+        let manifest = category_manifest(category_dir.path())?;
+        let Some(category) = manifest.category.clone().or_else(|| {
+            PredictionCategory::from_dir_name(&category_dir.file_name().to_string_lossy())
+        }) else {
+            if !is_expected_extra(category_dir.path()) {
+                report.unknown_dirs.push(category_dir.path().to_path_buf());
+            }
+            continue;
         };
 
-        if !config.categories().contains(&category) {
-            continue;
+        if let Some(categories) = categories {
+            if !categories.contains(&category) {
+                log::debug!("Skipping disabled category {category}");
+                continue;
+            }
         }
 
+        let start = Instant::now();
+        let before = models.len();
+        let encoding = manifest.encoding()?;
+
         for model_file_res in WalkDir::new(category_dir.path())
             .min_depth(1)
             .max_depth(1)
             .sort_by_file_name()
         {
             let model_file = model_file_res?.path().to_path_buf();
-            if let Some(ext) = model_file.extension() {
-                if ext != "mdl" {
-                    continue;
+            if !is_model_file(&model_file) {
+                if !is_expected_extra(&model_file) {
+                    report.skipped_files.push(model_file);
                 }
-            } else {
                 continue;
             }
             let name = extract_name(&model_file);
-            let handle = File::open(&model_file)?;
-            models.push(SVMlightModel::from_handle(handle, name, category)?);
+            if !is_wanted_model(&name, substrates) {
+                log::trace!("Skipping unwanted model {}", model_file.display());
+                continue;
+            }
+            let model = SVMlightModel::from_file(&model_file, name, category.clone(), encoding)?;
+            manifest.check_model(&model)?;
+            models.push(model);
         }
+        log::debug!(
+            "Loaded {} {category} models in {:.2?}",
+            models.len() - before,
+            start.elapsed()
+        );
     }
 
-    Ok(models)
+    log::debug!(
+        "Loaded {} models from {}",
+        models.len(),
+        model_dir.display()
+    );
+    Ok((models, report))
 }
 
-fn extract_name(filename: &Path) -> String {
+/// The custom categories of the model dirs in `model_dir` that hold any model files
+pub fn custom_categories(model_dir: &Path) -> Result<Vec<PredictionCategory>, NrpsError> {
+    let mut categories = Vec::new();
+    if !model_dir.is_dir() {
+        return Ok(categories);
+    }
+    for category_dir_res in WalkDir::new(model_dir)
+        .min_depth(1)
+        .max_depth(1)
+        .sort_by_file_name()
+    {
+        let category_dir = category_dir_res?;
+        if !category_dir.path().is_dir() {
+            continue;
+        }
+        let Some(category) = category_manifest(category_dir.path())?
+            .category
+            .or_else(|| {
+                PredictionCategory::from_dir_name(&category_dir.file_name().to_string_lossy())
+            })
+            .filter(PredictionCategory::is_custom)
+        else {
+            continue;
+        };
+        let has_models = WalkDir::new(category_dir.path())
+            .min_depth(1)
+            .max_depth(1)
+            .into_iter()
+            .filter_map(Result::ok)
+            .any(|entry| is_model_file(entry.path()));
+        if has_models && !categories.contains(&category) {
+            categories.push(category);
+        }
+    }
+    Ok(categories)
+}
+
+/// Whether `path` is an SVMlight model file, possibly compressed
+pub fn is_model_file(path: &Path) -> bool {
+    uncompressed_path(path)
+        .extension()
+        .is_some_and(|ext| ext == "mdl")
+}
+
+/// Whether a model named `name` predicts one of `substrates`, all models do if it's empty
+///
+/// Names are compared case-insensitively, models of combined substrates like `val|leu` match if
+/// any of their substrates does.
+pub fn is_wanted_model(name: &str, substrates: &[String]) -> bool {
+    substrates.is_empty()
+        || name.split(['|', ',']).any(|substrate| {
+            substrates
+                .iter()
+                .any(|wanted| wanted.eq_ignore_ascii_case(substrate))
+        })
+}
+
+pub fn extract_name(filename: &Path) -> String {
     let square_brackets: &[_] = &['[', ']'];
-    filename
+    uncompressed_path(filename)
         .file_stem()
         .unwrap()
         .to_str()
@@ -96,3 +487,413 @@ fn extract_name(filename: &Path) -> String {
         .trim_matches(square_brackets)
         .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::svm::models::KernelParameters;
+    use crate::svm::vectors::SupportVectors;
+    use predictions::ModelId;
+
+    fn model(name: &str, bias: f64) -> SVMlightModel {
+        SVMlightModel::new(
+            name.to_string(),
+            PredictionCategory::SingleV3,
+            SupportVectors::default(),
+            bias,
+            FeatureEncoding::Wold,
+            KernelType::Linear,
+            KernelParameters::default(),
+        )
+    }
+
+    #[test]
+    fn test_predict_attributions() {
+        let mut vectors = SupportVectors::new(102);
+        let values: Vec<f64> = (0..102).map(|i| (i % 5) as f64 * 0.1).collect();
+        vectors.push(&values, 1.0).unwrap();
+        let linear = SVMlightModel::new(
+            "leu".to_string(),
+            PredictionCategory::SingleV3,
+            vectors,
+            -0.5,
+            FeatureEncoding::Wold,
+            KernelType::Linear,
+            KernelParameters::default(),
+        );
+        let mut predictor = SvmPredictor {
+            store: ModelStore::from_models(Vec::from([linear, model("val", -0.5)])),
+            min_score: f64::NEG_INFINITY,
+            category_min_scores: BTreeMap::new(),
+            attributions: false,
+            encoding_options: EncodingOptions::default(),
+        };
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string();
+
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.clone())];
+        predictor.predict(&mut domains).unwrap();
+        assert!(domains[0].attributions.is_empty());
+
+        predictor.attributions = true;
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34)];
+        predictor.predict(&mut domains).unwrap();
+        // Models without support vectors have nothing to attribute
+        assert_eq!(domains[0].attributions.len(), 1);
+        let attribution = domains[0]
+            .attribution(&PredictionCategory::SingleV3, "leu")
+            .unwrap();
+        assert_eq!(attribution.positions.len(), 34);
+        let total: f64 = attribution.positions.iter().map(|p| p.contribution).sum();
+        let all = domains[0].get_all(&PredictionCategory::SingleV3);
+        let score = all.iter().find(|p| p.name == "leu").unwrap().score;
+        // The bias isn't attributed to any position
+        assert_approx_eq!(total + 0.5, score);
+    }
+
+    #[test]
+    fn test_predict_min_score() {
+        let mut predictor = SvmPredictor {
+            store: ModelStore::from_models(Vec::from([model("leu", -0.5), model("val", 0.5)])),
+            min_score: 0.0,
+            category_min_scores: BTreeMap::new(),
+            attributions: false,
+            encoding_options: EncodingOptions::default(),
+        };
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string();
+
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.clone())];
+        predictor.predict(&mut domains).unwrap();
+        let got = domains[0].get_all(&PredictionCategory::SingleV3);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "leu");
+        assert_eq!(
+            got[0].model,
+            Some(ModelId::new(PredictionCategory::SingleV3, "leu"))
+        );
+
+        predictor.min_score = f64::NEG_INFINITY;
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.clone())];
+        predictor.predict(&mut domains).unwrap();
+        let got = domains[0].get_all(&PredictionCategory::SingleV3);
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[1].name, "val");
+        assert_eq!(got[1].score, -0.5);
+
+        predictor.min_score = 0.0;
+        predictor
+            .category_min_scores
+            .insert(PredictionCategory::SingleV3, 0.75);
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34)];
+        predictor.predict(&mut domains).unwrap();
+        assert!(domains[0].get_all(&PredictionCategory::SingleV3).is_empty());
+    }
+
+    #[test]
+    fn test_predict_mixed_encodings() {
+        let models: Vec<SVMlightModel> = [
+            (FeatureEncoding::Wold, PredictionCategory::SingleV3),
+            (FeatureEncoding::Rausch, PredictionCategory::SingleV3),
+            (FeatureEncoding::Rausch, PredictionCategory::LargeClusterV1),
+            (FeatureEncoding::Blin, PredictionCategory::SingleV3),
+            (FeatureEncoding::Reduced, PredictionCategory::SingleV3),
+        ]
+        .iter()
+        .map(|(encoding, category)| {
+            let values: Vec<f64> = (0..encoding.dimensions())
+                .map(|i| (i % 7) as f64 * 0.1)
+                .collect();
+            let mut vectors = SupportVectors::new(values.len());
+            vectors.push(&values, 1.0).unwrap();
+            SVMlightModel::new(
+                format!("{encoding:?}"),
+                category.clone(),
+                vectors,
+                0.0,
+                *encoding,
+                KernelType::Linear,
+                KernelParameters::default(),
+            )
+        })
+        .collect();
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let expected: Vec<(PredictionCategory, String, f64)> = models
+            .iter()
+            .map(|m| {
+                (
+                    m.category.clone(),
+                    m.name.clone(),
+                    m.predict_seq(aa34).unwrap(),
+                )
+            })
+            .collect();
+
+        let predictor = SvmPredictor {
+            store: ModelStore::from_models(models),
+            min_score: f64::NEG_INFINITY,
+            category_min_scores: BTreeMap::new(),
+            attributions: false,
+            encoding_options: EncodingOptions::default(),
+        };
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.to_string())];
+        predictor.predict(&mut domains).unwrap();
+
+        for (category, name, score) in expected {
+            let got = domains[0].get_all(&category);
+            let pred = got.iter().find(|p| p.name == name).unwrap();
+            assert_eq!(pred.score, score);
+        }
+    }
+
+    #[test]
+    fn test_load_compressed_models() {
+        use std::fs::File;
+
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let category_dir = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        std::fs::create_dir_all(&category_dir).unwrap();
+        model("leu", 0.5)
+            .write(
+                &mut File::create(category_dir.join("[leu].mdl")).unwrap(),
+                0,
+            )
+            .unwrap();
+        let mut encoder = GzEncoder::new(
+            File::create(category_dir.join("[val].mdl.gz")).unwrap(),
+            Compression::default(),
+        );
+        model("val", 0.5).write(&mut encoder, 0).unwrap();
+        encoder.finish().unwrap();
+
+        let models = load_models_from_dir(&model_dir, None).unwrap();
+        let names: Vec<&str> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["leu", "val"]);
+        assert_eq!(models[1].bias, 0.5);
+    }
+
+    #[test]
+    fn test_custom_categories() {
+        use std::fs::{self, File};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        for dir_name in [
+            "MY_CUSTOM_SET",
+            "NRPS3_SINGLE_CLUSTER",
+            "EMPTY_SET",
+            "notes",
+        ] {
+            fs::create_dir_all(model_dir.join(dir_name)).unwrap();
+        }
+        for dir_name in ["MY_CUSTOM_SET", "NRPS3_SINGLE_CLUSTER", "notes"] {
+            model("leu", -0.5)
+                .write(
+                    &mut File::create(model_dir.join(dir_name).join("[leu].mdl")).unwrap(),
+                    0,
+                )
+                .unwrap();
+        }
+
+        let custom = PredictionCategory::custom("MY_CUSTOM_SET").unwrap();
+        assert_eq!(
+            custom_categories(&model_dir).unwrap(),
+            Vec::from([custom.clone()])
+        );
+
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.clone());
+        config.skip_stachelhaus = true;
+        config.discover_custom_categories().unwrap();
+        assert_eq!(config.categories().last(), Some(&custom));
+
+        let predictor = SvmPredictor::from_config(&config).unwrap();
+        let mut domains = [ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        )];
+        predictor.predict(&mut domains).unwrap();
+        let got = domains[0].get_all(&custom);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "leu");
+
+        let tsv = crate::output::to_tsv(&config, &domains);
+        assert!(tsv.lines().next().unwrap().ends_with("\tMY_CUSTOM_SET"));
+
+        config.enabled_categories = Some(Vec::from([PredictionCategory::SingleV3]));
+        assert_eq!(config.categories(), [PredictionCategory::SingleV3]);
+        config.enabled_categories = Some(Vec::from(["MY_CUSTOM_SET".parse().unwrap()]));
+        assert_eq!(config.categories(), [custom]);
+    }
+
+    #[test]
+    fn test_with_aa34() {
+        let mut domains = vec![
+            ADomain::from_aa10("aa10".to_string(), "DAWTIAAVCK".to_string()),
+            ADomain::new("aa34".to_string(), "A".repeat(34)),
+        ];
+        let names: Vec<&str> = with_aa34(&mut domains)
+            .map(|domain| domain.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["aa34"]);
+    }
+
+    #[test]
+    fn test_optional_backends() {
+        let mut config = Config::new();
+        config.skip_stachelhaus = true;
+        let backends = OptionalBackends::from_config(&config).unwrap();
+        assert!(backends.backends().is_empty());
+        assert_eq!(load_backends(&config).unwrap().len(), 1);
+
+        // Enabled backends fail to load without their data
+        config.skip_stachelhaus = false;
+        let tmp = tempfile::tempdir().unwrap();
+        config.set_model_dir(tmp.path().join("missing"));
+        assert!(OptionalBackends::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_encoding_tables() {
+        use std::fs;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let path = dir.join("tables.toml");
+        fs::write(&path, "[wold.size]\nvalues = { A = 3.0 }\n").unwrap();
+
+        let mut config = Config::new();
+        config.encoding_tables = Some(path);
+        let predictor = SvmPredictor::from_config(&config).unwrap();
+        let tables = &predictor.encoding_options.tables;
+        assert!(tables.overrides(&FeatureEncoding::Wold));
+        assert_eq!(
+            tables.get(&FeatureEncoding::Wold).unwrap()[1].values[&'A'],
+            3.0
+        );
+
+        config.encoding_tables = Some(dir.join("missing.toml"));
+        assert!(matches!(
+            SvmPredictor::from_config(&config),
+            Err(NrpsError::FileError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_load_report() {
+        use std::fs;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let single = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        let typo = model_dir.join("NRPS3_THREECLUSTER");
+        fs::create_dir_all(&single).unwrap();
+        fs::create_dir_all(&typo).unwrap();
+        fs::write(single.join("README"), "not a model").unwrap();
+        fs::write(single.join(".hidden"), "not a model").unwrap();
+
+        let (models, report) = load_models_with_report(&model_dir, None).unwrap();
+        assert!(models.is_empty());
+        assert_eq!(report.unknown_dirs, [typo]);
+        assert_eq!(report.skipped_files, [single.join("README")]);
+        assert!(report.check(false).is_ok());
+        assert!(report.check(true).is_err());
+
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.clone());
+        config.skip_stachelhaus = true;
+        config.strict_models = true;
+        let predictor = SvmPredictor::from_config(&config).unwrap();
+        let mut domains = [ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        )];
+        assert!(predictor.predict(&mut domains).is_err());
+    }
+
+    #[test]
+    fn test_load_models_for_substrates() {
+        use std::fs::{self, File};
+
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let single = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        fs::create_dir_all(&single).unwrap();
+        for name in ["leu", "val", "orn|lys"] {
+            model(name, 0.5)
+                .write(
+                    &mut File::create(single.join(format!("[{name}].mdl"))).unwrap(),
+                    0,
+                )
+                .unwrap();
+        }
+
+        let wanted = Vec::from(["Leu".to_string(), "lys".to_string()]);
+        let (models, report) = load_models_for_substrates(&model_dir, None, &wanted).unwrap();
+        let names: Vec<&str> = models.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["leu", "orn|lys"]);
+        assert!(report.is_clean());
+
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.clone());
+        config.model_substrates = Vec::from(["val".to_string()]);
+        let models = load_models(&config).unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "val");
+        config.model_substrates.clear();
+        assert_eq!(load_models(&config).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_load_models_dimension_mismatch() {
+        use std::fs::{self, File};
+        use std::sync::Arc;
+
+        use crate::encodings::custom::{register_encoding, CustomEncoding};
+
+        /// Declares one feature per residue, but encodes two
+        struct Miscounted;
+
+        impl CustomEncoding for Miscounted {
+            fn encode(&self, sequence: &str) -> Vec<f64> {
+                sequence.chars().flat_map(|_| [0.0, 1.0]).collect()
+            }
+
+            fn dimensions(&self) -> usize {
+                34
+            }
+        }
+
+        let encoding = register_encoding("test-miscounted", Arc::new(Miscounted)).unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let single = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        fs::create_dir_all(&single).unwrap();
+        fs::write(
+            single.join(MANIFEST_FILE),
+            "encoding = \"test-miscounted\"\n",
+        )
+        .unwrap();
+        let mut leu = model("leu", 0.5);
+        leu.encoding = encoding;
+        leu.write(&mut File::create(single.join("[leu].mdl")).unwrap(), 0)
+            .unwrap();
+
+        match load_models_from_dir(&model_dir, None) {
+            Err(NrpsError::ModelDimensionError {
+                path,
+                expected,
+                found,
+                ..
+            }) => {
+                assert_eq!(path, single.join("[leu].mdl"));
+                assert_eq!((expected, found), (68, 34));
+            }
+            other => panic!("expected a dimension error, got {other:?}"),
+        }
+    }
+}
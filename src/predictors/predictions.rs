@@ -1,18 +1,32 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
-use std::cmp::min;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::stachelhaus::extract_aa10;
+use crate::svm::models::PositionContribution;
 
-#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+/// Serialized, parsed and printed by its public name, e.g. `ThreeClusterV3`
+#[derive(Debug, Clone, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum PredictionCategory {
     ThreeClusterV3,
     LargeClusterV3,
     SmallClusterV3,
     SingleV3,
     Stachelhaus,
+    /// Distance-weighted vote of the nearest Stachelhaus references in feature space
+    Knn,
+    /// Tree ensemble loaded from a forest file, see `predictors::forest`
+    Forest,
+    /// Position weight matrices built from aligned signatures, see `predictors::pwm`
+    Pwm,
     ThreeClusterV2,
     ThreeClusterFungalV2,
     LargeClusterV2,
@@ -20,22 +34,283 @@ pub enum PredictionCategory {
     SingleV2,
     LargeClusterV1,
     SmallClusterV1,
+    /// A user-defined category, named after its model dir, e.g. `MY_CUSTOM_SET`
+    Custom(Arc<str>),
+}
+
+/// Public name and model directory name of each built-in category
+const CATEGORY_NAMES: [(PredictionCategory, &str, Option<&str>); 15] = [
+    (
+        PredictionCategory::ThreeClusterV3,
+        "ThreeClusterV3",
+        Some("NRPS3_THREE_CLUSTER"),
+    ),
+    (
+        PredictionCategory::LargeClusterV3,
+        "LargeClusterV3",
+        Some("NRPS3_LARGE_CLUSTER"),
+    ),
+    (
+        PredictionCategory::SmallClusterV3,
+        "SmallClusterV3",
+        Some("NRPS3_SMALL_CLUSTER"),
+    ),
+    (
+        PredictionCategory::SingleV3,
+        "SingleV3",
+        Some("NRPS3_SINGLE_CLUSTER"),
+    ),
+    (PredictionCategory::Stachelhaus, "Stachelhaus", None),
+    (PredictionCategory::Knn, "Knn", None),
+    (PredictionCategory::Forest, "Forest", None),
+    (PredictionCategory::Pwm, "Pwm", None),
+    (
+        PredictionCategory::ThreeClusterV2,
+        "ThreeClusterV2",
+        Some("NRPS2_THREE_CLUSTER"),
+    ),
+    (
+        PredictionCategory::ThreeClusterFungalV2,
+        "ThreeClusterFungalV2",
+        Some("NRPS2_THREE_CLUSTER_FUNGAL"),
+    ),
+    (
+        PredictionCategory::LargeClusterV2,
+        "LargeClusterV2",
+        Some("NRPS2_LARGE_CLUSTER"),
+    ),
+    (
+        PredictionCategory::SmallClusterV2,
+        "SmallClusterV2",
+        Some("NRPS2_SMALL_CLUSTER"),
+    ),
+    (
+        PredictionCategory::SingleV2,
+        "SingleV2",
+        Some("NRPS2_SINGLE_CLUSTER"),
+    ),
+    (
+        PredictionCategory::LargeClusterV1,
+        "LargeClusterV1",
+        Some("NRPS1_LARGE_CLUSTER"),
+    ),
+    (
+        PredictionCategory::SmallClusterV1,
+        "SmallClusterV1",
+        Some("NRPS1_SMALL_CLUSTER"),
+    ),
+];
+
+impl PredictionCategory {
+    /// All built-in categories, in output order
+    pub const ALL: [PredictionCategory; 15] = [
+        PredictionCategory::ThreeClusterV3,
+        PredictionCategory::LargeClusterV3,
+        PredictionCategory::SmallClusterV3,
+        PredictionCategory::SingleV3,
+        PredictionCategory::Stachelhaus,
+        PredictionCategory::Knn,
+        PredictionCategory::Forest,
+        PredictionCategory::Pwm,
+        PredictionCategory::ThreeClusterV2,
+        PredictionCategory::LargeClusterV2,
+        PredictionCategory::SmallClusterV2,
+        PredictionCategory::SingleV2,
+        PredictionCategory::ThreeClusterFungalV2,
+        PredictionCategory::LargeClusterV1,
+        PredictionCategory::SmallClusterV1,
+    ];
+
+    /// A custom category, `name` needs to consist of upper case letters, digits and underscores
+    ///
+    /// Names starting like the built-in dir names, e.g. `NRPS3_`, are reserved, so typos in
+    /// those aren't loaded as custom categories.
+    pub fn custom(name: &str) -> Result<Self, String> {
+        if !PredictionCategory::is_custom_name(name) {
+            return Err(format!("invalid custom category name `{name}`"));
+        }
+        Ok(PredictionCategory::Custom(Arc::from(name)))
+    }
+
+    /// Whether `name` is valid for a custom category, see `custom`
+    pub fn is_custom_name(name: &str) -> bool {
+        let valid = name.starts_with(|c: char| c.is_ascii_uppercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+        let reserved = name
+            .strip_prefix("NRPS")
+            .is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()));
+        valid && !reserved
+    }
+
+    pub fn is_custom(&self) -> bool {
+        matches!(self, PredictionCategory::Custom(_))
+    }
+
+    /// The stable name used in config files, output headers and JSON
+    pub fn name(&self) -> &str {
+        if let PredictionCategory::Custom(name) = self {
+            return name;
+        }
+        CATEGORY_NAMES
+            .iter()
+            .find(|(category, _, _)| category == self)
+            .map(|(_, name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    /// Model directory name, `None` for categories without models
+    pub fn dir_name(&self) -> Option<&str> {
+        if let PredictionCategory::Custom(name) = self {
+            return Some(name);
+        }
+        CATEGORY_NAMES
+            .iter()
+            .find(|(category, _, _)| category == self)
+            .and_then(|(_, _, dir_name)| *dir_name)
+    }
+
+    /// Whether the category is predicted by SVM models, rather than from the Stachelhaus signatures
+    pub fn has_models(&self) -> bool {
+        self.dir_name().is_some()
+    }
+
+    /// The category of a model dir, unknown dirs with a valid name are custom categories
+    pub fn from_dir_name(dir_name: &str) -> Option<Self> {
+        CATEGORY_NAMES
+            .iter()
+            .find(|(_, _, name)| *name == Some(dir_name))
+            .map(|(category, _, _)| category.clone())
+            .or_else(|| PredictionCategory::custom(dir_name).ok())
+    }
+}
+
+impl fmt::Display for PredictionCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl FromStr for PredictionCategory {
+    type Err = String;
+
+    /// Parse a public name, case-insensitively, a model directory name or a custom category name
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        CATEGORY_NAMES
+            .iter()
+            .find(|(_, name, dir_name)| name.eq_ignore_ascii_case(text) || *dir_name == Some(text))
+            .map(|(category, _, _)| category.clone())
+            .or_else(|| PredictionCategory::custom(text).ok())
+            .ok_or_else(|| format!("unknown prediction category `{text}`"))
+    }
+}
+
+impl Serialize for PredictionCategory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for PredictionCategory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Identifies an SVM model across categories, parsed from its file name when loading
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct ModelId {
+    pub category: PredictionCategory,
+    /// The model name, its file stem without brackets, e.g. `ser,thr`
+    pub label: String,
+    /// The substrates predicted by the model, from combined labels like `ser,thr` or `val|leu`
+    pub substrates: Vec<String>,
+}
+
+impl ModelId {
+    pub fn new(category: PredictionCategory, label: &str) -> Self {
+        ModelId {
+            category,
+            label: label.to_string(),
+            substrates: label
+                .split(['|', ','])
+                .map(str::trim)
+                .filter(|substrate| !substrate.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for ModelId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.category, self.label)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct Prediction {
     pub name: String,
     pub score: f64,
+    /// Calibrated probability, for models with Platt-scaling parameters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probability: Option<f64>,
+    /// The SVM model that made the prediction, `None` for other predictors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<ModelId>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// How well the substrate of a Stachelhaus signature is supported
+#[derive(Debug, Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvidenceLevel {
+    Inferred,
+    Characterized,
+}
+
+impl EvidenceLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvidenceLevel::Inferred => "inferred",
+            EvidenceLevel::Characterized => "characterized",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
 pub struct StachPrediction {
     pub name: String,
     pub aa10_score: f64,
     pub aa10_sig: String,
     pub aa34_score: f64,
     pub aa34_sig: String,
+    /// All substrates of the matched reference entry
+    pub all_substrates: String,
+    /// Accessions of the matched reference entries
+    pub ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evidence: Option<EvidenceLevel>,
 }
+impl StachPrediction {
+    /// The match as `name:aa10 sig:aa34 sig:aa10 score:aa34 score:ids`
+    pub fn to_detail(&self) -> String {
+        format!(
+            "{}:{}:{}:{:.2}:{:.2}:{}",
+            self.name,
+            self.aa10_sig,
+            self.aa34_sig,
+            self.aa10_score,
+            self.aa34_score,
+            self.ids.join(",")
+        )
+    }
+}
+
 impl PartialOrd for StachPrediction {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         if let Some(aa10_ord) = self.aa10_score.partial_cmp(&other.aa10_score) {
@@ -47,40 +322,118 @@ impl PartialOrd for StachPrediction {
     }
 }
 
+/// How predictions tied with the last of the best N are handled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Ties {
+    /// Also return all predictions tied with the last one, so there can be more than N
+    #[default]
+    Include,
+    /// Return at most N predictions, ties are cut off in the sorting order
+    Exclude,
+}
+
+/// The best N predictions of a list
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TopN<T> {
+    pub predictions: Vec<T>,
+    /// Whether predictions tied with the last one were added beyond N
+    pub ties_included: bool,
+}
+
+impl<T> TopN<T> {
+    pub fn len(&self) -> usize {
+        self.predictions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.predictions.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.predictions.iter()
+    }
+}
+
+impl<T> IntoIterator for TopN<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.predictions.into_iter()
+    }
+}
+
+/// The first `count` of the `sorted` predictions, plus the ones `tied` with the last of them
+fn top_n<T, F>(sorted: &[T], count: usize, ties: Ties, tied: F) -> TopN<T>
+where
+    T: Clone,
+    F: Fn(&T, &T) -> bool,
+{
+    let mut end = count.min(sorted.len());
+    if ties == Ties::Include && end > 0 {
+        while end < sorted.len() && tied(&sorted[end], &sorted[end - 1]) {
+            end += 1;
+        }
+    }
+    TopN {
+        predictions: sorted[..end].to_vec(),
+        ties_included: end > count,
+    }
+}
+
+/// Serialized as plain list, deserialized lists are sorted again
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "Vec<Prediction>", into = "Vec<Prediction>")
+)]
 pub struct PredictionList {
     predictions: Vec<Prediction>,
 }
 
+impl From<Vec<Prediction>> for PredictionList {
+    fn from(predictions: Vec<Prediction>) -> Self {
+        let mut list = PredictionList::new();
+        for prediction in predictions {
+            list.add(prediction);
+        }
+        list
+    }
+}
+
+impl From<PredictionList> for Vec<Prediction> {
+    fn from(list: PredictionList) -> Self {
+        list.predictions
+    }
+}
+
 impl PredictionList {
     pub fn new() -> Self {
         let predictions = Vec::with_capacity(80);
         PredictionList { predictions }
     }
+    /// Add a prediction, keeping the list sorted by descending score
+    ///
+    /// Ties are sorted by name, so the order doesn't depend on the order predictions were added.
     pub fn add(&mut self, prediction: Prediction) {
         self.predictions.push(prediction);
-        self.predictions
-            .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap())
+        self.predictions.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.name.cmp(&b.name))
+        })
     }
-    pub fn get_best_n(&self, count: usize) -> Vec<Prediction> {
-        let mut predictions = Vec::with_capacity(count);
-        let slice_end = min(count, self.predictions.len());
-        if self.predictions.is_empty() {
-            return predictions;
-        }
-
-        predictions.extend_from_slice(&self.predictions[0..slice_end]);
-        for pred in self.predictions[slice_end..].iter() {
-            if pred.score < predictions[count - 1].score {
-                break;
-            }
-            predictions.push(pred.clone())
-        }
-
-        predictions
+    /// The `count` best predictions, see `Ties` for predictions with the same score
+    pub fn get_best_n(&self, count: usize, ties: Ties) -> TopN<Prediction> {
+        top_n(&self.predictions, count, ties, |a, b| a.score == b.score)
     }
+    /// The best prediction and all predictions tied with it
     pub fn get_best(&self) -> Vec<Prediction> {
-        self.get_best_n(1)
+        self.get_best_n(1, Ties::Include).predictions
     }
     pub fn len(&self) -> usize {
         self.predictions.len()
@@ -97,43 +450,62 @@ impl Default for PredictionList {
     }
 }
 
+/// Serialized as plain list, deserialized lists are sorted again
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "Vec<StachPrediction>", into = "Vec<StachPrediction>")
+)]
 pub struct StachPredictionList {
     predictions: Vec<StachPrediction>,
 }
 
+impl From<Vec<StachPrediction>> for StachPredictionList {
+    fn from(predictions: Vec<StachPrediction>) -> Self {
+        let mut list = StachPredictionList::new();
+        for prediction in predictions {
+            list.add(prediction);
+        }
+        list
+    }
+}
+
+impl From<StachPredictionList> for Vec<StachPrediction> {
+    fn from(list: StachPredictionList) -> Self {
+        list.predictions
+    }
+}
+
 impl StachPredictionList {
     pub fn new() -> Self {
         let predictions = Vec::with_capacity(5);
         StachPredictionList { predictions }
     }
 
+    /// Add a prediction, keeping the list sorted by descending aa10 and aa34 scores
+    ///
+    /// Ties are sorted by name, so the order doesn't depend on the order predictions were added.
     pub fn add(&mut self, prediction: StachPrediction) {
         self.predictions.push(prediction);
-        self.predictions.sort_by(|a, b| a.partial_cmp(b).unwrap());
-        self.predictions.reverse()
+        self.predictions.sort_by(|a, b| {
+            b.aa10_score
+                .total_cmp(&a.aa10_score)
+                .then_with(|| b.aa34_score.total_cmp(&a.aa34_score))
+                .then_with(|| a.name.cmp(&b.name))
+        });
     }
 
-    pub fn get_best_n(&self, count: usize) -> Vec<StachPrediction> {
-        let mut predictions = Vec::with_capacity(count);
-        let slice_end = min(count, self.predictions.len());
-        if self.predictions.is_empty() {
-            return predictions;
-        }
-
-        predictions.extend_from_slice(&self.predictions[0..slice_end]);
-        for pred in self.predictions[slice_end..].iter() {
-            if pred.aa10_score < predictions[count - 1].aa10_score {
-                break;
-            }
-            predictions.push(pred.clone())
-        }
-
-        predictions
+    /// The `count` best matches, see `Ties` for matches with the same scores
+    pub fn get_best_n(&self, count: usize, ties: Ties) -> TopN<StachPrediction> {
+        top_n(&self.predictions, count, ties, |a, b| {
+            a.partial_cmp(b) == Some(std::cmp::Ordering::Equal)
+        })
     }
 
+    /// The best match and all matches tied with it
     pub fn get_best(&self) -> Vec<StachPrediction> {
-        self.get_best_n(1)
+        self.get_best_n(1, Ties::Include).predictions
     }
 
     pub fn len(&self) -> usize {
@@ -149,12 +521,16 @@ impl StachPredictionList {
         let mut aa10_scores: Vec<f64> = Vec::with_capacity(self.len());
         let mut aa10_seqs: Vec<String> = Vec::with_capacity(self.len());
         let mut aa34_scores: Vec<f64> = Vec::with_capacity(self.len());
+        let mut evidence: Vec<&str> = Vec::with_capacity(self.len());
+        let mut ids: Vec<String> = Vec::with_capacity(self.len());
 
         for pred in self.get_best().iter() {
             substrates.push(pred.name.clone());
             aa10_scores.push(pred.aa10_score);
             aa10_seqs.push(pred.aa10_sig.clone());
             aa34_scores.push(pred.aa34_score);
+            evidence.push(pred.evidence.map_or("N/A", |level| level.as_str()));
+            ids.push(pred.ids.join(","));
         }
 
         let substrate_string = substrates.join("/");
@@ -176,7 +552,26 @@ impl StachPredictionList {
             .trim_matches('/')
             .to_string();
 
-        format!("{substrate_string}\t{aa10_string}\t{aa10_seq_string}\t{aa34_string}")
+        let evidence_string = evidence.join("/");
+        let ids_string = ids.join("/");
+
+        format!(
+            "{substrate_string}\t{aa10_string}\t{aa10_seq_string}\t{aa34_string}\t{evidence_string}\t{ids_string}"
+        )
+    }
+
+    /// The best `count` matches as `name:aa10 sig:aa34 sig:aa10 score:aa34 score:ids`,
+    /// separated by `|`
+    pub fn to_detail(&self, count: usize, ties: Ties) -> String {
+        let matches: Vec<String> = self
+            .get_best_n(count, ties)
+            .iter()
+            .map(StachPrediction::to_detail)
+            .collect();
+        if matches.is_empty() {
+            return "N/A".to_string();
+        }
+        matches.join("|")
     }
 }
 
@@ -186,27 +581,166 @@ impl Default for StachPredictionList {
     }
 }
 
+/// Location of a domain on its protein or record
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct Coordinates {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl std::str::FromStr for Coordinates {
+    type Err = String;
+
+    /// Parse `start-end` or `start..end`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (start, end) = text
+            .split_once("..")
+            .or_else(|| text.split_once('-'))
+            .ok_or_else(|| format!("invalid coordinates `{text}`"))?;
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid coordinates `{text}`"))
+        };
+        Ok(Coordinates {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl std::fmt::Display for Coordinates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// Why a domain's predictions should be reviewed manually
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct Ambiguity {
+    /// Categories whose two best predictions score within the configured delta
+    pub close_calls: Vec<PredictionCategory>,
+    /// The best Stachelhaus and single substrate SVM predictions name different substrates
+    pub stachelhaus_disagrees: bool,
+}
+
+impl Ambiguity {
+    pub fn is_ambiguous(&self) -> bool {
+        !self.close_calls.is_empty() || self.stachelhaus_disagrees
+    }
+}
+
+impl std::fmt::Display for Ambiguity {
+    /// `no`, or the reasons separated by `|`, e.g. `SingleV3|Stachelhaus disagrees`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.is_ambiguous() {
+            return write!(f, "no");
+        }
+        let mut reasons: Vec<String> = self
+            .close_calls
+            .iter()
+            .map(PredictionCategory::to_string)
+            .collect();
+        if self.stachelhaus_disagrees {
+            reasons.push("Stachelhaus disagrees".to_string());
+        }
+        write!(f, "{}", reasons.join("|"))
+    }
+}
+
+/// Per-position contributions of a linear model to its prediction score
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct Attribution {
+    pub category: PredictionCategory,
+    pub model: String,
+    /// Strongest absolute contribution first, see `SVMlightModel::position_contributions`
+    pub positions: Vec<PositionContribution>,
+}
+
+/// Serializable with all its predictions with the `serde` feature, e.g. to persist results
+/// between pipeline steps
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ADomain {
     pub name: String,
     pub aa34: String,
     pub aa10: String,
-    predictions: HashMap<PredictionCategory, PredictionList>,
+    pub protein_id: Option<String>,
+    pub gene: Option<String>,
+    pub module_index: Option<usize>,
+    pub coords: Option<Coordinates>,
+    predictions: BTreeMap<PredictionCategory, PredictionList>,
     pub stach_predictions: StachPredictionList,
+    /// Only computed if attributions are enabled, see `Config::attributions`
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub attributions: Vec<Attribution>,
+    /// aa34 Hamming distance to the closest Stachelhaus reference signature, only computed if
+    /// novelty is enabled. Large distances hint at a substrate not covered by the references
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub novelty: Option<usize>,
+    /// The most similar Stachelhaus references, best first, only computed if enabled
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub nearest_references: Vec<StachPrediction>,
 }
 
 impl ADomain {
     pub fn new(name: String, aa34: String) -> Self {
         let aa10 = extract_aa10(&aa34).unwrap();
+        ADomain::with_signatures(name, aa34, aa10)
+    }
+
+    fn with_signatures(name: String, aa34: String, aa10: String) -> Self {
         ADomain {
             name,
             aa34,
             aa10,
-            predictions: HashMap::new(),
+            protein_id: None,
+            gene: None,
+            module_index: None,
+            coords: None,
+            predictions: BTreeMap::new(),
             stach_predictions: StachPredictionList::new(),
+            attributions: Vec::new(),
+            novelty: None,
+            nearest_references: Vec::new(),
+        }
+    }
+
+    /// A domain only known by its aa10 Stachelhaus code, `aa34` is left empty
+    pub fn from_aa10(name: String, aa10: String) -> Self {
+        ADomain::with_signatures(name, String::new(), aa10)
+    }
+
+    /// The aa34 signature, or the aa10 code for domains from aa10-only input
+    pub fn signature(&self) -> &str {
+        if self.aa34.is_empty() {
+            &self.aa10
+        } else {
+            &self.aa34
         }
     }
 
+    /// Whether any of the optional protein ID, gene, module index or coordinates are set
+    pub fn has_metadata(&self) -> bool {
+        self.protein_id.is_some()
+            || self.gene.is_some()
+            || self.module_index.is_some()
+            || self.coords.is_some()
+    }
+
     pub fn add(&mut self, category: PredictionCategory, prediction: Prediction) {
         match self.predictions.get_mut(&category) {
             Some(existing) => existing.add(prediction),
@@ -218,14 +752,53 @@ impl ADomain {
         }
     }
 
-    pub fn get_best_n(&self, category: &PredictionCategory, count: usize) -> Vec<Prediction> {
+    /// Add all predictions of `other`, replacing the Stachelhaus predictions and the aa10 code,
+    /// which Stachelhaus lookups with custom aa10 positions set
+    pub fn add_predictions_from(&mut self, other: &ADomain) {
+        self.aa10 = other.aa10.clone();
+        for (category, plist) in other.predictions.iter() {
+            for prediction in plist.predictions.iter() {
+                self.add(category.clone(), prediction.clone());
+            }
+        }
+        self.stach_predictions = other.stach_predictions.clone();
+        self.attributions.extend(other.attributions.iter().cloned());
+        if other.novelty.is_some() {
+            self.novelty = other.novelty;
+        }
+        if !other.nearest_references.is_empty() {
+            self.nearest_references = other.nearest_references.clone();
+        }
+    }
+
+    /// The attribution of the `category` prediction of `model`, if computed
+    pub fn attribution(&self, category: &PredictionCategory, model: &str) -> Option<&Attribution> {
+        self.attributions
+            .iter()
+            .find(|attribution| &attribution.category == category && attribution.model == model)
+    }
+
+    pub fn get_best_n(
+        &self,
+        category: &PredictionCategory,
+        count: usize,
+        ties: Ties,
+    ) -> TopN<Prediction> {
         if let Some(results) = self.predictions.get(category) {
-            results.get_best_n(count)
+            results.get_best_n(count, ties)
         } else {
-            Vec::new()
+            TopN {
+                predictions: Vec::new(),
+                ties_included: false,
+            }
         }
     }
 
+    /// The best prediction of `category` and all predictions tied with it
+    pub fn get_best(&self, category: &PredictionCategory) -> Vec<Prediction> {
+        self.get_best_n(category, 1, Ties::Include).predictions
+    }
+
     pub fn get_all(&self, category: &PredictionCategory) -> Vec<Prediction> {
         if let Some(results) = self.predictions.get(category) {
             results.predictions.clone()
@@ -233,6 +806,63 @@ impl ADomain {
             Vec::new()
         }
     }
+
+    /// The categories with at least one prediction, built-in categories before custom ones
+    pub fn categories(&self) -> Vec<PredictionCategory> {
+        self.predictions
+            .iter()
+            .filter(|(_, results)| !results.is_empty())
+            .map(|(category, _)| category.clone())
+            .collect()
+    }
+
+    /// All predictions with their category, in `categories()` order and best first
+    pub fn iter_predictions(&self) -> impl Iterator<Item = (PredictionCategory, &Prediction)> {
+        self.predictions.iter().flat_map(|(category, results)| {
+            results
+                .predictions
+                .iter()
+                .map(move |prediction| (category.clone(), prediction))
+        })
+    }
+
+    /// Flag close calls in `categories` and disagreements between Stachelhaus and SVM predictions
+    pub fn ambiguity(&self, categories: &[PredictionCategory], delta: f64) -> Ambiguity {
+        let close_calls = categories
+            .iter()
+            .filter(|category| match self.predictions.get(category) {
+                Some(plist) if plist.len() > 1 => {
+                    plist.predictions[0].score - plist.predictions[1].score <= delta
+                }
+                _ => false,
+            })
+            .cloned()
+            .collect();
+
+        let substrates = |preds: Vec<Prediction>| -> Vec<String> {
+            preds
+                .iter()
+                .flat_map(|pred| pred.name.split(['|', ',']))
+                .map(str::to_lowercase)
+                .collect()
+        };
+        let stachelhaus = substrates(self.get_best(&PredictionCategory::Stachelhaus));
+        let svm = [PredictionCategory::SingleV3, PredictionCategory::SingleV2]
+            .iter()
+            .filter(|category| categories.contains(category))
+            .map(|category| substrates(self.get_best(category)))
+            .find(|names| !names.is_empty())
+            .unwrap_or_default();
+        let stachelhaus_disagrees = categories.contains(&PredictionCategory::Stachelhaus)
+            && !stachelhaus.is_empty()
+            && !svm.is_empty()
+            && !svm.iter().any(|name| stachelhaus.contains(name));
+
+        Ambiguity {
+            close_calls,
+            stachelhaus_disagrees,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -247,18 +877,26 @@ mod tests {
             Prediction {
                 name: "Ala".to_string(),
                 score: 23.0,
+                probability: None,
+                model: None,
             },
             Prediction {
                 name: "Leu".to_string(),
                 score: 42.0,
+                probability: None,
+                model: None,
             },
             Prediction {
                 name: "D-Ala".to_string(),
                 score: 17.0,
+                probability: None,
+                model: None,
             },
             Prediction {
                 name: "Ile".to_string(),
                 score: 42.0,
+                probability: None,
+                model: None,
             },
         ]
     }
@@ -279,7 +917,50 @@ mod tests {
 
         pred_list.add(data[3].clone());
         assert_eq!(pred_list.len(), 4);
-        assert_eq!(pred_list.predictions[1], data[3]);
+        // Ties are sorted by name
+        assert_eq!(pred_list.predictions[0], data[3]);
+        assert_eq!(pred_list.predictions[1], data[1]);
+    }
+
+    #[rstest]
+    fn test_add_order_independent(data: [Prediction; 4]) {
+        let mut forward = PredictionList::new();
+        let mut backward = PredictionList::new();
+        for pred in data.iter() {
+            forward.add(pred.clone());
+        }
+        for pred in data.iter().rev() {
+            backward.add(pred.clone());
+        }
+        assert_eq!(forward, backward);
+        let names: Vec<&str> = forward
+            .predictions
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(names, ["Ile", "Leu", "Ala", "D-Ala"]);
+    }
+
+    #[test]
+    fn test_stach_add() {
+        let pred = |name: &str, aa10_score: f64, aa34_score: f64| StachPrediction {
+            name: name.to_string(),
+            aa10_score,
+            aa10_sig: String::new(),
+            aa34_score,
+            aa34_sig: String::new(),
+            all_substrates: name.to_string(),
+            ids: Vec::new(),
+            evidence: None,
+        };
+        let mut list = StachPredictionList::new();
+        list.add(pred("val", 0.8, 0.9));
+        list.add(pred("nan", f64::NAN, 1.0));
+        list.add(pred("leu", 1.0, 0.9));
+        list.add(pred("ile", 1.0, 0.9));
+        list.add(pred("ala", 1.0, 1.0));
+        let names: Vec<&str> = list.predictions.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["nan", "ala", "ile", "leu", "val"]);
     }
 
     #[rstest]
@@ -290,7 +971,226 @@ mod tests {
         pred_list.add(data[2].clone());
         pred_list.add(data[3].clone());
 
-        let expected = Vec::from([data[1].clone(), data[3].clone()]);
+        let expected = Vec::from([data[3].clone(), data[1].clone()]);
         assert_eq!(pred_list.get_best(), expected);
     }
+
+    #[rstest]
+    #[case(0, Ties::Include, &[], false)]
+    #[case(0, Ties::Exclude, &[], false)]
+    #[case(1, Ties::Include, &["Ile", "Leu"], true)]
+    #[case(1, Ties::Exclude, &["Ile"], false)]
+    #[case(2, Ties::Include, &["Ile", "Leu"], false)]
+    #[case(3, Ties::Exclude, &["Ile", "Leu", "Ala"], false)]
+    #[case(10, Ties::Include, &["Ile", "Leu", "Ala", "D-Ala"], false)]
+    fn test_get_best_n(
+        data: [Prediction; 4],
+        #[case] count: usize,
+        #[case] ties: Ties,
+        #[case] expected: &[&str],
+        #[case] ties_included: bool,
+    ) {
+        let mut pred_list = PredictionList::new();
+        for pred in data {
+            pred_list.add(pred);
+        }
+
+        let got = pred_list.get_best_n(count, ties);
+        let names: Vec<&str> = got.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, expected);
+        assert_eq!(got.ties_included, ties_included);
+
+        assert!(PredictionList::new().get_best_n(count, ties).is_empty());
+    }
+
+    #[test]
+    fn test_category_names() {
+        for category in PredictionCategory::ALL {
+            assert_eq!(category.to_string().parse(), Ok(category.clone()));
+            assert_eq!(format!("{category}"), format!("{category:?}"));
+            if let Some(dir_name) = category.dir_name() {
+                assert_eq!(
+                    PredictionCategory::from_dir_name(dir_name),
+                    Some(category.clone())
+                );
+                assert_eq!(dir_name.parse(), Ok(category.clone()));
+            }
+        }
+        assert_eq!("singlev3".parse(), Ok(PredictionCategory::SingleV3));
+        assert_eq!(PredictionCategory::Stachelhaus.dir_name(), None);
+        assert!(!PredictionCategory::Knn.has_models());
+        assert!(PredictionCategory::SingleV3.has_models());
+        assert!("SingleV4".parse::<PredictionCategory>().is_err());
+
+        let json = serde_json::to_string(&PredictionCategory::ThreeClusterFungalV2).unwrap();
+        assert_eq!(json, "\"ThreeClusterFungalV2\"");
+        let parsed: PredictionCategory = serde_json::from_str("\"NRPS1_LARGE_CLUSTER\"").unwrap();
+        assert_eq!(parsed, PredictionCategory::LargeClusterV1);
+        assert!(serde_json::from_str::<PredictionCategory>("\"Foo\"").is_err());
+    }
+
+    #[test]
+    fn test_model_id() {
+        let id = ModelId::new(PredictionCategory::LargeClusterV2, "ser,thr");
+        assert_eq!(id.substrates, ["ser", "thr"]);
+        assert_eq!(id.to_string(), "LargeClusterV2/ser,thr");
+        assert_eq!(
+            ModelId::new(PredictionCategory::SingleV3, "val|leu").substrates,
+            ["val", "leu"]
+        );
+        // The same label in another category is another model
+        assert_ne!(
+            id,
+            ModelId::new(PredictionCategory::LargeClusterV3, "ser,thr")
+        );
+
+        let json = serde_json::to_string(&id).unwrap();
+        assert_eq!(
+            json,
+            r#"{"category":"LargeClusterV2","label":"ser,thr","substrates":["ser","thr"]}"#
+        );
+
+        #[cfg(feature = "serde")]
+        {
+            assert_eq!(serde_json::from_str::<ModelId>(&json).unwrap(), id);
+
+            // Predictions without a model don't list one
+            let pred: Prediction = serde_json::from_str(r#"{"name":"leu","score":1.0}"#).unwrap();
+            assert_eq!(pred.model, None);
+        }
+    }
+
+    #[test]
+    fn test_custom_category() {
+        let custom = PredictionCategory::custom("MY_SET_2").unwrap();
+        assert!(custom.is_custom());
+        assert_eq!(custom, PredictionCategory::custom("MY_SET_2").unwrap());
+        assert_eq!(custom.to_string(), "MY_SET_2");
+        assert_eq!(custom.dir_name(), Some("MY_SET_2"));
+        assert_eq!("MY_SET_2".parse(), Ok(custom.clone()));
+        assert_eq!(
+            PredictionCategory::from_dir_name("MY_SET_2"),
+            Some(custom.clone())
+        );
+        assert_eq!(serde_json::to_string(&custom).unwrap(), "\"MY_SET_2\"");
+        // Custom names parse without their model dir, e.g. from results of another run
+        assert_eq!(
+            serde_json::from_str::<PredictionCategory>("\"MY_OTHER_SET\"").unwrap(),
+            PredictionCategory::custom("MY_OTHER_SET").unwrap()
+        );
+        assert!("my_set".parse::<PredictionCategory>().is_err());
+
+        assert!(PredictionCategory::custom("my_set").is_err());
+        assert!(PredictionCategory::custom("2_SET").is_err());
+        assert!(PredictionCategory::custom("NRPS2_SINGLE_CLUSTER").is_err());
+        assert!(PredictionCategory::custom("NRPS3_THREECLUSTER").is_err());
+        assert_eq!(
+            "NRPS2_SINGLE_CLUSTER".parse(),
+            Ok(PredictionCategory::SingleV2)
+        );
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        let pred = |name: &str, score: f64| Prediction {
+            name: name.to_string(),
+            score,
+            probability: None,
+            model: None,
+        };
+        let categories = [
+            PredictionCategory::Stachelhaus,
+            PredictionCategory::SingleV3,
+        ];
+        let mut domain = ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        );
+        domain.add(PredictionCategory::Stachelhaus, pred("Leu", 1.0));
+        domain.add(PredictionCategory::SingleV3, pred("leu", 1.0));
+        domain.add(PredictionCategory::SingleV3, pred("val", 0.5));
+        let ambiguity = domain.ambiguity(&categories, 0.1);
+        assert!(!ambiguity.is_ambiguous());
+        assert_eq!(ambiguity.to_string(), "no");
+
+        let ambiguity = domain.ambiguity(&categories, 0.5);
+        assert_eq!(ambiguity.close_calls, [PredictionCategory::SingleV3]);
+        assert!(!ambiguity.stachelhaus_disagrees);
+
+        domain.add(PredictionCategory::SingleV3, pred("ile", 2.0));
+        let ambiguity = domain.ambiguity(&categories, 0.1);
+        assert!(ambiguity.stachelhaus_disagrees);
+        assert_eq!(ambiguity.to_string(), "Stachelhaus disagrees");
+        assert!(!domain
+            .ambiguity(&[PredictionCategory::SingleV3], 0.1)
+            .is_ambiguous());
+    }
+
+    #[rstest]
+    fn test_iter_predictions(data: [Prediction; 4]) {
+        let mut domain = ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        );
+        assert!(domain.categories().is_empty());
+        assert_eq!(domain.iter_predictions().count(), 0);
+
+        domain.add(PredictionCategory::SingleV2, data[0].clone());
+        domain.add(PredictionCategory::SingleV3, data[2].clone());
+        domain.add(PredictionCategory::SingleV3, data[1].clone());
+
+        assert_eq!(
+            domain.categories(),
+            [PredictionCategory::SingleV3, PredictionCategory::SingleV2]
+        );
+        let got: Vec<(PredictionCategory, &str)> = domain
+            .iter_predictions()
+            .map(|(category, pred)| (category, pred.name.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                (PredictionCategory::SingleV3, "Leu"),
+                (PredictionCategory::SingleV3, "D-Ala"),
+                (PredictionCategory::SingleV2, "Ala"),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[rstest]
+    fn test_serde_round_trip(data: [Prediction; 4]) {
+        let mut domain = ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+        );
+        domain.coords = Some(Coordinates { start: 3, end: 400 });
+        for pred in data.iter() {
+            domain.add(PredictionCategory::SingleV3, pred.clone());
+        }
+        domain.add(PredictionCategory::SingleV2, data[0].clone());
+        domain.stach_predictions.add(StachPrediction {
+            name: "Leu".to_string(),
+            aa10_score: 1.0,
+            aa10_sig: "DAWFLGNVVK".to_string(),
+            aa34_score: 0.9,
+            aa34_sig: "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+            all_substrates: "Leu".to_string(),
+            ids: Vec::from(["Q70AZ9".to_string()]),
+            evidence: Some(EvidenceLevel::Characterized),
+        });
+
+        let json = serde_json::to_string(&domain).unwrap();
+        let parsed: ADomain = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, domain);
+        // Categories are keyed by name, in a stable order
+        assert!(json.find("\"SingleV3\"").unwrap() < json.find("\"SingleV2\"").unwrap());
+
+        // Lists are sorted when deserialized
+        let list: PredictionList = serde_json::from_str(
+            r#"[{"name": "Ala", "score": 1.0}, {"name": "Leu", "score": 2.0}]"#,
+        )
+        .unwrap();
+        assert_eq!(list.get_best()[0].name, "Leu");
+    }
 }
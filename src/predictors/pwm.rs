@@ -0,0 +1,311 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Position weight matrices of the aa34 signatures of each substrate.
+//!
+//! PWMs are read from the `pwm` dir of the model dir, one `<substrate>.pwm` file each. The first
+//! non-comment line lists the residues of the columns, e.g. `A C D ... Y`, followed by one line of
+//! residue counts per aa34 position. Lines starting with `#` are comments. Signatures score the
+//! log2-odds of their residues against a uniform background, with a pseudocount of 1.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::compression::{self, uncompressed_path};
+use crate::config::Config;
+use crate::errors::NrpsError;
+
+use super::extract_name;
+use super::predictions::{ADomain, Prediction, PredictionCategory};
+use super::{with_aa34, PredictionBackend};
+
+/// Dir in the model dir holding the PWM files
+pub const PWM_DIR: &str = "pwm";
+const RESIDUES: &str = "ACDEFGHIKLMNPQRSTVWY";
+const SIGNATURE_LENGTH: usize = 34;
+const PSEUDOCOUNT: f64 = 1.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionWeightMatrix {
+    pub name: String,
+    /// Residue counts per position, in `RESIDUES` order
+    counts: Vec<[f64; 20]>,
+}
+
+impl PositionWeightMatrix {
+    /// Count the residues of aligned aa34 `signatures`, gaps and unknown residues aren't counted
+    pub fn from_signatures<'a, I>(name: String, signatures: I) -> Result<Self, NrpsError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut counts = vec![[0.0; 20]; SIGNATURE_LENGTH];
+        for signature in signatures {
+            if signature.len() != SIGNATURE_LENGTH {
+                return Err(NrpsError::PwmError(format!(
+                    "{name}: invalid signature length {} of `{signature}`",
+                    signature.len()
+                )));
+            }
+            for (position, c) in counts.iter_mut().zip(signature.chars()) {
+                if let Some(index) = RESIDUES.find(c) {
+                    position[index] += 1.0;
+                }
+            }
+        }
+        Ok(PositionWeightMatrix { name, counts })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, NrpsError> {
+        let handle = compression::open(path).map_err(|source| NrpsError::FileError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        PositionWeightMatrix::parse(extract_name(path), handle).map_err(|err| match err {
+            NrpsError::PwmError(msg) => NrpsError::PwmError(format!("{}: {msg}", path.display())),
+            err => err,
+        })
+    }
+
+    pub fn parse<R: Read>(name: String, handle: R) -> Result<Self, NrpsError> {
+        let mut columns: Option<Vec<usize>> = None;
+        let mut counts = Vec::with_capacity(SIGNATURE_LENGTH);
+        for (i, line_res) in BufReader::new(handle).lines().enumerate() {
+            let line = line_res?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg: String| NrpsError::PwmError(format!("line {}: {msg}", i + 1));
+            let Some(columns) = &columns else {
+                let mut header = Vec::new();
+                for residue in line.split_whitespace() {
+                    match RESIDUES.find(residue).filter(|_| residue.len() == 1) {
+                        Some(index) if !header.contains(&index) => header.push(index),
+                        _ => return Err(error(format!("invalid residue column `{residue}`"))),
+                    }
+                }
+                columns = Some(header);
+                continue;
+            };
+            let values: Vec<&str> = line.split_whitespace().collect();
+            if values.len() != columns.len() {
+                return Err(error(format!(
+                    "expected {} counts, got {}",
+                    columns.len(),
+                    values.len()
+                )));
+            }
+            let mut position = [0.0; 20];
+            for (index, value) in columns.iter().zip(values) {
+                position[*index] = match value.parse::<f64>() {
+                    Ok(count) if count.is_finite() && count >= 0.0 => count,
+                    _ => return Err(error(format!("invalid count `{value}`"))),
+                };
+            }
+            counts.push(position);
+        }
+        if counts.len() != SIGNATURE_LENGTH {
+            return Err(NrpsError::PwmError(format!(
+                "expected {SIGNATURE_LENGTH} positions, got {}",
+                counts.len()
+            )));
+        }
+        Ok(PositionWeightMatrix { name, counts })
+    }
+
+    /// The matrix in the PWM file format
+    pub fn to_text(&self) -> String {
+        let residues: Vec<String> = RESIDUES.chars().map(String::from).collect();
+        let mut text = format!("{}\n", residues.join(" "));
+        for position in self.counts.iter() {
+            let counts: Vec<String> = position.iter().map(f64::to_string).collect();
+            text.push_str(&counts.join(" "));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Sum of the log2-odds of the residues of `aa34`, gaps and unknown residues score 0
+    pub fn score(&self, aa34: &str) -> f64 {
+        let background = 1.0 / RESIDUES.len() as f64;
+        self.counts
+            .iter()
+            .zip(aa34.chars())
+            .filter_map(|(position, c)| {
+                let index = RESIDUES.find(c)?;
+                let total: f64 = position.iter().sum();
+                let frequency =
+                    (position[index] + PSEUDOCOUNT) / (total + PSEUDOCOUNT * RESIDUES.len() as f64);
+                Some((frequency / background).log2())
+            })
+            .sum()
+    }
+}
+
+/// Build one PWM per substrate from `(aa34, substrate)` pairs, ordered by substrate
+pub fn build_pwms<'a, I>(samples: I) -> Result<Vec<PositionWeightMatrix>, NrpsError>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut by_substrate: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (aa34, substrate) in samples {
+        by_substrate.entry(substrate).or_default().push(aa34);
+    }
+    by_substrate
+        .into_iter()
+        .map(|(substrate, signatures)| {
+            PositionWeightMatrix::from_signatures(substrate.to_string(), signatures)
+        })
+        .collect()
+}
+
+/// Write `matrices` to the `pwm` dir of `model_dir`, replacing PWMs of the same substrate
+pub fn write_pwms(matrices: &[PositionWeightMatrix], model_dir: &Path) -> Result<(), NrpsError> {
+    let dir = model_dir.join(PWM_DIR);
+    fs::create_dir_all(&dir)?;
+    for matrix in matrices.iter() {
+        fs::write(dir.join(format!("{}.pwm", matrix.name)), matrix.to_text())?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct PwmPredictor {
+    pub matrices: Vec<PositionWeightMatrix>,
+    /// Only predictions scoring above this are reported
+    pub min_score: f64,
+}
+
+impl PwmPredictor {
+    /// Load the PWMs of the configured model dir, with the configured threshold for Pwm
+    pub fn from_config(config: &Config) -> Result<Self, NrpsError> {
+        let mut predictor = PwmPredictor::from_dir(&config.model_dir().join(PWM_DIR))?;
+        predictor.min_score = config
+            .category_min_scores
+            .get(&PredictionCategory::Pwm)
+            .copied()
+            .unwrap_or(config.min_score);
+        Ok(predictor)
+    }
+
+    /// Load all `.pwm` files in `dir`, in file name order
+    pub fn from_dir(dir: &Path) -> Result<Self, NrpsError> {
+        let entries = fs::read_dir(dir).map_err(|source| {
+            NrpsError::PwmError(format!("Failed to read {}: {source}", dir.display()))
+        })?;
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if uncompressed_path(&path)
+                .extension()
+                .is_some_and(|ext| ext == "pwm")
+            {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        let matrices = paths
+            .iter()
+            .map(|path| PositionWeightMatrix::from_file(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        if matrices.is_empty() {
+            return Err(NrpsError::PwmError(format!(
+                "No PWM files in {}",
+                dir.display()
+            )));
+        }
+        log::debug!("Loaded {} PWMs from {}", matrices.len(), dir.display());
+        Ok(PwmPredictor {
+            matrices,
+            min_score: 0.0,
+        })
+    }
+}
+
+impl PredictionBackend for PwmPredictor {
+    fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+        for domain in with_aa34(domains) {
+            for matrix in self.matrices.iter() {
+                let score = matrix.score(&domain.aa34);
+                if score > self.min_score {
+                    domain.add(
+                        PredictionCategory::Pwm,
+                        Prediction {
+                            name: matrix.name.to_string(),
+                            score,
+                            probability: None,
+                            model: None,
+                        },
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    const LEU: &str = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+    const VAL: &str = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCAAA";
+
+    #[test]
+    fn test_pwm_roundtrip() {
+        let pwm = PositionWeightMatrix::from_signatures("leu".to_string(), [LEU, LEU]).unwrap();
+        let text = format!("# leu, 2 signatures\n{}", pwm.to_text());
+        let parsed = PositionWeightMatrix::parse("leu".to_string(), text.as_bytes()).unwrap();
+        assert_eq!(parsed, pwm);
+
+        // (2 + 1) / (2 + 20) against 1 / 20 at all 34 positions
+        assert_approx_eq!(pwm.score(LEU), 34.0 * (3.0_f64 / 22.0 * 20.0).log2());
+        assert!(pwm.score(VAL) < pwm.score(LEU));
+        // Unknown residues don't count
+        assert!(pwm.score(&LEU.replace('W', "X")) < pwm.score(LEU));
+    }
+
+    #[test]
+    fn test_pwm_parse_errors() {
+        let header = "A C D E F G H I K L M N P Q R S T V W Y\n";
+        let row = "1 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n";
+        let parse = |text: String| PositionWeightMatrix::parse("leu".to_string(), text.as_bytes());
+        assert!(parse(format!("{header}{}", row.repeat(34))).is_ok());
+        assert!(parse(format!("{header}{}", row.repeat(33))).is_err());
+        assert!(parse(format!("A A\n{}", "1 0\n".repeat(34))).is_err());
+        assert!(parse(format!("A B\n{}", "1 0\n".repeat(34))).is_err());
+        assert!(parse(format!("A C\n{}", "1 -1\n".repeat(34))).is_err());
+        assert!(parse(format!("A C\n{}", "1 inf\n".repeat(34))).is_err());
+        assert!(parse(format!("A C\n{}", "NaN 0\n".repeat(34))).is_err());
+        assert!(parse(format!("A C\n{}", "1\n".repeat(34))).is_err());
+    }
+
+    #[test]
+    fn test_pwm_predict() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let pwms = build_pwms([(LEU, "leu"), (LEU, "leu"), (VAL, "val")]).unwrap();
+        write_pwms(&pwms, &model_dir).unwrap();
+        let dir = model_dir.join(PWM_DIR);
+        fs::write(dir.join("README"), "not a PWM").unwrap();
+
+        let predictor = PwmPredictor::from_dir(&dir).unwrap();
+        assert_eq!(predictor.matrices.len(), 2);
+        let mut domains = [
+            ADomain::new("bpsA".to_string(), LEU.to_string()),
+            ADomain::from_aa10("aa10".to_string(), "DAFYLGMMCK".to_string()),
+        ];
+        predictor.predict(&mut domains).unwrap();
+        let best = domains[0].get_best(&PredictionCategory::Pwm);
+        assert_eq!(best[0].name, "leu");
+        assert_eq!(domains[0].get_all(&PredictionCategory::Pwm).len(), 2);
+        assert!(domains[1].get_all(&PredictionCategory::Pwm).is_empty());
+
+        fs::remove_dir_all(&model_dir).unwrap();
+        assert!(PwmPredictor::from_dir(&dir).is_err());
+    }
+}
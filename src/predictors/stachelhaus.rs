@@ -1,89 +1,395 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
 use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
 
-use crate::config::Config;
+use crate::compression;
+use crate::config::{Config, StachelhausScoring};
 use crate::errors::NrpsError;
+use crate::extraction::alignment::blosum62;
 
 use super::predictions::{
-    ADomain, Prediction, PredictionCategory, PredictionList, StachPrediction, StachPredictionList,
+    ADomain, EvidenceLevel, Prediction, PredictionCategory, PredictionList, StachPrediction,
+    StachPredictionList,
 };
+use super::PredictionBackend;
 
+const MIN_AA10_MATCHES: usize = 7;
+/// A gap aligned to a residue counts as a full mismatch by default
+pub const DEFAULT_GAP_PENALTY: f64 = 1.0;
+const GAP: u8 = b'-';
+/// Positions of the first nine aa10 residues in the aa34 signature
+const AA10_POSITIONS: [usize; 9] = [5, 6, 9, 12, 14, 16, 21, 29, 30];
+
+/// Parsed Stachelhaus signatures, share it with an `Arc` to predict from several runs or threads
+#[derive(Debug)]
+pub struct StachelhausDb {
+    signatures: Vec<StachelhausSignature>,
+    /// Signature indices by aa34, for looking up exact matches without a full scan
+    index: HashMap<String, Vec<usize>>,
+    pub scoring: StachelhausScoring,
+    /// Report ambiguous best matches as a single call listing all their substrates
+    pub alternatives: bool,
+    /// Keep the best match of every substrate, not just the overall best ones. Without this,
+    /// exact matches are looked up in the index instead of scanning all signatures
+    pub runners_up: bool,
+    /// Record the distance to the closest signature, see `ADomain::novelty`
+    pub novelty: bool,
+    /// Number of most similar signatures to list, see `ADomain::nearest_references`
+    pub nearest_references: usize,
+    /// Fraction of a mismatch a gap aligned to a residue costs, gaps aligned to gaps count as
+    /// identical
+    pub gap_penalty: f64,
+    /// 0-based aa34 positions of the aa10 code, if not the NRPSPredictor2 ones
+    aa10_positions: Option<Vec<usize>>,
+}
+
+impl StachelhausDb {
+    pub fn new(signatures: Vec<StachelhausSignature>) -> Self {
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, sig) in signatures.iter().enumerate() {
+            index.entry(sig.aa34.clone()).or_default().push(i);
+        }
+        StachelhausDb {
+            signatures,
+            index,
+            scoring: StachelhausScoring::Identity,
+            alternatives: false,
+            runners_up: true,
+            novelty: false,
+            nearest_references: 0,
+            gap_penalty: DEFAULT_GAP_PENALTY,
+            aa10_positions: None,
+        }
+    }
+
+    /// Parse the configured signature file
+    pub fn from_config(config: &Config) -> Result<Self, NrpsError> {
+        if !(0.0..=1.0).contains(&config.stachelhaus_gap_penalty) {
+            return Err(NrpsError::GapPenaltyError(config.stachelhaus_gap_penalty));
+        }
+        let mut db = StachelhausDb::new(parse_stachelhaus_sigs(config)?);
+        db.scoring = config.stachelhaus_scoring;
+        db.alternatives = config.stachelhaus_alternatives;
+        db.runners_up = config.stachelhaus_detail;
+        db.novelty = config.novelty;
+        db.nearest_references = config.nearest_references.unwrap_or_default();
+        db.gap_penalty = config.stachelhaus_gap_penalty;
+        if !config.aa10_positions.is_empty() {
+            db.set_aa10_positions(&config.aa10_positions)?;
+        }
+        Ok(db)
+    }
+
+    /// Use the 1-based aa34 `positions` for the aa10 codes of both the signatures and the
+    /// queries, instead of the NRPSPredictor2 positions. Nine positions get the conserved K appended
+    pub fn set_aa10_positions(&mut self, positions: &[usize]) -> Result<(), NrpsError> {
+        if !(9..=10).contains(&positions.len()) {
+            return Err(NrpsError::Aa10PositionError(format!(
+                "expected 9 or 10 positions, got {}",
+                positions.len()
+            )));
+        }
+        if let Some(position) = positions.iter().find(|p| !(1..=34).contains(*p)) {
+            return Err(NrpsError::Aa10PositionError(format!(
+                "position {position} is outside of the aa34 signature"
+            )));
+        }
+        let positions: Vec<usize> = positions.iter().map(|position| position - 1).collect();
+        for sig in self.signatures.iter_mut() {
+            sig.aa10 = extract_aa10_at(&sig.aa34, &positions)?;
+        }
+        self.aa10_positions = Some(positions);
+        Ok(())
+    }
+
+    /// Parse signatures in the `signatures.tsv` format
+    pub fn from_reader<R: Read>(handle: R) -> Result<Self, NrpsError> {
+        Ok(StachelhausDb::new(parse_signatures(handle)?))
+    }
+
+    pub fn signatures(&self) -> &[StachelhausSignature] {
+        &self.signatures
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    /// Signatures identical to the query in both aa10 and aa34, these always score best
+    fn exact_matches(&self, aa10: &str, aa34: &str) -> Vec<Scored<'_>> {
+        self.index
+            .get(aa34)
+            .into_iter()
+            .flatten()
+            .map(|i| &self.signatures[*i])
+            .filter(|sig| sig.aa10 == aa10)
+            .map(|sig| (1.0, 1.0, sig))
+            .collect()
+    }
+
+    /// aa34 Hamming distance to the closest signature, `None` if there are no signatures
+    pub fn min_distance(&self, aa34: &str) -> Option<usize> {
+        if self.index.contains_key(aa34) {
+            return Some(0);
+        }
+        self.signatures
+            .iter()
+            .map(|sig| hamming_dist(aa34.as_bytes(), sig.aa34.as_bytes()))
+            .min()
+    }
+
+    /// The `count` signatures most similar to the query by aa34 and then aa10, ignoring the
+    /// minimum aa10 matches required for predictions
+    pub fn nearest(&self, aa10: &str, aa34: &str, count: usize) -> Vec<StachPrediction> {
+        let mut scored: Vec<Scored> = self
+            .signatures
+            .iter()
+            .map(|sig| {
+                let aa34_score = if aa34.is_empty() {
+                    0.0
+                } else {
+                    self.scoring
+                        .similarity_with_gap_penalty(aa34, &sig.aa34, self.gap_penalty)
+                };
+                let aa10_score =
+                    self.scoring
+                        .similarity_with_gap_penalty(aa10, &sig.aa10, self.gap_penalty);
+                (aa10_score, aa34_score, sig)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| b.0.total_cmp(&a.0)));
+        scored
+            .into_iter()
+            .take(count)
+            .map(|(aa10_score, aa34_score, sig)| StachPrediction {
+                name: sig.winner.clone(),
+                aa10_score,
+                aa10_sig: sig.aa10.clone(),
+                aa34_score,
+                aa34_sig: sig.aa34.clone(),
+                all_substrates: sig.all.clone(),
+                ids: Vec::from([sig.ids.clone()]),
+                evidence: sig.evidence,
+            })
+            .collect()
+    }
+}
+
+impl Default for StachelhausDb {
+    fn default() -> Self {
+        StachelhausDb::new(Vec::new())
+    }
+}
+
+impl PredictionBackend for StachelhausDb {
+    fn predict(&self, domains: &mut [ADomain]) -> Result<(), NrpsError> {
+        let use_index = !self.runners_up
+            && self.scoring == StachelhausScoring::Identity
+            && self.gap_penalty == DEFAULT_GAP_PENALTY;
+        for domain in domains.iter_mut() {
+            if let Some(positions) = &self.aa10_positions {
+                if !domain.aa34.is_empty() {
+                    domain.aa10 = extract_aa10_at(&domain.aa34, positions)?;
+                }
+            }
+            let mut scored = Vec::new();
+            if use_index {
+                scored = self.exact_matches(&domain.aa10, &domain.aa34);
+            }
+            if scored.is_empty() {
+                scored = score_signatures(
+                    &self.signatures,
+                    &domain.aa10,
+                    &domain.aa34,
+                    self.scoring,
+                    self.gap_penalty,
+                );
+            }
+            add_predictions(domain, &scored, self.alternatives);
+            if self.novelty && !domain.aa34.is_empty() {
+                domain.novelty = self.min_distance(&domain.aa34);
+            }
+            if self.nearest_references > 0 {
+                domain.nearest_references =
+                    self.nearest(&domain.aa10, &domain.aa34, self.nearest_references);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse the configured signatures and predict `domains`, use a `StachelhausDb` for repeated runs
 pub fn predict_stachelhaus(config: &Config, domains: &mut [ADomain]) -> Result<(), NrpsError> {
-    let signatures = parse_stachelhaus_sigs(config)?;
-    predict(domains, signatures)
+    StachelhausDb::from_config(config)?.predict(domains)
 }
 
-fn predict(
+pub fn predict(
     domains: &mut [ADomain],
-    signatures: Vec<StachelhausSignature>,
+    signatures: &[StachelhausSignature],
 ) -> Result<(), NrpsError> {
     for domain in domains.iter_mut() {
-        let aa10 = extract_aa10(&domain.aa34)?;
-        let mut max_aa10_matches: usize = 6; // Don't bother showing hits < 7 matches
-        let mut max_aa34_matches: usize = max_aa10_matches;
-        let mut predictions = PredictionList::new();
-        let mut stach_predictions = StachPredictionList::new();
-
-        for sig in signatures.iter() {
-            let aa10_matches = aa10.len() - hamming_dist(&aa10, &sig.aa10);
-            let aa34_matches = domain.aa34.len() - hamming_dist(&domain.aa34, &sig.aa34);
-            if aa10_matches > max_aa10_matches {
-                max_aa10_matches = aa10_matches;
-                predictions.add(Prediction {
-                    name: sig.winner.clone(),
-                    score: calculate_score(
-                        aa10_matches,
-                        aa10.len(),
-                        aa34_matches,
-                        domain.aa34.len(),
-                    ),
-                });
-                stach_predictions.add(StachPrediction {
-                    name: sig.winner.clone(),
-                    aa10_score: similarity(aa10_matches, aa10.len()),
-                    aa10_sig: sig.aa10.clone(),
-                    aa34_score: similarity(aa34_matches, sig.aa34.len()),
-                    aa34_sig: sig.aa34.clone(),
-                })
-            } else if aa10_matches == max_aa10_matches && aa34_matches > max_aa34_matches {
-                max_aa34_matches = aa34_matches;
-                predictions.add(Prediction {
-                    name: sig.winner.clone(),
-                    score: calculate_score(
-                        aa10_matches,
-                        aa10.len(),
-                        aa34_matches,
-                        domain.aa34.len(),
-                    ),
-                });
-                stach_predictions.add(StachPrediction {
-                    name: sig.winner.clone(),
-                    aa10_score: similarity(aa10_matches, aa10.len()),
-                    aa10_sig: sig.aa10.clone(),
-                    aa34_score: similarity(aa34_matches, sig.aa34.len()),
-                    aa34_sig: sig.aa34.clone(),
-                })
+        let scored = score_signatures(
+            signatures,
+            &domain.aa10,
+            &domain.aa34,
+            StachelhausScoring::Identity,
+            DEFAULT_GAP_PENALTY,
+        );
+        add_predictions(domain, &scored, false);
+    }
+    Ok(())
+}
+
+/// aa10 similarity, aa34 similarity and the signature they were calculated for
+type Scored<'a> = (f64, f64, &'a StachelhausSignature);
+
+/// Score all signatures, don't bother showing hits < 7 aa10 matches
+fn score_signatures<'a>(
+    signatures: &'a [StachelhausSignature],
+    aa10: &str,
+    aa34: &str,
+    scoring: StachelhausScoring,
+    gap_penalty: f64,
+) -> Vec<Scored<'a>> {
+    // With identity scoring, this is the same as requiring 7 aa10 matches
+    let min_aa10_score = similarity(MIN_AA10_MATCHES, 10);
+    signatures
+        .iter()
+        .filter_map(|sig| {
+            let aa10_score = match scoring {
+                // Most signatures are way off, stop counting as soon as they can't make the cut
+                StachelhausScoring::Identity if gap_penalty == DEFAULT_GAP_PENALTY => similarity(
+                    bounded_matches(aa10, &sig.aa10, MIN_AA10_MATCHES)?,
+                    aa10.len(),
+                ),
+                _ => scoring.similarity_with_gap_penalty(aa10, &sig.aa10, gap_penalty),
+            };
+            if aa10_score < min_aa10_score {
+                return None;
             }
+            // Domains from aa10-only input have no aa34 to compare
+            let aa34_score = if aa34.is_empty() {
+                0.0
+            } else {
+                scoring.similarity_with_gap_penalty(aa34, &sig.aa34, gap_penalty)
+            };
+            Some((aa10_score, aa34_score, sig))
+        })
+        .collect()
+}
+
+fn add_predictions(domain: &mut ADomain, scored: &[Scored], alternatives: bool) {
+    let mut predictions = PredictionList::new();
+    let mut stach_predictions = StachPredictionList::new();
+
+    // Keep the best match per winner, merging the evidence and reference IDs of
+    // tied signatures. Only the winners tied for the overall best match count as predictions
+    let best = scored
+        .iter()
+        .map(|(aa10, aa34, _)| (*aa10, *aa34))
+        .max_by(|a, b| a.0.total_cmp(&b.0).then_with(|| a.1.total_cmp(&b.1)));
+    let mut winners: Vec<WinnerMatch> = Vec::new();
+    for (aa10_score, aa34_score, sig) in scored.iter() {
+        let scores = (*aa10_score, *aa34_score);
+        match winners.iter_mut().find(|w| w.sig.winner == sig.winner) {
+            Some(winner) if scores > winner.scores => *winner = WinnerMatch::new(scores, sig),
+            Some(winner) if scores == winner.scores => winner.merge(sig),
+            Some(_) => continue,
+            None => winners.push(WinnerMatch::new(scores, sig)),
         }
-        for pred in predictions.get_best().iter() {
-            domain.add(PredictionCategory::Stachelhaus, pred.clone());
+    }
+    for winner in winners {
+        let (aa10_score, aa34_score) = winner.scores;
+        let sig = winner.sig;
+        if Some(winner.scores) == best {
+            predictions.add(Prediction {
+                name: sig.winner.clone(),
+                score: if domain.aa34.is_empty() {
+                    aa10_score
+                } else {
+                    calculate_score(aa10_score, aa34_score)
+                },
+                probability: None,
+                model: None,
+            });
+        }
+        stach_predictions.add(StachPrediction {
+            name: sig.winner.clone(),
+            aa10_score,
+            aa10_sig: sig.aa10.clone(),
+            aa34_score,
+            aa34_sig: sig.aa34.clone(),
+            all_substrates: sig.all.clone(),
+            ids: winner.ids,
+            evidence: winner.evidence,
+        })
+    }
+
+    let mut best_predictions = predictions.get_best();
+    if alternatives {
+        if let Some(best) = best {
+            // Like NRPSPredictor2, combine the winners and the other substrates of all
+            // signatures tied for the best match into one call, e.g. "Val|Leu"
+            let mut substrates: Vec<&str> = Vec::new();
+            for (_, _, sig) in scored.iter().filter(|(a, b, _)| (*a, *b) == best) {
+                for substrate in std::iter::once(sig.winner.as_str()).chain(sig.substrates()) {
+                    if !substrates.contains(&substrate) {
+                        substrates.push(substrate);
+                    }
+                }
+            }
+            if substrates.len() > 1 {
+                best_predictions = Vec::from([Prediction {
+                    name: substrates.join("|"),
+                    score: best_predictions[0].score,
+                    probability: None,
+                    model: None,
+                }]);
+            }
+        }
+    }
+    for pred in best_predictions.into_iter() {
+        domain.add(PredictionCategory::Stachelhaus, pred);
+    }
+    domain.stach_predictions = stach_predictions;
+}
+
+/// The best signature match found for one winner so far
+struct WinnerMatch<'a> {
+    /// aa10 and aa34 similarity
+    scores: (f64, f64),
+    sig: &'a StachelhausSignature,
+    evidence: Option<EvidenceLevel>,
+    ids: Vec<String>,
+}
+
+impl<'a> WinnerMatch<'a> {
+    fn new(scores: (f64, f64), sig: &'a StachelhausSignature) -> Self {
+        WinnerMatch {
+            scores,
+            sig,
+            evidence: sig.evidence,
+            ids: Vec::from([sig.ids.clone()]),
+        }
+    }
+
+    fn merge(&mut self, sig: &StachelhausSignature) {
+        self.evidence = self.evidence.max(sig.evidence);
+        if !self.ids.contains(&sig.ids) {
+            self.ids.push(sig.ids.clone());
         }
-        domain.stach_predictions = stach_predictions;
     }
-    Ok(())
 }
 
-fn calculate_score(
-    primary_matches: usize,
-    primary_len: usize,
-    secondary_matches: usize,
-    secondary_len: usize,
-) -> f64 {
-    let primary_score = similarity(primary_matches, primary_len);
-    let penalty = 1.0 - similarity(secondary_matches, secondary_len);
+fn calculate_score(primary_score: f64, secondary_score: f64) -> f64 {
+    let penalty = 1.0 - secondary_score;
     primary_score - (penalty / 10.0)
 }
 
@@ -92,38 +398,131 @@ fn similarity(matches: usize, len: usize) -> f64 {
 }
 
 #[derive(Debug)]
-struct StachelhausSignature {
+pub struct StachelhausSignature {
     pub aa10: String,
     pub aa34: String,
-    // pub all: String,
+    /// All substrates reported for the reference entry
+    pub all: String,
     pub winner: String,
-    // pub ids: String,
+    /// Accession(s) of the reference entry
+    pub ids: String,
+    pub evidence: Option<EvidenceLevel>,
+}
+
+impl StachelhausSignature {
+    /// The substrates listed in the `all` column
+    pub fn substrates(&self) -> impl Iterator<Item = &str> {
+        self.all
+            .split(['|', ','])
+            .map(str::trim)
+            .filter(|substrate| !substrate.is_empty())
+    }
+}
+
+/// Parse the configured signature file and merge in the extra signature files
+pub fn parse_stachelhaus_sigs(config: &Config) -> Result<Vec<StachelhausSignature>, NrpsError> {
+    let mut signatures = parse_main_sigs(config)?;
+    for path in config.extra_stachelhaus_signatures.iter() {
+        let extra = parse_signatures(open(path)?).map_err(|err| match err {
+            NrpsError::SignatureFileError(msg) => {
+                NrpsError::SignatureFileError(format!("{}: {msg}", path.display()))
+            }
+            err => err,
+        })?;
+        log::debug!(
+            "Merging {} Stachelhaus signatures from {}",
+            extra.len(),
+            path.display()
+        );
+        merge_signatures(&mut signatures, extra);
+    }
+    Ok(signatures)
+}
+
+fn parse_main_sigs(config: &Config) -> Result<Vec<StachelhausSignature>, NrpsError> {
+    #[cfg(feature = "bundled-models")]
+    if !config.stachelhaus_signatures().exists() {
+        if let Some(data) = crate::bundled::BUNDLED_SIGNATURES {
+            return parse_signatures(data);
+        }
+    }
+
+    let reader = open(config.stachelhaus_signatures())?;
+    parse_signatures(reader)
+}
+
+fn open(path: &Path) -> Result<Box<dyn BufRead>, NrpsError> {
+    compression::open(path)
+        .map_err(|err| NrpsError::SignatureFileError(format!("{}: {err}", path.display())))
 }
 
-fn parse_stachelhaus_sigs(config: &Config) -> Result<Vec<StachelhausSignature>, NrpsError> {
-    let reader = File::open(config.stachelhaus_signatures())?;
-    parse_sigs_internal(reader)
+/// Append `extra` to `signatures`, skipping signatures already present with the same substrate
+pub fn merge_signatures(
+    signatures: &mut Vec<StachelhausSignature>,
+    extra: Vec<StachelhausSignature>,
+) {
+    let mut seen: HashSet<(String, String)> = signatures
+        .iter()
+        .map(|sig| (sig.aa34.clone(), sig.winner.clone()))
+        .collect();
+    for sig in extra {
+        if seen.insert((sig.aa34.clone(), sig.winner.clone())) {
+            signatures.push(sig);
+        }
+    }
 }
 
-fn parse_sigs_internal<R>(handle: R) -> Result<Vec<StachelhausSignature>, NrpsError>
+pub fn parse_signatures<R>(handle: R) -> Result<Vec<StachelhausSignature>, NrpsError>
 where
     R: Read,
 {
     let mut signatures = Vec::with_capacity(2500);
     let reader = BufReader::new(handle);
-    for line_res in reader.lines() {
-        let parts: Vec<String> = line_res?
-            .trim()
-            .split('\t')
-            .map(|s| s.to_string())
-            .collect();
-        if parts.len() != 5 {
-            return Err(NrpsError::SignatureError(parts.join("")));
+    for (i, line_res) in reader.lines().enumerate() {
+        let line = line_res?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() != 5 && parts.len() != 6 {
+            return Err(NrpsError::SignatureFileError(format!(
+                "line {}: expected 5 or 6 columns, got {}",
+                i + 1,
+                parts.len()
+            )));
         }
+        // Signatures are compared byte by byte, so they need to be ASCII
+        if !parts[0].is_ascii()
+            || parts[0].len() != 10
+            || !parts[1].is_ascii()
+            || parts[1].len() != 34
+        {
+            return Err(NrpsError::SignatureFileError(format!(
+                "line {}: invalid signature lengths in `{line}`",
+                i + 1
+            )));
+        }
+        let evidence = match parts.get(5).map(|level| level.trim()) {
+            None | Some("") => None,
+            Some(level) if level.eq_ignore_ascii_case("characterized") => {
+                Some(EvidenceLevel::Characterized)
+            }
+            Some(level) if level.eq_ignore_ascii_case("inferred") => Some(EvidenceLevel::Inferred),
+            Some(level) => {
+                return Err(NrpsError::SignatureFileError(format!(
+                    "line {}: invalid evidence level `{level}`",
+                    i + 1
+                )))
+            }
+        };
         let sig = StachelhausSignature {
             aa10: parts[0].to_string(),
             aa34: parts[1].to_string(),
+            all: parts[2].to_string(),
             winner: parts[3].to_string(),
+            ids: parts[4].to_string(),
+            evidence,
         };
         signatures.push(sig);
     }
@@ -131,30 +530,128 @@ where
 }
 
 pub fn extract_aa10(aa34: &str) -> Result<String, NrpsError> {
-    let mut aa10 = String::with_capacity(10);
-    for (i, c) in aa34.chars().enumerate() {
-        match i {
-            5 | 6 | 9 | 12 | 14 | 16 | 21 | 29 | 30 => aa10.push(c),
-            _ => continue,
+    match aa10_bytes(aa34.as_bytes()) {
+        Some(aa10) if aa34.is_ascii() => Ok(aa10.iter().map(|residue| *residue as char).collect()),
+        _ => Err(NrpsError::SignatureError(aa34.to_string())),
+    }
+}
+
+/// The aa10 code at the 0-based aa34 `positions`, with the conserved K appended to nine positions
+pub fn extract_aa10_at(aa34: &str, positions: &[usize]) -> Result<String, NrpsError> {
+    let error = || NrpsError::SignatureError(aa34.to_string());
+    if !aa34.is_ascii() {
+        return Err(error());
+    }
+    let mut aa10 = positions
+        .iter()
+        .map(|position| {
+            aa34.as_bytes()
+                .get(*position)
+                .map(|residue| *residue as char)
+        })
+        .collect::<Option<String>>()
+        .ok_or_else(error)?;
+    if positions.len() == AA10_POSITIONS.len() {
+        aa10.push('K');
+    }
+    Ok(aa10)
+}
+
+/// The aa10 code of an ASCII aa34 signature without allocating, `None` if it's too short
+pub fn aa10_bytes(aa34: &[u8]) -> Option<[u8; 10]> {
+    // The last aa10 residue is always the conserved lysine
+    let mut aa10 = [b'K'; 10];
+    for (residue, position) in aa10.iter_mut().zip(AA10_POSITIONS) {
+        *residue = *aa34.get(position)?;
+    }
+    Some(aa10)
+}
+
+impl StachelhausScoring {
+    /// Similarity of `query` to a `reference` signature of the same length, 1.0 for identity
+    pub fn similarity(&self, query: &str, reference: &str) -> f64 {
+        self.similarity_with_gap_penalty(query, reference, DEFAULT_GAP_PENALTY)
+    }
+
+    /// Like `similarity`, but a gap aligned to a residue only costs `gap_penalty` of a mismatch.
+    /// Gaps aligned to gaps count as identical
+    pub fn similarity_with_gap_penalty(
+        &self,
+        query: &str,
+        reference: &str,
+        gap_penalty: f64,
+    ) -> f64 {
+        let (query, reference) = (query.as_bytes(), reference.as_bytes());
+        let gap_mismatches = gap_mismatches(query, reference);
+        let gap_credit = (1.0 - gap_penalty) * gap_mismatches as f64;
+        match self {
+            StachelhausScoring::Identity => {
+                let matches = query.len() - hamming_dist(query, reference);
+                (matches as f64 + gap_credit) / query.len() as f64
+            }
+            StachelhausScoring::Blosum62 => {
+                // Residues aligned to residues are scored relative to the self-score of the
+                // query, clamped so scores stay in 0..=1
+                let residues = || {
+                    query
+                        .iter()
+                        .zip(reference)
+                        .filter(|(a, b)| **a != GAP && **b != GAP)
+                };
+                let max: i32 = residues().map(|(a, _)| blosum62(*a, *a)).sum();
+                let score: i32 = residues().map(|(a, b)| blosum62(*a, *b)).sum();
+                let ratio = if max <= 0 {
+                    0.0
+                } else {
+                    (score as f64 / max as f64).clamp(0.0, 1.0)
+                };
+                let residue_count = residues().count();
+                let gap_matches = query.len() - residue_count - gap_mismatches;
+                (ratio * residue_count as f64 + gap_matches as f64 + gap_credit)
+                    / query.len() as f64
+            }
         }
     }
-    aa10.push('K');
-    if aa10.len() != 10 {
-        return Err(NrpsError::SignatureError(aa34.to_string()));
+}
+
+/// Number of matching residues, `None` as soon as there can't be at least `min` matches
+fn bounded_matches(a: &str, b: &str, min: usize) -> Option<usize> {
+    let max_mismatches = a.len().checked_sub(min)?;
+    let mut mismatches = 0;
+    for (x, y) in a.bytes().zip(b.bytes()) {
+        if x != y {
+            mismatches += 1;
+            if mismatches > max_mismatches {
+                return None;
+            }
+        }
     }
+    Some(a.len() - mismatches)
+}
 
-    Ok(aa10)
+fn hamming_dist(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).filter(|(x, y)| x != y).count()
 }
 
-fn hamming_dist(a: &str, b: &str) -> usize {
-    a.chars().zip(b.chars()).filter(|t| t.0 != t.1).count()
+/// Number of positions where exactly one of `a` and `b` has a gap
+fn gap_mismatches(a: &[u8], b: &[u8]) -> usize {
+    a.iter()
+        .zip(b)
+        .filter(|(x, y)| (**x == GAP) != (**y == GAP))
+        .count()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::sync::Arc;
+    use std::thread;
+
     use assert_approx_eq::assert_approx_eq;
+    use proptest::prelude::*;
+
+    use crate::predictors::predictions::Ties;
 
     #[test]
     fn test_extract_aa10() {
@@ -168,16 +665,523 @@ mod tests {
     fn test_extract_aa10_error() {
         let got = extract_aa10("THISISWAYTOOSHORT");
         assert!(got.is_err());
+        assert!(extract_aa10("HAKSF\u{e9}MSVVQCIACMGGETNCYGPTEITAAATF").is_err());
+    }
+
+    #[test]
+    fn test_aa10_bytes() {
+        let aa34 = b"HAKSFDMSVVQCIACMGGETNCYGPTEITAAATF";
+        assert_eq!(aa10_bytes(aa34), Some(*b"DMVICGCAAK"));
+        assert_eq!(aa10_bytes(&aa34[..30]), None);
+        assert_eq!(aa10_bytes(&aa34[..31]), Some(*b"DMVICGCAAK"));
     }
 
     #[test]
     fn test_hamming_dist() {
-        let a = String::from("ABCDE");
-        let b = String::from("ABCDF");
-        let c = String::from("EDCBA");
-        assert_eq!(hamming_dist(&a, &a), 0);
-        assert_eq!(hamming_dist(&a, &b), 1);
-        assert_eq!(hamming_dist(&a, &c), 4);
+        let a = b"ABCDE";
+        let b = b"ABCDF";
+        let c = b"EDCBA";
+        assert_eq!(hamming_dist(a, a), 0);
+        assert_eq!(hamming_dist(a, b), 1);
+        assert_eq!(hamming_dist(a, c), 4);
+    }
+
+    #[test]
+    fn test_parse_sigs() {
+        let data = "DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000311\n\n";
+        let sigs = parse_signatures(data.as_bytes()).unwrap();
+        assert_eq!(sigs.len(), 1);
+        assert_eq!(sigs[0].winner, "Leu");
+        assert_eq!(sigs[0].all, "Leu");
+        assert_eq!(sigs[0].ids, "BGC0000311");
+    }
+
+    #[test]
+    fn test_parse_sigs_evidence() {
+        let data = "DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000311\tcharacterized\n\
+                    DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000312\tInferred\n\
+                    DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000313\t\n\
+                    DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000314\n";
+        let sigs = parse_signatures(data.as_bytes()).unwrap();
+        let evidence: Vec<Option<EvidenceLevel>> = sigs.iter().map(|s| s.evidence).collect();
+        assert_eq!(
+            evidence,
+            [
+                Some(EvidenceLevel::Characterized),
+                Some(EvidenceLevel::Inferred),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_sigs_malformed() {
+        let cases = [
+            "DAFYLGMMCK",
+            "DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu",
+            "DAFYLGMMC\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000311",
+            "DAFYLGMMCK\tLDASFDASLFEMYLLTGG\tLeu\tLeu\tBGC0000311",
+            "DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000311\textra",
+            "DAFYLGMM\u{e9}K\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000311",
+        ];
+        for case in cases.iter() {
+            assert!(parse_signatures(case.as_bytes()).is_err(), "{case}");
+        }
+    }
+
+    fn sig(aa34: &str, winner: &str) -> StachelhausSignature {
+        StachelhausSignature {
+            aa10: extract_aa10(aa34).unwrap(),
+            aa34: aa34.to_string(),
+            all: winner.to_string(),
+            winner: winner.to_string(),
+            ids: format!("BGC_{winner}"),
+            evidence: None,
+        }
+    }
+
+    #[test]
+    fn test_predict_ties() {
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let signatures = [
+            // One aa34 mismatch
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATA", "Val"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATA", "Ile"),
+            // Exact matches, before and after the best hits so far
+            sig(aa34, "Leu"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATA", "Ala"),
+            sig(aa34, "Abu"),
+            sig(aa34, "Leu"),
+        ];
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.to_string())];
+        predict(&mut domains, &signatures).unwrap();
+
+        let mut names: Vec<String> = domains[0]
+            .get_all(&PredictionCategory::Stachelhaus)
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["Abu", "Leu"]);
+        // The runners-up are kept for the detailed output
+        assert_eq!(domains[0].stach_predictions.len(), 5);
+        assert_eq!(domains[0].stach_predictions.get_best().len(), 2);
+        for pred in domains[0].stach_predictions.get_best().iter() {
+            assert_approx_eq!(pred.aa34_score, 1.0);
+        }
+    }
+
+    #[test]
+    fn test_predict_runners_up() {
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let signatures = [
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATA", "Val"),
+            sig(aa34, "Leu"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCAAA", "Val"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCAAA", "Ile"),
+        ];
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.to_string())];
+        predict(&mut domains, &signatures).unwrap();
+
+        let domain = &domains[0];
+        assert_eq!(domain.get_all(&PredictionCategory::Stachelhaus).len(), 1);
+        assert_eq!(domain.stach_predictions.len(), 3);
+        let best = domain.stach_predictions.get_best();
+        assert_eq!(best.len(), 1);
+        assert_eq!(best[0].name, "Leu");
+
+        let names: Vec<String> = domain
+            .stach_predictions
+            .get_best_n(2, Ties::Include)
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, ["Leu", "Val"]);
+        assert_eq!(
+            domain.stach_predictions.to_detail(2, Ties::Include),
+            "Leu:DAFYLGMMCK:LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW:1.00:1.00:BGC_Leu|\
+             Val:DAFYLGMMCK:LDASFDASLFEMYLLTGGDRNMYGPTEATMCATA:1.00:0.97:BGC_Val"
+        );
+    }
+
+    #[test]
+    fn test_predict_alternatives() {
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let mut ambiguous = sig(aa34, "Val");
+        ambiguous.all = "Val|Leu".to_string();
+        let mut db = StachelhausDb::new(Vec::from([ambiguous, sig(aa34, "Ile"), sig(aa34, "Leu")]));
+        db.alternatives = true;
+
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.to_string())];
+        db.predict(&mut domains).unwrap();
+        let got = domains[0].get_all(&PredictionCategory::Stachelhaus);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "Val|Leu|Ile");
+        assert_approx_eq!(got[0].score, 1.0);
+
+        // A single unambiguous winner is reported as before
+        let mut db = StachelhausDb::new(Vec::from([sig(aa34, "Leu")]));
+        db.alternatives = true;
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.to_string())];
+        db.predict(&mut domains).unwrap();
+        let got = domains[0].get_all(&PredictionCategory::Stachelhaus);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "Leu");
+    }
+
+    #[test]
+    fn test_blosum62_scoring() {
+        let query = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let scoring = StachelhausScoring::Blosum62;
+        assert_approx_eq!(scoring.similarity(query, query), 1.0);
+
+        // L -> I is conservative, L -> P is not, identity scoring can't tell them apart
+        let conservative = "IDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let radical = "PDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        assert!(scoring.similarity(query, conservative) > scoring.similarity(query, radical));
+        assert_approx_eq!(
+            StachelhausScoring::Identity.similarity(query, conservative),
+            StachelhausScoring::Identity.similarity(query, radical)
+        );
+
+        let mut db = StachelhausDb::new(Vec::from([sig(radical, "Pro"), sig(conservative, "Ile")]));
+        db.scoring = scoring;
+        let mut domains = [ADomain::new("bpsA".to_string(), query.to_string())];
+        db.predict(&mut domains).unwrap();
+        let got = domains[0].get_all(&PredictionCategory::Stachelhaus);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "Ile");
+
+        // A NaN gap penalty makes all scores NaN, finding the best one must not panic
+        db.gap_penalty = f64::NAN;
+        let mut domains = [ADomain::new("bpsA".to_string(), query.to_string())];
+        db.predict(&mut domains).unwrap();
+        assert!(domains[0].stach_predictions.get_best()[0]
+            .aa34_score
+            .is_nan());
+    }
+
+    #[test]
+    fn test_gap_penalty() {
+        let query = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let gapped = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCA--";
+        for scoring in [StachelhausScoring::Identity, StachelhausScoring::Blosum62] {
+            // Gaps aligned to gaps are identical
+            assert_approx_eq!(scoring.similarity(gapped, gapped), 1.0);
+            assert_approx_eq!(scoring.similarity(query, gapped), 32.0 / 34.0);
+            assert_approx_eq!(scoring.similarity(gapped, query), 32.0 / 34.0);
+            assert_approx_eq!(
+                scoring.similarity_with_gap_penalty(query, gapped, 0.5),
+                33.0 / 34.0
+            );
+            assert_approx_eq!(scoring.similarity_with_gap_penalty(query, gapped, 0.0), 1.0);
+        }
+
+        let mut db = StachelhausDb::new(Vec::from([sig(gapped, "Leu")]));
+        for (gap_penalty, expected) in [(1.0, 32.0 / 34.0), (0.0, 1.0)] {
+            db.gap_penalty = gap_penalty;
+            let mut domains = [ADomain::new("bpsA".to_string(), query.to_string())];
+            db.predict(&mut domains).unwrap();
+            let best = &domains[0].stach_predictions.get_best()[0];
+            assert_approx_eq!(best.aa34_score, expected);
+        }
+
+        // Without a gap penalty, residues aligned to the query's gaps tie with the identical hit
+        let mut db = StachelhausDb::new(Vec::from([sig(query, "Val"), sig(gapped, "Leu")]));
+        db.gap_penalty = 0.0;
+        db.runners_up = false;
+        let mut domains = [ADomain::new("bpsA".to_string(), gapped.to_string())];
+        db.predict(&mut domains).unwrap();
+        let mut names: Vec<String> = domains[0]
+            .get_all(&PredictionCategory::Stachelhaus)
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["Leu", "Val"]);
+
+        let mut config = Config::new();
+        config.stachelhaus_gap_penalty = 1.5;
+        assert!(matches!(
+            StachelhausDb::from_config(&config),
+            Err(NrpsError::GapPenaltyError(_))
+        ));
+    }
+
+    #[test]
+    fn test_exact_match_index() {
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let signatures = || {
+            Vec::from([
+                sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATA", "Val"),
+                sig(aa34, "Leu"),
+                sig(aa34, "Abu"),
+            ])
+        };
+        let domain = || {
+            [
+                ADomain::new("exact".to_string(), aa34.to_string()),
+                ADomain::new(
+                    "close".to_string(),
+                    "LDASFDASLFEMYLLTGGDRNMYGPTEATMCAAA".to_string(),
+                ),
+            ]
+        };
+
+        let mut scanned = domain();
+        predict(&mut scanned, &signatures()).unwrap();
+        let mut db = StachelhausDb::new(signatures());
+        db.runners_up = false;
+        let mut indexed = domain();
+        db.predict(&mut indexed).unwrap();
+
+        for (scanned, indexed) in scanned.iter().zip(indexed.iter()) {
+            assert_eq!(
+                scanned.get_all(&PredictionCategory::Stachelhaus),
+                indexed.get_all(&PredictionCategory::Stachelhaus)
+            );
+            assert_eq!(
+                scanned.stach_predictions.get_best(),
+                indexed.stach_predictions.get_best()
+            );
+        }
+        // The index only returns the exact hits, the scan also keeps the runners-up
+        assert_eq!(indexed[0].stach_predictions.len(), 2);
+        assert_eq!(scanned[0].stach_predictions.len(), 3);
+    }
+
+    #[test]
+    fn test_novelty() {
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let mut db = StachelhausDb::new(Vec::from([
+            sig(aa34, "Leu"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCAAA", "Val"),
+        ]));
+        let mut domains = [
+            ADomain::new("exact".to_string(), aa34.to_string()),
+            ADomain::new(
+                "novel".to_string(),
+                "LEPAFDISLFEVHLLTGGDRHLYGPTEATMCAAA".to_string(),
+            ),
+            ADomain::from_aa10("aa10".to_string(), "DAFYLGMMCK".to_string()),
+        ];
+        db.predict(&mut domains).unwrap();
+        assert!(domains.iter().all(|domain| domain.novelty.is_none()));
+
+        db.novelty = true;
+        db.predict(&mut domains).unwrap();
+        assert_eq!(domains[0].novelty, Some(0));
+        assert_eq!(domains[1].novelty, Some(8));
+        assert_eq!(domains[2].novelty, None);
+        assert_eq!(StachelhausDb::default().min_distance(aa34), None);
+    }
+
+    #[test]
+    fn test_nearest() {
+        let mut db = StachelhausDb::new(Vec::from([
+            sig("LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW", "Hpg"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCAAA", "Val"),
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW", "Leu"),
+        ]));
+        db.nearest_references = 2;
+        let mut domains = [ADomain::new(
+            "bpsA".to_string(),
+            "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATT".to_string(),
+        )];
+        db.predict(&mut domains).unwrap();
+
+        let got = &domains[0].nearest_references;
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].name, "Leu");
+        assert_approx_eq!(got[0].aa34_score, 33.0 / 34.0);
+        assert_eq!(got[1].name, "Val");
+        assert_approx_eq!(got[1].aa34_score, 32.0 / 34.0);
+
+        // Also listed if too different to be predicted
+        let got = db.nearest("AAAAAAAAAK", "", 1);
+        assert_eq!(got.len(), 1);
+        assert_approx_eq!(got[0].aa10_score, 0.2);
+
+        // NaN scores from a NaN gap penalty still sort
+        db.gap_penalty = f64::NAN;
+        let got = db.nearest("AAAAAAAAAK", "", 2);
+        assert_eq!(got.len(), 2);
+        assert!(got[0].aa10_score.is_nan());
+    }
+
+    #[test]
+    fn test_bounded_matches() {
+        assert_eq!(bounded_matches("ABCDE", "ABCDE", 3), Some(5));
+        assert_eq!(bounded_matches("ABCDE", "ABCXX", 3), Some(3));
+        assert_eq!(bounded_matches("ABCDE", "AXXXE", 3), None);
+        assert_eq!(bounded_matches("ABC", "ABC", 4), None);
+    }
+
+    #[test]
+    fn test_predict_aa10_only() {
+        let signatures = [
+            sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW", "Leu"),
+            sig("LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW", "Hpg"),
+        ];
+        let mut domains = [ADomain::from_aa10(
+            "bpsA".to_string(),
+            "DAFYLGMMCK".to_string(),
+        )];
+        predict(&mut domains, &signatures).unwrap();
+
+        let got = domains[0].get_all(&PredictionCategory::Stachelhaus);
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "Leu");
+        assert_approx_eq!(got[0].score, 1.0);
+        assert_approx_eq!(domains[0].stach_predictions.get_best()[0].aa34_score, 0.0);
+    }
+
+    #[test]
+    fn test_predict_ids() {
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let mut first = sig(aa34, "Leu");
+        first.ids = "BGC0000311".to_string();
+        let mut second = sig(aa34, "Leu");
+        second.all = "Leu|Ile".to_string();
+        second.ids = "BGC0000404".to_string();
+        let signatures = [first, second];
+
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.to_string())];
+        predict(&mut domains, &signatures).unwrap();
+        let best = domains[0].stach_predictions.get_best();
+        assert_eq!(best.len(), 1);
+        assert_eq!(best[0].all_substrates, "Leu");
+        assert_eq!(best[0].ids, ["BGC0000311", "BGC0000404"]);
+    }
+
+    #[test]
+    fn test_predict_evidence() {
+        let aa34 = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW";
+        let mut inferred = sig(aa34, "Leu");
+        inferred.evidence = Some(EvidenceLevel::Inferred);
+        let mut characterized = sig(aa34, "Leu");
+        characterized.evidence = Some(EvidenceLevel::Characterized);
+        let signatures = [inferred, sig(aa34, "Leu"), characterized];
+
+        let mut domains = [ADomain::new("bpsA".to_string(), aa34.to_string())];
+        predict(&mut domains, &signatures).unwrap();
+        let best = domains[0].stach_predictions.get_best();
+        assert_eq!(best.len(), 1);
+        assert_eq!(best[0].evidence, Some(EvidenceLevel::Characterized));
+        assert!(domains[0]
+            .stach_predictions
+            .to_table()
+            .ends_with("\tcharacterized\tBGC_Leu"));
+    }
+
+    #[test]
+    fn test_extract_aa10_at() {
+        let aa34 = "HAKSFDMSVVQCIACMGGETNCYGPTEITAAATF";
+        assert_eq!(
+            extract_aa10_at(aa34, &AA10_POSITIONS).unwrap(),
+            extract_aa10(aa34).unwrap()
+        );
+        let positions: Vec<usize> = (0..10).collect();
+        assert_eq!(extract_aa10_at(aa34, &positions).unwrap(), "HAKSFDMSVV");
+        assert!(extract_aa10_at(&aa34[..20], &positions[..9]).is_ok());
+        assert!(extract_aa10_at(&aa34[..9], &positions).is_err());
+    }
+
+    #[test]
+    fn test_aa10_positions() {
+        // Identical at the NRPSPredictor2 positions, different at the first ten
+        let data = "DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000311\n";
+        let query = "WWWWWDAWWFEMYLLTGGDRNMYGPTEATMCATW";
+        let mut db = StachelhausDb::from_reader(data.as_bytes()).unwrap();
+        let mut domains = [ADomain::new("query".to_string(), query.to_string())];
+        db.predict(&mut domains).unwrap();
+        assert_eq!(domains[0].aa10, "DAFYLGMMCK");
+        assert_eq!(domains[0].stach_predictions.get_best()[0].aa10_score, 1.0);
+
+        let positions: Vec<usize> = (1..=10).collect();
+        db.set_aa10_positions(&positions).unwrap();
+        assert_eq!(db.signatures()[0].aa10, "LDASFDASLF");
+        let mut domains = [ADomain::new("query".to_string(), query.to_string())];
+        db.predict(&mut domains).unwrap();
+        assert_eq!(domains[0].aa10, "WWWWWDAWWF");
+        assert!(domains[0].stach_predictions.is_empty());
+
+        assert!(db.set_aa10_positions(&positions[..8]).is_err());
+        assert!(db.set_aa10_positions(&[0, 1, 2, 3, 4, 5, 6, 7, 8]).is_err());
+        assert!(db
+            .set_aa10_positions(&[2, 3, 4, 5, 6, 7, 8, 9, 35])
+            .is_err());
+    }
+
+    #[test]
+    fn test_shared_db() {
+        let data = "DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000311\n";
+        let db = Arc::new(StachelhausDb::from_reader(data.as_bytes()).unwrap());
+        assert_eq!(db.len(), 1);
+
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let db = Arc::clone(&db);
+                thread::spawn(move || {
+                    let mut domains = [ADomain::new(
+                        "bpsA".to_string(),
+                        "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".to_string(),
+                    )];
+                    db.predict(&mut domains).unwrap();
+                    domains[0].get_all(&PredictionCategory::Stachelhaus)[0]
+                        .name
+                        .clone()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), "Leu");
+        }
+    }
+
+    #[test]
+    fn test_parse_sigs_extra() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let main = dir.join("signatures.tsv");
+        let extra = dir.join("extra.tsv");
+        std::fs::write(
+            &main,
+            "DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tBGC0000311\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &extra,
+            "DAFYLGMMCK\tLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tLeu\tLeu\tinhouse_1\n\
+             DVWHVSLVDK\tLEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW\tHpg\tHpg\tinhouse_2\n",
+        )
+        .unwrap();
+
+        let mut config = Config::new();
+        config.set_stachelhaus_signatures(main);
+        config.extra_stachelhaus_signatures = Vec::from([extra.clone()]);
+        let sigs = parse_stachelhaus_sigs(&config).unwrap();
+        let winners: Vec<&str> = sigs.iter().map(|s| s.winner.as_str()).collect();
+        assert_eq!(winners, ["Leu", "Hpg"]);
+
+        std::fs::write(&extra, "garbage\n").unwrap();
+        let err = parse_stachelhaus_sigs(&config).unwrap_err().to_string();
+        assert!(err.contains("extra.tsv"), "{err}");
+    }
+
+    #[test]
+    fn test_predict_no_hit() {
+        let signatures = [sig("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW", "Leu")];
+        let mut domains = [ADomain::new(
+            "other".to_string(),
+            "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA".to_string(),
+        )];
+        predict(&mut domains, &signatures).unwrap();
+        assert!(domains[0].stach_predictions.is_empty());
+        assert!(domains[0]
+            .get_all(&PredictionCategory::Stachelhaus)
+            .is_empty());
     }
 
     type Parts = (usize, usize, usize, usize);
@@ -194,8 +1198,40 @@ mod tests {
             let expected = case.1;
             assert_approx_eq!(
                 expected,
-                calculate_score(values.0, values.1, values.2, values.3)
+                calculate_score(
+                    similarity(values.0, values.1),
+                    similarity(values.2, values.3)
+                )
             );
         }
     }
+
+    proptest! {
+        #[test]
+        fn test_parse_sigs_never_panics(data in "\\PC*(\n\\PC*){0,3}") {
+            let _ = parse_signatures(data.as_bytes());
+        }
+
+        #[test]
+        fn test_parse_sigs_predict(
+            aa10 in "[A-Z-]{10}|[A-Z\u{e9}]{9,10}",
+            aa34 in "[A-Z-]{34}|[A-Z\u{e9}]{33,34}",
+            columns in prop::collection::vec("[ -~]{0,8}", 3..5),
+            query in "[A-Z-]{34}",
+        ) {
+            let line = [aa10, aa34].into_iter().chain(columns).collect::<Vec<_>>().join("\t");
+            let Ok(mut db) = StachelhausDb::from_reader(line.as_bytes()) else {
+                return Ok(());
+            };
+            prop_assert_eq!(db.signatures()[0].aa10.len(), 10);
+            prop_assert_eq!(db.signatures()[0].aa34.len(), 34);
+            let mut domains = [ADomain::new("query".to_string(), query)];
+            for scoring in [StachelhausScoring::Identity, StachelhausScoring::Blosum62] {
+                db.scoring = scoring;
+                db.novelty = true;
+                db.nearest_references = 2;
+                db.predict(&mut domains).unwrap();
+            }
+        }
+    }
 }
@@ -0,0 +1,327 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Models grouped by category, loaded from the model dir the first time a category is used.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::predictors::predictions::PredictionCategory;
+use crate::predictors::{cache, is_wanted_model, load_models_for_substrates, LoadReport};
+use crate::svm::calibration::Calibration;
+use crate::svm::models::SVMlightModel;
+
+#[derive(Debug, Default)]
+pub struct ModelStore {
+    /// Where to load missing categories from, `None` for stores built from in-memory models
+    config: Option<Config>,
+    models: Vec<(PredictionCategory, OnceLock<Vec<SVMlightModel>>)>,
+    /// Unknown model dirs are found on every load, only report them once
+    unknown_dirs_reported: AtomicBool,
+}
+
+impl ModelStore {
+    /// A store for the categories enabled in `config`, nothing is loaded yet
+    pub fn new(config: &Config) -> Self {
+        let models = config
+            .categories()
+            .into_iter()
+            .filter(PredictionCategory::has_models)
+            .map(|category| (category, OnceLock::new()))
+            .collect();
+        ModelStore {
+            config: Some(config.clone()),
+            models,
+            unknown_dirs_reported: AtomicBool::new(false),
+        }
+    }
+
+    /// A store holding exactly `models`
+    pub fn from_models(models: Vec<SVMlightModel>) -> Self {
+        let mut store = ModelStore::default();
+        for model in models {
+            store.push(model);
+        }
+        store
+    }
+
+    /// Add a model, a category that wasn't loaded yet won't be loaded from the model dir anymore
+    pub fn push(&mut self, model: SVMlightModel) {
+        let category = model.category.clone();
+        match self.models.iter_mut().find(|(cat, _)| *cat == category) {
+            Some((_, cell)) => match cell.get_mut() {
+                Some(models) => models.push(model),
+                None => {
+                    let _ = cell.set(Vec::from([model]));
+                }
+            },
+            None => self
+                .models
+                .push((category, OnceLock::from(Vec::from([model])))),
+        }
+    }
+
+    /// The categories this store has or can load models for
+    pub fn categories(&self) -> Vec<PredictionCategory> {
+        self.models
+            .iter()
+            .map(|(category, _)| category.clone())
+            .collect()
+    }
+
+    /// The models for `category`, loading them if needed, empty for disabled categories
+    pub fn models_for(&self, category: &PredictionCategory) -> Result<&[SVMlightModel], NrpsError> {
+        let Some((_, cell)) = self.models.iter().find(|(cat, _)| cat == category) else {
+            return Ok(&[]);
+        };
+        if let Some(models) = cell.get() {
+            return Ok(models);
+        }
+        let Some(config) = &self.config else {
+            return Ok(&[]);
+        };
+
+        if config.cache_models {
+            // The cache is a single file, so fill in all categories at once
+            self.fill_from_cache(config)?;
+        } else {
+            let (models, mut report) = load_category(config, category.clone())?;
+            if self.unknown_dirs_reported.swap(true, Ordering::Relaxed) {
+                report.unknown_dirs.clear();
+            }
+            report.warn();
+            report.check(config.strict_models)?;
+            let _ = cell.set(models);
+        }
+        Ok(cell.get().map_or(&[], Vec::as_slice))
+    }
+
+    /// Reload all models from the model dir, returns the new number of models
+    ///
+    /// The new models are loaded before the old ones are dropped, so on errors the store keeps
+    /// its current models. Stores built from in-memory models have nothing to reload.
+    pub fn reload(&mut self) -> Result<usize, NrpsError> {
+        let Some(config) = &self.config else {
+            return Ok(self.model_count());
+        };
+        let store = ModelStore::new(config);
+        // Unknown dirs were already reported on the first load
+        store.unknown_dirs_reported.store(
+            self.unknown_dirs_reported.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        let count = store.load_all()?;
+        *self = store;
+        Ok(count)
+    }
+
+    /// Load all categories that aren't loaded yet, returns the total number of models
+    pub fn load_all(&self) -> Result<usize, NrpsError> {
+        let mut count = 0;
+        for category in self.categories() {
+            count += self.models_for(&category)?.len();
+        }
+        Ok(count)
+    }
+
+    /// Number of models loaded so far
+    pub fn model_count(&self) -> usize {
+        self.models
+            .iter()
+            .filter_map(|(_, cell)| cell.get())
+            .map(Vec::len)
+            .sum()
+    }
+
+    fn fill_from_cache(&self, config: &Config) -> Result<(), NrpsError> {
+        let mut by_category: HashMap<PredictionCategory, Vec<SVMlightModel>> = HashMap::new();
+        let mut models = cache::load_or_build(config)?;
+        models.retain(|model| is_wanted_model(&model.name, &config.model_substrates));
+        calibrate(config, &mut models)?;
+        for model in models {
+            by_category
+                .entry(model.category.clone())
+                .or_default()
+                .push(model);
+        }
+        for (category, cell) in self.models.iter() {
+            let _ = cell.set(by_category.remove(category).unwrap_or_default());
+        }
+        Ok(())
+    }
+}
+
+fn load_category(
+    config: &Config,
+    category: PredictionCategory,
+) -> Result<(Vec<SVMlightModel>, LoadReport), NrpsError> {
+    let substrates = &config.model_substrates;
+    #[cfg(feature = "bundled-models")]
+    let (mut models, report) = if !config.model_dir().exists() {
+        let mut models = crate::bundled::load_bundled_models(&[category])?;
+        models.retain(|model| is_wanted_model(&model.name, substrates));
+        (models, LoadReport::default())
+    } else {
+        load_models_for_substrates(config.model_dir(), Some(&[category]), substrates)?
+    };
+    #[cfg(not(feature = "bundled-models"))]
+    let (mut models, report) =
+        load_models_for_substrates(config.model_dir(), Some(&[category]), substrates)?;
+
+    calibrate(config, &mut models)?;
+    Ok((models, report))
+}
+
+/// Modification time of the newest model file, Stachelhaus signature or calibration file
+///
+/// Used to detect when the models need to be reloaded, files that don't exist are ignored.
+pub fn last_modified(config: &Config) -> Result<SystemTime, NrpsError> {
+    let mut newest = if config.model_dir().is_dir() {
+        cache::newest_model_file(config.model_dir(), &config.model_cache())?
+    } else {
+        SystemTime::UNIX_EPOCH
+    };
+    let files = std::iter::once(config.stachelhaus_signatures())
+        .chain(config.extra_stachelhaus_signatures.iter())
+        .chain(config.calibration_file.iter());
+    for file in files {
+        if let Ok(modified) = std::fs::metadata(file).and_then(|m| m.modified()) {
+            newest = newest.max(modified);
+        }
+    }
+    Ok(newest)
+}
+
+/// Attach the Platt-scaling parameters from the configured calibration file, if any
+fn calibrate(config: &Config, models: &mut [SVMlightModel]) -> Result<(), NrpsError> {
+    if let Some(path) = &config.calibration_file {
+        Calibration::from_file(path)?.apply(models);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    use crate::encodings::FeatureEncoding;
+    use crate::svm::calibration::PlattScaling;
+    use crate::svm::models::{KernelParameters, KernelType};
+    use crate::svm::vectors::SupportVectors;
+
+    fn model(name: &str, category: PredictionCategory) -> SVMlightModel {
+        SVMlightModel::new(
+            name.to_string(),
+            category,
+            SupportVectors::default(),
+            0.0,
+            FeatureEncoding::Wold,
+            KernelType::Linear,
+            KernelParameters::default(),
+        )
+    }
+
+    #[test]
+    fn test_from_models() {
+        let mut store = ModelStore::from_models(Vec::from([
+            model("leu", PredictionCategory::SingleV3),
+            model("val", PredictionCategory::SingleV2),
+        ]));
+        store.push(model("ile", PredictionCategory::SingleV3));
+
+        assert_eq!(
+            store.categories(),
+            [PredictionCategory::SingleV3, PredictionCategory::SingleV2]
+        );
+        assert_eq!(store.model_count(), 3);
+        let names: Vec<&str> = store
+            .models_for(&PredictionCategory::SingleV3)
+            .unwrap()
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect();
+        assert_eq!(names, ["leu", "ile"]);
+        assert!(store
+            .models_for(&PredictionCategory::LargeClusterV1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_lazy_loading() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let category_dir = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        fs::create_dir_all(&category_dir).unwrap();
+        let mut handle = fs::File::create(category_dir.join("[leu].mdl")).unwrap();
+        model("leu", PredictionCategory::SingleV2)
+            .write(&mut handle, 0)
+            .unwrap();
+
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.clone());
+        let store = ModelStore::new(&config);
+        assert_eq!(store.model_count(), 0);
+
+        assert!(store
+            .models_for(&PredictionCategory::SingleV3)
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.model_count(), 0);
+
+        let models = store.models_for(&PredictionCategory::SingleV2).unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "leu");
+        assert_eq!(store.model_count(), 1);
+        assert_eq!(store.load_all().unwrap(), 1);
+        assert_eq!(models[0].calibration, None);
+
+        let calibration_file = model_dir.join("platt.tsv");
+        fs::write(&calibration_file, "NRPS2_SINGLE_CLUSTER\tleu\t-2.0\t0.0\n").unwrap();
+        config.calibration_file = Some(calibration_file);
+        let store = ModelStore::new(&config);
+        let models = store.models_for(&PredictionCategory::SingleV2).unwrap();
+        assert_eq!(
+            models[0].calibration,
+            Some(PlattScaling { a: -2.0, b: 0.0 })
+        );
+    }
+
+    #[test]
+    fn test_reload() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let category_dir = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        fs::create_dir_all(&category_dir).unwrap();
+        let write_model = |name: &str| {
+            let mut handle = fs::File::create(category_dir.join(format!("[{name}].mdl"))).unwrap();
+            model(name, PredictionCategory::SingleV2)
+                .write(&mut handle, 0)
+                .unwrap();
+        };
+        write_model("leu");
+
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.clone());
+        let mut store = ModelStore::new(&config);
+        assert_eq!(store.load_all().unwrap(), 1);
+        let before = last_modified(&config).unwrap();
+
+        write_model("val");
+        assert_eq!(store.model_count(), 1);
+        assert_eq!(store.reload().unwrap(), 2);
+        assert_eq!(store.model_count(), 2);
+        assert!(last_modified(&config).unwrap() >= before);
+
+        // A broken model keeps the old models around
+        fs::write(category_dir.join("[ile].mdl"), "not a model").unwrap();
+        assert!(store.reload().is_err());
+        assert_eq!(store.model_count(), 2);
+    }
+}
@@ -0,0 +1,203 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::encodings::FeatureEncoding;
+use crate::errors::NrpsError;
+use crate::svm::models::SVMlightModel;
+
+use super::manifest::{category_manifest, model_set_version, MANIFEST_FILE};
+use super::predictions::PredictionCategory;
+use super::{extract_name, is_model_file};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelIssue {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    /// Model set version from the model dir manifest
+    pub version: Option<String>,
+    /// Number of valid models per category, in model dir order
+    pub counts: Vec<(PredictionCategory, usize)>,
+    pub issues: Vec<ModelIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// Parse every model in `model_dir` and collect all problems instead of stopping at the first
+pub fn validate_model_dir(model_dir: &Path) -> Result<ValidationReport, NrpsError> {
+    if !model_dir.is_dir() {
+        return Err(NrpsError::ModelDirError(format!(
+            "Model dir '{}' doesn't exist",
+            model_dir.display()
+        )));
+    }
+
+    let mut report = ValidationReport::default();
+    match model_set_version(model_dir) {
+        Ok(version) => report.version = version,
+        Err(err) => report.issues.push(ModelIssue {
+            path: model_dir.join(MANIFEST_FILE),
+            message: err.to_string(),
+        }),
+    }
+
+    for category_dir_res in WalkDir::new(model_dir)
+        .min_depth(1)
+        .max_depth(1)
+        .sort_by_file_name()
+    {
+        let category_dir = category_dir_res?;
+        if !category_dir.path().is_dir() {
+            continue;
+        }
+        let manifest = match category_manifest(category_dir.path()) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                report.issues.push(ModelIssue {
+                    path: category_dir.path().join(MANIFEST_FILE),
+                    message: err.to_string(),
+                });
+                continue;
+            }
+        };
+        let Some(category) = manifest.category.clone().or_else(|| {
+            PredictionCategory::from_dir_name(&category_dir.file_name().to_string_lossy())
+        }) else {
+            continue;
+        };
+
+        let mut count = 0;
+        let manifest_encoding = manifest.encoding()?;
+        let mut category_encoding: Option<FeatureEncoding> = manifest_encoding;
+
+        for model_file_res in WalkDir::new(category_dir.path())
+            .min_depth(1)
+            .max_depth(1)
+            .sort_by_file_name()
+        {
+            let model_file = model_file_res?.path().to_path_buf();
+            if !is_model_file(&model_file) {
+                continue;
+            }
+
+            let name = extract_name(&model_file);
+            let model = match SVMlightModel::from_file(
+                &model_file,
+                name,
+                category.clone(),
+                manifest_encoding,
+            ) {
+                Ok(model) => model,
+                Err(err) => {
+                    // The issue already names the file
+                    let message = match err {
+                        NrpsError::ModelParseError { line, source, .. } => {
+                            format!("line {line}: {source}")
+                        }
+                        NrpsError::FileError { source, .. } => source.to_string(),
+                        err => err.to_string(),
+                    };
+                    report.issues.push(ModelIssue {
+                        path: model_file,
+                        message,
+                    });
+                    continue;
+                }
+            };
+
+            if let Some(message) =
+                check_model(&model, *category_encoding.get_or_insert(model.encoding))
+                    .or_else(|| manifest.check_model(&model).err().map(|e| e.to_string()))
+            {
+                report.issues.push(ModelIssue {
+                    path: model_file,
+                    message,
+                });
+                continue;
+            }
+            count += 1;
+        }
+
+        report.counts.push((category, count));
+    }
+
+    Ok(report)
+}
+
+fn check_model(model: &SVMlightModel, category_encoding: FeatureEncoding) -> Option<String> {
+    if model.vectors.is_empty() {
+        return Some("No support vectors".to_string());
+    }
+
+    let expected = model.expected_dimensions();
+    if model.vectors.dimensions() != expected {
+        return Some(format!(
+            "{} features, but the {:?} encoding has {expected}",
+            model.vectors.dimensions(),
+            model.encoding
+        ));
+    }
+
+    if model.encoding != category_encoding {
+        return Some(format!(
+            "{:?} encoding, but other {} models use {category_encoding:?}",
+            model.encoding, model.category
+        ));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::svmlight_model;
+
+    #[test]
+    fn test_validate_model_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        let single = model_dir.join("NRPS2_SINGLE_CLUSTER");
+        let large = model_dir.join("NRPS2_LARGE_CLUSTER");
+        std::fs::create_dir_all(&single).unwrap();
+        std::fs::create_dir_all(&large).unwrap();
+
+        std::fs::write(single.join("[leu].mdl"), svmlight_model(102)).unwrap();
+        std::fs::write(single.join("[val].mdl"), svmlight_model(510)).unwrap();
+        std::fs::write(single.join("README"), "not a model").unwrap();
+        std::fs::write(large.join("[ala].mdl"), svmlight_model(102)).unwrap();
+        std::fs::write(large.join("[gly].mdl"), "garbage").unwrap();
+
+        let report = validate_model_dir(&model_dir).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.total(), 2);
+        assert_eq!(
+            report.counts,
+            Vec::from([
+                (PredictionCategory::LargeClusterV2, 1),
+                (PredictionCategory::SingleV2, 1)
+            ])
+        );
+        assert_eq!(report.issues.len(), 2);
+        assert_eq!(report.issues[0].path, large.join("[gly].mdl"));
+        assert_eq!(report.issues[1].path, single.join("[val].mdl"));
+
+        assert!(validate_model_dir(&model_dir.join("missing")).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+use std::path::PathBuf;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::config;
+use crate::errors::NrpsError;
+use crate::predictors::predictions::{self, PredictionCategory, Ties};
+
+impl From<NrpsError> for PyErr {
+    fn from(err: NrpsError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+fn parse_category(name: &str) -> PyResult<PredictionCategory> {
+    name.parse().map_err(PyValueError::new_err)
+}
+
+#[pyclass(name = "Config")]
+#[derive(Debug)]
+pub struct PyConfig {
+    inner: config::Config,
+}
+
+#[pymethods]
+impl PyConfig {
+    #[new]
+    #[pyo3(signature = (
+        model_dir=None,
+        stachelhaus_signatures=None,
+        count=1,
+        fungal=false,
+        skip_v3=false,
+        skip_v2=false,
+        skip_v1=false,
+        skip_stachelhaus=false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        model_dir: Option<PathBuf>,
+        stachelhaus_signatures: Option<PathBuf>,
+        count: usize,
+        fungal: bool,
+        skip_v3: bool,
+        skip_v2: bool,
+        skip_v1: bool,
+        skip_stachelhaus: bool,
+    ) -> PyResult<Self> {
+        if count < 1 {
+            return Err(NrpsError::CountError(count).into());
+        }
+        let mut inner = config::Config::new();
+        if let Some(dir) = model_dir {
+            inner.set_model_dir(dir);
+        }
+        if let Some(sigs) = stachelhaus_signatures {
+            inner.set_stachelhaus_signatures(sigs);
+        }
+        inner.count = count;
+        inner.fungal = fungal;
+        inner.skip_v3 = skip_v3;
+        inner.skip_v2 = skip_v2;
+        inner.skip_v1 = skip_v1;
+        inner.skip_stachelhaus = skip_stachelhaus;
+        inner.discover_custom_categories()?;
+        Ok(PyConfig { inner })
+    }
+
+    #[getter]
+    fn model_dir(&self) -> PathBuf {
+        self.inner.model_dir().clone()
+    }
+
+    #[getter]
+    fn stachelhaus_signatures(&self) -> PathBuf {
+        self.inner.stachelhaus_signatures().clone()
+    }
+
+    #[getter]
+    fn count(&self) -> usize {
+        self.inner.count
+    }
+
+    /// Names of the prediction categories enabled by this config
+    fn categories(&self) -> Vec<String> {
+        self.inner
+            .categories()
+            .iter()
+            .map(PredictionCategory::to_string)
+            .collect()
+    }
+}
+
+#[pyclass(name = "Prediction", get_all)]
+#[derive(Debug, Clone)]
+pub struct PyPrediction {
+    name: String,
+    score: f64,
+    probability: Option<f64>,
+    /// `category/label` of the SVM model that made the prediction
+    model: Option<String>,
+    substrates: Vec<String>,
+}
+
+impl From<&predictions::Prediction> for PyPrediction {
+    fn from(pred: &predictions::Prediction) -> Self {
+        PyPrediction {
+            name: pred.name.to_string(),
+            score: pred.score,
+            probability: pred.probability,
+            model: pred.model.as_ref().map(ToString::to_string),
+            substrates: pred
+                .model
+                .as_ref()
+                .map(|model| model.substrates.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[pymethods]
+impl PyPrediction {
+    fn __repr__(&self) -> String {
+        format!("Prediction(name={:?}, score={})", self.name, self.score)
+    }
+}
+
+#[pyclass(name = "ADomain")]
+#[derive(Debug)]
+pub struct PyADomain {
+    inner: predictions::ADomain,
+}
+
+#[pymethods]
+impl PyADomain {
+    #[getter]
+    fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    #[getter]
+    fn aa34(&self) -> &str {
+        &self.inner.aa34
+    }
+
+    #[getter]
+    fn aa10(&self) -> &str {
+        &self.inner.aa10
+    }
+
+    #[getter]
+    fn protein_id(&self) -> Option<&str> {
+        self.inner.protein_id.as_deref()
+    }
+
+    #[getter]
+    fn gene(&self) -> Option<&str> {
+        self.inner.gene.as_deref()
+    }
+
+    #[getter]
+    fn module_index(&self) -> Option<usize> {
+        self.inner.module_index
+    }
+
+    /// `(start, end)` of the domain, if known
+    #[getter]
+    fn coords(&self) -> Option<(usize, usize)> {
+        self.inner.coords.map(|coords| (coords.start, coords.end))
+    }
+
+    #[pyo3(signature = (category, count, include_ties = true))]
+    fn get_best_n(
+        &self,
+        category: &str,
+        count: usize,
+        include_ties: bool,
+    ) -> PyResult<Vec<PyPrediction>> {
+        let category = parse_category(category)?;
+        let ties = if include_ties {
+            Ties::Include
+        } else {
+            Ties::Exclude
+        };
+        Ok(self
+            .inner
+            .get_best_n(&category, count, ties)
+            .iter()
+            .map(PyPrediction::from)
+            .collect())
+    }
+
+    fn get_all(&self, category: &str) -> PyResult<Vec<PyPrediction>> {
+        let category = parse_category(category)?;
+        Ok(self
+            .inner
+            .get_all(&category)
+            .iter()
+            .map(PyPrediction::from)
+            .collect())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ADomain(name={:?}, aa34={:?})",
+            self.inner.name, self.inner.aa34
+        )
+    }
+}
+
+/// Run predictions on signature lines in the same format as the signature input file
+#[pyfunction]
+fn run_on_strings(config: &PyConfig, lines: Vec<String>) -> PyResult<Vec<PyADomain>> {
+    let domains = crate::run_on_strings(&config.inner, lines)?;
+    Ok(domains
+        .into_iter()
+        .map(|inner| PyADomain { inner })
+        .collect())
+}
+
+#[pymodule]
+fn nrps_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyConfig>()?;
+    m.add_class::<PyPrediction>()?;
+    m.add_class::<PyADomain>()?;
+    m.add_function(wrap_pyfunction!(run_on_strings, m)?)?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
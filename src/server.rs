@@ -0,0 +1,277 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! A minimal HTTP server keeping the models in memory between requests.
+//!
+//! `POST /predict` takes signature lines in the request body, in the same format as the
+//! signature files, and returns the predictions as JSON. `GET /health` reports the number
+//! of loaded models, `POST /reload` reloads the models and Stachelhaus signatures.
+
+use std::io::Read;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::output::{format_results, OutputFormat};
+use crate::parse_domain;
+use crate::predictors::manifest::ModelSetInfo;
+use crate::predictors::predictions::ADomain;
+use crate::predictors::store::last_modified;
+use crate::NrpsPredictor;
+
+/// Largest `POST /predict` body accepted, larger ones are answered with 413
+pub const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct PredictionServer {
+    predictor: RwLock<NrpsPredictor>,
+    /// How often to check the model files for changes, `None` to only reload on request
+    watch_interval: Option<Duration>,
+    /// Modification time of the loaded files and when they were last checked
+    watch_state: Mutex<(SystemTime, Instant)>,
+}
+
+impl PredictionServer {
+    /// Load the models and Stachelhaus signatures for `config`
+    pub fn new(config: &Config) -> Result<Self, NrpsError> {
+        let config = server_config(config)?;
+        let modified = last_modified(&config)?;
+        Ok(PredictionServer {
+            predictor: RwLock::new(NrpsPredictor::new(&config)?),
+            watch_interval: None,
+            watch_state: Mutex::new((modified, Instant::now())),
+        })
+    }
+
+    /// Reload the models whenever the model files change, checked every `interval`
+    pub fn watch(mut self, interval: Duration) -> Self {
+        self.watch_interval = Some(interval);
+        self
+    }
+
+    pub fn model_count(&self) -> usize {
+        self.predictor.read().unwrap().model_count()
+    }
+
+    /// Reload the models and Stachelhaus signatures, returns the new number of models
+    ///
+    /// Requests are answered with the old models until the new ones are loaded.
+    pub fn reload(&self) -> Result<usize, NrpsError> {
+        let config = server_config(self.predictor.read().unwrap().config())?;
+        let predictor = NrpsPredictor::new(&config)?;
+        let count = predictor.model_count();
+        *self.predictor.write().unwrap() = predictor;
+        Ok(count)
+    }
+
+    /// Reload the models if any of the files changed since they were loaded
+    ///
+    /// Only checks once per watch interval, returns whether the models were reloaded.
+    pub fn reload_if_changed(&self) -> bool {
+        let Some(interval) = self.watch_interval else {
+            return false;
+        };
+        let mut state = self.watch_state.lock().unwrap();
+        if state.1.elapsed() < interval {
+            return false;
+        }
+        state.1 = Instant::now();
+        let config = self.predictor.read().unwrap().config().clone();
+        let modified = match last_modified(&config) {
+            Ok(modified) => modified,
+            Err(e) => {
+                log::warn!("Failed to check the model files: {e}");
+                return false;
+            }
+        };
+        if modified <= state.0 {
+            return false;
+        }
+        // Don't retry broken files until they change again
+        state.0 = modified;
+        match self.reload() {
+            Ok(count) => {
+                log::info!("Model files changed, reloaded {count} models");
+                true
+            }
+            Err(e) => {
+                log::warn!("Failed to reload the models, keeping the old ones: {e}");
+                false
+            }
+        }
+    }
+
+    /// Run the predictions on the signature lines in `body` and return them as JSON
+    pub fn predict(&self, body: &str) -> Result<String, NrpsError> {
+        let mut domains: Vec<ADomain> = Vec::new();
+        for line in body.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            domains.push(parse_domain(line.to_string())?);
+        }
+
+        let predictor = self.predictor.read().unwrap();
+        predictor.predict(&mut domains)?;
+
+        format_results(predictor.config(), &domains)
+    }
+
+    /// Serve requests on `address` until the process is stopped
+    pub fn serve(&self, address: &str) -> Result<(), NrpsError> {
+        let server = Server::http(address).map_err(|e| NrpsError::ServerError(e.to_string()))?;
+
+        loop {
+            let request = match self.watch_interval {
+                Some(interval) => {
+                    let request = server.recv_timeout(interval)?;
+                    self.reload_if_changed();
+                    match request {
+                        Some(request) => request,
+                        None => continue,
+                    }
+                }
+                None => server.recv()?,
+            };
+            self.handle(request);
+        }
+    }
+
+    fn handle(&self, mut request: Request) {
+        let (status, body) = match (request.method(), request.url()) {
+            (Method::Post, "/predict") => match read_body(request.as_reader(), MAX_BODY_SIZE) {
+                Ok(input) => self.respond(&input),
+                Err(response) => response,
+            },
+            (Method::Get, "/health") => (
+                200,
+                serde_json::json!({"status": "ok", "models": self.model_count()}).to_string(),
+            ),
+            (Method::Post, "/reload") => match self.reload() {
+                Ok(count) => (
+                    200,
+                    serde_json::json!({"status": "ok", "models": count}).to_string(),
+                ),
+                Err(e) => (500, error_json(&e.to_string())),
+            },
+            (_, "/predict") | (_, "/health") | (_, "/reload") => {
+                (405, error_json("Method not allowed"))
+            }
+            _ => (404, error_json("Not found")),
+        };
+
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(json_header());
+        if let Err(e) = request.respond(response) {
+            log::warn!("Failed to send response: {e}");
+        }
+    }
+
+    fn respond(&self, input: &str) -> (u16, String) {
+        match self.predict(input) {
+            Ok(json) => (200, json),
+            Err(e) => (400, error_json(&e.to_string())),
+        }
+    }
+}
+
+/// The config with JSON output, and the custom categories and model set of the current model dir
+fn server_config(config: &Config) -> Result<Config, NrpsError> {
+    let mut config = config.clone();
+    config.output_format = OutputFormat::Json;
+    config.discover_custom_categories()?;
+    config.model_set = ModelSetInfo::from_config(&config).ok();
+    Ok(config)
+}
+
+/// Read a request body of at most `limit` bytes, or the status and error to respond with
+fn read_body<R: Read>(reader: R, limit: u64) -> Result<String, (u16, String)> {
+    let mut input = String::new();
+    reader
+        .take(limit + 1)
+        .read_to_string(&mut input)
+        .map_err(|e| (400, error_json(&e.to_string())))?;
+    if input.len() as u64 > limit {
+        return Err((
+            413,
+            error_json(&format!("Request body exceeds {limit} bytes")),
+        ));
+    }
+    Ok(input)
+}
+
+fn error_json(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn json_header() -> Header {
+    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::testing::svmlight_model;
+
+    fn server(model_dir: &Path) -> PredictionServer {
+        let mut config = Config::new();
+        config.set_model_dir(model_dir.to_path_buf());
+        config.skip_stachelhaus = true;
+        PredictionServer::new(&config).unwrap()
+    }
+
+    #[test]
+    fn test_predict() {
+        let tmp = tempfile::tempdir().unwrap();
+        let server = server(tmp.path());
+        assert_eq!(server.model_count(), 0);
+
+        let got: serde_json::Value = serde_json::from_str(
+            &server
+                .predict("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA_A1\n\n")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(got[0]["name"], "bpsA_A1");
+
+        assert_eq!(server.respond("too short").0, 400);
+    }
+
+    #[test]
+    fn test_reload() {
+        let tmp = tempfile::tempdir().unwrap();
+        let category_dir = tmp.path().join("NRPS2_SINGLE_CLUSTER");
+        std::fs::create_dir_all(&category_dir).unwrap();
+
+        let server = server(tmp.path());
+        assert!(!server.reload_if_changed());
+        let server = server.watch(Duration::ZERO);
+        assert!(!server.reload_if_changed());
+
+        std::fs::write(category_dir.join("[leu].mdl"), svmlight_model(102)).unwrap();
+        assert_eq!(server.reload().unwrap(), 1);
+        assert_eq!(server.model_count(), 1);
+
+        // Broken files keep the old models
+        std::fs::write(category_dir.join("[val].mdl"), "not a model").unwrap();
+        assert!(server.reload().is_err());
+        assert_eq!(server.model_count(), 1);
+    }
+
+    #[test]
+    fn test_read_body() {
+        assert_eq!(read_body("abc".as_bytes(), 3).unwrap(), "abc");
+        let (status, _) = read_body("abcd".as_bytes(), 3).unwrap_err();
+        assert_eq!(status, 413);
+        let (status, _) = read_body([0xff].as_slice(), 3).unwrap_err();
+        assert_eq!(status, 400);
+    }
+}
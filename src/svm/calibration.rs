@@ -0,0 +1,139 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Platt scaling of SVM decision values to probabilities.
+//!
+//! Calibration files are tab-separated with the columns category, model name, A and B,
+//! e.g. `SingleV2\tleu\t-1.7\t0.2`. Categories can also be given by their model dir name. Lines starting with `#` are comments.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::compression;
+use crate::errors::NrpsError;
+use crate::predictors::predictions::PredictionCategory;
+use crate::svm::models::SVMlightModel;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PlattScaling {
+    pub a: f64,
+    pub b: f64,
+}
+
+impl PlattScaling {
+    /// Probability of a positive prediction, `1 / (1 + exp(A * score + B))`
+    pub fn probability(&self, score: f64) -> f64 {
+        1.0 / (1.0 + (self.a * score + self.b).exp())
+    }
+}
+
+/// Platt-scaling parameters by category and model name
+#[derive(Debug, Clone, Default)]
+pub struct Calibration {
+    params: HashMap<(PredictionCategory, String), PlattScaling>,
+}
+
+impl Calibration {
+    pub fn from_file(path: &Path) -> Result<Self, NrpsError> {
+        let handle = compression::open(path).map_err(|source| NrpsError::FileError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Calibration::parse(handle).map_err(|err| match err {
+            NrpsError::CalibrationError(msg) => {
+                NrpsError::CalibrationError(format!("{}: {msg}", path.display()))
+            }
+            err => err,
+        })
+    }
+
+    pub fn parse<R>(handle: R) -> Result<Self, NrpsError>
+    where
+        R: Read,
+    {
+        let mut params = HashMap::new();
+        let reader = BufReader::new(handle);
+        for (i, line_res) in reader.lines().enumerate() {
+            let line = line_res?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            if parts.len() != 4 {
+                return Err(NrpsError::CalibrationError(format!(
+                    "line {}: expected 4 columns, got {}",
+                    i + 1,
+                    parts.len()
+                )));
+            }
+            let category: PredictionCategory = parts[0]
+                .parse()
+                .map_err(|err| NrpsError::CalibrationError(format!("line {}: {err}", i + 1)))?;
+            let (Ok(a), Ok(b)) = (parts[2].parse(), parts[3].parse()) else {
+                return Err(NrpsError::CalibrationError(format!(
+                    "line {}: invalid parameters in `{line}`",
+                    i + 1
+                )));
+            };
+            params.insert((category, parts[1].to_string()), PlattScaling { a, b });
+        }
+        Ok(Calibration { params })
+    }
+
+    pub fn get(&self, category: PredictionCategory, name: &str) -> Option<PlattScaling> {
+        self.params.get(&(category, name.to_string())).copied()
+    }
+
+    /// Attach the parameters to `models`, models without an entry stay uncalibrated
+    pub fn apply(&self, models: &mut [SVMlightModel]) {
+        for model in models.iter_mut() {
+            model.calibration = self.get(model.category.clone(), &model.name);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn test_probability() {
+        let platt = PlattScaling { a: -2.0, b: 0.0 };
+        assert_approx_eq!(platt.probability(0.0), 0.5);
+        assert!(platt.probability(1.0) > 0.85);
+        assert!(platt.probability(-1.0) < 0.15);
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = "# category\tmodel\tA\tB\n\
+                    NRPS2_SINGLE_CLUSTER\tleu\t-1.5\t0.25\n\
+                    \n\
+                    LargeClusterV2\thydrophobic-aliphatic\t-2\t0\n";
+        let calibration = Calibration::parse(text.as_bytes()).unwrap();
+        assert_eq!(calibration.len(), 2);
+        assert_eq!(
+            calibration.get(PredictionCategory::SingleV2, "leu"),
+            Some(PlattScaling { a: -1.5, b: 0.25 })
+        );
+        assert_eq!(calibration.get(PredictionCategory::SingleV3, "leu"), None);
+
+        assert!(Calibration::parse("NRPS2_SINGLE_CLUSTER\tleu\t-1.5\n".as_bytes()).is_err());
+        assert!(Calibration::parse("Unknown\tleu\t-1.5\t0\n".as_bytes()).is_err());
+        assert!(Calibration::parse("NRPS2_SINGLE_CLUSTER\tleu\tA\t0\n".as_bytes()).is_err());
+    }
+}
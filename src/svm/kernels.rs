@@ -2,11 +2,30 @@
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 use std::fmt::Debug;
 
-use crate::errors::NrpsError;
-use crate::svm::vectors::{FeatureVector, SupportVector, Vector};
+use crate::svm::simd;
+use crate::svm::vectors::Value;
 
-pub trait Kernel {
-    fn compute(&self, vec1: &SupportVector, vec2: &FeatureVector) -> Result<f64, NrpsError>;
+/// Kernel function on a support vector and a feature vector, callers check the lengths match
+pub trait Kernel: Send + Sync {
+    fn compute(&self, vec1: &[f64], vec2: &[f64]) -> f64;
+
+    /// `compute` on `f32` vectors, the default widens them to `f64` first
+    fn compute_f32(&self, vec1: &[f32], vec2: &[f32]) -> f64 {
+        let widen = |vec: &[f32]| vec.iter().map(|v| f64::from(*v)).collect::<Vec<f64>>();
+        self.compute(&widen(vec1), &widen(vec2))
+    }
+}
+
+/// `compute` on vectors in the support vector storage type, see `vectors::Value`
+#[cfg(not(feature = "f32-vectors"))]
+pub fn compute_values(kernel: &dyn Kernel, vec1: &[Value], vec2: &[Value]) -> f64 {
+    kernel.compute(vec1, vec2)
+}
+
+/// `compute` on vectors in the support vector storage type, see `vectors::Value`
+#[cfg(feature = "f32-vectors")]
+pub fn compute_values(kernel: &dyn Kernel, vec1: &[Value], vec2: &[Value]) -> f64 {
+    kernel.compute_f32(vec1, vec2)
 }
 
 impl Debug for dyn Kernel {
@@ -19,8 +38,12 @@ impl Debug for dyn Kernel {
 pub struct LinearKernel {}
 
 impl Kernel for LinearKernel {
-    fn compute(&self, vec1: &SupportVector, vec2: &FeatureVector) -> Result<f64, NrpsError> {
-        vec1.similarity(vec2)
+    fn compute(&self, vec1: &[f64], vec2: &[f64]) -> f64 {
+        simd::dot(vec1, vec2)
+    }
+
+    fn compute_f32(&self, vec1: &[f32], vec2: &[f32]) -> f64 {
+        simd::dot_f32(vec1, vec2)
     }
 }
 
@@ -36,7 +59,120 @@ impl RBFKernel {
 }
 
 impl Kernel for RBFKernel {
-    fn compute(&self, vec1: &SupportVector, vec2: &FeatureVector) -> Result<f64, NrpsError> {
-        Ok((-self.gamma * vec1.square_dist(vec2)?).exp())
+    fn compute(&self, vec1: &[f64], vec2: &[f64]) -> f64 {
+        (-self.gamma * simd::square_dist(vec1, vec2)).exp()
+    }
+
+    fn compute_f32(&self, vec1: &[f32], vec2: &[f32]) -> f64 {
+        (-self.gamma * simd::square_dist_f32(vec1, vec2)).exp()
+    }
+}
+
+#[derive(Debug)]
+pub struct PolynomialKernel {
+    degree: i32,
+    coef_lin: f64,
+    coef_const: f64,
+}
+
+impl PolynomialKernel {
+    pub fn new(degree: i32, coef_lin: f64, coef_const: f64) -> Self {
+        PolynomialKernel {
+            degree,
+            coef_lin,
+            coef_const,
+        }
+    }
+}
+
+impl Kernel for PolynomialKernel {
+    fn compute(&self, vec1: &[f64], vec2: &[f64]) -> f64 {
+        (self.coef_lin * simd::dot(vec1, vec2) + self.coef_const).powi(self.degree)
+    }
+
+    fn compute_f32(&self, vec1: &[f32], vec2: &[f32]) -> f64 {
+        (self.coef_lin * simd::dot_f32(vec1, vec2) + self.coef_const).powi(self.degree)
+    }
+}
+
+#[derive(Debug)]
+pub struct SigmoidKernel {
+    coef_lin: f64,
+    coef_const: f64,
+}
+
+impl SigmoidKernel {
+    pub fn new(coef_lin: f64, coef_const: f64) -> Self {
+        SigmoidKernel {
+            coef_lin,
+            coef_const,
+        }
+    }
+}
+
+impl Kernel for SigmoidKernel {
+    fn compute(&self, vec1: &[f64], vec2: &[f64]) -> f64 {
+        (self.coef_lin * simd::dot(vec1, vec2) + self.coef_const).tanh()
+    }
+
+    fn compute_f32(&self, vec1: &[f32], vec2: &[f32]) -> f64 {
+        (self.coef_lin * simd::dot_f32(vec1, vec2) + self.coef_const).tanh()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    fn vectors() -> ([f64; 3], [f64; 3]) {
+        ([1.0, 0.0, 1.0], [1.0, 2.0, 3.0])
+    }
+
+    #[test]
+    fn test_linear() {
+        let (svec, fvec) = vectors();
+        assert_approx_eq!(LinearKernel {}.compute(&svec, &fvec), 4.0);
+    }
+
+    #[test]
+    fn test_rbf() {
+        let (svec, fvec) = vectors();
+        let kernel = RBFKernel::new(0.5);
+        assert_approx_eq!(kernel.compute(&svec, &fvec), (-4.0_f64).exp());
+    }
+
+    #[test]
+    fn test_polynomial() {
+        let (svec, fvec) = vectors();
+        let kernel = PolynomialKernel::new(2, 0.5, 1.0);
+        assert_approx_eq!(kernel.compute(&svec, &fvec), 9.0);
+    }
+
+    #[test]
+    fn test_sigmoid() {
+        let (svec, fvec) = vectors();
+        let kernel = SigmoidKernel::new(0.25, -1.0);
+        assert_approx_eq!(kernel.compute(&svec, &fvec), 0.0);
+    }
+
+    #[test]
+    fn test_compute_f32() {
+        let (svec, fvec) = vectors();
+        let (svec32, fvec32) = (svec.map(|v| v as f32), fvec.map(|v| v as f32));
+        let kernels: [Box<dyn Kernel>; 4] = [
+            Box::new(LinearKernel {}),
+            Box::new(RBFKernel::new(0.5)),
+            Box::new(PolynomialKernel::new(2, 0.5, 1.0)),
+            Box::new(SigmoidKernel::new(0.25, -1.0)),
+        ];
+        for kernel in kernels.iter() {
+            assert_approx_eq!(
+                kernel.compute_f32(&svec32, &fvec32),
+                kernel.compute(&svec, &fvec),
+                1e-6
+            );
+        }
     }
 }
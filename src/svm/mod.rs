@@ -1,6 +1,9 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
+pub mod calibration;
 pub mod kernels;
 pub mod models;
+pub mod simd;
+pub mod train;
 pub mod vectors;
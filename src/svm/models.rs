@@ -1,49 +1,163 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
-use std::io::{self, BufRead, BufReader, Lines, Read};
+use std::io::{BufRead, BufReader, Lines, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
 
-use crate::encodings::{encode, FeatureEncoding};
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::compression;
+use crate::encodings::{
+    encode, encode_with_options, is_legacy_layout, EncodingOptions, FeatureEncoding, AA34_LENGTH,
+};
 use crate::errors::NrpsError;
-use crate::predictors::predictions::PredictionCategory;
-use crate::svm::kernels::{Kernel, LinearKernel, RBFKernel};
-use crate::svm::vectors::{FeatureVector, SupportVector};
+use crate::predictors::predictions::{ModelId, PredictionCategory};
+use crate::svm::calibration::PlattScaling;
+use crate::svm::kernels::{
+    compute_values, Kernel, LinearKernel, PolynomialKernel, RBFKernel, SigmoidKernel,
+};
+use crate::svm::vectors::{
+    to_f64, to_storage, FeatureVector, SupportVector, SupportVectors, Vector,
+};
 
-#[derive(Debug)]
+/// Comments of the header lines after the highest feature index, in SVMlight order
+const HEADER_FIELDS: [&str; 3] = [
+    "number of training documents",
+    "number of support vectors",
+    "threshold b",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
 pub enum KernelType {
     Linear,
     Polynomial,
+    #[cfg_attr(feature = "cli", value(name = "rbf"))]
     RBF,
     Sigmoid,
-    Custom,
+}
+
+/// Kernel parameters as stored in the SVMlight model header
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KernelParameters {
+    /// -d, degree of the polynomial kernel
+    pub degree: i32,
+    /// -g, gamma of the RBF kernel
+    pub gamma: f64,
+    /// -s, linear coefficient of the polynomial and sigmoid kernels
+    pub coef_lin: f64,
+    /// -r, constant coefficient of the polynomial and sigmoid kernels
+    pub coef_const: f64,
+}
+
+impl Default for KernelParameters {
+    fn default() -> Self {
+        KernelParameters {
+            degree: 3,
+            gamma: 1.0,
+            coef_lin: 1.0,
+            coef_const: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionContribution {
+    /// Signature position, starting at 0
+    pub position: usize,
+    pub residue: char,
+    pub contribution: f64,
+}
+
+/// The share `yalpha × K(sv, x)` of one support vector in a decision value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SupportVectorContribution {
+    /// Position of the support vector in the model file, starting at 0
+    pub index: usize,
+    pub yalpha: f64,
+    pub kernel_value: f64,
+    pub contribution: f64,
+}
+
+/// A decision value broken down into its support vector contributions and the bias
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    /// Strongest absolute contribution first
+    pub contributions: Vec<SupportVectorContribution>,
+    pub bias: f64,
+    /// Sum of the contributions minus the bias, the same as `predict_seq()`
+    pub score: f64,
 }
 
 #[derive(Debug)]
 pub struct SVMlightModel {
     pub name: String,
     pub category: PredictionCategory,
-    pub vectors: Vec<SupportVector>,
+    pub vectors: SupportVectors,
     pub bias: f64,
     pub encoding: FeatureEncoding,
     pub kernel_type: KernelType,
+    pub params: KernelParameters,
     pub kernel: Box<dyn Kernel>,
+    /// Platt-scaling parameters to turn scores into probabilities
+    pub calibration: Option<PlattScaling>,
+    /// Weight vector of linear models, computed on first use
+    weights: OnceLock<Vec<f64>>,
+}
+
+impl KernelType {
+    /// Kernel type id used in SVMlight model files
+    pub fn svmlight_id(&self) -> usize {
+        match self {
+            KernelType::Linear => 0,
+            KernelType::Polynomial => 1,
+            KernelType::RBF => 2,
+            KernelType::Sigmoid => 3,
+        }
+    }
+
+    /// Find a built-in kernel type by name, e.g. `rbf`, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            (KernelType::Linear, "linear"),
+            (KernelType::Polynomial, "polynomial"),
+            (KernelType::RBF, "rbf"),
+            (KernelType::Sigmoid, "sigmoid"),
+        ]
+        .into_iter()
+        .find(|(_, known)| known.eq_ignore_ascii_case(name))
+        .map(|(kernel, _)| kernel)
+    }
+}
+
+pub fn new_kernel(kernel_type: KernelType, params: &KernelParameters) -> Box<dyn Kernel> {
+    match kernel_type {
+        KernelType::Linear => Box::new(LinearKernel {}),
+        KernelType::Polynomial => Box::new(PolynomialKernel::new(
+            params.degree,
+            params.coef_lin,
+            params.coef_const,
+        )),
+        KernelType::RBF => Box::new(RBFKernel::new(params.gamma)),
+        KernelType::Sigmoid => Box::new(SigmoidKernel::new(params.coef_lin, params.coef_const)),
+    }
 }
 
 impl SVMlightModel {
     pub fn new(
         name: String,
         category: PredictionCategory,
-        vectors: Vec<SupportVector>,
+        vectors: SupportVectors,
         bias: f64,
         encoding: FeatureEncoding,
         kernel_type: KernelType,
-        gamma: f64,
+        params: KernelParameters,
     ) -> Self {
-        let kernel: Box<dyn Kernel> = match kernel_type {
-            KernelType::Linear => Box::new(LinearKernel {}),
-            KernelType::RBF => Box::new(RBFKernel::new(gamma)),
-            _ => unimplemented!(),
-        };
+        let kernel = new_kernel(kernel_type, &params);
         SVMlightModel {
             name,
             category,
@@ -51,15 +165,60 @@ impl SVMlightModel {
             bias,
             encoding,
             kernel_type,
+            params,
             kernel,
+            calibration: None,
+            weights: OnceLock::new(),
         }
     }
 
     pub fn predict(&self, vec: &FeatureVector) -> Result<f64, NrpsError> {
-        let res: Result<f64, NrpsError> = self.vectors.iter().try_fold(0.0, |sum, svec| {
-            Ok(sum + svec.yalpha * self.kernel.compute(svec, vec)?)
-        });
-        Ok(res? - self.bias)
+        if !self.vectors.is_empty() && self.vectors.dimensions() != vec.dim() {
+            return Err(NrpsError::DimensionMismatch {
+                first: self.vectors.dimensions(),
+                second: vec.dim(),
+            });
+        }
+        let features = to_storage(vec.values());
+        let sum: f64 = self
+            .vectors
+            .iter()
+            .map(|(svec, yalpha)| yalpha * compute_values(self.kernel.as_ref(), svec, &features))
+            .sum();
+        Ok(sum - self.bias)
+    }
+
+    /// Predict `sequence`, reporting the contribution of each support vector
+    pub fn predict_explain(&self, sequence: &str) -> Result<Explanation, NrpsError> {
+        let fvec = FeatureVector::new(self.encode(sequence));
+        if !self.vectors.is_empty() && self.vectors.dimensions() != fvec.dim() {
+            return Err(NrpsError::DimensionMismatch {
+                first: self.vectors.dimensions(),
+                second: fvec.dim(),
+            });
+        }
+        let features = to_storage(fvec.values());
+        let mut contributions: Vec<SupportVectorContribution> = self
+            .vectors
+            .iter()
+            .enumerate()
+            .map(|(index, (svec, yalpha))| {
+                let kernel_value = compute_values(self.kernel.as_ref(), svec, &features);
+                SupportVectorContribution {
+                    index,
+                    yalpha,
+                    kernel_value,
+                    contribution: yalpha * kernel_value,
+                }
+            })
+            .collect();
+        let score = contributions.iter().map(|c| c.contribution).sum::<f64>() - self.bias;
+        contributions.sort_by(|a, b| b.contribution.abs().total_cmp(&a.contribution.abs()));
+        Ok(Explanation {
+            contributions,
+            bias: self.bias,
+            score,
+        })
     }
 
     pub fn encode(&self, sequence: &str) -> Vec<f64> {
@@ -71,6 +230,152 @@ impl SVMlightModel {
         self.predict(&fvec)
     }
 
+    /// Collapse the support vectors into a single weight vector, linear kernels only
+    ///
+    /// The weights are only computed once, changing the support vectors afterwards doesn't
+    /// update them.
+    pub fn weights(&self) -> Result<&[f64], NrpsError> {
+        if !matches!(self.kernel_type, KernelType::Linear) {
+            return Err(NrpsError::UnsupportedKernel(format!(
+                "{:?}",
+                self.kernel_type
+            )));
+        }
+        let weights = self.weights.get_or_init(|| {
+            let mut weights = vec![0.0; self.vectors.dimensions()];
+            for (svec, yalpha) in self.vectors.iter() {
+                for (w, v) in weights.iter_mut().zip(to_f64(svec).iter()) {
+                    *w += yalpha * v;
+                }
+            }
+            weights
+        });
+        Ok(weights)
+    }
+
+    /// Identifier of the model, from `name` and `category`
+    pub fn id(&self) -> ModelId {
+        ModelId::new(self.category.clone(), &self.name)
+    }
+
+    /// Length of the vectors the encoding of the model produces for an aa34 signature
+    ///
+    /// Custom encodings are only trusted to produce the features they declare after encoding.
+    pub fn expected_dimensions(&self) -> usize {
+        match self.encoding {
+            FeatureEncoding::Custom(_)
+                if self.encoding.dimensions() == self.vectors.dimensions() =>
+            {
+                encode(&"A".repeat(AA34_LENGTH), &self.encoding, &self.category).len()
+            }
+            encoding => encoding.dimensions(),
+        }
+    }
+
+    /// Models not matching their encoding would only fail when predicting, for every domain
+    ///
+    /// Errors name the model file `path`, or the model for models not read from disk.
+    fn check_dimensions(&self, path: Option<&Path>) -> Result<(), NrpsError> {
+        let expected = self.expected_dimensions();
+        let found = self.vectors.dimensions();
+        if expected == found {
+            return Ok(());
+        }
+        let encoding = self.encoding.name().to_string();
+        Err(match path {
+            Some(path) => NrpsError::ModelDimensionError {
+                path: path.to_path_buf(),
+                encoding,
+                expected,
+                found,
+            },
+            None => NrpsError::ModelEncodingError {
+                name: self.name.clone(),
+                encoding,
+                expected,
+                found,
+            },
+        })
+    }
+
+    /// Per-feature contributions (weight × feature) to the decision value, excluding the bias
+    pub fn feature_contributions(&self, sequence: &str) -> Result<Vec<f64>, NrpsError> {
+        self.feature_contributions_with_options(sequence, &EncodingOptions::default())
+    }
+
+    /// Like `feature_contributions`, with gaps and ambiguous residues encoded as set in `options`
+    pub fn feature_contributions_with_options(
+        &self,
+        sequence: &str,
+        options: &EncodingOptions,
+    ) -> Result<Vec<f64>, NrpsError> {
+        let weights = self.weights()?;
+        let features = encode_with_options(sequence, &self.encoding, &self.category, options)?;
+        if weights.len() != features.len() {
+            return Err(NrpsError::DimensionMismatch {
+                first: weights.len(),
+                second: features.len(),
+            });
+        }
+        Ok(weights
+            .iter()
+            .zip(features.iter())
+            .map(|(w, f)| w * f)
+            .collect())
+    }
+
+    /// Feature contributions summed up per signature position, strongest absolute contribution first
+    pub fn position_contributions(
+        &self,
+        sequence: &str,
+    ) -> Result<Vec<PositionContribution>, NrpsError> {
+        self.position_contributions_with_options(sequence, &EncodingOptions::default())
+    }
+
+    /// Like `position_contributions`, with gaps and ambiguous residues encoded as set in `options`
+    pub fn position_contributions_with_options(
+        &self,
+        sequence: &str,
+        options: &EncodingOptions,
+    ) -> Result<Vec<PositionContribution>, NrpsError> {
+        let contributions = self.feature_contributions_with_options(sequence, options)?;
+        let residues: Vec<char> = sequence.chars().collect();
+        if residues.is_empty() {
+            return Ok(Vec::new());
+        }
+        let legacy = is_legacy_layout(&self.encoding, &self.category);
+
+        let mut positions: Vec<PositionContribution> = residues
+            .iter()
+            .enumerate()
+            .map(|(position, residue)| PositionContribution {
+                position,
+                residue: *residue,
+                contribution: 0.0,
+            })
+            .collect();
+
+        // Each residue needs the same number of features to be attributed to its position
+        if contributions.is_empty() || contributions.len() % residues.len() != 0 {
+            return Err(NrpsError::DimensionMismatch {
+                first: contributions.len(),
+                second: residues.len(),
+            });
+        }
+        let width = contributions.len() / residues.len();
+        for (i, value) in contributions.iter().enumerate() {
+            let position = if legacy {
+                i % residues.len()
+            } else {
+                i / width
+            };
+            positions[position].contribution += value;
+        }
+
+        positions.sort_by(|a, b| b.contribution.abs().total_cmp(&a.contribution.abs()));
+        Ok(positions)
+    }
+
     pub fn from_handle<R>(
         handle: R,
         name: String,
@@ -79,51 +384,163 @@ impl SVMlightModel {
     where
         R: Read,
     {
-        let mut line_iter = io::BufReader::new(handle).lines();
-        line_iter.next(); // skip
+        SVMlightModel::from_handle_with_encoding(handle, name, category, None)
+    }
+
+    /// Parse a model, with `encoding` instead of guessing the encoding from the feature count
+    pub fn from_handle_with_encoding<R>(
+        handle: R,
+        name: String,
+        category: PredictionCategory,
+        encoding: Option<FeatureEncoding>,
+    ) -> Result<Self, NrpsError>
+    where
+        R: Read,
+    {
+        let model = SVMlightModel::parse(&mut ModelLines::new(handle), name, category, encoding)?;
+        model.check_dimensions(None)?;
+        Ok(model)
+    }
+
+    /// Like `from_handle_with_encoding`, errors are reported with `path` and the line number
+    pub fn from_handle_with_path<R>(
+        handle: R,
+        path: &Path,
+        name: String,
+        category: PredictionCategory,
+        encoding: Option<FeatureEncoding>,
+    ) -> Result<Self, NrpsError>
+    where
+        R: Read,
+    {
+        let mut lines = ModelLines::new(handle);
+        let model = SVMlightModel::parse(&mut lines, name, category, encoding).map_err(|err| {
+            NrpsError::ModelParseError {
+                path: path.to_path_buf(),
+                line: lines.number,
+                source: Box::new(err),
+            }
+        })?;
+        model.check_dimensions(Some(path))?;
+        Ok(model)
+    }
+
+    /// Read a possibly compressed model file
+    pub fn from_file(
+        path: &Path,
+        name: String,
+        category: PredictionCategory,
+        encoding: Option<FeatureEncoding>,
+    ) -> Result<Self, NrpsError> {
+        let handle = compression::open(path).map_err(|source| NrpsError::FileError {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        SVMlightModel::from_handle_with_path(handle, path, name, category, encoding)
+    }
+
+    fn parse<R>(
+        line_iter: &mut ModelLines<R>,
+        name: String,
+        category: PredictionCategory,
+        encoding: Option<FeatureEncoding>,
+    ) -> Result<Self, NrpsError>
+    where
+        R: Read,
+    {
+        skip_line(line_iter, "version")?;
 
-        let kernel_type = match parse_int(&mut line_iter)? {
+        let kernel_type = match parse_int(line_iter, "kernel type")? {
             0 => KernelType::Linear,
+            1 => KernelType::Polynomial,
             2 => KernelType::RBF,
-            _ => {
-                return Err(NrpsError::InvalidFeatureLine(
-                    "Failed to match kernel type".to_string(),
-                ))
+            3 => KernelType::Sigmoid,
+            other => {
+                return Err(NrpsError::InvalidFeatureLine(format!(
+                    "Failed to match kernel type {other}"
+                )))
             }
         };
 
-        line_iter.next(); // skip
-
-        let gamma: f64 = parse_float(&mut line_iter)?;
+        let params = KernelParameters {
+            degree: parse_value::<i32>(line_iter, "kernel parameter -d")?,
+            gamma: parse_float(line_iter, "kernel parameter -g")?,
+            coef_lin: parse_float(line_iter, "kernel parameter -s")?,
+            coef_const: parse_float(line_iter, "kernel parameter -r")?,
+        };
 
-        line_iter.next(); // skip
-        line_iter.next(); // skip
-        line_iter.next(); // skip
+        skip_line(line_iter, "kernel parameter -u")?;
 
-        let dimensions = parse_int(&mut line_iter)?;
+        let dimensions = parse_int(line_iter, "highest feature index")?;
 
-        let encoding = match dimensions {
-            102 => FeatureEncoding::Wold,
-            408 => FeatureEncoding::Rausch,
-            510 => FeatureEncoding::Blin,
-            _ => {
-                return Err(NrpsError::InvalidFeatureLine(format!(
+        // A declared encoding is checked against the model once it's parsed
+        let encoding = match encoding {
+            Some(encoding) => encoding,
+            None => FeatureEncoding::from_dimensions(dimensions).ok_or_else(|| {
+                NrpsError::InvalidFeatureLine(format!(
                     "Can't determine encoding type from {} features",
                     dimensions
-                )));
-            }
+                ))
+            })?,
         };
 
-        line_iter.next(); // skip
-        let num_vecs = parse_int(&mut line_iter)?;
-
-        let bias = parse_float(&mut line_iter)?;
-
-        let mut vectors = Vec::with_capacity(num_vecs);
-
-        for line_res in &mut line_iter {
-            let svec = SupportVector::from_line(line_res?, dimensions)?;
-            vectors.push(svec);
+        // Some exports omit the support vector count or move the remaining header lines, so
+        // they're identified by their comments. Lines without one follow the SVMlight order up
+        // to the threshold b, all lines after it are support vectors
+        let mut declared_vecs = None;
+        let mut bias = None;
+        let mut vectors = SupportVectors::new(dimensions);
+        let mut position = 0;
+        while let Some(line) = line_iter.next_line()? {
+            let label = line
+                .split_once('#')
+                .map(|(_, label)| label.trim().to_lowercase())
+                .unwrap_or_default();
+            let field = match HEADER_FIELDS
+                .iter()
+                .position(|field| label.starts_with(field))
+            {
+                Some(field) => Some(field),
+                None if bias.is_none() => Some(position),
+                None => None,
+            };
+            position += 1;
+            match field {
+                Some(0) => continue,
+                Some(1) => {
+                    let count = parse_line_value::<usize>(&line, "number of support vectors")?;
+                    // SVMlight itself writes the count plus 1
+                    let count = if label.is_empty() || label.contains("plus 1") {
+                        count.checked_sub(1).ok_or_else(|| {
+                            NrpsError::InvalidFeatureLine(format!(
+                                "Invalid number of support vectors in `{line}`"
+                            ))
+                        })?
+                    } else {
+                        count
+                    };
+                    declared_vecs = Some(count);
+                }
+                Some(_) => bias = Some(parse_line_float(&line, "threshold b")?),
+                None if line.trim().is_empty() => continue,
+                None => {
+                    let svec = SupportVector::from_line(line, dimensions)?;
+                    vectors.push(svec.values(), svec.yalpha)?;
+                }
+            }
+        }
+        let bias = bias.ok_or_else(|| {
+            NrpsError::InvalidFeatureLine(
+                "Unexpected end of file, expected threshold b".to_string(),
+            )
+        })?;
+        if let Some(declared) = declared_vecs {
+            if declared != vectors.len() {
+                return Err(NrpsError::SupportVectorCountMismatch {
+                    declared,
+                    found: vectors.len(),
+                });
+            }
         }
 
         Ok(SVMlightModel::new(
@@ -133,29 +550,596 @@ impl SVMlightModel {
             bias,
             encoding,
             kernel_type,
-            gamma,
+            params,
         ))
     }
 }
 
-fn parse_float(line_iter: &mut Lines<BufReader<impl Read>>) -> Result<f64, NrpsError> {
-    if let Some(line_result) = line_iter.next() {
-        if let Some(raw_value) = line_result?.trim_end().split('#').next() {
-            return Ok(raw_value.trim().parse::<f64>()?);
+impl SVMlightModel {
+    /// Write the model in SVMlight format, `training_docs` is only informational
+    pub fn write<W>(&self, mut writer: W, training_docs: usize) -> Result<(), NrpsError>
+    where
+        W: Write,
+    {
+        writeln!(writer, "SVM-light Version V6.02")?;
+        writeln!(writer, "{} # kernel type", self.kernel_type.svmlight_id())?;
+        writeln!(writer, "{} # kernel parameter -d", self.params.degree)?;
+        writeln!(writer, "{} # kernel parameter -g", self.params.gamma)?;
+        writeln!(writer, "{} # kernel parameter -s", self.params.coef_lin)?;
+        writeln!(writer, "{} # kernel parameter -r", self.params.coef_const)?;
+        writeln!(writer, "empty# kernel parameter -u")?;
+        writeln!(
+            writer,
+            "{} # highest feature index",
+            self.encoding.dimensions()
+        )?;
+        writeln!(writer, "{training_docs} # number of training documents")?;
+        writeln!(
+            writer,
+            "{} # number of support vectors plus 1",
+            self.vectors.len() + 1
+        )?;
+        writeln!(
+            writer,
+            "{} # threshold b, each following line is a SV (starting with alpha*y)",
+            self.bias
+        )?;
+
+        for (svec, yalpha) in self.vectors.iter() {
+            write!(writer, "{yalpha}")?;
+            for (i, value) in svec.iter().enumerate() {
+                if *value != 0.0 {
+                    write!(writer, " {}:{value}", i + 1)?;
+                }
+            }
+            writeln!(writer, " #")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lines of a model file, counting the lines read so far for error messages
+struct ModelLines<R> {
+    lines: Lines<BufReader<R>>,
+    number: usize,
+}
+
+impl<R: Read> ModelLines<R> {
+    fn new(handle: R) -> Self {
+        ModelLines {
+            lines: BufReader::new(handle).lines(),
+            number: 0,
+        }
+    }
+
+    fn next_line(&mut self) -> Result<Option<String>, NrpsError> {
+        match self.lines.next() {
+            Some(line_result) => {
+                self.number += 1;
+                Ok(Some(line_result?))
+            }
+            None => Ok(None),
         }
     }
-    Err(NrpsError::InvalidFeatureLine(
-        "Failed to read line".to_string(),
-    ))
 }
 
-fn parse_int(line_iter: &mut Lines<BufReader<impl Read>>) -> Result<usize, NrpsError> {
-    if let Some(line_result) = line_iter.next() {
-        if let Some(raw_value) = line_result?.trim_end().split('#').next() {
-            return Ok(raw_value.trim().parse::<usize>()?);
+fn next_line(line_iter: &mut ModelLines<impl Read>, what: &str) -> Result<String, NrpsError> {
+    match line_iter.next_line()? {
+        Some(line) => Ok(line),
+        None => Err(NrpsError::InvalidFeatureLine(format!(
+            "Unexpected end of file, expected {what}"
+        ))),
+    }
+}
+
+fn skip_line(line_iter: &mut ModelLines<impl Read>, what: &str) -> Result<(), NrpsError> {
+    next_line(line_iter, what)?;
+    Ok(())
+}
+
+fn parse_value<T: FromStr>(
+    line_iter: &mut ModelLines<impl Read>,
+    what: &str,
+) -> Result<T, NrpsError> {
+    let line = next_line(line_iter, what)?;
+    parse_line_value(&line, what)
+}
+
+/// The value of a header line, ignoring its comment
+fn parse_line_value<T: FromStr>(line: &str, what: &str) -> Result<T, NrpsError> {
+    let raw_value = line.split('#').next().unwrap_or_default().trim();
+    raw_value
+        .parse::<T>()
+        .map_err(|_| NrpsError::InvalidFeatureLine(format!("Invalid {what} in `{line}`")))
+}
+
+/// Like `parse_line_value`, rejecting infinite and NaN values
+fn parse_line_float(line: &str, what: &str) -> Result<f64, NrpsError> {
+    match parse_line_value::<f64>(line, what)? {
+        value if value.is_finite() => Ok(value),
+        _ => Err(NrpsError::InvalidFeatureLine(format!(
+            "Invalid {what} in `{line}`"
+        ))),
+    }
+}
+
+fn parse_float(line_iter: &mut ModelLines<impl Read>, what: &str) -> Result<f64, NrpsError> {
+    let line = next_line(line_iter, what)?;
+    parse_line_float(&line, what)
+}
+
+fn parse_int(line_iter: &mut ModelLines<impl Read>, what: &str) -> Result<usize, NrpsError> {
+    parse_value::<usize>(line_iter, what)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::encodings::wold;
+
+    fn linear_model() -> SVMlightModel {
+        let vectors = Vec::from([
+            SupportVector::new(Vec::from([1.0, 0.0, 0.0, 0.0, 0.0, 0.0]), 1.0),
+            SupportVector::new(Vec::from([0.0, 0.0, 0.0, 0.0, 0.0, 2.0]), -0.5),
+        ]);
+        SVMlightModel::new(
+            "test".to_string(),
+            PredictionCategory::SingleV3,
+            vectors.try_into().unwrap(),
+            0.0,
+            FeatureEncoding::Wold,
+            KernelType::Linear,
+            KernelParameters::default(),
+        )
+    }
+
+    #[test]
+    fn test_weights() {
+        let model = linear_model();
+        assert_eq!(model.weights().unwrap(), [1.0, 0.0, 0.0, 0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn test_weights_rbf() {
+        let model = SVMlightModel::new(
+            "test".to_string(),
+            PredictionCategory::SingleV3,
+            SupportVectors::default(),
+            0.0,
+            FeatureEncoding::Wold,
+            KernelType::RBF,
+            KernelParameters::default(),
+        );
+        assert!(model.weights().is_err());
+    }
+
+    #[test]
+    fn test_predict_within_tolerance() {
+        // Scores of the storage type, f32 with the f32-vectors feature, against plain f64 maths
+        let dimensions = 510;
+        let rows: Vec<(Vec<f64>, f64)> = (0..50)
+            .map(|i| {
+                let values = (0..dimensions)
+                    .map(|j| ((i * dimensions + j) as f64 * 0.013).sin() * 2.5)
+                    .collect();
+                (values, if i % 3 == 0 { -0.8 } else { 0.6 })
+            })
+            .collect();
+        let query: Vec<f64> = (0..dimensions).map(|j| (j as f64 * 0.07).cos()).collect();
+        let vectors = || {
+            let mut vectors = SupportVectors::new(dimensions);
+            for (values, yalpha) in rows.iter() {
+                vectors.push(values, *yalpha).unwrap();
+            }
+            vectors
+        };
+
+        let rbf = SVMlightModel::new(
+            "test".to_string(),
+            PredictionCategory::SingleV3,
+            vectors(),
+            0.25,
+            FeatureEncoding::Wold,
+            KernelType::RBF,
+            KernelParameters {
+                gamma: 0.001,
+                ..Default::default()
+            },
+        );
+        let expected: f64 = rows
+            .iter()
+            .map(|(values, yalpha)| {
+                let dist: f64 = values
+                    .iter()
+                    .zip(&query)
+                    .map(|(a, b)| (a - b).powi(2))
+                    .sum();
+                yalpha * (-0.001 * dist).exp()
+            })
+            .sum::<f64>()
+            - 0.25;
+        let got = rbf.predict(&FeatureVector::new(query.clone())).unwrap();
+        assert_approx_eq!(got, expected, 1e-4);
+
+        let linear = SVMlightModel::new(
+            "test".to_string(),
+            PredictionCategory::SingleV3,
+            vectors(),
+            0.25,
+            FeatureEncoding::Wold,
+            KernelType::Linear,
+            KernelParameters::default(),
+        );
+        let expected: f64 = rows
+            .iter()
+            .map(|(values, yalpha)| {
+                yalpha * values.iter().zip(&query).map(|(a, b)| a * b).sum::<f64>()
+            })
+            .sum::<f64>()
+            - 0.25;
+        let got = linear.predict(&FeatureVector::new(query)).unwrap();
+        assert_approx_eq!(got, expected, 1e-3);
+    }
+
+    #[test]
+    fn test_position_contributions() {
+        let model = linear_model();
+        let got = model.position_contributions("AC").unwrap();
+        let a = wold::encode_one('A')[0];
+        let c = -wold::encode_one('C')[2];
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].residue, 'C');
+        assert_eq!(got[0].position, 1);
+        assert_approx_eq!(got[0].contribution, c);
+        assert_eq!(got[1].residue, 'A');
+        assert_approx_eq!(got[1].contribution, a);
+
+        let total: f64 = got.iter().map(|p| p.contribution).sum();
+        assert_approx_eq!(total, model.predict_seq("AC").unwrap());
+    }
+
+    #[test]
+    fn test_position_contributions_mismatch() {
+        use std::sync::Arc;
+
+        use crate::encodings::custom::{register_encoding, CustomEncoding};
+
+        /// One feature for the whole signature
+        struct Length;
+
+        impl CustomEncoding for Length {
+            fn encode(&self, sequence: &str) -> Vec<f64> {
+                Vec::from([sequence.len() as f64])
+            }
+
+            fn dimensions(&self) -> usize {
+                1
+            }
+        }
+
+        let encoding = register_encoding("test-length", Arc::new(Length)).unwrap();
+        let vectors = Vec::from([SupportVector::new(Vec::from([1.0]), 1.0)]);
+        let model = SVMlightModel::new(
+            "test".to_string(),
+            PredictionCategory::SingleV3,
+            vectors.try_into().unwrap(),
+            0.0,
+            encoding,
+            KernelType::Linear,
+            KernelParameters::default(),
+        );
+        assert_eq!(model.feature_contributions("AC").unwrap(), [2.0]);
+        assert!(matches!(
+            model.position_contributions("AC"),
+            Err(NrpsError::DimensionMismatch {
+                first: 1,
+                second: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_predict_explain() {
+        let mut model = linear_model();
+        model.bias = 0.25;
+        let got = model.predict_explain("AC").unwrap();
+        assert_eq!(got.contributions.len(), 2);
+        assert_eq!(got.bias, 0.25);
+        assert_approx_eq!(got.score, model.predict_seq("AC").unwrap());
+
+        let total: f64 = got.contributions.iter().map(|c| c.contribution).sum();
+        assert_approx_eq!(total - got.bias, got.score);
+        // -0.5 × 2 × C's polarity feature outweighs A's hydrophobicity
+        assert_eq!(got.contributions[0].index, 1);
+        assert_approx_eq!(got.contributions[0].yalpha, -0.5);
+        assert_approx_eq!(got.contributions[1].kernel_value, wold::encode_one('A')[0]);
+    }
+
+    fn model_text() -> String {
+        let mut lines = Vec::from([
+            "SVM-light Version V6.01".to_string(),
+            "2 # kernel type".to_string(),
+            "3 # kernel parameter -d".to_string(),
+            "0.5 # kernel parameter -g".to_string(),
+            "1 # kernel parameter -s".to_string(),
+            "1 # kernel parameter -r".to_string(),
+            "empty# kernel parameter -u".to_string(),
+            "102 # highest feature index".to_string(),
+            "20 # number of training documents".to_string(),
+            "3 # number of support vectors plus 1".to_string(),
+            "0.25 # threshold b, each following line is a SV (starting with alpha*y)".to_string(),
+        ]);
+        lines.push("1 1:0.5 2:-0.25 102:1 #".to_string());
+        lines.push("-1 3:0.5 101:-0.25 #".to_string());
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_from_handle() {
+        let mut model = SVMlightModel::from_handle(
+            model_text().as_bytes(),
+            "test".to_string(),
+            PredictionCategory::SingleV3,
+        )
+        .unwrap();
+        assert_eq!(model.vectors.len(), 2);
+        assert_approx_eq!(model.bias, 0.25);
+        assert!(matches!(model.encoding, FeatureEncoding::Wold));
+        assert!(matches!(model.kernel_type, KernelType::RBF));
+        assert_eq!(model.params.degree, 3);
+        assert_approx_eq!(model.params.gamma, 0.5);
+
+        // The id follows a category set from a manifest
+        model.category = PredictionCategory::SingleV2;
+        assert_eq!(
+            model.id(),
+            ModelId::new(PredictionCategory::SingleV2, "test")
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let model = SVMlightModel::from_handle(
+            model_text().as_bytes(),
+            "test".to_string(),
+            PredictionCategory::SingleV3,
+        )
+        .unwrap();
+        let mut buffer = Vec::new();
+        model.write(&mut buffer, 20).unwrap();
+
+        let written = String::from_utf8(buffer).unwrap();
+        assert!(written.contains("1 1:0.5 2:-0.25 102:1 #\n"));
+        let reloaded = SVMlightModel::from_handle(
+            written.as_bytes(),
+            "test".to_string(),
+            PredictionCategory::SingleV3,
+        )
+        .unwrap();
+        assert_eq!(reloaded.kernel_type, model.kernel_type);
+        assert_eq!(reloaded.params, model.params);
+        assert_approx_eq!(reloaded.bias, model.bias);
+        let sequence = "A".repeat(34);
+        assert_approx_eq!(
+            reloaded.predict_seq(&sequence).unwrap(),
+            model.predict_seq(&sequence).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_from_handle_kernels() {
+        let text = model_text();
+        for (kernel_line, expected) in [("1", KernelType::Polynomial), ("3", KernelType::Sigmoid)] {
+            let mut lines: Vec<&str> = text.lines().collect();
+            lines[1] = kernel_line;
+            let model = SVMlightModel::from_handle(
+                lines.join("\n").as_bytes(),
+                "test".to_string(),
+                PredictionCategory::SingleV3,
+            )
+            .unwrap();
+            assert_eq!(model.kernel_type, expected);
+            assert!(model.predict_seq(&"A".repeat(34)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_from_handle_truncated() {
+        let text = model_text();
+        for end in 0..text.len() {
+            if !text.is_char_boundary(end) {
+                continue;
+            }
+            // Must never panic, errors are fine
+            let _ = SVMlightModel::from_handle(
+                &text.as_bytes()[..end],
+                "test".to_string(),
+                PredictionCategory::SingleV3,
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_handle_corrupted() {
+        let text = model_text();
+        let lines: Vec<&str> = text.lines().collect();
+        let garbage = ["", "#", "abc", ":", "1:", "1 0:1", "\u{fffd}"];
+        for i in 1..lines.len() {
+            for junk in garbage.iter() {
+                let mut corrupted = lines.clone();
+                corrupted[i] = junk;
+                let got = SVMlightModel::from_handle(
+                    corrupted.join("\n").as_bytes(),
+                    "test".to_string(),
+                    PredictionCategory::SingleV3,
+                );
+                // The skipped header lines don't matter, all parsed ones must fail cleanly
+                if [1, 2, 3, 4, 5, 7, 9, 10].contains(&i) {
+                    assert!(got.is_err(), "line {i} replaced by `{junk}`");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_handle_non_finite() {
+        let text = model_text();
+        let lines: Vec<&str> = text.lines().collect();
+        for (i, line) in [
+            (3, "nan # kernel parameter -g"),
+            (4, "inf # kernel parameter -s"),
+            (5, "-inf # kernel parameter -r"),
+            (10, "NaN # threshold b"),
+            (11, "inf 1:0.5"),
+            (12, "-1 3:nan"),
+        ] {
+            let mut corrupted = lines.clone();
+            corrupted[i] = line;
+            let got = SVMlightModel::from_handle(
+                corrupted.join("\n").as_bytes(),
+                "test".to_string(),
+                PredictionCategory::SingleV3,
+            );
+            assert!(
+                matches!(got, Err(NrpsError::InvalidFeatureLine(_))),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_handle_header_order() {
+        let text = model_text();
+        let lines: Vec<&str> = text.lines().collect();
+        let parse = |lines: Vec<&str>| {
+            SVMlightModel::from_handle(
+                lines.join("\n").as_bytes(),
+                "test".to_string(),
+                PredictionCategory::SingleV3,
+            )
+        };
+
+        // No support vector count
+        let mut missing = lines.clone();
+        missing.remove(9);
+        let model = parse(missing).unwrap();
+        assert_eq!(model.vectors.len(), 2);
+        assert_approx_eq!(model.bias, 0.25);
+
+        // Threshold b first, the count without the SVMlight offset after it
+        let mut reordered = lines.clone();
+        reordered[8] = lines[10];
+        reordered[9] = "2 # number of support vectors";
+        reordered[10] = lines[8];
+        let model = parse(reordered).unwrap();
+        assert_eq!(model.vectors.len(), 2);
+        assert_approx_eq!(model.bias, 0.25);
+
+        // Without comments, the SVMlight order applies
+        let mut unlabelled = lines.clone();
+        unlabelled[9] = "3";
+        unlabelled[10] = "0.25";
+        assert_approx_eq!(parse(unlabelled).unwrap().bias, 0.25);
+
+        let mut mismatch = lines.clone();
+        mismatch[9] = "5 # number of support vectors plus 1";
+        assert!(matches!(
+            parse(mismatch).unwrap_err(),
+            NrpsError::SupportVectorCountMismatch {
+                declared: 4,
+                found: 2
+            }
+        ));
+
+        let mut no_bias = lines.clone();
+        no_bias.remove(10);
+        no_bias.truncate(10);
+        assert!(parse(no_bias).is_err());
+    }
+
+    #[test]
+    fn test_from_handle_with_path() {
+        let text = model_text();
+        let mut lines: Vec<&str> = text.lines().collect();
+        lines[9] = "abc # threshold b";
+        let path = Path::new("NRPS3_SINGLE_CLUSTER/[leu].mdl");
+        let err = SVMlightModel::from_handle_with_path(
+            lines.join("\n").as_bytes(),
+            path,
+            "leu".to_string(),
+            PredictionCategory::SingleV3,
+            None,
+        )
+        .unwrap_err();
+        match &err {
+            NrpsError::ModelParseError {
+                path: got_path,
+                line,
+                source,
+            } => {
+                assert_eq!(got_path, path);
+                assert_eq!(*line, 10);
+                assert!(matches!(**source, NrpsError::InvalidFeatureLine(_)));
+            }
+            other => panic!("unexpected error {other:?}"),
         }
+        assert!(err
+            .to_string()
+            .starts_with("Invalid model file `NRPS3_SINGLE_CLUSTER/[leu].mdl`, line 10: "));
+
+        let err = SVMlightModel::from_file(
+            Path::new("/nonexistent/[leu].mdl"),
+            "leu".to_string(),
+            PredictionCategory::SingleV3,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(err, NrpsError::FileError { .. }));
+    }
+
+    #[test]
+    fn test_from_handle_with_path_dimension_mismatch() {
+        let path = Path::new("NRPS3_SINGLE_CLUSTER/[leu].mdl");
+        let err = SVMlightModel::from_handle_with_path(
+            model_text().as_bytes(),
+            path,
+            "leu".to_string(),
+            PredictionCategory::SingleV3,
+            Some(FeatureEncoding::Rausch),
+        )
+        .unwrap_err();
+        match err {
+            NrpsError::ModelDimensionError {
+                path: got_path,
+                expected,
+                found,
+                ..
+            } => {
+                assert_eq!(got_path, path);
+                assert_eq!(expected, FeatureEncoding::Rausch.dimensions());
+                assert_eq!(found, FeatureEncoding::Wold.dimensions());
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_from_handle_dimension_mismatch() {
+        let err = SVMlightModel::from_handle_with_encoding(
+            model_text().as_bytes(),
+            "leu".to_string(),
+            PredictionCategory::SingleV3,
+            Some(FeatureEncoding::Rausch),
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&err, NrpsError::ModelEncodingError { name, .. } if name == "leu"),
+            "{err:?}"
+        );
     }
-    Err(NrpsError::InvalidFeatureLine(
-        "Failed to read line".to_string(),
-    ))
 }
@@ -0,0 +1,429 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Dot product and squared distance, the inner loops of all kernels.
+//!
+//! On x86_64, AVX2/FMA versions are picked at runtime if the CPU supports them, aarch64 always
+//! has NEON. Everything else uses a scalar version with independent accumulators, which the
+//! compiler can vectorise for the baseline target. Callers have to check the lengths match.
+//! The `_f32` versions process twice as many values per instruction, for `f32` support vectors.
+
+/// Dot product of two slices of equal length
+pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+    debug_assert_eq!(a.len(), b.len());
+
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required CPU features were just checked
+        return unsafe { x86::dot(a, b) };
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    // Safety: NEON is part of the aarch64 baseline
+    return unsafe { neon::dot(a, b) };
+
+    #[allow(unreachable_code)]
+    dot_scalar(a, b)
+}
+
+/// Squared euclidean distance of two slices of equal length
+pub fn square_dist(a: &[f64], b: &[f64]) -> f64 {
+    debug_assert_eq!(a.len(), b.len());
+
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required CPU features were just checked
+        return unsafe { x86::square_dist(a, b) };
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    // Safety: NEON is part of the aarch64 baseline
+    return unsafe { neon::square_dist(a, b) };
+
+    #[allow(unreachable_code)]
+    square_dist_scalar(a, b)
+}
+
+/// Dot product of two `f32` slices of equal length
+pub fn dot_f32(a: &[f32], b: &[f32]) -> f64 {
+    debug_assert_eq!(a.len(), b.len());
+
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required CPU features were just checked
+        return unsafe { x86::dot_f32(a, b) };
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    // Safety: NEON is part of the aarch64 baseline
+    return unsafe { neon::dot_f32(a, b) };
+
+    #[allow(unreachable_code)]
+    dot_f32_scalar(a, b)
+}
+
+/// Squared euclidean distance of two `f32` slices of equal length
+pub fn square_dist_f32(a: &[f32], b: &[f32]) -> f64 {
+    debug_assert_eq!(a.len(), b.len());
+
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+        // Safety: the required CPU features were just checked
+        return unsafe { x86::square_dist_f32(a, b) };
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    // Safety: NEON is part of the aarch64 baseline
+    return unsafe { neon::square_dist_f32(a, b) };
+
+    #[allow(unreachable_code)]
+    square_dist_f32_scalar(a, b)
+}
+
+const LANES: usize = 4;
+const LANES_F32: usize = 8;
+
+pub fn dot_scalar(a: &[f64], b: &[f64]) -> f64 {
+    let mut sums = [0.0; LANES];
+    let (a_chunks, b_chunks) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+    let tail: f64 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for i in 0..LANES {
+            sums[i] += x[i] * y[i];
+        }
+    }
+    sums.iter().sum::<f64>() + tail
+}
+
+pub fn square_dist_scalar(a: &[f64], b: &[f64]) -> f64 {
+    let mut sums = [0.0; LANES];
+    let (a_chunks, b_chunks) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+    let tail: f64 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for i in 0..LANES {
+            let diff = x[i] - y[i];
+            sums[i] += diff * diff;
+        }
+    }
+    sums.iter().sum::<f64>() + tail
+}
+
+pub fn dot_f32_scalar(a: &[f32], b: &[f32]) -> f64 {
+    let mut sums = [0.0_f32; LANES_F32];
+    let (a_chunks, b_chunks) = (a.chunks_exact(LANES_F32), b.chunks_exact(LANES_F32));
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for i in 0..LANES_F32 {
+            sums[i] += x[i] * y[i];
+        }
+    }
+    f64::from(sums.iter().sum::<f32>() + tail)
+}
+
+pub fn square_dist_f32_scalar(a: &[f32], b: &[f32]) -> f64 {
+    let mut sums = [0.0_f32; LANES_F32];
+    let (a_chunks, b_chunks) = (a.chunks_exact(LANES_F32), b.chunks_exact(LANES_F32));
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for i in 0..LANES_F32 {
+            let diff = x[i] - y[i];
+            sums[i] += diff * diff;
+        }
+    }
+    f64::from(sums.iter().sum::<f32>() + tail)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn horizontal_sum(v: __m256d) -> f64 {
+        let mut lanes = [0.0; 4];
+        _mm256_storeu_pd(lanes.as_mut_ptr(), v);
+        lanes.iter().sum()
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn dot(a: &[f64], b: &[f64]) -> f64 {
+        let len = a.len().min(b.len());
+        let chunks = len / 8;
+        let mut sum0 = _mm256_setzero_pd();
+        let mut sum1 = _mm256_setzero_pd();
+        for i in 0..chunks {
+            let offset = i * 8;
+            let a0 = _mm256_loadu_pd(a.as_ptr().add(offset));
+            let b0 = _mm256_loadu_pd(b.as_ptr().add(offset));
+            let a1 = _mm256_loadu_pd(a.as_ptr().add(offset + 4));
+            let b1 = _mm256_loadu_pd(b.as_ptr().add(offset + 4));
+            sum0 = _mm256_fmadd_pd(a0, b0, sum0);
+            sum1 = _mm256_fmadd_pd(a1, b1, sum1);
+        }
+        let tail: f64 = a[chunks * 8..len]
+            .iter()
+            .zip(&b[chunks * 8..len])
+            .map(|(x, y)| x * y)
+            .sum();
+        horizontal_sum(_mm256_add_pd(sum0, sum1)) + tail
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn square_dist(a: &[f64], b: &[f64]) -> f64 {
+        let len = a.len().min(b.len());
+        let chunks = len / 8;
+        let mut sum0 = _mm256_setzero_pd();
+        let mut sum1 = _mm256_setzero_pd();
+        for i in 0..chunks {
+            let offset = i * 8;
+            let d0 = _mm256_sub_pd(
+                _mm256_loadu_pd(a.as_ptr().add(offset)),
+                _mm256_loadu_pd(b.as_ptr().add(offset)),
+            );
+            let d1 = _mm256_sub_pd(
+                _mm256_loadu_pd(a.as_ptr().add(offset + 4)),
+                _mm256_loadu_pd(b.as_ptr().add(offset + 4)),
+            );
+            sum0 = _mm256_fmadd_pd(d0, d0, sum0);
+            sum1 = _mm256_fmadd_pd(d1, d1, sum1);
+        }
+        let tail: f64 = a[chunks * 8..len]
+            .iter()
+            .zip(&b[chunks * 8..len])
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum();
+        horizontal_sum(_mm256_add_pd(sum0, sum1)) + tail
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn horizontal_sum_f32(v: __m256) -> f32 {
+        let mut lanes = [0.0; 8];
+        _mm256_storeu_ps(lanes.as_mut_ptr(), v);
+        lanes.iter().sum()
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn dot_f32(a: &[f32], b: &[f32]) -> f64 {
+        let len = a.len().min(b.len());
+        let chunks = len / 16;
+        let mut sum0 = _mm256_setzero_ps();
+        let mut sum1 = _mm256_setzero_ps();
+        for i in 0..chunks {
+            let offset = i * 16;
+            let a0 = _mm256_loadu_ps(a.as_ptr().add(offset));
+            let b0 = _mm256_loadu_ps(b.as_ptr().add(offset));
+            let a1 = _mm256_loadu_ps(a.as_ptr().add(offset + 8));
+            let b1 = _mm256_loadu_ps(b.as_ptr().add(offset + 8));
+            sum0 = _mm256_fmadd_ps(a0, b0, sum0);
+            sum1 = _mm256_fmadd_ps(a1, b1, sum1);
+        }
+        let tail: f32 = a[chunks * 16..len]
+            .iter()
+            .zip(&b[chunks * 16..len])
+            .map(|(x, y)| x * y)
+            .sum();
+        f64::from(horizontal_sum_f32(_mm256_add_ps(sum0, sum1)) + tail)
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn square_dist_f32(a: &[f32], b: &[f32]) -> f64 {
+        let len = a.len().min(b.len());
+        let chunks = len / 16;
+        let mut sum0 = _mm256_setzero_ps();
+        let mut sum1 = _mm256_setzero_ps();
+        for i in 0..chunks {
+            let offset = i * 16;
+            let d0 = _mm256_sub_ps(
+                _mm256_loadu_ps(a.as_ptr().add(offset)),
+                _mm256_loadu_ps(b.as_ptr().add(offset)),
+            );
+            let d1 = _mm256_sub_ps(
+                _mm256_loadu_ps(a.as_ptr().add(offset + 8)),
+                _mm256_loadu_ps(b.as_ptr().add(offset + 8)),
+            );
+            sum0 = _mm256_fmadd_ps(d0, d0, sum0);
+            sum1 = _mm256_fmadd_ps(d1, d1, sum1);
+        }
+        let tail: f32 = a[chunks * 16..len]
+            .iter()
+            .zip(&b[chunks * 16..len])
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum();
+        f64::from(horizontal_sum_f32(_mm256_add_ps(sum0, sum1)) + tail)
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    pub unsafe fn dot(a: &[f64], b: &[f64]) -> f64 {
+        let len = a.len().min(b.len());
+        let chunks = len / 4;
+        let mut sum0 = vdupq_n_f64(0.0);
+        let mut sum1 = vdupq_n_f64(0.0);
+        for i in 0..chunks {
+            let offset = i * 4;
+            sum0 = vfmaq_f64(
+                sum0,
+                vld1q_f64(a.as_ptr().add(offset)),
+                vld1q_f64(b.as_ptr().add(offset)),
+            );
+            sum1 = vfmaq_f64(
+                sum1,
+                vld1q_f64(a.as_ptr().add(offset + 2)),
+                vld1q_f64(b.as_ptr().add(offset + 2)),
+            );
+        }
+        let tail: f64 = a[chunks * 4..len]
+            .iter()
+            .zip(&b[chunks * 4..len])
+            .map(|(x, y)| x * y)
+            .sum();
+        vaddvq_f64(vaddq_f64(sum0, sum1)) + tail
+    }
+
+    pub unsafe fn square_dist(a: &[f64], b: &[f64]) -> f64 {
+        let len = a.len().min(b.len());
+        let chunks = len / 4;
+        let mut sum0 = vdupq_n_f64(0.0);
+        let mut sum1 = vdupq_n_f64(0.0);
+        for i in 0..chunks {
+            let offset = i * 4;
+            let d0 = vsubq_f64(
+                vld1q_f64(a.as_ptr().add(offset)),
+                vld1q_f64(b.as_ptr().add(offset)),
+            );
+            let d1 = vsubq_f64(
+                vld1q_f64(a.as_ptr().add(offset + 2)),
+                vld1q_f64(b.as_ptr().add(offset + 2)),
+            );
+            sum0 = vfmaq_f64(sum0, d0, d0);
+            sum1 = vfmaq_f64(sum1, d1, d1);
+        }
+        let tail: f64 = a[chunks * 4..len]
+            .iter()
+            .zip(&b[chunks * 4..len])
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum();
+        vaddvq_f64(vaddq_f64(sum0, sum1)) + tail
+    }
+
+    pub unsafe fn dot_f32(a: &[f32], b: &[f32]) -> f64 {
+        let len = a.len().min(b.len());
+        let chunks = len / 8;
+        let mut sum0 = vdupq_n_f32(0.0);
+        let mut sum1 = vdupq_n_f32(0.0);
+        for i in 0..chunks {
+            let offset = i * 8;
+            sum0 = vfmaq_f32(
+                sum0,
+                vld1q_f32(a.as_ptr().add(offset)),
+                vld1q_f32(b.as_ptr().add(offset)),
+            );
+            sum1 = vfmaq_f32(
+                sum1,
+                vld1q_f32(a.as_ptr().add(offset + 4)),
+                vld1q_f32(b.as_ptr().add(offset + 4)),
+            );
+        }
+        let tail: f32 = a[chunks * 8..len]
+            .iter()
+            .zip(&b[chunks * 8..len])
+            .map(|(x, y)| x * y)
+            .sum();
+        f64::from(vaddvq_f32(vaddq_f32(sum0, sum1)) + tail)
+    }
+
+    pub unsafe fn square_dist_f32(a: &[f32], b: &[f32]) -> f64 {
+        let len = a.len().min(b.len());
+        let chunks = len / 8;
+        let mut sum0 = vdupq_n_f32(0.0);
+        let mut sum1 = vdupq_n_f32(0.0);
+        for i in 0..chunks {
+            let offset = i * 8;
+            let d0 = vsubq_f32(
+                vld1q_f32(a.as_ptr().add(offset)),
+                vld1q_f32(b.as_ptr().add(offset)),
+            );
+            let d1 = vsubq_f32(
+                vld1q_f32(a.as_ptr().add(offset + 4)),
+                vld1q_f32(b.as_ptr().add(offset + 4)),
+            );
+            sum0 = vfmaq_f32(sum0, d0, d0);
+            sum1 = vfmaq_f32(sum1, d1, d1);
+        }
+        let tail: f32 = a[chunks * 8..len]
+            .iter()
+            .zip(&b[chunks * 8..len])
+            .map(|(x, y)| (x - y) * (x - y))
+            .sum();
+        f64::from(vaddvq_f32(vaddq_f32(sum0, sum1)) + tail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    fn naive_dot(a: &[f64], b: &[f64]) -> f64 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    #[test]
+    fn test_dot_and_square_dist() {
+        // Cover the vector loops as well as the remainders
+        for len in [0, 1, 3, 4, 7, 8, 9, 17, 102, 510] {
+            let a: Vec<f64> = (0..len).map(|i| (i as f64 * 0.37).sin()).collect();
+            let b: Vec<f64> = (0..len).map(|i| (i as f64 * 0.11).cos()).collect();
+            let diff: Vec<f64> = a.iter().zip(&b).map(|(x, y)| x - y).collect();
+
+            let expected = naive_dot(&a, &b);
+            assert_approx_eq!(dot(&a, &b), expected);
+            assert_approx_eq!(dot_scalar(&a, &b), expected);
+
+            let expected = naive_dot(&diff, &diff);
+            assert_approx_eq!(square_dist(&a, &b), expected);
+            assert_approx_eq!(square_dist_scalar(&a, &b), expected);
+        }
+    }
+
+    #[test]
+    fn test_f32_within_tolerance() {
+        for len in [0, 1, 7, 8, 15, 16, 17, 102, 510] {
+            let a: Vec<f64> = (0..len).map(|i| (i as f64 * 0.37).sin()).collect();
+            let b: Vec<f64> = (0..len).map(|i| (i as f64 * 0.11).cos()).collect();
+            let a32: Vec<f32> = a.iter().map(|x| *x as f32).collect();
+            let b32: Vec<f32> = b.iter().map(|x| *x as f32).collect();
+
+            let expected = dot(&a, &b);
+            assert_approx_eq!(dot_f32(&a32, &b32), expected, 1e-4);
+            assert_approx_eq!(dot_f32_scalar(&a32, &b32), expected, 1e-4);
+
+            let expected = square_dist(&a, &b);
+            assert_approx_eq!(square_dist_f32(&a32, &b32), expected, 1e-4);
+            assert_approx_eq!(square_dist_f32_scalar(&a32, &b32), expected, 1e-4);
+        }
+    }
+}
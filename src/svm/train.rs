@@ -0,0 +1,393 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Training of one-vs-rest SVM models with sequential minimal optimisation (SMO), using the
+//! first order working set selection and the threshold calculation of LIBSVM.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read};
+use std::path::Path;
+
+use crate::encodings::{encode, FeatureEncoding};
+use crate::errors::NrpsError;
+use crate::predictors::manifest::MANIFEST_FILE;
+use crate::predictors::predictions::PredictionCategory;
+use crate::svm::models::{new_kernel, KernelParameters, KernelType, SVMlightModel};
+use crate::svm::vectors::SupportVectors;
+
+const TAU: f64 = 1e-12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingSample {
+    pub aa34: String,
+    pub substrate: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrainingParameters {
+    pub category: PredictionCategory,
+    pub encoding: FeatureEncoding,
+    pub kernel_type: KernelType,
+    pub kernel_params: KernelParameters,
+    /// Soft margin cost, `-c` in SVMlight
+    pub cost: f64,
+    /// Stopping tolerance on the KKT violation
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for TrainingParameters {
+    fn default() -> Self {
+        TrainingParameters {
+            category: PredictionCategory::SingleV3,
+            encoding: FeatureEncoding::Blin,
+            kernel_type: KernelType::Linear,
+            kernel_params: KernelParameters::default(),
+            cost: 1.0,
+            tolerance: 1e-3,
+            max_iterations: 10_000_000,
+        }
+    }
+}
+
+/// Parse tab-separated `aa34 substrate` lines, skipping blank lines and `#` comments
+pub fn parse_training_data<R>(handle: R) -> Result<Vec<TrainingSample>, NrpsError>
+where
+    R: Read,
+{
+    let mut samples = Vec::new();
+    for (i, line_res) in BufReader::new(handle).lines().enumerate() {
+        let line = line_res?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() != 2 || !parts[0].is_ascii() || parts[0].len() != 34 || parts[1].is_empty() {
+            return Err(NrpsError::TrainingError(format!(
+                "line {}: expected `aa34<TAB>substrate`, got `{line}`",
+                i + 1
+            )));
+        }
+        samples.push(TrainingSample {
+            aa34: parts[0].to_string(),
+            substrate: parts[1].to_string(),
+        });
+    }
+    Ok(samples)
+}
+
+/// Solve the SVM dual problem for the precomputed `n x n` kernel matrix and labels of +1/-1
+///
+/// Returns the alphas and the threshold `rho`, the decision function is
+/// `sum(y_i * alpha_i * K(x_i, x)) - rho`.
+pub fn smo(
+    kernel: &[f64],
+    labels: &[f64],
+    cost: f64,
+    tolerance: f64,
+    max_iterations: usize,
+) -> (Vec<f64>, f64) {
+    let n = labels.len();
+    let q = |i: usize, j: usize| labels[i] * labels[j] * kernel[i * n + j];
+
+    let mut alpha = vec![0.0; n];
+    let mut gradient = vec![-1.0; n];
+
+    let is_upper = |a: f64| a >= cost;
+    let is_lower = |a: f64| a <= 0.0;
+
+    for _ in 0..max_iterations {
+        // Maximal violating pair
+        let mut g_max = f64::NEG_INFINITY;
+        let mut g_max2 = f64::NEG_INFINITY;
+        let mut i = usize::MAX;
+        let mut j = usize::MAX;
+        for t in 0..n {
+            let y_grad = -labels[t] * gradient[t];
+            let in_up = if labels[t] > 0.0 {
+                !is_upper(alpha[t])
+            } else {
+                !is_lower(alpha[t])
+            };
+            let in_low = if labels[t] > 0.0 {
+                !is_lower(alpha[t])
+            } else {
+                !is_upper(alpha[t])
+            };
+            if in_up && y_grad >= g_max {
+                g_max = y_grad;
+                i = t;
+            }
+            if in_low && -y_grad >= g_max2 {
+                g_max2 = -y_grad;
+                j = t;
+            }
+        }
+        if i == usize::MAX || j == usize::MAX || g_max + g_max2 < tolerance {
+            break;
+        }
+
+        let (old_i, old_j) = (alpha[i], alpha[j]);
+        if labels[i] != labels[j] {
+            let quad = (q(i, i) + q(j, j) + 2.0 * q(i, j)).max(TAU);
+            let delta = (-gradient[i] - gradient[j]) / quad;
+            let diff = alpha[i] - alpha[j];
+            alpha[i] += delta;
+            alpha[j] += delta;
+            if diff > 0.0 {
+                if alpha[j] < 0.0 {
+                    alpha[j] = 0.0;
+                    alpha[i] = diff;
+                }
+            } else if alpha[i] < 0.0 {
+                alpha[i] = 0.0;
+                alpha[j] = -diff;
+            }
+            if diff > 0.0 {
+                if alpha[i] > cost {
+                    alpha[i] = cost;
+                    alpha[j] = cost - diff;
+                }
+            } else if alpha[j] > cost {
+                alpha[j] = cost;
+                alpha[i] = cost + diff;
+            }
+        } else {
+            let quad = (q(i, i) + q(j, j) - 2.0 * q(i, j)).max(TAU);
+            let delta = (gradient[i] - gradient[j]) / quad;
+            let sum = alpha[i] + alpha[j];
+            alpha[i] -= delta;
+            alpha[j] += delta;
+            if sum > cost {
+                if alpha[i] > cost {
+                    alpha[i] = cost;
+                    alpha[j] = sum - cost;
+                }
+            } else if alpha[j] < 0.0 {
+                alpha[j] = 0.0;
+                alpha[i] = sum;
+            }
+            if sum > cost {
+                if alpha[j] > cost {
+                    alpha[j] = cost;
+                    alpha[i] = sum - cost;
+                }
+            } else if alpha[i] < 0.0 {
+                alpha[i] = 0.0;
+                alpha[j] = sum;
+            }
+        }
+
+        let (delta_i, delta_j) = (alpha[i] - old_i, alpha[j] - old_j);
+        for (t, grad) in gradient.iter_mut().enumerate() {
+            *grad += q(i, t) * delta_i + q(j, t) * delta_j;
+        }
+    }
+
+    // Threshold, averaged over the free support vectors
+    let mut upper_bound = f64::INFINITY;
+    let mut lower_bound = f64::NEG_INFINITY;
+    let mut free = 0;
+    let mut free_sum = 0.0;
+    for t in 0..n {
+        let y_grad = labels[t] * gradient[t];
+        if is_upper(alpha[t]) {
+            if labels[t] < 0.0 {
+                upper_bound = upper_bound.min(y_grad);
+            } else {
+                lower_bound = lower_bound.max(y_grad);
+            }
+        } else if is_lower(alpha[t]) {
+            if labels[t] > 0.0 {
+                upper_bound = upper_bound.min(y_grad);
+            } else {
+                lower_bound = lower_bound.max(y_grad);
+            }
+        } else {
+            free += 1;
+            free_sum += y_grad;
+        }
+    }
+    let rho = if free > 0 {
+        free_sum / free as f64
+    } else {
+        (upper_bound + lower_bound) / 2.0
+    };
+
+    (alpha, rho)
+}
+
+/// Train one model per substrate, with all other substrates as negative examples
+pub fn train_models(
+    samples: &[TrainingSample],
+    params: &TrainingParameters,
+) -> Result<Vec<SVMlightModel>, NrpsError> {
+    let features: Vec<Vec<f64>> = samples
+        .iter()
+        .map(|s| encode(&s.aa34, &params.encoding, &params.category))
+        .collect();
+
+    let kernel = new_kernel(params.kernel_type, &params.kernel_params);
+    let n = samples.len();
+    let mut matrix = vec![0.0; n * n];
+    for i in 0..n {
+        for j in i..n {
+            let value = kernel.compute(&features[i], &features[j]);
+            matrix[i * n + j] = value;
+            matrix[j * n + i] = value;
+        }
+    }
+
+    let substrates: BTreeSet<&str> = samples.iter().map(|s| s.substrate.as_str()).collect();
+    let mut models = Vec::with_capacity(substrates.len());
+    for substrate in substrates.iter() {
+        let labels: Vec<f64> = samples
+            .iter()
+            .map(|s| if s.substrate == *substrate { 1.0 } else { -1.0 })
+            .collect();
+        if labels.iter().all(|l| *l > 0.0) {
+            log::warn!("Skipping {substrate}, there are no negative examples");
+            continue;
+        }
+
+        let (alpha, rho) = smo(
+            &matrix,
+            &labels,
+            params.cost,
+            params.tolerance,
+            params.max_iterations,
+        );
+        let dimensions = params.encoding.dimensions();
+        let mut vectors = SupportVectors::new(dimensions);
+        for (i, a) in alpha.iter().enumerate().filter(|(_, a)| **a > 0.0) {
+            vectors.push(&features[i], labels[i] * a)?;
+        }
+        log::debug!("Trained {substrate} with {} support vectors", vectors.len());
+
+        models.push(SVMlightModel::new(
+            substrate.to_string(),
+            params.category.clone(),
+            vectors,
+            rho,
+            params.encoding,
+            params.kernel_type,
+            params.kernel_params,
+        ));
+    }
+
+    Ok(models)
+}
+
+/// Write `models` in the model dir layout used by `load_models`
+pub fn write_models(
+    models: &[SVMlightModel],
+    model_dir: &Path,
+    training_docs: usize,
+) -> Result<(), NrpsError> {
+    for model in models.iter() {
+        let Some(dir_name) = model.category.dir_name() else {
+            return Err(NrpsError::TrainingError(format!(
+                "Can't write models for category {}",
+                model.category
+            )));
+        };
+        let category_dir = model_dir.join(dir_name);
+        fs::create_dir_all(&category_dir)?;
+        // Encodings that can't be told apart by their dimensions need a manifest to load
+        if FeatureEncoding::from_dimensions(model.encoding.dimensions()) != Some(model.encoding) {
+            fs::write(
+                category_dir.join(MANIFEST_FILE),
+                format!("encoding = \"{}\"\n", model.encoding.name()),
+            )?;
+        }
+        let handle = File::create(category_dir.join(format!("[{}].mdl", model.name)))?;
+        model.write(BufWriter::new(handle), training_docs)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::predictors::load_models_from_dir;
+
+    #[test]
+    fn test_smo() {
+        // Two separable clusters on a line, linear kernel
+        let points = [-3.0, -2.0, -1.5, 1.0, 2.0, 4.0];
+        let labels = [-1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+        let n = points.len();
+        let kernel: Vec<f64> = (0..n * n).map(|k| points[k / n] * points[k % n]).collect();
+
+        let (alpha, rho) = smo(&kernel, &labels, 10.0, 1e-6, 10_000);
+        for (x, label) in points.iter().zip(labels.iter()) {
+            let decision: f64 = (0..n)
+                .map(|i| labels[i] * alpha[i] * points[i] * x)
+                .sum::<f64>()
+                - rho;
+            assert!(decision * label >= 1.0 - 1e-3, "{x}: {decision}");
+        }
+        // Only the points closest to the margin are support vectors
+        assert_eq!(alpha[0], 0.0);
+        assert_eq!(alpha[5], 0.0);
+    }
+
+    #[test]
+    fn test_parse_training_data() {
+        let data = "# comment\nLDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tleu\n\n";
+        let samples = parse_training_data(data.as_bytes()).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].substrate, "leu");
+
+        assert!(parse_training_data("LDASFDASLFEMYLLTGG\tleu".as_bytes()).is_err());
+        assert!(parse_training_data("LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_train_and_load() {
+        let data = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tleu\n\
+                    LDASFDASLFEMYLLTGGDRNMYGPTEATMCATL\tleu\n\
+                    LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATW\thpg\n\
+                    LEPAFDISLFEVHLLTGGDRHLYGPTEATLCATA\thpg\n";
+        let samples = parse_training_data(data.as_bytes()).unwrap();
+        let params = TrainingParameters {
+            kernel_type: KernelType::RBF,
+            kernel_params: KernelParameters {
+                gamma: 0.01,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let models = train_models(&samples, &params).unwrap();
+        assert_eq!(models.len(), 2);
+
+        let tmp = tempfile::tempdir().unwrap();
+        let model_dir = tmp.path().to_path_buf();
+        write_models(&models, &model_dir, samples.len()).unwrap();
+
+        let loaded = load_models_from_dir(&model_dir, None).unwrap();
+        assert_eq!(loaded.len(), 2);
+        let (hpg, leu) = (&loaded[0], &loaded[1]);
+        assert_eq!(leu.name, "leu");
+        assert_eq!(leu.category, PredictionCategory::SingleV3);
+        assert!(leu.predict_seq(&samples[0].aa34).unwrap() > 0.0);
+        assert!(hpg.predict_seq(&samples[0].aa34).unwrap() < 0.0);
+        assert!(hpg.predict_seq(&samples[2].aa34).unwrap() > 0.0);
+        assert!(!model_dir
+            .join("NRPS3_SINGLE_CLUSTER")
+            .join(MANIFEST_FILE)
+            .exists());
+
+        let params = TrainingParameters {
+            encoding: FeatureEncoding::Reduced,
+            ..Default::default()
+        };
+        let models = train_models(&samples, &params).unwrap();
+        fs::remove_dir_all(&model_dir).unwrap();
+        write_models(&models, &model_dir, samples.len()).unwrap();
+        let loaded = load_models_from_dir(&model_dir, None).unwrap();
+        assert_eq!(loaded[0].encoding, FeatureEncoding::Reduced);
+    }
+}
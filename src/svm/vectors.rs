@@ -1,7 +1,52 @@
 // License: GNU Affero General Public License v3 or later
 // A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
 
+use std::borrow::Cow;
+
 use crate::errors::NrpsError;
+use crate::svm::simd;
+
+/// Storage type of `SupportVectors` values, `f32` with the `f32-vectors` feature
+///
+/// Single precision halves the memory of the support vectors and doubles the values per SIMD
+/// instruction, scores differ from `f64` by far less than any meaningful threshold.
+#[cfg(not(feature = "f32-vectors"))]
+pub type Value = f64;
+#[cfg(feature = "f32-vectors")]
+pub type Value = f32;
+
+/// `values` as storage values, only copied if those aren't `f64`
+#[cfg(not(feature = "f32-vectors"))]
+pub fn to_storage(values: &[f64]) -> Cow<'_, [Value]> {
+    Cow::Borrowed(values)
+}
+
+#[cfg(feature = "f32-vectors")]
+pub fn to_storage(values: &[f64]) -> Cow<'_, [Value]> {
+    Cow::Owned(values.iter().map(|v| *v as f32).collect())
+}
+
+/// Like `to_storage`, but taking ownership, so `f64` values are never copied
+#[cfg(not(feature = "f32-vectors"))]
+pub fn into_storage(values: Vec<f64>) -> Vec<Value> {
+    values
+}
+
+#[cfg(feature = "f32-vectors")]
+pub fn into_storage(values: Vec<f64>) -> Vec<Value> {
+    values.iter().map(|v| *v as f32).collect()
+}
+
+/// Storage `values` as `f64`, only copied if they aren't `f64` already
+#[cfg(not(feature = "f32-vectors"))]
+pub fn to_f64(values: &[Value]) -> Cow<'_, [f64]> {
+    Cow::Borrowed(values)
+}
+
+#[cfg(feature = "f32-vectors")]
+pub fn to_f64(values: &[Value]) -> Cow<'_, [f64]> {
+    Cow::Owned(values.iter().map(|v| f64::from(*v)).collect())
+}
 
 pub trait Vector {
     fn values(&self) -> &Vec<f64>;
@@ -9,8 +54,8 @@ pub trait Vector {
         self.values().len()
     }
     fn square_dist<T: Vector>(&self, other: &T) -> Result<f64, NrpsError> {
-        let temp = element_subtract(self.values(), other.values())?;
-        dot(&temp, &temp)
+        check_dimensions(self.values(), other.values())?;
+        Ok(simd::square_dist(self.values(), other.values()))
     }
 
     fn dist<T: Vector>(&self, other: &T) -> Result<f64, NrpsError> {
@@ -51,23 +96,45 @@ impl SupportVector {
     }
     pub fn from_line(line: String, dimension: usize) -> Result<Self, NrpsError> {
         let mut values = vec![0.0; dimension];
-        let parts: Vec<&str> = line.split(char::is_whitespace).collect();
+        let parts: Vec<&str> = line.split_whitespace().collect();
         if parts.len() < 2 {
             return Err(NrpsError::InvalidFeatureLine(line));
         }
-        let yalpha = parts[0].parse::<f64>()?;
+        let yalpha = match parts[0].parse::<f64>() {
+            Ok(yalpha) if yalpha.is_finite() => yalpha,
+            _ => {
+                return Err(NrpsError::InvalidFeatureLine(format!(
+                    "Invalid alpha value `{}` in `{line}`",
+                    parts[0]
+                )))
+            }
+        };
 
         for token in parts[1..].iter() {
-            if token == &"#" {
+            if token.starts_with('#') {
                 break;
             }
-            let value_parts: Vec<&str> = token.splitn(2, ':').collect();
-            let idx = value_parts[0].parse::<usize>()? - 1;
-            if idx > dimension - 1 {
-                return Err(NrpsError::InvalidFeatureLine(line));
-            }
-            let value = value_parts[1].parse::<f64>()?;
-            values[idx] = value;
+            let Some((raw_idx, raw_value)) = token.split_once(':') else {
+                return Err(NrpsError::InvalidFeatureLine(format!(
+                    "Missing ':' in token `{token}` in `{line}`"
+                )));
+            };
+            let idx = match raw_idx.parse::<usize>() {
+                Ok(idx) if idx >= 1 && idx <= dimension => idx - 1,
+                _ => {
+                    return Err(NrpsError::InvalidFeatureLine(format!(
+                        "Invalid feature index `{raw_idx}` for dimension {dimension} in `{line}`"
+                    )))
+                }
+            };
+            values[idx] = match raw_value.parse::<f64>() {
+                Ok(value) if value.is_finite() => value,
+                _ => {
+                    return Err(NrpsError::InvalidFeatureLine(format!(
+                        "Invalid feature value `{raw_value}` in `{line}`"
+                    )))
+                }
+            };
         }
 
         Ok(SupportVector { values, yalpha })
@@ -80,18 +147,121 @@ impl Vector for SupportVector {
     }
 }
 
-fn dot(a: &[f64], b: &[f64]) -> Result<f64, NrpsError> {
+/// All support vectors of a model, stored row-major in one contiguous buffer
+#[derive(Debug, Default)]
+pub struct SupportVectors {
+    values: Vec<Value>,
+    yalphas: Vec<f64>,
+    dimensions: usize,
+}
+
+impl SupportVectors {
+    pub fn new(dimensions: usize) -> Self {
+        Self::with_capacity(dimensions, 0)
+    }
+
+    pub fn with_capacity(dimensions: usize, capacity: usize) -> Self {
+        SupportVectors {
+            values: Vec::with_capacity(dimensions * capacity),
+            yalphas: Vec::with_capacity(capacity),
+            dimensions,
+        }
+    }
+
+    /// Build from a row-major `values` buffer with one row per `yalphas` entry
+    pub fn from_parts(
+        dimensions: usize,
+        values: Vec<f64>,
+        yalphas: Vec<f64>,
+    ) -> Result<Self, NrpsError> {
+        if values.len() != dimensions * yalphas.len() {
+            return Err(NrpsError::DimensionMismatch {
+                first: dimensions * yalphas.len(),
+                second: values.len(),
+            });
+        }
+        Ok(SupportVectors {
+            values: into_storage(values),
+            yalphas,
+            dimensions,
+        })
+    }
+
+    pub fn push(&mut self, values: &[f64], yalpha: f64) -> Result<(), NrpsError> {
+        if values.len() != self.dimensions {
+            return Err(NrpsError::DimensionMismatch {
+                first: self.dimensions,
+                second: values.len(),
+            });
+        }
+        self.values.extend_from_slice(&to_storage(values));
+        self.yalphas.push(yalpha);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.yalphas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.yalphas.is_empty()
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// All values, row-major
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn yalphas(&self) -> &[f64] {
+        &self.yalphas
+    }
+
+    pub fn get(&self, index: usize) -> Option<(&[Value], f64)> {
+        let yalpha = *self.yalphas.get(index)?;
+        let start = index * self.dimensions;
+        Some((&self.values[start..start + self.dimensions], yalpha))
+    }
+
+    /// Iterate over `(values, yalpha)` pairs
+    pub fn iter(&self) -> impl Iterator<Item = (&[Value], f64)> + '_ {
+        (0..self.len()).filter_map(|i| self.get(i))
+    }
+}
+
+impl TryFrom<Vec<SupportVector>> for SupportVectors {
+    type Error = NrpsError;
+
+    /// Takes the dimensions from the first vector, all others have to match
+    fn try_from(vectors: Vec<SupportVector>) -> Result<Self, Self::Error> {
+        let dimensions = vectors.first().map_or(0, |v| v.dim());
+        let mut flat = SupportVectors::with_capacity(dimensions, vectors.len());
+        for vector in vectors.iter() {
+            flat.push(vector.values(), vector.yalpha)?;
+        }
+        Ok(flat)
+    }
+}
+
+fn check_dimensions(a: &[f64], b: &[f64]) -> Result<(), NrpsError> {
     if a.len() != b.len() {
         return Err(NrpsError::DimensionMismatch {
             first: a.len(),
             second: b.len(),
         });
     }
-    Ok(a.iter()
-        .zip(b.iter())
-        .fold(0.0, |sum, (el_a, el_b)| sum + el_a * el_b))
+    Ok(())
+}
+
+fn dot(a: &[f64], b: &[f64]) -> Result<f64, NrpsError> {
+    check_dimensions(a, b)?;
+    Ok(simd::dot(a, b))
 }
 
+#[cfg(test)]
 fn element_subtract(a: &[f64], b: &[f64]) -> Result<Vec<f64>, NrpsError> {
     if a.len() != b.len() {
         return Err(NrpsError::DimensionMismatch {
@@ -109,6 +279,8 @@ fn element_subtract(a: &[f64], b: &[f64]) -> Result<Vec<f64>, NrpsError> {
 mod tests {
     use super::*;
 
+    use proptest::prelude::*;
+
     #[test]
     fn test_square_dist() {
         let v1 = FeatureVector::new(Vec::<f64>::from([1.0, 0.0, 1.0]));
@@ -130,6 +302,37 @@ mod tests {
         assert_eq!(v1.similarity(&v2).unwrap(), 4.0);
     }
 
+    #[test]
+    fn test_support_vectors() {
+        let vectors = SupportVectors::try_from(Vec::from([
+            SupportVector::new(Vec::from([1.0, 2.0]), 0.5),
+            SupportVector::new(Vec::from([3.0, 4.0]), -1.0),
+        ]))
+        .unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(vectors.dimensions(), 2);
+        assert_eq!(vectors.get(1), Some((&[3.0, 4.0][..], -1.0)));
+        assert_eq!(vectors.get(2), None);
+        let got: Vec<(&[Value], f64)> = vectors.iter().collect();
+        assert_eq!(got, [(&[1.0, 2.0][..], 0.5), (&[3.0, 4.0][..], -1.0)]);
+
+        let mismatched = SupportVectors::try_from(Vec::from([
+            SupportVector::new(Vec::from([1.0, 2.0]), 0.5),
+            SupportVector::new(Vec::from([3.0]), -1.0),
+        ]));
+        assert!(mismatched.is_err());
+
+        let parts =
+            SupportVectors::from_parts(2, Vec::from([1.0, 2.0, 3.0, 4.0]), Vec::from([0.5, -1.0]))
+                .unwrap();
+        assert_eq!(parts.values(), vectors.values());
+        assert_eq!(parts.yalphas(), vectors.yalphas());
+        assert!(
+            SupportVectors::from_parts(2, Vec::from([1.0, 2.0, 3.0]), Vec::from([0.5, -1.0]))
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_element_subtract() {
         let v1 = FeatureVector::new(Vec::<f64>::from([3.0, 2.0]));
@@ -148,4 +351,46 @@ mod tests {
         assert_eq!(v1.yalpha, 10.0);
         assert_eq!(v1.values, [-1.6023999, 0., -0.55470002, 0., -0.63520002]);
     }
+
+    #[test]
+    fn test_from_line_malformed() {
+        let cases = [
+            "",
+            "10",
+            "abc 1:0.5",
+            "10 1",
+            "10 1:",
+            "10 :0.5",
+            "10 0:0.5",
+            "10 6:0.5",
+            "10 x:0.5",
+            "10 1:0.5:3",
+            "10 -1:0.5",
+            "nan 1:0.5",
+            "inf 1:0.5",
+            "10 1:NaN",
+            "10 1:-inf",
+        ];
+        for case in cases.iter() {
+            let got = SupportVector::from_line(case.to_string(), 5);
+            assert!(got.is_err(), "{case}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_from_line_never_panics(line in "\\PC*", dimension in 0usize..8) {
+            let _ = SupportVector::from_line(line, dimension);
+        }
+
+        #[test]
+        fn test_from_line_tokens(
+            line in r"-?[0-9.e]{0,4}( -?[0-9x]{0,2}:?-?[0-9.:]{0,4}| #.*){0,4}",
+            dimension in 0usize..8,
+        ) {
+            if let Ok(vector) = SupportVector::from_line(line, dimension) {
+                prop_assert_eq!(vector.values.len(), dimension);
+            }
+        }
+    }
 }
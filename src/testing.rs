@@ -0,0 +1,279 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Helpers for golden-file regression tests of model sets and signature files.
+//!
+//! A snapshot is a tab-separated file with one `domain category substrate score` line
+//! per prediction, which keeps it easy to review in diffs.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::errors::NrpsError;
+use crate::predictors::predictions::ADomain;
+use crate::run_on_file;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotEntry {
+    pub domain: String,
+    pub category: String,
+    pub name: String,
+    pub score: f64,
+}
+
+impl SnapshotEntry {
+    fn key(&self) -> (&str, &str, &str) {
+        (&self.domain, &self.category, &self.name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotMismatch {
+    Missing(SnapshotEntry),
+    Unexpected(SnapshotEntry),
+    Score {
+        expected: SnapshotEntry,
+        got: SnapshotEntry,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub entries: Vec<SnapshotEntry>,
+}
+
+impl Snapshot {
+    pub fn from_domains(config: &Config, domains: &[ADomain]) -> Self {
+        let mut entries = Vec::new();
+        for domain in domains.iter() {
+            for cat in config.categories().iter() {
+                for pred in domain.get_all(cat).iter() {
+                    entries.push(SnapshotEntry {
+                        domain: domain.name.to_string(),
+                        category: cat.to_string(),
+                        name: pred.name.to_string(),
+                        score: pred.score,
+                    });
+                }
+            }
+        }
+        Snapshot { entries }
+    }
+
+    pub fn from_reader<R>(reader: R) -> Result<Self, NrpsError>
+    where
+        R: Read,
+    {
+        let mut entries = Vec::new();
+        for line_res in BufReader::new(reader).lines() {
+            let line = line_res?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split('\t').collect();
+            if parts.len() != 4 {
+                return Err(NrpsError::SnapshotError(line.to_string()));
+            }
+            entries.push(SnapshotEntry {
+                domain: parts[0].to_string(),
+                category: parts[1].to_string(),
+                name: parts[2].to_string(),
+                score: parts[3].parse::<f64>()?,
+            });
+        }
+        Ok(Snapshot { entries })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, NrpsError> {
+        Snapshot::from_reader(File::open(path)?)
+    }
+
+    pub fn write<W>(&self, mut writer: W) -> Result<(), NrpsError>
+    where
+        W: Write,
+    {
+        for entry in self.entries.iter() {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                entry.domain, entry.category, entry.name, entry.score
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Compare against an expected snapshot, treating scores within `tolerance` as equal
+    ///
+    /// Entries sharing a domain, category and name are paired up in file order, so a duplicated
+    /// prediction is reported as missing or unexpected instead of being collapsed.
+    pub fn compare(&self, expected: &Snapshot, tolerance: f64) -> Vec<SnapshotMismatch> {
+        let mut mismatches = Vec::new();
+        let mut got_map: HashMap<_, Vec<&SnapshotEntry>> = HashMap::new();
+        for entry in self.entries.iter() {
+            got_map.entry(entry.key()).or_default().push(entry);
+        }
+        let mut expected_counts: HashMap<_, usize> = HashMap::new();
+
+        for entry in expected.entries.iter() {
+            let seen = expected_counts.entry(entry.key()).or_default();
+            match got_map.get(&entry.key()).and_then(|got| got.get(*seen)) {
+                Some(got) => {
+                    // Compared this way round so a NaN on either side is a mismatch
+                    let within = (got.score - entry.score).abs() <= tolerance;
+                    if !within {
+                        mismatches.push(SnapshotMismatch::Score {
+                            expected: entry.clone(),
+                            got: (*got).clone(),
+                        });
+                    }
+                }
+                None => mismatches.push(SnapshotMismatch::Missing(entry.clone())),
+            }
+            *seen += 1;
+        }
+
+        let mut got_counts: HashMap<_, usize> = HashMap::new();
+        for entry in self.entries.iter() {
+            let expected_count = expected_counts.get(&entry.key()).copied().unwrap_or(0);
+            let seen = got_counts.entry(entry.key()).or_default();
+            if *seen >= expected_count {
+                mismatches.push(SnapshotMismatch::Unexpected(entry.clone()));
+            }
+            *seen += 1;
+        }
+
+        mismatches
+    }
+}
+
+/// Run the predictions for `signature_file` and capture them as a snapshot
+pub fn run_snapshot(config: &Config, signature_file: PathBuf) -> Result<Snapshot, NrpsError> {
+    let domains = run_on_file(config, signature_file)?;
+    Ok(Snapshot::from_domains(config, &domains))
+}
+
+/// Run the predictions for `signature_file` and compare them to the snapshot in `snapshot_file`
+pub fn check_snapshot(
+    config: &Config,
+    signature_file: PathBuf,
+    snapshot_file: &Path,
+    tolerance: f64,
+) -> Result<Vec<SnapshotMismatch>, NrpsError> {
+    let expected = Snapshot::from_file(snapshot_file)?;
+    let got = run_snapshot(config, signature_file)?;
+    Ok(got.compare(&expected, tolerance))
+}
+
+/// A linear SVMlight model with two support vectors, for `dimensions` features
+#[cfg(test)]
+pub(crate) fn svmlight_model(dimensions: usize) -> String {
+    format!(
+        "SVM-light Version V6.01\n\
+         0 # kernel type\n\
+         3 # kernel parameter -d\n\
+         1 # kernel parameter -g\n\
+         1 # kernel parameter -s\n\
+         1 # kernel parameter -r\n\
+         empty# kernel parameter -u\n\
+         {dimensions} # highest feature index\n\
+         20 # number of training documents\n\
+         3 # number of support vectors plus 1\n\
+         0.5 # threshold b, each following line is a SV (starting with alpha*y)\n\
+         1 1:1 #\n\
+         -0.5 2:1 #\n"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SNAPSHOT: &str = "# comment\nbpsA\tSingleV2\tleu\t0.43\nbpsA\tStachelhaus\tLeu\t1\n";
+
+    fn entry(category: &str, name: &str, score: f64) -> SnapshotEntry {
+        SnapshotEntry {
+            domain: "bpsA".to_string(),
+            category: category.to_string(),
+            name: name.to_string(),
+            score,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let snapshot = Snapshot::from_reader(SNAPSHOT.as_bytes()).unwrap();
+        assert_eq!(snapshot.entries.len(), 2);
+        assert_eq!(snapshot.entries[0], entry("SingleV2", "leu", 0.43));
+
+        let mut written = Vec::new();
+        snapshot.write(&mut written).unwrap();
+        let reread = Snapshot::from_reader(written.as_slice()).unwrap();
+        assert_eq!(snapshot, reread);
+    }
+
+    #[test]
+    fn test_invalid_line() {
+        assert!(Snapshot::from_reader("bpsA\tSingleV2\tleu".as_bytes()).is_err());
+        assert!(Snapshot::from_reader("bpsA\tSingleV2\tleu\tabc".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = Snapshot::from_reader(SNAPSHOT.as_bytes()).unwrap();
+        let got = Snapshot {
+            entries: Vec::from([
+                entry("SingleV2", "leu", 0.4301),
+                entry("SingleV2", "val", 0.2),
+            ]),
+        };
+
+        assert_eq!(
+            got.compare(&expected, 0.001),
+            Vec::from([
+                SnapshotMismatch::Missing(entry("Stachelhaus", "Leu", 1.0)),
+                SnapshotMismatch::Unexpected(entry("SingleV2", "val", 0.2)),
+            ])
+        );
+
+        let mismatches = got.compare(&expected, 0.00001);
+        assert_eq!(mismatches.len(), 3);
+        assert_eq!(
+            mismatches[0],
+            SnapshotMismatch::Score {
+                expected: entry("SingleV2", "leu", 0.43),
+                got: entry("SingleV2", "leu", 0.4301),
+            }
+        );
+    }
+
+    #[test]
+    fn test_compare_nan() {
+        let expected = Snapshot::from_reader(SNAPSHOT.as_bytes()).unwrap();
+        let mut got = expected.clone();
+        got.entries[0].score = f64::NAN;
+        let mismatches = got.compare(&expected, 0.001);
+        assert_eq!(mismatches.len(), 1);
+        assert!(matches!(mismatches[0], SnapshotMismatch::Score { .. }));
+        assert_eq!(expected.compare(&got, 0.001).len(), 1);
+    }
+
+    #[test]
+    fn test_compare_duplicates() {
+        let expected = Snapshot::from_reader(SNAPSHOT.as_bytes()).unwrap();
+        let mut got = expected.clone();
+        got.entries.push(entry("SingleV2", "leu", 0.43));
+        assert_eq!(
+            got.compare(&expected, 0.001),
+            Vec::from([SnapshotMismatch::Unexpected(entry("SingleV2", "leu", 0.43))])
+        );
+        assert_eq!(
+            expected.compare(&got, 0.001),
+            Vec::from([SnapshotMismatch::Missing(entry("SingleV2", "leu", 0.43))])
+        );
+        assert!(got.compare(&got, 0.001).is_empty());
+    }
+}
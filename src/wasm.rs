@@ -0,0 +1,192 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! JavaScript bindings for running the predictions in the browser.
+//!
+//! There is no file system on wasm32, so the model files and the Stachelhaus signatures
+//! are handed over as byte arrays, typically from `fetch()` responses.
+
+use std::path::Path;
+
+use wasm_bindgen::prelude::*;
+
+use crate::config::Config;
+use crate::encodings::EncodingOptions;
+use crate::errors::NrpsError;
+use crate::output::{format_results, OutputFormat};
+use crate::parse_domain;
+use crate::predictors::predictions::{ADomain, PredictionCategory};
+use crate::predictors::stachelhaus::StachelhausDb;
+use crate::predictors::store::ModelStore;
+use crate::predictors::{extract_name, PredictionBackend, SvmPredictor};
+use crate::svm::models::SVMlightModel;
+
+#[wasm_bindgen]
+#[derive(Debug)]
+pub struct WasmPredictor {
+    config: Config,
+    predictor: SvmPredictor,
+    stachelhaus: StachelhausDb,
+}
+
+impl Default for WasmPredictor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmPredictor {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        let mut config = Config::new();
+        config.output_format = OutputFormat::Json;
+        let predictor = SvmPredictor {
+            store: ModelStore::default(),
+            min_score: config.min_score,
+            category_min_scores: config.category_min_scores.clone(),
+            attributions: config.attributions,
+            encoding_options: EncodingOptions::default(),
+        };
+        WasmPredictor {
+            config,
+            predictor,
+            stachelhaus: StachelhausDb::default(),
+        }
+    }
+
+    #[wasm_bindgen(js_name = setCount)]
+    pub fn set_count(&mut self, count: usize) {
+        self.config.count = count;
+    }
+
+    #[wasm_bindgen(js_name = setFungal)]
+    pub fn set_fungal(&mut self, fungal: bool) {
+        self.config.fungal = fungal;
+    }
+
+    #[wasm_bindgen(js_name = setSkipV3)]
+    pub fn set_skip_v3(&mut self, skip: bool) {
+        self.config.skip_v3 = skip;
+    }
+
+    #[wasm_bindgen(js_name = setSkipV2)]
+    pub fn set_skip_v2(&mut self, skip: bool) {
+        self.config.skip_v2 = skip;
+    }
+
+    #[wasm_bindgen(js_name = setSkipV1)]
+    pub fn set_skip_v1(&mut self, skip: bool) {
+        self.config.skip_v1 = skip;
+    }
+
+    /// Only report predictions scoring above `min_score`, use `-Infinity` to report all
+    #[wasm_bindgen(js_name = setMinScore)]
+    pub fn set_min_score(&mut self, min_score: f64) {
+        self.config.min_score = min_score;
+        self.predictor.min_score = min_score;
+    }
+
+    /// Add a model, `category_dir` is the model directory name, e.g. `NRPS2_SINGLE_CLUSTER`
+    #[wasm_bindgen(js_name = addModel)]
+    pub fn add_model(
+        &mut self,
+        category_dir: &str,
+        file_name: &str,
+        data: &[u8],
+    ) -> Result<(), JsError> {
+        Ok(self.add_model_internal(category_dir, file_name, data)?)
+    }
+
+    /// Load the Stachelhaus signatures from the contents of a `signatures.tsv` file
+    #[wasm_bindgen(js_name = loadSignatures)]
+    pub fn load_signatures(&mut self, data: &[u8]) -> Result<(), JsError> {
+        self.stachelhaus = StachelhausDb::from_reader(data)?;
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = modelCount)]
+    pub fn model_count(&self) -> usize {
+        self.predictor.store.model_count()
+    }
+
+    /// Predict the signature lines in `input` and return the results as JSON
+    pub fn predict(&self, input: &str) -> Result<String, JsError> {
+        Ok(self.predict_internal(input)?)
+    }
+}
+
+impl WasmPredictor {
+    fn add_model_internal(
+        &mut self,
+        category_dir: &str,
+        file_name: &str,
+        data: &[u8],
+    ) -> Result<(), NrpsError> {
+        let Some(category) = PredictionCategory::from_dir_name(category_dir) else {
+            return Err(NrpsError::CategoryError(category_dir.to_string()));
+        };
+        let name = extract_name(Path::new(file_name));
+        self.predictor
+            .store
+            .push(SVMlightModel::from_handle(data, name, category)?);
+        Ok(())
+    }
+
+    fn predict_internal(&self, input: &str) -> Result<String, NrpsError> {
+        let mut domains: Vec<ADomain> = Vec::new();
+        for line in input.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            domains.push(parse_domain(line.to_string())?);
+        }
+
+        let mut config = self.config.clone();
+        config.skip_stachelhaus = self.stachelhaus.is_empty();
+        if !config.skip_stachelhaus {
+            self.stachelhaus.predict(&mut domains)?;
+        }
+        self.predictor.predict(&mut domains)?;
+
+        format_results(&config, &domains)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::svmlight_model;
+
+    const SIGNATURE: &str = "LDASFDASLFEMYLLTGGDRNMYGPTEATMCATW\tbpsA_A1\n";
+
+    #[test]
+    fn test_wasm_predictor() {
+        let mut predictor = WasmPredictor::new();
+        predictor.set_skip_v3(true);
+        predictor.set_skip_v1(true);
+        predictor
+            .add_model_internal(
+                "NRPS2_SINGLE_CLUSTER",
+                "[leu].mdl",
+                svmlight_model(102).as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(predictor.model_count(), 1);
+        assert!(predictor
+            .add_model_internal(
+                "not a category",
+                "[leu].mdl",
+                svmlight_model(102).as_bytes()
+            )
+            .is_err());
+
+        let got: serde_json::Value =
+            serde_json::from_str(&predictor.predict_internal(SIGNATURE).unwrap()).unwrap();
+        assert_eq!(got[0]["name"], "bpsA_A1");
+        assert!(got[0].get("stachelhaus").is_none());
+        assert!(predictor.predict_internal("too short").is_err());
+    }
+}
@@ -0,0 +1,129 @@
+// License: GNU Affero General Public License v3 or later
+// A copy of GNU AGPL v3 should have been included in this software package in LICENSE.txt.
+
+//! Golden-file tests running the mini model set in `tests/data` through `run_on_file()` and the
+//! output formatting. After intended output changes, rerun with `UPDATE_GOLDEN=1` to rewrite the
+//! golden files and review their diff.
+
+use std::fs;
+use std::path::PathBuf;
+
+use nrps_rs::config::Config;
+use nrps_rs::output::{format_results, OutputFormat};
+use nrps_rs::run_on_file;
+use serde_json::Value;
+
+fn data_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+}
+
+/// Only the v2 categories have models in the mini model set
+fn config(output_format: OutputFormat) -> Config {
+    let mut config = Config::new();
+    config.set_model_dir(data_dir().join("models"));
+    config.set_stachelhaus_signatures(data_dir().join("stachelhaus.tsv"));
+    config.skip_v1 = true;
+    config.skip_v3 = true;
+    config.output_format = output_format;
+    config
+}
+
+fn run(config: &Config) -> String {
+    let domains = run_on_file(config, data_dir().join("signatures.tsv")).unwrap();
+    format_results(config, &domains).unwrap()
+}
+
+/// Compare `got` to the golden file `name`, or rewrite it if `UPDATE_GOLDEN` is set
+fn check_golden(name: &str, got: &str, compare: fn(&str, &str)) {
+    let path = data_dir().join("golden").join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, got).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("{}: {err}, run with UPDATE_GOLDEN=1", path.display()));
+    compare(got, &expected);
+}
+
+fn compare_text(got: &str, expected: &str) {
+    for (i, (got_line, expected_line)) in got.lines().zip(expected.lines()).enumerate() {
+        assert_eq!(got_line, expected_line, "line {}", i + 1);
+    }
+    assert_eq!(got.lines().count(), expected.lines().count());
+}
+
+/// Scores are compared with a tolerance, so builds with `f32-vectors` match as well
+fn compare_json(got: &str, expected: &str) {
+    let got: Value = serde_json::from_str(got).unwrap();
+    let expected: Value = serde_json::from_str(expected).unwrap();
+    assert_json_close(&got, &expected, "");
+}
+
+fn assert_json_close(got: &Value, expected: &Value, path: &str) {
+    match (got, expected) {
+        (Value::Number(a), Value::Number(b)) => {
+            let (a, b) = (a.as_f64().unwrap(), b.as_f64().unwrap());
+            assert!((a - b).abs() < 1e-4, "{path}: {a} != {b}");
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            assert_eq!(a.len(), b.len(), "{path}: length");
+            for (i, (a, b)) in a.iter().zip(b).enumerate() {
+                assert_json_close(a, b, &format!("{path}[{i}]"));
+            }
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            let keys: Vec<&String> = a.keys().collect();
+            assert_eq!(keys, b.keys().collect::<Vec<_>>(), "{path}: keys");
+            for (key, value) in a.iter() {
+                assert_json_close(value, &b[key], &format!("{path}.{key}"));
+            }
+        }
+        _ => assert_eq!(got, expected, "{path}"),
+    }
+}
+
+#[test]
+fn test_golden_tsv() {
+    check_golden(
+        "results.tsv",
+        &run(&config(OutputFormat::Tsv)),
+        compare_text,
+    );
+}
+
+#[test]
+fn test_golden_json() {
+    check_golden(
+        "results.json",
+        &run(&config(OutputFormat::Json)),
+        compare_json,
+    );
+}
+
+#[test]
+fn test_golden_long() {
+    let mut config = config(OutputFormat::Long);
+    config.count = 2;
+    check_golden("results_long.tsv", &run(&config), compare_text);
+}
+
+#[test]
+fn test_golden_legacy() {
+    check_golden(
+        "results_legacy.txt",
+        &run(&config(OutputFormat::Legacy)),
+        compare_text,
+    );
+}
+
+#[test]
+fn test_golden_options() {
+    let mut config = config(OutputFormat::Tsv);
+    config.summary = true;
+    config.novelty = true;
+    config.stachelhaus_detail = true;
+    config.count = 2;
+    check_golden("results_options.tsv", &run(&config), compare_text);
+}
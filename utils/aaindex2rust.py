@@ -48,6 +48,7 @@ def run(data: IO, out: IO) -> None:
 use phf::phf_map;
 
 use crate::encodings::get_value;
+use crate::encodings::tables::PropertyTable;
 
 static {name}_MAP: phf::Map<char, f64> = phf_map! {{''', file=out)
 
@@ -67,6 +68,16 @@ pub fn get(c: char) -> f64 {{
         {name}_STDEV,
         true,
     )
+}}
+
+pub fn table() -> PropertyTable {{
+    PropertyTable::from_map(
+        "{name.lower()}",
+        &{name}_MAP,
+        {name}_MEAN,
+        {name}_STDEV,
+        true,
+    )
 }}''', file=out)
 
 